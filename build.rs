@@ -0,0 +1,17 @@
+//! 在 Windows（MSVC）上为 `pae` 嵌入程序清单 `pae.manifest`，声明支持长路径。
+//! GNU 工具链需要资源编译器才能嵌入清单，不做处理；长路径仍由 `longpath` 模块转换。
+
+fn main() {
+    println!("cargo:rerun-if-changed=pae.manifest");
+    let windows = std::env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "windows");
+    let msvc = std::env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|env| env == "msvc");
+    if windows && msvc {
+        let manifest = std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("pae.manifest");
+        println!("cargo:rustc-link-arg-bin=pae=/MANIFEST:EMBED");
+        println!(
+            "cargo:rustc-link-arg-bin=pae=/MANIFESTINPUT:{}",
+            manifest.display()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! 一次性模式的 zip 打包（`--archive`）：每个文件导出完成后立即写入同一个 zip，
+//! 不必等全部导出结束。可选择保留相对于根目录的目录结构。要求输出确定时改为在
+//! 结束时按路径顺序写入，条目顺序不受导出完成先后的影响。
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::BufWriter,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::zip::ZipWriter;
+
+/// 已经压缩过的格式，直接存储，再次 deflate 只会浪费时间
+const COMPRESSED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "avif", "gif", "ora"];
+
+pub struct Archive {
+    path: PathBuf,
+    root: PathBuf,
+    /// 是否保留目录结构
+    tree: bool,
+    state: Mutex<State>,
+}
+
+struct State {
+    writer: ZipWriter<BufWriter<File>>,
+    /// 已使用的条目名，用于处理重名
+    names: HashSet<String>,
+    /// 等待结束时按顺序写入的文件，为空表示立即写入
+    pending: Option<Vec<PathBuf>>,
+    /// 写入 zip 已经失败，之后的文件不再写入，只在第一次失败时报告错误
+    broken: bool,
+}
+
+impl Archive {
+    pub fn create(path: &Path, root: &Path, tree: bool, deterministic: bool) -> Result<Self> {
+        let file = File::create(path).context(format!("无法创建输出文件：{:?}", path))?;
+        Ok(Archive {
+            path: path.to_path_buf(),
+            root: root.to_path_buf(),
+            tree,
+            state: Mutex::new(State {
+                writer: ZipWriter::new(BufWriter::new(file)),
+                names: HashSet::new(),
+                pending: deterministic.then(Vec::new),
+                broken: false,
+            }),
+        })
+    }
+
+    /// 把一个导出文件加入 zip
+    pub fn add(&self, file: &Path) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match &mut state.pending {
+            Some(pending) => pending.push(file.to_path_buf()),
+            None => self.write(&mut state, file)?,
+        }
+        Ok(())
+    }
+
+    fn write(&self, state: &mut State, file: &Path) -> Result<()> {
+        if state.broken {
+            return Ok(());
+        }
+        let data = std::fs::read(file).context(format!("无法读取输出文件：{:?}", file))?;
+        let compressed = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        let name = unique_name(&state.names, &self.entry_name(file));
+        let written = if compressed {
+            state.writer.add_stored(&name, &data)
+        } else {
+            state.writer.add_deflated(&name, &data)
+        };
+        // 写到一半失败后 zip 已经损坏，后续条目写入也没有意义
+        state.broken = written.is_err();
+        written.context(format!("无法写入 zip 文件：{:?}", self.path))?;
+        state.names.insert(name);
+        Ok(())
+    }
+
+    /// 写入中央目录，返回条目数量
+    pub fn finish(self) -> Result<usize> {
+        let pending = self.state.lock().unwrap().pending.take();
+        if let Some(mut pending) = pending {
+            pending.sort();
+            let mut state = self.state.lock().unwrap();
+            for file in pending {
+                self.write(&mut state, &file)?;
+            }
+        }
+        let state = self.state.into_inner().unwrap();
+        if state.broken {
+            bail!("写入 zip 文件失败，打包未完成：{:?}", self.path);
+        }
+        let count = state.names.len();
+        state
+            .writer
+            .finish()
+            .context(format!("无法写入 zip 文件：{:?}", self.path))?;
+        Ok(count)
+    }
+
+    /// 条目名使用 `/` 分隔；不在根目录下的文件（例如脚本指定的输出路径）只保留文件名
+    fn entry_name(&self, file: &Path) -> String {
+        let file_name = || file.file_name().unwrap_or_default().to_string_lossy();
+        if !self.tree {
+            return file_name().into_owned();
+        }
+        let Ok(relative) = file.strip_prefix(&self.root) else {
+            return file_name().into_owned();
+        };
+        let components: Vec<_> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+        if components.is_empty() {
+            file_name().into_owned()
+        } else {
+            components.join("/")
+        }
+    }
+}
+
+/// 重名时在扩展名前加上序号：`a.png`、`a-2.png`、`a-3.png`……
+fn unique_name(names: &HashSet<String>, name: &str) -> String {
+    if !names.contains(name) {
+        return name.to_string();
+    }
+    // 只在最后一级的文件名中找扩展名
+    let base = name.rfind('/').map_or(0, |i| i + 1);
+    let (stem, ext) = match name[base..].rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(base + dot),
+        _ => (name, ""),
+    };
+    (2..)
+        .map(|i| format!("{}-{}{}", stem, i, ext))
+        .find(|candidate| !names.contains(candidate))
+        .unwrap_or_default()
+}
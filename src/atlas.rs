@@ -0,0 +1,259 @@
+//! 图集（`--atlas`）：一次性模式结束后，把每个目录中导出的图片裁掉透明边缘后排进一张
+//! `atlas.png`，并按 TexturePacker 的通用 JSON（Hash）或 XML 格式写入各精灵的位置、
+//! 裁剪与旋转信息，引擎中现有的加载器可以直接使用。
+//!
+//! 精灵按高度从高到低逐行排列，不旋转；旋转标记始终为 `false`。
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::RgbaImage;
+
+use crate::{ora, report::json_string, transform};
+
+/// 图集图片文件名
+pub const ATLAS_NAME: &str = "atlas.png";
+
+/// 精灵之间的间距，避免纹理过滤时相邻精灵互相渗色
+const PADDING: u32 = 2;
+
+/// 图集描述文件的格式
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtlasFormat {
+    /// TexturePacker 的 JSON（Hash），写入 `atlas.json`
+    Json,
+    /// TexturePacker 的通用 XML，写入 `atlas.xml`
+    Xml,
+}
+
+impl AtlasFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            AtlasFormat::Json => "json",
+            AtlasFormat::Xml => "xml",
+        }
+    }
+}
+
+/// 一帧在图集或总览图中的位置，以及裁剪前的尺寸
+pub(crate) struct Frame {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// 裁剪后的图像在原图中的位置
+    pub offset: (u32, u32),
+    /// 原图尺寸
+    pub source: (u32, u32),
+}
+
+impl Frame {
+    fn trimmed(&self) -> bool {
+        self.source != (self.width, self.height)
+    }
+}
+
+/// 按所在目录分组为每个目录生成图集，返回写入的文件列表。无法读取的图片跳过
+pub fn write_atlases(images: &[PathBuf], format: AtlasFormat) -> Result<Vec<PathBuf>> {
+    let mut groups: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for path in images {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        groups.entry(dir).or_default().push(path);
+    }
+
+    let mut written = Vec::new();
+    for (dir, mut paths) in groups {
+        paths.sort();
+        let mut sprites = Vec::new();
+        for path in paths {
+            let image = match image::open(path) {
+                Ok(image) => image.into_rgba8(),
+                Err(e) => {
+                    log::warn!("无法读取图片，不放入图集：{:?}：{}", path, e);
+                    continue;
+                }
+            };
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            sprites.push((name, image));
+        }
+        if sprites.is_empty() {
+            continue;
+        }
+        let (atlas, frames) = pack(sprites);
+        let output = dir.join(ATLAS_NAME);
+        atlas
+            .save(&output)
+            .context(format!("无法保存图集：{:?}", output))?;
+        written.push(output);
+
+        let output = dir.join(ATLAS_NAME).with_extension(format.extension());
+        let text = match format {
+            AtlasFormat::Json => frames_json(&frames, false, ATLAS_NAME, atlas.dimensions()),
+            AtlasFormat::Xml => frames_xml(&frames, ATLAS_NAME, atlas.dimensions()),
+        };
+        std::fs::write(&output, text).context(format!("无法写入图集描述：{:?}", output))?;
+        written.push(output);
+    }
+    Ok(written)
+}
+
+/// 裁掉透明边缘后逐行排列。图集宽度取能放下最宽精灵、且接近正方形的 2 的幂
+fn pack(sprites: Vec<(String, RgbaImage)>) -> (RgbaImage, Vec<Frame>) {
+    let mut trimmed: Vec<(Frame, RgbaImage)> = sprites
+        .into_iter()
+        .map(|(name, image)| {
+            let source = image.dimensions();
+            // 完全透明的图片保留一个像素，加载器仍能找到这一帧
+            let (offset, cropped) = match transform::opaque_bounds(&image) {
+                Some((left, top, right, bottom)) => (
+                    (left, top),
+                    image::imageops::crop_imm(&image, left, top, right - left, bottom - top)
+                        .to_image(),
+                ),
+                None => ((0, 0), RgbaImage::new(1, 1)),
+            };
+            let frame = Frame {
+                name,
+                x: 0,
+                y: 0,
+                width: cropped.width(),
+                height: cropped.height(),
+                offset,
+                source,
+            };
+            (frame, cropped)
+        })
+        .collect();
+    trimmed.sort_by(|(a, _), (b, _)| b.height.cmp(&a.height).then(a.name.cmp(&b.name)));
+
+    let area: u64 = trimmed
+        .iter()
+        .map(|(frame, _)| (frame.width + PADDING) as u64 * (frame.height + PADDING) as u64)
+        .sum();
+    let widest = trimmed
+        .iter()
+        .map(|(frame, _)| frame.width)
+        .max()
+        .unwrap_or(1);
+    let width = ((area as f64).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two();
+
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for (frame, _) in &mut trimmed {
+        if x > 0 && x + frame.width > width {
+            x = 0;
+            y += row_height + PADDING;
+            row_height = 0;
+        }
+        (frame.x, frame.y) = (x, y);
+        x += frame.width + PADDING;
+        row_height = row_height.max(frame.height);
+    }
+
+    let mut atlas = RgbaImage::new(width, (y + row_height).max(1));
+    for (frame, image) in &trimmed {
+        image::imageops::replace(&mut atlas, image, frame.x as i64, frame.y as i64);
+    }
+    let mut frames: Vec<Frame> = trimmed.into_iter().map(|(frame, _)| frame).collect();
+    frames.sort_by(|a, b| a.name.cmp(&b.name));
+    (atlas, frames)
+}
+
+/// TexturePacker / Aseprite 共用的 JSON 帧格式，`array` 为 `true` 时 `frames` 是数组
+pub(crate) fn frames_json(
+    frames: &[Frame],
+    array: bool,
+    image: &str,
+    (width, height): (u32, u32),
+) -> String {
+    let mut out = String::from("{\n");
+    out.push_str(if array {
+        "  \"frames\": ["
+    } else {
+        "  \"frames\": {"
+    });
+    for (i, frame) in frames.iter().enumerate() {
+        out.push_str(if i == 0 { "\n    " } else { ",\n    " });
+        let name = json_string(&frame.name);
+        if array {
+            _ = write!(out, "{{\"filename\": {}, ", name);
+        } else {
+            _ = write!(out, "{}: {{", name);
+        }
+        _ = write!(
+            out,
+            "\"frame\": {{\"x\": {}, \"y\": {}, \"w\": {}, \"h\": {}}}, \
+             \"rotated\": false, \"trimmed\": {}, \
+             \"spriteSourceSize\": {{\"x\": {}, \"y\": {}, \"w\": {}, \"h\": {}}}, \
+             \"sourceSize\": {{\"w\": {}, \"h\": {}}}, \"duration\": 100}}",
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+            frame.trimmed(),
+            frame.offset.0,
+            frame.offset.1,
+            frame.width,
+            frame.height,
+            frame.source.0,
+            frame.source.1
+        );
+    }
+    let close = if array { "]" } else { "}" };
+    if frames.is_empty() {
+        _ = writeln!(out, "{},", close);
+    } else {
+        _ = writeln!(out, "\n  {},", close);
+    }
+    _ = writeln!(
+        out,
+        "  \"meta\": {{\"app\": \"{}\", \"version\": \"{}\", \"image\": {}, \
+         \"format\": \"RGBA8888\", \"size\": {{\"w\": {}, \"h\": {}}}, \"scale\": \"1\"}}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        json_string(image),
+        width,
+        height
+    );
+    out.push_str("}\n");
+    out
+}
+
+/// TexturePacker 的通用 XML 格式：`oX`/`oY` 为裁剪偏移，`oW`/`oH` 为原图尺寸
+fn frames_xml(frames: &[Frame], image: &str, (width, height): (u32, u32)) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <TextureAtlas imagePath=\"{}\" width=\"{}\" height=\"{}\">\n",
+        ora::escape(image),
+        width,
+        height
+    );
+    for frame in frames {
+        _ = writeln!(
+            out,
+            "    <sprite n=\"{}\" x=\"{}\" y=\"{}\" w=\"{}\" h=\"{}\" \
+             oX=\"{}\" oY=\"{}\" oW=\"{}\" oH=\"{}\"/>",
+            ora::escape(&frame.name),
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+            frame.offset.0,
+            frame.offset.1,
+            frame.source.0,
+            frame.source.1
+        );
+    }
+    out.push_str("</TextureAtlas>\n");
+    out
+}
@@ -0,0 +1,213 @@
+//! 按内存预算限制同时进行的导出任务（`--max-memory`）。每个任务开始前根据源文件
+//! 头部估算所需内存，预算不足时等待其它任务完成，而不是只按线程数并发。
+//!
+//! 预算在分派任务的线程上获取，拿到后才把任务交给 rayon 线程池，不能在线程池的
+//! 工作线程中等待：持有预算的导出会执行嵌套的并行工作（逐行解压、PNG 分块压缩），
+//! 它在等待时可能取走另一个文件的任务，若该任务在同一线程上等待预算，而预算正被
+//! 栈中更早的导出占用，就会永远等下去。
+
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use anyhow::{Context, Result, bail};
+use rayon::prelude::*;
+
+/// 解析内存或文件大小：`8G`、`512M`、`1024K`、`1.5GiB` 或纯字节数，单位按 1024 进制计算
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let number = upper.trim_end_matches(['B', 'I']);
+    let (number, unit) = match number.char_indices().last() {
+        Some((i, c @ ('K' | 'M' | 'G' | 'T'))) => (&number[..i], c),
+        _ => (number, ' '),
+    };
+    let shift = match unit {
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        'T' => 40,
+        _ => 0,
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .context(format!("无效的大小：{}", s))?;
+    if !value.is_finite() || value <= 0.0 {
+        bail!("大小必须大于 0：{}", s);
+    }
+    Ok((value * (1u64 << shift) as f64) as u64)
+}
+
+/// 以字节为单位的内存预算
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+/// 已占用的预算，离开作用域时归还。可以随任务一起交给线程池
+pub struct Permit {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: u64) -> Self {
+        MemoryBudget {
+            limit,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// 等待直到有足够的预算。超过整个预算的任务会在没有其它任务运行时单独执行。
+    /// 不能在 rayon 工作线程中调用，见模块说明
+    pub fn acquire(self: &Arc<Self>, bytes: u64) -> Permit {
+        debug_assert!(
+            rayon::current_thread_index().is_none(),
+            "不能在导出线程中等待内存预算"
+        );
+        let bytes = bytes.min(self.limit);
+        let mut used = self.used.lock().unwrap();
+        while *used + bytes > self.limit {
+            used = self.released.wait(used).unwrap();
+        }
+        *used += bytes;
+        Permit {
+            budget: Arc::clone(self),
+            bytes,
+        }
+    }
+
+    /// 与 [`MemoryBudget::acquire`] 相同，但预算不足时立即返回 `None`，供不能阻塞的
+    /// 事件循环使用
+    pub fn try_acquire(self: &Arc<Self>, bytes: u64) -> Option<Permit> {
+        let bytes = bytes.min(self.limit);
+        let mut used = self.used.lock().unwrap();
+        if *used + bytes > self.limit {
+            return None;
+        }
+        *used += bytes;
+        Some(Permit {
+            budget: Arc::clone(self),
+            bytes,
+        })
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+/// PSB 每边最多 300000 像素（PSD 为 30000）
+const MAX_PSD_SIDE: u64 = 300_000;
+
+/// 估算导出一个源文件需要的内存。PSD 按文件头中的尺寸计算：读入的文件本身、
+/// 原始通道数据、合成后的 RGBA 画布，以及变换或编码时的一份副本；其它格式
+/// 无法廉价读取尺寸，按文件大小的 4 倍估算。文件头来自不可信的文件，尺寸按 PSB 的
+/// 上限截断，计算时饱和而不溢出
+pub fn estimate(path: &Path) -> u64 {
+    let mut header = [0u8; 26];
+    let Ok(mut file) = File::open(path) else {
+        return 0;
+    };
+    let file_len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    if file.read_exact(&mut header).is_err() || &header[..4] != b"8BPS" {
+        return file_len.saturating_mul(4);
+    }
+    let channels = u16::from_be_bytes([header[12], header[13]]) as u64;
+    let height = u32::from_be_bytes([header[14], header[15], header[16], header[17]]) as u64;
+    let width = u32::from_be_bytes([header[18], header[19], header[20], header[21]]) as u64;
+    let depth = u16::from_be_bytes([header[22], header[23]]) as u64;
+    let pixels = width.min(MAX_PSD_SIDE) * height.min(MAX_PSD_SIDE);
+    pixels
+        .saturating_mul(channels)
+        .saturating_mul(depth.div_ceil(8))
+        .saturating_add(pixels.saturating_mul(4 * 2))
+        .saturating_add(file_len)
+}
+
+/// 在线程池中对 `items` 逐个执行 `f`，按 `items` 的顺序返回结果，开始执行的顺序也与
+/// `items` 相同。设置了预算时，在调用线程上为每一项等待 `estimate` 字节的预算，拿到后
+/// 才交给线程池，执行完成后归还；调用线程不能是 rayon 工作线程
+pub fn map<T: Send, R: Send>(
+    budget: Option<&Arc<MemoryBudget>>,
+    items: impl Iterator<Item = T> + Send,
+    estimate: impl Fn(&T) -> u64,
+    f: impl Fn(T) -> R + Sync,
+) -> Vec<R> {
+    let mut results: Vec<(usize, R)> = match budget {
+        None => items
+            .enumerate()
+            .par_bridge()
+            .map(|(index, item)| (index, f(item)))
+            .collect(),
+        Some(budget) => {
+            let results = Mutex::new(Vec::new());
+            rayon::in_place_scope(|scope| {
+                for (index, item) in items.enumerate() {
+                    let permit = budget.acquire(estimate(&item));
+                    let (f, results) = (&f, &results);
+                    scope.spawn(move |_| {
+                        let result = f(item);
+                        drop(permit);
+                        results.lock().unwrap().push((index, result));
+                    });
+                }
+            });
+            results.into_inner().unwrap()
+        }
+    };
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn psd_header(channels: u16, height: u32, width: u32, depth: u16) -> Vec<u8> {
+        let mut header = b"8BPS".to_vec();
+        header.extend_from_slice(&1u16.to_be_bytes());
+        header.extend_from_slice(&[0; 6]);
+        header.extend_from_slice(&channels.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&depth.to_be_bytes());
+        header.extend_from_slice(&3u16.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn estimate_does_not_overflow() {
+        let dir = std::env::temp_dir().join(format!("pae-budget-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("normal.psd");
+        std::fs::write(&path, psd_header(4, 100, 200, 8)).unwrap();
+        assert_eq!(estimate(&path), 100 * 200 * 4 + 100 * 200 * 8 + 26);
+
+        // 畸形的文件头：各字段都取最大值时饱和，而不是溢出
+        let path = dir.join("huge.psd");
+        std::fs::write(&path, psd_header(u16::MAX, u32::MAX, u32::MAX, u16::MAX)).unwrap();
+        assert_eq!(estimate(&path), u64::MAX);
+        // 尺寸按上限截断
+        let path = dir.join("wide.psd");
+        std::fs::write(&path, psd_header(1, 1, u32::MAX, 8)).unwrap();
+        assert_eq!(estimate(&path), MAX_PSD_SIDE * 9 + 26);
+
+        let path = dir.join("other.bin");
+        std::fs::write(&path, [0; 10]).unwrap();
+        assert_eq!(estimate(&path), 40);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
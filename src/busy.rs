@@ -0,0 +1,62 @@
+//! 判断源文件是否仍被绘图软件占用。保存大文件需要一段时间，文件系统事件在写入开始时
+//! 就已发出，这时读取只会得到写了一半的文件；监听模式下检测到占用时推迟导出，稍后再
+//! 检查，而不是记为失败。检查分两步：先记下 [`stamp`]，隔 [`SETTLE`] 后再 [`check`]，
+//! 调用方自行安排这段等待，不占用导出线程。
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// 比较两次大小与修改时间的间隔
+pub const SETTLE: Duration = Duration::from_millis(100);
+
+/// 文件的大小与修改时间
+#[derive(Clone, Copy, PartialEq)]
+pub struct Stamp(u64, SystemTime);
+
+/// 读取文件当前的大小与修改时间，无法读取时返回 `None`
+pub fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = path.metadata().ok()?;
+    Some(Stamp(metadata.len(), metadata.modified().ok()?))
+}
+
+/// 文件仍被占用时返回原因。所有平台上检查大小与修改时间自 `before` 以来是否变化；
+/// Windows 上还检查是否被其它程序独占打开，Unix 上检查是否有进程持有 `flock` 排他锁
+pub fn check(path: &Path, before: Stamp) -> Option<&'static str> {
+    if stamp(path)? != before {
+        return Some("仍在写入");
+    }
+    if locked(path) {
+        return Some("被其它程序锁定");
+    }
+    None
+}
+
+#[cfg(windows)]
+fn locked(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    // 不与其它句柄共享时，其它程序仍打开着文件就会失败
+    std::fs::OpenOptions::new()
+        .read(true)
+        .share_mode(0)
+        .open(path)
+        .is_err_and(|e| e.raw_os_error() == Some(ERROR_SHARING_VIOLATION))
+}
+
+#[cfg(unix)]
+fn locked(path: &Path) -> bool {
+    use std::os::fd::AsRawFd;
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    // SAFETY: 文件描述符在 file 离开作用域之前有效，关闭时锁随之释放
+    let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    result != 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EWOULDBLOCK)
+}
+
+#[cfg(not(any(windows, unix)))]
+fn locked(_path: &Path) -> bool {
+    false
+}
@@ -0,0 +1,205 @@
+//! 一次性模式的导出进度：每个源文件导出完成（或被脚本跳过）后，把它与生成的文件追加到
+//! 根目录的 `.psd-export-progress`。程序被结束或崩溃后，用 `--resume` 跳过已完成的文件
+//! 继续导出；正常结束时删除该文件。
+//!
+//! 导出失败的文件不记录，继续时重新导出。路径按找到时的形式记录，继续时需要使用与
+//! 上次相同的监听路径。每行同时记录源文件的修改时间与大小，中断后被修改过的源文件
+//! 继续时重新导出；行首是其余内容的 CRC32，进程在写入途中被结束时不完整的行校验失败，
+//! 读取时丢弃。
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result};
+
+/// 进度文件名
+pub const CHECKPOINT_NAME: &str = ".psd-export-progress";
+
+const HEADER: &str = "# psd-auto-export 导出进度，正常结束后自动删除\n";
+
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// 上次运行已完成的源文件
+    done: HashMap<PathBuf, Done>,
+}
+
+/// 上次运行中完成的一个源文件
+struct Done {
+    /// 完成时源文件的修改时间与大小
+    fingerprint: Fingerprint,
+    /// 生成的文件，被脚本跳过的源文件没有输出
+    outputs: Vec<PathBuf>,
+}
+
+/// 源文件的修改时间（自 Unix 纪元起的纳秒数）与大小
+type Fingerprint = (u128, u64);
+
+impl Checkpoint {
+    /// 在 `root` 下开始记录进度。`resume` 为真时读取并沿用上次的进度，否则丢弃
+    pub fn open(root: &Path, resume: bool) -> Result<Self> {
+        let path = root.join(CHECKPOINT_NAME);
+        let done = if resume { load(&path)? } else { HashMap::new() };
+        if !resume && path.exists() {
+            log::warn!("丢弃上次未完成的导出进度，使用 --resume 可以从中断处继续");
+        }
+        let mut options = OpenOptions::new();
+        if resume {
+            options.create(true).append(true);
+        } else {
+            options.create(true).write(true).truncate(true);
+        }
+        let mut file = options
+            .open(&path)
+            .context(format!("无法写入导出进度：{:?}", path))?;
+        // 继续时先换行，新记录不会接在上次没有写完整的最后一行后面
+        let start = if file.metadata().is_ok_and(|m| m.len() > 0) {
+            "\n"
+        } else {
+            HEADER
+        };
+        file.write_all(start.as_bytes())
+            .context(format!("无法写入导出进度：{:?}", path))?;
+        Ok(Checkpoint {
+            path,
+            file: Mutex::new(file),
+            done,
+        })
+    }
+
+    /// 源文件是否在上次运行中已经完成，且之后没有被修改
+    pub fn is_done(&self, source: &Path) -> bool {
+        self.done
+            .get(source)
+            .is_some_and(|done| fingerprint(source) == Some(done.fingerprint))
+    }
+
+    /// 上次运行中导出成功、本次跳过的源文件及其输出，同步与锁文件需要包括它们
+    pub fn previous(&self) -> impl Iterator<Item = (PathBuf, Vec<PathBuf>)> + '_ {
+        self.done
+            .iter()
+            .filter(|(source, done)| !done.outputs.is_empty() && self.is_done(source))
+            .map(|(source, done)| (source.clone(), done.outputs.clone()))
+    }
+
+    /// 记录一个已完成的源文件。写入失败只给出警告，导出照常进行；无法读取源文件的
+    /// 修改时间时不记录，继续时重新导出
+    pub fn record(&self, source: &Path, outputs: &[PathBuf]) {
+        let Some((modified, size)) = fingerprint(source) else {
+            return;
+        };
+        let mut fields = format!("{}\t{}\t{}", modified, size, source.to_string_lossy());
+        for output in outputs {
+            fields.push('\t');
+            fields.push_str(&output.to_string_lossy());
+        }
+        let line = format!("{:08x}\t{}\n", crc32fast::hash(fields.as_bytes()), fields);
+        // 每行一次写入，进程被结束时最多丢失正在写入的一行
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            log::warn!("无法写入导出进度 {:?}：{}", self.path, e);
+        }
+    }
+
+    /// 全部完成，删除进度文件
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+        std::fs::remove_file(&self.path).context(format!("无法删除导出进度：{:?}", self.path))
+    }
+}
+
+fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    let metadata = path.metadata().ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((modified.as_nanos(), metadata.len()))
+}
+
+/// 读取进度文件，不存在时为空
+fn load(path: &Path) -> Result<HashMap<PathBuf, Done>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e).context(format!("无法读取导出进度：{:?}", path)),
+    };
+    Ok(text.lines().filter_map(parse_line).collect())
+}
+
+/// 解析一行记录，注释、空行与校验失败（没有写完整）的行返回 `None`
+fn parse_line(line: &str) -> Option<(PathBuf, Done)> {
+    let (checksum, fields) = line.split_once('\t')?;
+    if u32::from_str_radix(checksum, 16).ok()? != crc32fast::hash(fields.as_bytes()) {
+        return None;
+    }
+    let mut fields = fields.split('\t');
+    let modified = fields.next()?.parse().ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let source = PathBuf::from(fields.next()?);
+    Some((
+        source,
+        Done {
+            fingerprint: (modified, size),
+            outputs: fields.map(PathBuf::from).collect(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_recorded_sources() {
+        let dir = std::env::temp_dir().join(format!("pae-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a.psd"), dir.join("b.psd"), dir.join("c psd.psd"));
+        for source in [&a, &b, &c] {
+            std::fs::write(source, "psd").unwrap();
+        }
+        let outputs = [dir.join("a.png"), dir.join("a@2x.png")];
+
+        let checkpoint = Checkpoint::open(&dir, false).unwrap();
+        checkpoint.record(&a, &outputs);
+        // 被脚本跳过的源文件没有输出
+        checkpoint.record(&b, &[]);
+        checkpoint.record(&c, &[dir.join("c psd.png")]);
+        drop(checkpoint);
+        // 进程在写入途中被结束：最后一行不完整
+        let path = dir.join(CHECKPOINT_NAME);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let last = text.trim_end().lines().last().unwrap();
+        assert!(parse_line(last).is_some());
+        assert!(parse_line(&last[..last.len() - 1]).is_none());
+        std::fs::write(&path, &text[..text.len() - 2]).unwrap();
+        // 中断后修改过的源文件重新导出
+        std::fs::write(&b, "changed").unwrap();
+
+        let checkpoint = Checkpoint::open(&dir, true).unwrap();
+        assert!(checkpoint.is_done(&a));
+        assert!(!checkpoint.is_done(&b));
+        assert!(!checkpoint.is_done(&c));
+        assert_eq!(
+            checkpoint.previous().collect::<Vec<_>>(),
+            [(a.clone(), outputs.to_vec())]
+        );
+        // 继续时追加在不完整的行之后，新记录仍可读取
+        checkpoint.record(&c, &[]);
+        drop(checkpoint);
+        let checkpoint = Checkpoint::open(&dir, true).unwrap();
+        assert!(checkpoint.is_done(&a));
+        assert!(checkpoint.is_done(&c));
+        checkpoint.finish().unwrap();
+        assert!(!path.exists());
+
+        // 不继续时丢弃上次的进度
+        Checkpoint::open(&dir, false).unwrap().record(&a, &outputs);
+        assert!(!Checkpoint::open(&dir, false).unwrap().is_done(&a));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+//! 写入输出文件的时间（zip 条目时间、水印中的 `{date}` 与 `{time}`）。`--deterministic`
+//! 时固定为 `SOURCE_DATE_EPOCH`（未设置时为 1970-01-01），相同的输入得到逐字节相同的输出。
+
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+static FIXED: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+/// 设置是否固定时间，重新加载配置时会再次调用
+pub fn set_deterministic(deterministic: bool) -> Result<()> {
+    let fixed = if deterministic {
+        Some(source_date_epoch()?)
+    } else {
+        None
+    };
+    *FIXED.lock().unwrap() = fixed;
+    Ok(())
+}
+
+pub fn now() -> SystemTime {
+    FIXED.lock().unwrap().unwrap_or_else(SystemTime::now)
+}
+
+/// 可重现构建的通用约定：环境变量 `SOURCE_DATE_EPOCH` 为 Unix 时间戳（秒）
+fn source_date_epoch() -> Result<SystemTime> {
+    let Ok(value) = std::env::var("SOURCE_DATE_EPOCH") else {
+        return Ok(UNIX_EPOCH);
+    };
+    let secs: u64 = value.trim().parse().context(format!(
+        "SOURCE_DATE_EPOCH 应为 Unix 时间戳（秒）：{}",
+        value
+    ))?;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// 解析 `--since` 的时间：`2024-01-01`、`2024-01-01 08:30:00` 或 RFC 3339 格式，
+/// 不带时区时按 UTC 计算
+pub fn parse_time(s: &str) -> Result<SystemTime> {
+    let text = s.trim();
+    let text = if text.len() == 10 {
+        format!("{}T00:00:00Z", text)
+    } else {
+        text.to_string()
+    };
+    humantime::parse_rfc3339_weak(&text).context(format!("无效的时间：{}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: &str) -> u64 {
+        parse_time(s)
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn parses_since_times() {
+        // 2024-01-01T00:00:00Z
+        let midnight = 1_704_067_200;
+        assert_eq!(secs("2024-01-01"), midnight);
+        assert_eq!(secs(" 2024-01-01 "), midnight);
+        assert_eq!(secs("2024-01-01 08:30:00"), midnight + 8 * 3600 + 30 * 60);
+        assert_eq!(secs("2024-01-01T08:30:00Z"), midnight + 8 * 3600 + 30 * 60);
+        assert_eq!(
+            secs("2024-01-01T08:30:00.5Z"),
+            midnight + 8 * 3600 + 30 * 60
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        for s in [
+            "",
+            "yesterday",
+            "2024-13-01",
+            "2024-02-30",
+            "2024/01/01",
+            "7d",
+        ] {
+            assert!(parse_time(s).is_err(), "应当拒绝：{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! 输出冲突检测：不同的源文件写入同一个输出路径时（如 `logo.psd` 与 `logo.kra`、
+//! `[[target]]` 的 `dir` 为同一个绝对路径、或大小写不敏感的文件系统上的 `Logo.psd` 与
+//! `logo.psd`），在导出前发现并按 [`Policy`] 处理，而不是让它们互相覆盖。
+//!
+//! 按源文件路径排序，排在前面的源文件保留原来的输出；边扫描边导出（`--stream`）时
+//! 无法预先得到全部源文件，先开始导出的源文件保留原来的输出。
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::absolute;
+
+/// 冲突的处理方式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// 后面的源文件改为输出 `name-2.png`、`name-3.png` 等
+    #[default]
+    Rename,
+    /// 后面的源文件导出失败
+    Fail,
+}
+
+/// 一个源文件计划写入的输出
+pub struct Planned {
+    pub source: PathBuf,
+    pub outputs: Vec<PathBuf>,
+    /// 是否可以改名：只有一个输出且没有使用 `[[target]]`
+    pub renamable: bool,
+}
+
+/// 一个冲突的源文件的处理结果
+#[derive(Clone, Debug)]
+pub enum Resolution {
+    /// 改为写入该路径
+    Rename(PathBuf),
+    /// 输出与 `winner` 的冲突，不导出
+    Fail { output: PathBuf, winner: PathBuf },
+}
+
+/// 找出冲突的源文件并决定处理方式，返回以源文件绝对路径为键的结果
+pub fn detect(mut planned: Vec<Planned>, policy: Policy) -> HashMap<PathBuf, Resolution> {
+    planned.sort_by(|a, b| a.source.cmp(&b.source));
+    let mut claims = Claims::new(policy);
+    planned
+        .iter()
+        .filter_map(|plan| Some((absolute(&plan.source), claims.claim(plan)?)))
+        .collect()
+}
+
+/// 已被源文件占用的输出。源文件逐个加入，先加入的保留原来的输出
+pub struct Claims {
+    policy: Policy,
+    /// 比较用的输出路径与占用它的源文件
+    claimed: HashMap<PathBuf, PathBuf>,
+}
+
+impl Claims {
+    pub fn new(policy: Policy) -> Self {
+        Claims {
+            policy,
+            claimed: HashMap::new(),
+        }
+    }
+
+    /// 加入一个源文件的输出，与已加入的源文件冲突时返回处理方式
+    pub fn claim(&mut self, plan: &Planned) -> Option<Resolution> {
+        let collision = plan.outputs.iter().find_map(|output| {
+            self.claimed
+                .get(&key(output))
+                .filter(|winner| **winner != plan.source)
+                .map(|winner| (output, winner.clone()))
+        });
+        let Some((output, winner)) = collision else {
+            for output in &plan.outputs {
+                self.claimed
+                    .entry(key(output))
+                    .or_insert_with(|| plan.source.clone());
+            }
+            return None;
+        };
+        log::warn!("{:?} 与 {:?} 都会写入 {:?}", plan.source, winner, output);
+        if self.policy == Policy::Rename && plan.renamable {
+            let renamed = rename(output, &self.claimed.keys().cloned().collect());
+            log::warn!("  {:?} 改为输出 {:?}", plan.source, renamed);
+            self.claimed.insert(key(&renamed), plan.source.clone());
+            return Some(Resolution::Rename(renamed));
+        }
+        if self.policy == Policy::Rename {
+            log::warn!("  {:?} 有多个输出目标，无法改名，将不会导出", plan.source);
+        }
+        Some(Resolution::Fail {
+            output: output.clone(),
+            winner,
+        })
+    }
+}
+
+/// 在扩展名前加上 `-2`、`-3` 等，直到不与已有的输出冲突
+fn rename(output: &Path, claimed: &HashSet<PathBuf>) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = output
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| output.with_file_name(format!("{}-{}{}", stem, n, extension)))
+        .find(|path| !claimed.contains(&key(path)))
+        .unwrap_or_else(|| output.to_path_buf())
+}
+
+/// 比较用的路径：绝对路径，在大小写不敏感的文件系统（Windows、macOS 的默认设置）上
+/// 转为小写
+fn key(path: &Path) -> PathBuf {
+    let path = absolute(path);
+    if cfg!(any(windows, target_os = "macos")) {
+        PathBuf::from(path.to_string_lossy().to_lowercase())
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(source: &str, outputs: &[&str]) -> Planned {
+        Planned {
+            source: PathBuf::from(source),
+            outputs: outputs.iter().map(PathBuf::from).collect(),
+            renamable: outputs.len() == 1,
+        }
+    }
+
+    #[test]
+    fn renames_later_sources() {
+        // 按源文件路径排序，与传入的顺序无关
+        let planned = vec![
+            plan("out/logo.psd", &["out/logo.png"]),
+            plan("out/logo.kra", &["out/logo.png"]),
+            plan("out/logo.ora", &["out/logo.png"]),
+            plan("out/other.psd", &["out/other.png"]),
+        ];
+        let resolutions = detect(planned, Policy::Rename);
+        assert_eq!(resolutions.len(), 2);
+        assert!(!resolutions.contains_key(&absolute(Path::new("out/logo.kra"))));
+        let renamed = |source: &str| match &resolutions[&absolute(Path::new(source))] {
+            Resolution::Rename(path) => path.clone(),
+            other => panic!("应当改名：{:?}", other),
+        };
+        assert_eq!(renamed("out/logo.ora"), Path::new("out/logo-2.png"));
+        assert_eq!(renamed("out/logo.psd"), Path::new("out/logo-3.png"));
+    }
+
+    #[test]
+    fn fails_when_not_renamable() {
+        for policy in [Policy::Rename, Policy::Fail] {
+            // 有多个输出的源文件无法改名
+            let planned = vec![
+                plan("a.psd", &["out/a.png"]),
+                plan("b.psd", &["out/b.png", "out/a.png"]),
+            ];
+            let resolutions = detect(planned, policy);
+            match &resolutions[&absolute(Path::new("b.psd"))] {
+                Resolution::Fail { output, winner } => {
+                    assert_eq!(output, Path::new("out/a.png"));
+                    assert_eq!(winner, Path::new("a.psd"));
+                }
+                other => panic!("应当失败：{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn claims_ignore_the_same_source() {
+        let mut claims = Claims::new(Policy::Fail);
+        let a = plan("a.psd", &["a.png"]);
+        assert!(claims.claim(&a).is_none());
+        // 同一个源文件再次加入（如重新导出）不算冲突
+        assert!(claims.claim(&a).is_none());
+        assert!(claims.claim(&plan("b.psd", &["b.png"])).is_none());
+        assert!(matches!(
+            claims.claim(&plan("c.psd", &["b.png"])),
+            Some(Resolution::Fail { .. })
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+//! 并行读取 PSD 的合并图像。psd crate 的 `Psd::rgba()` 在一个线程中依次解压每个通道，
+//! 超大文档的这一步会让其余核心空闲，而同一线程池中排在后面的小文件只能等待。合并图像的
+//! 每一行各自压缩，这里按行交给 rayon 并行解压并写入 RGBA。
+//!
+//! 通道的对应方式与 `Psd::rgba()` 相同，结果完全一致。只处理 8 位、未压缩或 RLE 压缩的
+//! PSD（非 PSB），其它文件以及数据与行长度不符的文件返回 `None`，由调用方改用 psd crate。
+
+use rayon::prelude::*;
+
+/// 文件头固定长度
+const HEADER_LEN: usize = 26;
+
+/// 灰度模式，第 2 个通道不使用
+const GRAYSCALE: u16 = 1;
+
+/// 读取合并图像为 RGBA 像素
+pub fn rgba(bytes: &[u8]) -> Option<Vec<u8>> {
+    let header = bytes.get(..HEADER_LEN)?;
+    if header[4..6] != [0, 1] || read_u16(header, 22)? != 8 {
+        return None;
+    }
+    let channels = read_u16(header, 12)? as usize;
+    let height = read_u32(header, 14)? as usize;
+    let width = read_u32(header, 18)? as usize;
+    let grayscale = read_u16(header, 24)? == GRAYSCALE;
+    if channels == 0 || width == 0 || height == 0 {
+        return None;
+    }
+
+    // 跳过颜色模式数据、图像资源与图层信息三段
+    let mut pos = HEADER_LEN;
+    for _ in 0..3 {
+        let len = read_u32(bytes, pos)? as usize;
+        pos = pos.checked_add(4)?.checked_add(len)?;
+    }
+    let compression = read_u16(bytes, pos)?;
+    let data = bytes.get(pos + 2..)?;
+
+    // 与 psd crate 相同：缺少绿、蓝通道时使用第 1 个通道，恰好 4 个通道时第 4 个是透明度
+    let red = 0;
+    let green = if channels >= 2 && !grayscale { 1 } else { red };
+    let blue = if channels >= 3 { 2 } else { red };
+    let alpha = (channels == 4).then_some(3);
+    let used = channels.min(4);
+
+    let rows = match compression {
+        0 => {
+            let plane = width.checked_mul(height)?;
+            if data.len() < plane.checked_mul(used)? {
+                return None;
+            }
+            Rows::Raw { width, plane }
+        }
+        1 => Rows::Rle(rle_offsets(data, channels, used, height)?),
+        _ => return None,
+    };
+
+    let mut rgba = vec![0; width.checked_mul(height)?.checked_mul(4)?];
+    rgba.par_chunks_mut(width * 4)
+        .enumerate()
+        .try_for_each(|(y, row)| {
+            for (offset, channel) in [red, green, blue].into_iter().enumerate() {
+                rows.unpack(data, channel, height, y, row, offset)?;
+            }
+            match alpha {
+                Some(channel) => rows.unpack(data, channel, height, y, row, 3)?,
+                // 没有透明度通道时完全不透明
+                None => row.iter_mut().skip(3).step_by(4).for_each(|a| *a = 255),
+            }
+            Some(())
+        })?;
+    Some(rgba)
+}
+
+/// 每个通道每一行数据的位置
+enum Rows {
+    /// 未压缩：各通道依次存放，每个通道 `plane` 字节
+    Raw { width: usize, plane: usize },
+    /// RLE：按通道、行的顺序排列的每行数据的起止位置
+    Rle(Vec<(usize, usize)>),
+}
+
+impl Rows {
+    /// 把第 `channel` 个通道的第 `y` 行写入 RGBA 行中偏移为 `offset` 的分量
+    fn unpack(
+        &self,
+        data: &[u8],
+        channel: usize,
+        height: usize,
+        y: usize,
+        row: &mut [u8],
+        offset: usize,
+    ) -> Option<()> {
+        match self {
+            Rows::Raw { width, plane } => {
+                let start = channel * plane + y * width;
+                let source = data.get(start..start + width)?;
+                for (pixel, value) in row.chunks_exact_mut(4).zip(source) {
+                    pixel[offset] = *value;
+                }
+                Some(())
+            }
+            Rows::Rle(offsets) => {
+                let (start, end) = offsets[channel * height + y];
+                unpack_row(data.get(start..end)?, row, offset)
+            }
+        }
+    }
+}
+
+/// 由开头每行压缩后长度的列表算出前 `used` 个通道每一行的起止位置
+fn rle_offsets(
+    data: &[u8],
+    channels: usize,
+    used: usize,
+    height: usize,
+) -> Option<Vec<(usize, usize)>> {
+    let mut pos = channels.checked_mul(height)?.checked_mul(2)?;
+    let mut offsets = Vec::with_capacity(used * height);
+    for i in 0..used * height {
+        let len = read_u16(data, i * 2)? as usize;
+        offsets.push((pos, pos + len));
+        pos += len;
+    }
+    Some(offsets)
+}
+
+/// 解压一行 PackBits 数据，写入 RGBA 行中偏移为 `offset` 的分量；解压结果必须恰好是一行
+fn unpack_row(packed: &[u8], row: &mut [u8], offset: usize) -> Option<()> {
+    let width = row.len() / 4;
+    let (mut pos, mut x) = (0, 0);
+    while x < width {
+        let n = *packed.get(pos)? as i8;
+        pos += 1;
+        match n {
+            0.. => {
+                let len = n as usize + 1;
+                let literal = packed.get(pos..pos + len)?;
+                for value in literal {
+                    *row.get_mut(x * 4 + offset)? = *value;
+                    x += 1;
+                }
+                pos += len;
+            }
+            -127..=-1 => {
+                let value = *packed.get(pos)?;
+                for _ in 0..(1 - n as isize) {
+                    *row.get_mut(x * 4 + offset)? = value;
+                    x += 1;
+                }
+                pos += 1;
+            }
+            // -128 不表示任何数据
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
@@ -0,0 +1,773 @@
+//! TOML 配置文件（`--config`，或监听根目录下的 `psd-export.toml`）。
+//!
+//! 配置项与命令行参数一一对应：键名就是长参数名（`pad-to` 或 `pad_to` 均可），
+//! 解析时转换为命令行参数放在真实参数之前，因此命令行总是覆盖配置文件；
+//! 已由 `PSD_EXPORT_*` 环境变量设置的键会被忽略，环境变量同样优先。
+//! 子目录中的 `.psd-export.toml` 只对该目录树生效，覆盖上级设置；源文件旁的
+//! `file.psd.toml` 只对该文件生效，优先级最高。监听模式下根配置文件修改后自动重新加载。
+//! 只实现了 TOML 的常用子集：注释、字符串、整数、浮点数、布尔值、数组与表头。
+
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+/// 监听根目录下自动读取的配置文件名
+pub const FILE_NAME: &str = "psd-export.toml";
+
+/// 子目录中覆盖上级设置的配置文件名
+pub const DIR_FILE_NAME: &str = ".psd-export.toml";
+
+/// 源文件旁配置文件的扩展名：`file.psd` 的配置文件为 `file.psd.toml`
+const SIDECAR_EXTENSION: &str = "toml";
+
+/// 预设表名的前缀，`[profile.web]` 定义名为 `web` 的预设
+const PROFILE_PREFIX: &str = "profile.";
+
+/// `[[target]]` 表定义同一源文件的一个输出
+const TARGET_TABLE: &str = "target";
+
+/// 设置参数的环境变量前缀
+pub const ENV_PREFIX: &str = "PSD_EXPORT_";
+
+/// 子目录的 `.psd-export.toml` 与源文件旁的 `file.psd.toml` 可以设置的键：只影响导出
+/// 结果，不会运行命令，也不会写到源文件旁以外的位置。`script`、`fallback`、`upload`、
+/// `open-with` 等只能由根配置文件与命令行设置，能在监听目录中写文件的人无法借此执行命令
+const OVERRIDABLE_KEYS: &[&str] = &[
+    "format",
+    "rotate",
+    "flip",
+    "resize",
+    "filter",
+    "pad-to",
+    "pad-color",
+    "colors",
+    "dither",
+    "dither-strength",
+    "quality",
+    "avif-speed",
+    "png-compression",
+    "tiff-compression",
+    "bit-depth",
+    "thumbnail",
+    "stamp",
+    "stamp-position",
+    "stamp-scale",
+    "steps",
+    "preview",
+    "channels",
+    "export-alpha",
+    "mipmaps",
+    "unity-meta",
+    "godot-import",
+    "godot-compress",
+    "godot-mipmaps",
+    "layer-masks",
+    "name-replacement",
+];
+
+/// 源文件旁的配置文件路径
+pub fn sidecar_path(source: &Path) -> PathBuf {
+    let mut name = source.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    source.with_file_name(name)
+}
+
+/// 旁配置文件对应的源文件（去掉 `.toml` 后的路径），不检查文件是否存在
+pub fn sidecar_source(path: &Path) -> Option<PathBuf> {
+    if path.extension()? != SIDECAR_EXTENSION {
+        return None;
+    }
+    let source = path.with_extension("");
+    source.extension().is_some().then_some(source)
+}
+
+/// 参数对应的环境变量名，例如 `pad-to` -> `PSD_EXPORT_PAD_TO`
+pub fn env_name(key: &str) -> String {
+    format!(
+        "{}{}",
+        ENV_PREFIX,
+        key.replace('-', "_").to_ascii_uppercase()
+    )
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// 一个表中按出现顺序排列的键值对
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub entries: Vec<(String, Value)>,
+}
+
+/// 整个配置文件：顶层键值与各个 `[name]` / `[[name]]` 表（按出现顺序）
+#[derive(Clone, Debug, Default)]
+pub struct Document {
+    pub root: Table,
+    pub tables: Vec<(String, Table)>,
+}
+
+impl Table {
+    /// 查找键，`pad-to` 与 `pad_to` 视为同一个键
+    fn position(&self, key: &str) -> Option<usize> {
+        let key = key.replace('_', "-");
+        self.entries
+            .iter()
+            .position(|(k, _)| k.replace('_', "-") == key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.position(key).map(|i| self.entries.remove(i).1)
+    }
+
+    /// 用 `other` 中的键覆盖本表的同名键，其余键追加在后
+    pub fn merge(&mut self, other: &Table) {
+        for (key, value) in &other.entries {
+            match self.position(key) {
+                Some(i) => self.entries[i].1 = value.clone(),
+                None => self.entries.push((key.clone(), value.clone())),
+            }
+        }
+    }
+
+    /// 取出 `profile` 键指定的预设名
+    fn take_profile(&mut self) -> Result<Option<String>> {
+        self.take_string("profile")
+    }
+
+    /// 取出一个字符串类型的键，它不会再被转换为命令行参数
+    pub fn take_string(&mut self, key: &str) -> Result<Option<String>> {
+        match self.remove(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => bail!("{} 必须是字符串", key),
+        }
+    }
+
+    /// 检查所有键都在 [`OVERRIDABLE_KEYS`] 中，且 `steps` 不含运行外部程序的 `plugin:` 步骤
+    pub fn check_overridable(&self) -> Result<()> {
+        for (key, value) in &self.entries {
+            let key = key.replace('_', "-");
+            if !OVERRIDABLE_KEYS.contains(&key.as_str()) {
+                bail!(
+                    "不能在这里设置 {}，它只能由根配置文件或命令行设置（可以设置：{}）",
+                    key,
+                    OVERRIDABLE_KEYS.join(", ")
+                );
+            }
+            let values = match value {
+                Value::Array(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            if key == "steps"
+                && values.iter().any(|step| {
+                    matches!(step, Value::String(step)
+                        if step.split(':').next().unwrap_or_default().trim() == "plugin")
+                })
+            {
+                bail!("不能在这里使用 plugin 步骤，它只能由根配置文件或命令行设置");
+            }
+        }
+        Ok(())
+    }
+
+    /// 转换为等价的命令行参数：`true` 为开关，`false` 省略，数组展开为重复参数
+    pub fn to_args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        for (key, value) in &self.entries {
+            let flag = format!("--{}", key.replace('_', "-"));
+            let values = match value {
+                Value::Array(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            for value in values {
+                match value {
+                    Value::Boolean(true) => args.push(flag.clone().into()),
+                    Value::Boolean(false) => {}
+                    value => args.push(format!("{}={}", flag, value.as_arg()).into()),
+                }
+            }
+        }
+        args
+    }
+}
+
+impl Document {
+    /// 所有 `[profile.NAME]` 预设
+    pub fn profiles(&self) -> impl Iterator<Item = (&str, &Table)> {
+        self.tables
+            .iter()
+            .filter_map(|(name, table)| Some((name.strip_prefix(PROFILE_PREFIX)?, table)))
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Table> {
+        match self.profiles().find(|(n, _)| *n == name) {
+            Some((_, table)) => Ok(table),
+            None => {
+                let names: Vec<_> = self.profiles().map(|(n, _)| n).collect();
+                bail!("未定义的预设 {}（已定义：{}）", name, names.join(", "))
+            }
+        }
+    }
+
+    /// 所有 `[[target]]` 输出目标，按出现顺序
+    pub fn targets(&self) -> impl Iterator<Item = &Table> {
+        self.tables
+            .iter()
+            .filter(|(name, _)| name == TARGET_TABLE)
+            .map(|(_, table)| table)
+    }
+
+    /// 除预设与输出目标以外的表都不支持
+    pub fn check_tables(&self) -> Result<()> {
+        match self
+            .tables
+            .iter()
+            .find(|(name, _)| !name.starts_with(PROFILE_PREFIX) && name != TARGET_TABLE)
+        {
+            Some((name, _)) => bail!("不支持的表 [{}]", name),
+            None => Ok(()),
+        }
+    }
+
+    /// 根配置文件的最终设置：顶层设置是各预设共用的默认值，选中的预设覆盖它们。
+    /// `profile` 为空时使用顶层的 `profile` 键
+    pub fn resolve(&self, profile: Option<&str>) -> Result<Table> {
+        let mut table = self.root.clone();
+        let name = match (profile, table.take_profile()?) {
+            (Some(name), _) => Some(name.to_string()),
+            (None, name) => name,
+        };
+        if let Some(name) = name {
+            table.merge(self.profile(&name)?);
+            table.remove("profile");
+        }
+        Ok(table)
+    }
+
+    /// 子目录配置的最终设置：引用的预设（来自根配置文件 `root`）在前，
+    /// 本文件写出的键覆盖预设
+    pub fn resolve_in(&self, root: &Document) -> Result<Table> {
+        if let Some((name, _)) = self.tables.first() {
+            bail!(
+                "子目录配置中不支持表 [{}]，预设只能在根配置文件中定义",
+                name
+            );
+        }
+        let mut own = self.root.clone();
+        let Some(name) = own.take_profile()? else {
+            return Ok(own);
+        };
+        let mut table = root.profile(&name)?.clone();
+        table.remove("profile");
+        table.merge(&own);
+        Ok(table)
+    }
+}
+
+impl Value {
+    fn as_arg(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            Value::Integer(v) => v.to_string(),
+            Value::Float(v) => v.to_string(),
+            Value::Boolean(v) => v.to_string(),
+            Value::Array(values) => values
+                .iter()
+                .map(Value::as_arg)
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
+
+/// 由命令行定义生成配置文件模板：每个长参数一项，附上说明、可选值与默认值，
+/// 全部注释掉，取消注释即可生效
+pub fn template(command: &clap::Command) -> String {
+    let mut out = String::from(
+        "# psd-auto-export 配置文件。键名与命令行长参数相同，取消注释即可生效。\n\
+         # 优先级从高到低：子目录 .psd-export.toml、命令行参数、PSD_EXPORT_* 环境变量、本文件。\n",
+    );
+    for arg in command.get_arguments() {
+        let Some(key) = arg.get_long() else {
+            continue;
+        };
+        if matches!(key, "config" | "help" | "version") {
+            continue;
+        }
+        out.push('\n');
+        let help = arg.get_long_help().or(arg.get_help());
+        for line in help
+            .map(|help| help.to_string())
+            .unwrap_or_default()
+            .lines()
+        {
+            out.push_str(format!("# {}", line).trim_end());
+            out.push('\n');
+        }
+        let possible: Vec<_> = arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set() && arg.get_action().takes_values())
+            .map(|value| value.get_name().to_string())
+            .collect();
+        if !possible.is_empty() {
+            out.push_str(&format!("# 可选值：{}\n", possible.join(", ")));
+        }
+
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| toml_literal(&value.to_string_lossy()))
+            .collect();
+        let placeholder = || {
+            let name = arg
+                .get_value_names()
+                .and_then(|names| names.first())
+                .map_or_else(|| key.to_ascii_uppercase(), |name| name.to_string());
+            format!("\"<{}>\"", name)
+        };
+        let value = if !arg.get_action().takes_values() {
+            "false".to_string()
+        } else if matches!(arg.get_action(), clap::ArgAction::Append) {
+            format!("[{}]", placeholder())
+        } else {
+            defaults.first().cloned().unwrap_or_else(placeholder)
+        };
+        out.push_str(&format!("# {} = {}\n", key, value));
+    }
+    out
+}
+
+/// 默认值写成 TOML：数字原样输出，其余为带转义的字符串
+fn toml_literal(value: &str) -> String {
+    if value.parse::<f64>().is_ok() {
+        return value.to_string();
+    }
+    format!("{:?}", value)
+}
+
+/// 读取并解析配置文件
+pub fn load(path: &Path) -> Result<Document> {
+    let text = fs::read_to_string(path).context(format!("无法读取配置文件：{:?}", path))?;
+    parse(&text).context(format!("配置文件格式错误：{:?}", path))
+}
+
+/// 监听路径对应的默认配置文件：目录下的 `psd-export.toml`，单个文件则查找其所在目录
+pub fn discover(watch_path: &Path) -> Option<std::path::PathBuf> {
+    let root = if watch_path.is_dir() {
+        watch_path
+    } else {
+        watch_path.parent()?
+    };
+    let path = root.join(FILE_NAME);
+    path.is_file().then_some(path)
+}
+
+pub fn parse(text: &str) -> Result<Document> {
+    let mut document = Document::default();
+    let mut current: Option<usize> = None;
+    let mut lines = text.lines().enumerate();
+
+    while let Some((number, line)) = lines.next() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let context = || format!("第 {} 行：{}", number + 1, line);
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_prefix('[')
+                .and_then(|h| h.strip_suffix("]]"))
+                .or_else(|| header.strip_suffix(']'))
+                .with_context(context)?
+                .trim();
+            let array = header.starts_with('[');
+            if name.is_empty() {
+                bail!("表名为空，{}", context());
+            }
+            if !array && document.tables.iter().any(|(n, _)| n == name) {
+                bail!("重复定义的表 [{}]", name);
+            }
+            document.tables.push((name.to_string(), Table::default()));
+            current = Some(document.tables.len() - 1);
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').with_context(context)?;
+        let key = unquote_key(key.trim()).with_context(context)?;
+        let mut value = value.trim().to_string();
+        // 多行数组：一直读到方括号配平
+        while value.starts_with('[') && !brackets_balanced(&value) {
+            let (_, next) = lines.next().context(format!("数组未闭合，{}", context()))?;
+            value.push(' ');
+            value.push_str(strip_comment(next).trim());
+        }
+        let (value, rest) = parse_value(&value).with_context(context)?;
+        if !rest.trim().is_empty() {
+            bail!("值后面有多余内容，{}", context());
+        }
+
+        let table = match current {
+            Some(index) => &mut document.tables[index].1,
+            None => &mut document.root,
+        };
+        if table.position(&key).is_some() {
+            bail!("重复的键 {}，{}", key, context());
+        }
+        table.entries.push((key, value));
+    }
+    Ok(document)
+}
+
+/// 去掉不在字符串内的 `#` 注释
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn brackets_balanced(value: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    for c in value.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+fn unquote_key(key: &str) -> Option<String> {
+    if let Some(inner) = key.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+        return Some(inner.to_string());
+    }
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| key.to_string())
+}
+
+/// 解析一个值，返回 (值, 剩余文本)
+fn parse_value(s: &str) -> Result<(Value, &str)> {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(out), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('\\') => out.push('\\'),
+                    Some('"') => out.push('"'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let code = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        out.push(code.context("无效的 \\u 转义")?);
+                    }
+                    _ => bail!("无效的转义字符"),
+                },
+                c => out.push(c),
+            }
+        }
+        bail!("字符串未闭合");
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        let end = rest.find('\'').context("字符串未闭合")?;
+        return Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                bail!("数组元素之间缺少逗号");
+            }
+        }
+    }
+
+    let end = s.find([',', ']']).unwrap_or(s.len());
+    let (token, rest) = (s[..end].trim(), &s[end..]);
+    let value = match token {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => {
+            let number = token.replace('_', "");
+            if let Ok(v) = number.parse::<i64>() {
+                Value::Integer(v)
+            } else if let Ok(v) = number.parse::<f64>() {
+                Value::Float(v)
+            } else {
+                bail!("无法识别的值：{}", token);
+            }
+        }
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn parses_values_and_tables() {
+        let document = parse(
+            r#"
+            # 注释
+            format = "png"   # 行尾注释
+            quality = 9_0
+            scale = 0.5
+            flatten = true
+            "pad_to" = 'C:\dir\#1'
+            text = "a\"b\\c\n\u4e2d#"
+            exclude = [
+                "*.tmp", # 数组中的注释
+                'draft',
+            ]
+            nested = [[1, 2], []]
+
+            [profile.web]
+            format = "webp"
+
+            [[target]]
+            suffix = "-a"
+            [[target]]
+            suffix = "-b"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            document.root.entries,
+            vec![
+                ("format".to_string(), string("png")),
+                ("quality".to_string(), Value::Integer(90)),
+                ("scale".to_string(), Value::Float(0.5)),
+                ("flatten".to_string(), Value::Boolean(true)),
+                ("pad_to".to_string(), string(r"C:\dir\#1")),
+                ("text".to_string(), string("a\"b\\c\n中#")),
+                (
+                    "exclude".to_string(),
+                    Value::Array(vec![string("*.tmp"), string("draft")])
+                ),
+                (
+                    "nested".to_string(),
+                    Value::Array(vec![
+                        Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                        Value::Array(vec![]),
+                    ])
+                ),
+            ]
+        );
+        assert_eq!(
+            document.profile("web").unwrap().entries,
+            vec![("format".to_string(), string("webp"))]
+        );
+        let suffixes: Vec<_> = document
+            .targets()
+            .map(|table| table.entries.clone())
+            .collect();
+        assert_eq!(
+            suffixes,
+            vec![
+                vec![("suffix".to_string(), string("-a"))],
+                vec![("suffix".to_string(), string("-b"))],
+            ]
+        );
+        document.check_tables().unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for text in [
+            "a = 1\na = 2",
+            "a-b = 1\na_b = 2",
+            "[x]\n[x]",
+            "[]",
+            "[x",
+            "a = \"open",
+            "a = 'open",
+            "a = [1, 2",
+            "a = [1 2]",
+            "a = 1 2",
+            "a = \"x\" y",
+            "a = yes",
+            "a = \"\\q\"",
+            "a = \"\\uZZZZ\"",
+            "a",
+            "bad key = 1",
+        ] {
+            assert!(parse(text).is_err(), "应当拒绝：{:?}", text);
+        }
+    }
+
+    #[test]
+    fn to_args_expands_flags_and_arrays() {
+        let document = parse(
+            "pad_to = \"600x600\"\nflatten = true\nquiet = false\nexclude = [\"a\", \"b\"]\nsize = [1, 2.5]",
+        )
+        .unwrap();
+        assert_eq!(
+            document.root.to_args(),
+            [
+                "--pad-to=600x600",
+                "--flatten",
+                "--exclude=a",
+                "--exclude=b",
+                "--size=1",
+                "--size=2.5"
+            ]
+            .map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn resolve_merges_profile() {
+        let document = parse(
+            "profile = \"web\"\nformat = \"png\"\npad-to = \"10x10\"\n\
+             [profile.web]\nformat = \"webp\"\npad_to = \"20x20\"\n\
+             [profile.print]\nformat = \"tiff\"",
+        )
+        .unwrap();
+
+        let table = document.resolve(None).unwrap();
+        assert_eq!(
+            table.entries,
+            vec![
+                ("format".to_string(), string("webp")),
+                ("pad-to".to_string(), string("20x20")),
+            ]
+        );
+        let table = document.resolve(Some("print")).unwrap();
+        assert_eq!(table.entries[0], ("format".to_string(), string("tiff")));
+        assert!(document.resolve(Some("missing")).is_err());
+
+        let child = parse("profile = \"print\"\npad-to = \"5x5\"").unwrap();
+        let table = child.resolve_in(&document).unwrap();
+        assert_eq!(
+            table.entries,
+            vec![
+                ("format".to_string(), string("tiff")),
+                ("pad-to".to_string(), string("5x5")),
+            ]
+        );
+        assert!(parse("[profile.x]").unwrap().resolve_in(&document).is_err());
+        assert!(parse("[other]").unwrap().check_tables().is_err());
+    }
+
+    #[test]
+    fn overridable_keys_exclude_commands() {
+        let allowed =
+            parse("format = \"jpg\"\npad_to = \"1:1\"\nsteps = [\"trim\", \"resize:2x\"]").unwrap();
+        allowed.root.check_overridable().unwrap();
+        for text in [
+            "script = \"sh evil.sh\"",
+            "fallback = \"sh -c evil\"",
+            "upload = \"https://example.com/\"",
+            "open_with = \"sh\"",
+            "output = \"/etc/passwd\"",
+            "steps = \"plugin:sh evil.sh\"",
+            "steps = [\"trim\", \" plugin : sh\"]",
+        ] {
+            let document = parse(text).unwrap();
+            assert!(
+                document.root.check_overridable().is_err(),
+                "应当拒绝：{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn template_round_trip() {
+        let command = || {
+            clap::Command::new("pae")
+                .arg(clap::Arg::new("pad-to").long("pad-to").default_value("0"))
+                .arg(
+                    clap::Arg::new("suffix")
+                        .long("suffix")
+                        .default_value("a \"b\" \\ c"),
+                )
+                .arg(
+                    clap::Arg::new("exclude")
+                        .long("exclude")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    clap::Arg::new("flatten")
+                        .long("flatten")
+                        .action(clap::ArgAction::SetTrue),
+                )
+        };
+        // 取消注释所有设置行
+        let text: String = template(&command())
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| {
+                line.split_once(" = ")
+                    .is_some_and(|(key, _)| unquote_key(key).is_some())
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let document = parse(&text).unwrap();
+        let args = document.root.to_args();
+        let matches = command()
+            .try_get_matches_from(std::iter::once(OsString::from("pae")).chain(args))
+            .unwrap();
+        assert_eq!(matches.get_one::<String>("pad-to").unwrap(), "0");
+        assert_eq!(matches.get_one::<String>("suffix").unwrap(), "a \"b\" \\ c");
+        assert_eq!(
+            matches
+                .get_many::<String>("exclude")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["<EXCLUDE>"]
+        );
+        assert!(!matches.get_flag("flatten"));
+    }
+}
@@ -0,0 +1,364 @@
+//! 监听模式的终端界面（`--tui`）：实时显示队列、正在导出的文件、最近的失败与吞吐量，
+//! 并可以按键暂停、重试或退出。界面运行期间日志显示在界面底部而不是直接输出。
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{Log, Metadata, Record};
+
+/// 刷新界面的间隔
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 吞吐量按最近这段时间内完成的文件计算
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(60);
+
+const MAX_FAILURES: usize = 8;
+const MAX_LOG_LINES: usize = 8;
+
+/// 界面中文件路径的最大显示宽度（字符数）
+const PATH_WIDTH: usize = 48;
+
+/// 界面运行期间接收日志的面板，未运行时日志照常输出
+static CAPTURE: Mutex<Option<Arc<Dashboard>>> = Mutex::new(None);
+
+struct Failure {
+    path: PathBuf,
+    message: String,
+    at: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    queued: usize,
+    /// 正在导出的文件及其开始时间
+    running: HashMap<PathBuf, Instant>,
+    /// 每个文件上次导出的耗时，用于估计进度
+    durations: HashMap<PathBuf, Duration>,
+    completed: usize,
+    failed: usize,
+    finished_at: VecDeque<Instant>,
+    failures: VecDeque<Failure>,
+    logs: VecDeque<String>,
+    /// 暂停期间收到的文件，继续后再导出
+    held: Vec<PathBuf>,
+    retries: Vec<PathBuf>,
+}
+
+/// 界面状态，由监听循环与导出任务更新
+pub struct Dashboard {
+    root: PathBuf,
+    format: String,
+    paused: AtomicBool,
+    quit: AtomicBool,
+    state: Mutex<State>,
+}
+
+impl Dashboard {
+    pub fn new(root: &Path, format: String) -> Arc<Self> {
+        Arc::new(Dashboard {
+            root: root.to_path_buf(),
+            format,
+            paused: AtomicBool::new(false),
+            quit: AtomicBool::new(false),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// 文件已加入导出队列
+    pub fn queued(&self, _path: &Path) {
+        self.state.lock().unwrap().queued += 1;
+    }
+
+    pub fn started(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.queued = state.queued.saturating_sub(1);
+        state.running.insert(path.to_path_buf(), Instant::now());
+    }
+
+    /// 导出结束，`error` 为失败原因
+    pub fn finished(&self, path: &Path, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if let Some(start) = state.running.remove(path) {
+            state.durations.insert(path.to_path_buf(), now - start);
+        }
+        match error {
+            None => {
+                state.completed += 1;
+                state.finished_at.push_back(now);
+            }
+            Some(message) => {
+                state.failed += 1;
+                if state.failures.len() == MAX_FAILURES {
+                    state.failures.pop_front();
+                }
+                state.failures.push_back(Failure {
+                    path: path.to_path_buf(),
+                    message,
+                    at: now,
+                });
+            }
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::Relaxed)
+    }
+
+    /// 暂停期间暂存文件，同一文件只保留一次
+    pub fn hold(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if !state.held.iter().any(|held| held == path) {
+            state.held.push(path.to_path_buf());
+        }
+    }
+
+    /// 取出需要导出的文件：要求重试的失败文件，以及未暂停时暂停期间暂存的文件
+    pub fn take_pending(&self) -> Vec<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        let mut pending = std::mem::take(&mut state.retries);
+        if !self.paused() {
+            pending.append(&mut state.held);
+        }
+        pending
+    }
+
+    fn handle_key(&self, key: u8) {
+        match key {
+            b'p' | b'P' | b' ' => {
+                self.paused.fetch_xor(true, Ordering::Relaxed);
+            }
+            b'r' | b'R' => {
+                let mut state = self.state.lock().unwrap();
+                let failures: Vec<PathBuf> = state.failures.drain(..).map(|f| f.path).collect();
+                for path in failures {
+                    if !state.retries.contains(&path) {
+                        state.retries.push(path);
+                    }
+                }
+            }
+            // Ctrl-C 在原始模式下也作为退出键
+            b'q' | b'Q' | 3 => self.quit.store(true, Ordering::Relaxed),
+            _ => {}
+        }
+    }
+
+    fn log_line(&self, line: String) {
+        let mut state = self.state.lock().unwrap();
+        if state.logs.len() == MAX_LOG_LINES {
+            state.logs.pop_front();
+        }
+        state.logs.push_back(line);
+    }
+
+    fn render(&self) -> String {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        while state
+            .finished_at
+            .front()
+            .is_some_and(|at| now - *at > THROUGHPUT_WINDOW)
+        {
+            state.finished_at.pop_front();
+        }
+
+        let mut out = String::from("\x1b[H\x1b[2J");
+        let status = if self.paused() {
+            "\x1b[33m已暂停\x1b[0m"
+        } else {
+            "\x1b[32m运行中\x1b[0m"
+        };
+        out.push_str(&format!(
+            "\x1b[1mpsd-auto-export\x1b[0m  监听：{}  格式：{}  状态：{}\r\n",
+            self.root.display(),
+            self.format,
+            status
+        ));
+        out.push_str(&format!(
+            "队列：{}  进行中：{}  暂存：{}  已完成：{}  失败：{}  吞吐：{} 个/分钟\r\n\r\n",
+            state.queued,
+            state.running.len(),
+            state.held.len(),
+            state.completed,
+            state.failed,
+            state.finished_at.len()
+        ));
+
+        out.push_str("\x1b[1m进行中\x1b[0m\r\n");
+        let mut running: Vec<(&PathBuf, &Instant)> = state.running.iter().collect();
+        running.sort_by_key(|(_, start)| **start);
+        for (path, start) in running {
+            let elapsed = now - *start;
+            // 按该文件上次的耗时估计进度，第一次导出时无法估计
+            let progress = state.durations.get(path).map_or_else(
+                || "--".to_string(),
+                |last| {
+                    let ratio = elapsed.as_secs_f64() / last.as_secs_f64().max(0.001);
+                    format!("{:.0}%", (ratio * 100.0).min(99.0))
+                },
+            );
+            out.push_str(&format!(
+                "  {:<width$}  {:>6.1}s  {:>4}\r\n",
+                self.display(path),
+                elapsed.as_secs_f64(),
+                progress,
+                width = PATH_WIDTH
+            ));
+        }
+
+        out.push_str("\r\n\x1b[1m最近失败\x1b[0m\r\n");
+        for failure in state.failures.iter().rev() {
+            out.push_str(&format!(
+                "  \x1b[31m{}\x1b[0m（{} 秒前）：{}\r\n",
+                self.display(&failure.path),
+                (now - failure.at).as_secs(),
+                first_line(&failure.message)
+            ));
+        }
+
+        out.push_str("\r\n\x1b[1m日志\x1b[0m\r\n");
+        for line in &state.logs {
+            out.push_str(&format!("  {}\r\n", first_line(line)));
+        }
+        out.push_str("\r\n[p] 暂停/继续  [r] 重试失败的文件  [q] 退出\r\n");
+        out
+    }
+
+    /// 相对于监听根目录显示，过长时保留末尾
+    fn display(&self, path: &Path) -> String {
+        let text = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string();
+        let count = text.chars().count();
+        if count <= PATH_WIDTH {
+            return text;
+        }
+        let tail: String = text.chars().skip(count - PATH_WIDTH + 1).collect();
+        format!("…{}", tail)
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+/// 终端界面运行期间持有，离开作用域时恢复终端
+pub struct Terminal {
+    dashboard: Arc<Dashboard>,
+    #[cfg(unix)]
+    original: Option<libc::termios>,
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.dashboard.quit.store(true, Ordering::Relaxed);
+        *CAPTURE.lock().unwrap() = None;
+        #[cfg(unix)]
+        if let Some(original) = &self.original {
+            // SAFETY: 恢复启动时读取的终端设置
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+        // 显示光标并离开备用屏幕
+        print!("\x1b[?25h\x1b[?1049l");
+        _ = std::io::stdout().flush();
+    }
+}
+
+/// 切换到备用屏幕并开始刷新界面与读取按键。Unix 上终端切换为逐键读取；其它平台
+/// 上按键后需要回车
+pub fn start(dashboard: &Arc<Dashboard>) -> Terminal {
+    let terminal = Terminal {
+        dashboard: Arc::clone(dashboard),
+        #[cfg(unix)]
+        original: raw_mode(),
+    };
+    *CAPTURE.lock().unwrap() = Some(Arc::clone(dashboard));
+    print!("\x1b[?1049h\x1b[?25l");
+
+    let renderer = Arc::clone(dashboard);
+    thread::spawn(move || {
+        let mut stdout = std::io::stdout();
+        while !renderer.quit_requested() {
+            _ = stdout.write_all(renderer.render().as_bytes());
+            _ = stdout.flush();
+            thread::sleep(REFRESH_INTERVAL);
+        }
+    });
+    let reader = Arc::clone(dashboard);
+    thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut key = [0];
+        while let Ok(1) = stdin.read(&mut key) {
+            reader.handle_key(key[0]);
+        }
+    });
+    terminal
+}
+
+/// 关闭回显、行缓冲与信号键，返回原设置；标准输入不是终端时返回 `None`
+#[cfg(unix)]
+fn raw_mode() -> Option<libc::termios> {
+    // SAFETY: termios 是普通的 C 结构体，由 tcgetattr 填充
+    unsafe {
+        let mut original: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+            return None;
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+        Some(original)
+    }
+}
+
+/// 包装日志输出：界面运行期间把日志交给界面显示，否则交给原来的日志实现
+struct Logger {
+    inner: Box<dyn Log>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.enabled(record.metadata()) {
+            return;
+        }
+        let dashboard = CAPTURE.lock().unwrap().clone();
+        match dashboard {
+            Some(dashboard) => dashboard.log_line(format!("{} {}", record.level(), record.args())),
+            None => self.inner.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// 安装日志实现，使界面运行期间可以接管日志输出
+pub fn install_logger(inner: Box<dyn Log>, max_level: log::LevelFilter) {
+    if log::set_boxed_logger(Box::new(Logger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
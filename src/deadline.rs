@@ -0,0 +1,112 @@
+//! 单个文件的导出时限（`--timeout`）。Rust 线程无法从外部中止，因此在解码、变换、编码
+//! 与写入之间检查是否超时；期间运行的外部程序（转换器、插件、curl）超时时直接结束。
+//! 时限按线程记录，嵌套设置时取较早的时间。
+
+use std::{
+    cell::Cell,
+    fmt,
+    io::Read,
+    process::{Child, Output},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+/// 等待外部程序时检查是否超时的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+thread_local! {
+    /// 截止时间与设置的时限
+    static DEADLINE: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+}
+
+/// 超时错误，可用 [`timed_out`] 在错误链中识别
+#[derive(Debug)]
+pub struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "导出超过时限 {}", humantime::format_duration(self.0))
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+pub fn timed_out(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<TimedOut>())
+}
+
+/// 离开作用域时恢复之前的截止时间
+struct Guard(Option<(Instant, Duration)>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        DEADLINE.set(self.0);
+    }
+}
+
+/// 在时限内运行 `f`，`timeout` 为空时不限时
+pub fn run<T>(timeout: Option<Duration>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return f();
+    };
+    let previous = DEADLINE.get();
+    let _guard = Guard(previous);
+    let deadline = Instant::now() + timeout;
+    if previous.is_none_or(|(at, _)| deadline < at) {
+        DEADLINE.set(Some((deadline, timeout)));
+    }
+    f()
+}
+
+/// 已超过截止时间时返回 [`TimedOut`]
+pub fn check() -> Result<()> {
+    match DEADLINE.get() {
+        Some((at, timeout)) if Instant::now() >= at => Err(TimedOut(timeout).into()),
+        _ => Ok(()),
+    }
+}
+
+/// 与 [`Child::wait_with_output`] 相同，超过截止时间时结束子进程并返回 [`TimedOut`]
+pub fn wait_with_output(mut child: Child) -> Result<Output> {
+    let Some((at, timeout)) = DEADLINE.get() else {
+        return Ok(child.wait_with_output()?);
+    };
+    // 在其它线程中读取输出，避免子进程写满管道后阻塞。超时时不等待读取线程：
+    // 子进程启动的其它进程可能仍然持有管道
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| thread::spawn(|| read_all(pipe)));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| thread::spawn(|| read_all(pipe)));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= at {
+            _ = child.kill();
+            _ = child.wait();
+            return Err(TimedOut(timeout).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let join = |reader: Option<thread::JoinHandle<std::io::Result<Vec<u8>>>>| match reader {
+        Some(reader) => reader.join().unwrap_or_else(|_| Ok(Vec::new())),
+        None => Ok(Vec::new()),
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+fn read_all(mut pipe: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    pipe.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
@@ -0,0 +1,109 @@
+//! 一次性模式的输出去重（`--dedupe`）：内容完全相同的导出文件（常见于由同一模板
+//! 派生的 PSD）改为指向同一份数据的硬链接。先按大小与 CRC32 分组，再逐字节比较确认。
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+#[derive(Default)]
+pub struct Report {
+    /// 改为硬链接的文件数
+    pub linked: usize,
+    /// 节省的字节数
+    pub saved: u64,
+}
+
+/// 把内容相同的文件链接到每组中的第一个文件。无法链接（例如跨文件系统）时保留原文件
+pub fn dedupe(files: &[PathBuf]) -> Result<Report> {
+    let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+    for file in files {
+        if let Ok(metadata) = file.metadata() {
+            by_size.entry(metadata.len()).or_default().push(file);
+        }
+    }
+
+    let mut report = Report::default();
+    for (size, candidates) in by_size {
+        if candidates.len() < 2 {
+            continue;
+        }
+        // 每组保留第一个文件的内容用于逐字节比较
+        let mut groups: HashMap<u32, Vec<(&PathBuf, Vec<u8>)>> = HashMap::new();
+        for file in candidates {
+            let data = std::fs::read(file).context(format!("无法读取输出文件：{:?}", file))?;
+            let group = groups.entry(crc32fast::hash(&data)).or_default();
+            let Some(original) = group
+                .iter()
+                .find(|(_, first)| *first == data)
+                .map(|(p, _)| *p)
+            else {
+                group.push((file, data));
+                continue;
+            };
+            if same_inode(original, file) {
+                continue;
+            }
+            match link(original, file) {
+                Ok(()) => {
+                    report.linked += 1;
+                    report.saved += size;
+                }
+                Err(e) => log::warn!("无法创建硬链接 {:?} -> {:?}：{}", file, original, e),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// 写入输出之前调用：文件是硬链接时先删除，避免覆盖写入同时改写链接到同一份数据的
+/// 其它输出
+pub fn unlink_shared(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match path.metadata() {
+            Ok(metadata) if metadata.nlink() > 1 => std::fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+    // 其它平台无法方便地查询链接数，直接删除旧文件
+    #[cfg(not(unix))]
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 先在同目录建立临时链接再替换，中途失败不会丢失原文件
+fn link(original: &Path, file: &Path) -> io::Result<()> {
+    let mut temporary = file.as_os_str().to_owned();
+    temporary.push(".pae-link");
+    let temporary = PathBuf::from(temporary);
+    std::fs::hard_link(original, &temporary)?;
+    std::fs::rename(&temporary, file).inspect_err(|_| {
+        _ = std::fs::remove_file(&temporary);
+    })
+}
+
+/// 两个路径是否已经是同一个文件（之前运行时已经链接过）
+fn same_inode(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (a.metadata(), b.metadata()) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        _ = (a, b);
+        false
+    }
+}
@@ -0,0 +1,82 @@
+//! 比较两个版本的渲染结果（`pae diff`）：逐像素比较两张图像，生成标出差异的图像并
+//! 统计变化的像素比例。两边都可以是源文件或已导出的图片。
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::source;
+
+/// 差异图中变化的像素
+const CHANGED: Rgba<u8> = Rgba([255, 0, 64, 255]);
+
+/// 差异图中未变化的像素淡化到的程度，0 为原样，255 为纯白
+const FADE: u16 = 200;
+
+pub struct Diff {
+    /// 标出差异的图像，尺寸为两张图像的并集
+    pub image: RgbaImage,
+    pub changed: u64,
+    pub total: u64,
+}
+
+impl Diff {
+    /// 变化的像素占比（百分比）
+    pub fn percentage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.changed as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// 读取图像：支持的源文件按导出时的方式解码合并图像，其它文件按图片读取
+pub fn load(path: &Path) -> Result<RgbaImage> {
+    if source::is_supported(path) {
+        return Ok(source::decode(path)?.image);
+    }
+    Ok(image::open(path)
+        .context(format!("无法读取图像：{:?}", path))?
+        .into_rgba8())
+}
+
+/// 逐像素比较。任一通道相差超过 `threshold` 即视为变化，两边都完全透明的像素视为相同；
+/// 尺寸不同时只存在于一边的像素都算作变化。未变化的像素在差异图中淡化显示
+pub fn compare(a: &RgbaImage, b: &RgbaImage, threshold: u8) -> Diff {
+    let width = a.width().max(b.width());
+    let height = a.height().max(b.height());
+    let mut changed = 0;
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        let pa = a.get_pixel_checked(x, y);
+        let pb = b.get_pixel_checked(x, y);
+        let same = match (pa, pb) {
+            (Some(pa), Some(pb)) => {
+                (pa[3] == 0 && pb[3] == 0)
+                    || pa
+                        .0
+                        .iter()
+                        .zip(pb.0)
+                        .all(|(&ca, cb)| ca.abs_diff(cb) <= threshold)
+            }
+            _ => false,
+        };
+        if !same {
+            changed += 1;
+            return CHANGED;
+        }
+        let Some(&Rgba([red, green, blue, alpha])) = pb else {
+            return CHANGED;
+        };
+        // 按亮度转为灰度，与白色背景混合后再淡化
+        let luma = (red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000;
+        let over_white = (luma * alpha as u32 + 255 * (255 - alpha as u32)) / 255;
+        let faded = (over_white as u16 * (255 - FADE) + 255 * FADE) / 255;
+        Rgba([faded as u8, faded as u8, faded as u8, 255])
+    });
+    Diff {
+        image,
+        changed,
+        total: width as u64 * height as u64,
+    }
+}
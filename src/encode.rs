@@ -0,0 +1,567 @@
+//! 输出编码。每种导出格式对应一个 [`Encoder`]，在 [`ENCODERS`] 中按格式注册；
+//! 各格式的可调参数放在 [`EncodeOptions`] 里对应的设置结构中。
+
+use std::{
+    io::{Seek, Write},
+    ops::Range,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use image::{
+    ImageFormat, RgbImage, RgbaImage,
+    codecs::{
+        avif::AvifEncoder as AvifWriter,
+        jpeg::{JpegEncoder as JpegWriter, PixelDensity, PixelDensityUnit},
+    },
+};
+use rayon::prelude::*;
+use tiff::encoder::{self as tiff_encoder, Rational, TiffEncoder as TiffWriter, colortype};
+
+#[cfg(feature = "turbojpeg")]
+use crate::turbojpeg;
+use crate::{
+    ExportFormat, parallel_png,
+    pdf::{Page, PdfWriter},
+    pixel,
+    quantize::{self, Dither, QuantizeOptions},
+};
+
+/// 每英寸对应的米数，用于把 DPI 换算成 PNG pHYs 的像素/米
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// 需要转换像素格式时每次处理的行数，转换缓冲只占整幅图像的一小部分
+const STRIP_ROWS: usize = 64;
+
+/// GIF 未指定 `--colors` 时使用的量化设置
+const GIF_DEFAULT_QUANTIZE: QuantizeOptions = QuantizeOptions {
+    colors: 256,
+    dither: Dither::FloydSteinberg,
+    strength: 1.0,
+};
+
+/// 可写且可定位的输出，编码器以 trait 对象的形式接收
+pub trait WriteSeek: Write + Seek {}
+
+impl<T: Write + Seek> WriteSeek for T {}
+
+/// PNG 的压缩级别
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+/// 每个通道的位数
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BitDepth {
+    #[default]
+    #[value(name = "8")]
+    Eight,
+    /// 按比例扩展到 16 位，适合要求 16 位输入的后续流程
+    #[value(name = "16")]
+    Sixteen,
+}
+
+/// TIFF 的压缩方式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum TiffCompression {
+    #[default]
+    None,
+    Lzw,
+    Deflate,
+    Packbits,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub bit_depth: BitDepth,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct JpegOptions {
+    /// 1-100
+    pub quality: u8,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        JpegOptions { quality: 75 }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AvifOptions {
+    /// 1-100
+    pub quality: u8,
+    /// 1（最慢，压缩率最高）- 10（最快）
+    pub speed: u8,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        AvifOptions {
+            quality: 80,
+            speed: 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TiffOptions {
+    pub compression: TiffCompression,
+    pub bit_depth: BitDepth,
+}
+
+/// 所有格式的编码设置，编码器只读取与自己相关的部分
+#[derive(Clone, Debug, Default)]
+pub struct EncodeOptions {
+    /// 调色板量化，PNG 输出为索引色图像，其余格式输出量化后的颜色
+    pub quantize: Option<QuantizeOptions>,
+    pub png: PngOptions,
+    pub jpeg: JpegOptions,
+    pub avif: AvifOptions,
+    pub tiff: TiffOptions,
+    /// 输出不随运行环境变化（见 `--deterministic`）
+    pub deterministic: bool,
+}
+
+/// 一种输出格式的编码器
+pub trait Encoder: Sync {
+    /// 是否自行处理调色板量化；否则收到的是已经量化过颜色的 RGBA 图像
+    fn indexed(&self) -> bool {
+        false
+    }
+
+    /// 将图像编码写入 writer。`dpi` 为 (水平, 垂直) 分辨率，不支持的格式忽略
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()>;
+}
+
+/// 已注册的编码器。ORA 需要完整的图层信息，由 `ora` 模块单独写入
+static ENCODERS: &[(ExportFormat, &dyn Encoder)] = &[
+    (ExportFormat::Png, &PngEncoder),
+    #[cfg(not(feature = "turbojpeg"))]
+    (ExportFormat::Jpg, &JpegEncoder),
+    #[cfg(feature = "turbojpeg")]
+    (ExportFormat::Jpg, &TurboJpegEncoder),
+    (ExportFormat::Bmp, &ImageEncoder(ImageFormat::Bmp)),
+    (ExportFormat::Webp, &ImageEncoder(ImageFormat::WebP)),
+    (ExportFormat::Tiff, &TiffEncoder),
+    (ExportFormat::Avif, &AvifEncoder),
+    (ExportFormat::Ico, &ImageEncoder(ImageFormat::Ico)),
+    (ExportFormat::Gif, &GifEncoder),
+    (ExportFormat::Pdf, &PdfEncoder),
+];
+
+/// 将图像按指定格式与设置编码写入 writer
+pub fn write_image<W: Write + Seek>(
+    img: &RgbaImage,
+    format: &ExportFormat,
+    dpi: Option<(f64, f64)>,
+    options: &EncodeOptions,
+    writer: &mut W,
+) -> Result<()> {
+    let Some((_, encoder)) = ENCODERS.iter().find(|(f, _)| f == format) else {
+        bail!("{:?} 格式不能单独导出", format);
+    };
+    match &options.quantize {
+        Some(quantize) if !encoder.indexed() => {
+            let quantized = quantize::quantize(img, quantize, false);
+            let img = quantized.to_rgba(img.width(), img.height());
+            encoder.encode(&img, dpi, options, writer)
+        }
+        _ => encoder.encode(img, dpi, options, writer),
+    }
+}
+
+struct PngEncoder;
+
+impl Encoder for PngEncoder {
+    fn indexed(&self) -> bool {
+        true
+    }
+
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        let pixels_per_meter = dpi.map(|(x, y)| {
+            (
+                (x / METERS_PER_INCH).round() as u32,
+                (y / METERS_PER_INCH).round() as u32,
+            )
+        });
+
+        // 超大图像按行分块并行滤波与压缩。分块方式与线程数无关，要求输出确定时
+        // 单线程也走这条路径，避免不同机器上得到不同的结果
+        if options.quantize.is_none()
+            && img.width() as u64 * img.height() as u64 >= parallel_png::MIN_PIXELS
+            && (rayon::current_num_threads() > 1 || options.deterministic)
+        {
+            let level = match options.png.compression {
+                PngCompression::Fast => 1,
+                PngCompression::Default => 6,
+                PngCompression::Best => 9,
+            };
+            let row_len = img.width() as usize * 4;
+            let raw = img.as_raw();
+            let rows = |range: Range<usize>| &raw[range.start * row_len..range.end * row_len];
+            match options.png.bit_depth {
+                BitDepth::Eight => parallel_png::encode(
+                    writer,
+                    img.dimensions(),
+                    8,
+                    level,
+                    pixels_per_meter,
+                    |range| rows(range).to_vec(),
+                )?,
+                BitDepth::Sixteen => parallel_png::encode(
+                    writer,
+                    img.dimensions(),
+                    16,
+                    level,
+                    pixels_per_meter,
+                    |range| {
+                        let wide = pixel::widen(rows(range));
+                        wide.into_iter().flat_map(u16::to_be_bytes).collect()
+                    },
+                )?,
+            }
+            return Ok(());
+        }
+
+        let mut encoder = png::Encoder::new(writer, img.width(), img.height());
+        encoder.set_compression(match options.png.compression {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Balanced,
+            PngCompression::Best => png::Compression::High,
+        });
+        if let Some((xppu, yppu)) = pixels_per_meter {
+            encoder.set_pixel_dims(Some(png::PixelDimensions {
+                xppu,
+                yppu,
+                unit: png::Unit::Meter,
+            }));
+        }
+
+        match (&options.quantize, options.png.bit_depth) {
+            (Some(quantize), _) => {
+                let quantized = quantize::quantize(img, quantize, false);
+                let palette: Vec<u8> = quantized
+                    .palette
+                    .iter()
+                    .flat_map(|c| &c[..3])
+                    .copied()
+                    .collect();
+                // tRNS 只需保存到最后一个非不透明的颜色为止
+                let mut trns: Vec<u8> = quantized.palette.iter().map(|c| c[3]).collect();
+                while trns.last() == Some(&255) {
+                    trns.pop();
+                }
+                encoder.set_color(png::ColorType::Indexed);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_palette(palette);
+                if !trns.is_empty() {
+                    encoder.set_trns(trns);
+                }
+                encoder
+                    .write_header()?
+                    .write_image_data(&quantized.indices)?;
+            }
+            (None, BitDepth::Eight) => {
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(img.as_raw())?;
+            }
+            (None, BitDepth::Sixteen) => {
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Sixteen);
+                // 按条带扩展到 16 位，避免再分配整幅图像两倍大小的缓冲
+                let mut png = encoder.write_header()?;
+                let mut stream = png.stream_writer()?;
+                let mut buffer = Vec::new();
+                for rows in img.as_raw().chunks(strip_len(img)) {
+                    buffer.clear();
+                    buffer.extend(pixel::widen(rows).into_iter().flat_map(u16::to_be_bytes));
+                    stream.write_all(&buffer)?;
+                }
+                stream.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct JpegEncoder;
+
+impl Encoder for JpegEncoder {
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        // JPEG 不支持透明通道，先合成到白色背景上
+        let rgb = flatten(img, [255, 255, 255]);
+        let mut encoder = JpegWriter::new_with_quality(writer, options.jpeg.quality);
+        if let Some((x, y)) = dpi {
+            encoder.set_pixel_density(PixelDensity {
+                density: (
+                    x.round().clamp(1.0, u16::MAX as f64) as u16,
+                    y.round().clamp(1.0, u16::MAX as f64) as u16,
+                ),
+                unit: PixelDensityUnit::Inches,
+            });
+        }
+        encoder.encode_image(&rgb)?;
+        Ok(())
+    }
+}
+
+/// 使用 libjpeg-turbo 编码 JPEG，失败时退回 image-rs 的编码器
+#[cfg(feature = "turbojpeg")]
+struct TurboJpegEncoder;
+
+#[cfg(feature = "turbojpeg")]
+impl Encoder for TurboJpegEncoder {
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        let rgb = flatten(img, [255, 255, 255]);
+        match turbojpeg::encode(&rgb, options.jpeg.quality, dpi) {
+            Ok(jpeg) => Ok(writer.write_all(&jpeg)?),
+            Err(e) => {
+                log::warn!("{}，改用默认 JPEG 编码器", e);
+                JpegEncoder.encode(img, dpi, options, writer)
+            }
+        }
+    }
+}
+
+struct AvifEncoder;
+
+impl Encoder for AvifEncoder {
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        _dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        let encoder =
+            AvifWriter::new_with_speed_quality(writer, options.avif.speed, options.avif.quality);
+        img.write_with_encoder(encoder)?;
+        Ok(())
+    }
+}
+
+struct TiffEncoder;
+
+impl Encoder for TiffEncoder {
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        let mut tiff = tiff_writer(writer, &options.tiff)?;
+        write_tiff_page(&mut tiff, img, dpi, &options.tiff)
+    }
+}
+
+/// 逐页写入的多页 TIFF（`--combine`），所有页使用相同的压缩方式与位深
+pub struct MultiPageTiff<W: Write + Seek> {
+    tiff: TiffWriter<W>,
+    options: EncodeOptions,
+}
+
+impl<W: Write + Seek> MultiPageTiff<W> {
+    pub fn new(writer: W, options: &EncodeOptions) -> Result<Self> {
+        Ok(MultiPageTiff {
+            tiff: tiff_writer(writer, &options.tiff)?,
+            options: options.clone(),
+        })
+    }
+
+    /// 追加一页，设置了调色板量化时先量化颜色
+    pub fn add(&mut self, img: &RgbaImage, dpi: Option<(f64, f64)>) -> Result<()> {
+        match &self.options.quantize {
+            Some(quantize) => {
+                let quantized = quantize::quantize(img, quantize, false);
+                let img = quantized.to_rgba(img.width(), img.height());
+                write_tiff_page(&mut self.tiff, &img, dpi, &self.options.tiff)
+            }
+            None => write_tiff_page(&mut self.tiff, img, dpi, &self.options.tiff),
+        }
+    }
+}
+
+fn tiff_writer<W: Write + Seek>(writer: W, options: &TiffOptions) -> Result<TiffWriter<W>> {
+    let compression = match options.compression {
+        TiffCompression::None => tiff_encoder::Compression::Uncompressed,
+        TiffCompression::Lzw => tiff_encoder::Compression::Lzw,
+        TiffCompression::Deflate => {
+            tiff_encoder::Compression::Deflate(tiff_encoder::DeflateLevel::default())
+        }
+        TiffCompression::Packbits => tiff_encoder::Compression::Packbits,
+    };
+    Ok(TiffWriter::new(writer)
+        .context("无法创建 TIFF 文件")?
+        .with_compression(compression))
+}
+
+/// 写入一页（一个 IFD），多次调用时各页依次链接
+fn write_tiff_page<W: Write + Seek>(
+    tiff: &mut TiffWriter<W>,
+    img: &RgbaImage,
+    dpi: Option<(f64, f64)>,
+    options: &TiffOptions,
+) -> Result<()> {
+    let (width, height) = img.dimensions();
+    // 分辨率以 1/100 DPI 为单位保存
+    let resolution = dpi.map(|(x, y)| {
+        let rational = |v: f64| Rational {
+            n: (v * 100.0).round().clamp(1.0, u32::MAX as f64) as u32,
+            d: 100,
+        };
+        (rational(x), rational(y))
+    });
+    macro_rules! new_image {
+        ($color:ty) => {{
+            let mut image = tiff.new_image::<$color>(width, height)?;
+            if let Some((x, y)) = resolution {
+                image.resolution_unit(tiff::tags::ResolutionUnit::Inch);
+                image.x_resolution(x);
+                image.y_resolution(y);
+            }
+            image
+        }};
+    }
+    match options.bit_depth {
+        BitDepth::Eight => new_image!(colortype::RGBA8).write_data(img.as_raw())?,
+        // tiff crate 只在 write_data 中启用压缩，逐条带写入仅适用于不压缩的情况
+        BitDepth::Sixteen if options.compression != TiffCompression::None => {
+            let wide: Vec<u16> = img
+                .as_raw()
+                .par_chunks(strip_len(img))
+                .flat_map_iter(pixel::widen)
+                .collect();
+            new_image!(colortype::RGBA16).write_data(&wide)?
+        }
+        BitDepth::Sixteen => {
+            let mut image = new_image!(colortype::RGBA16);
+            image.rows_per_strip(STRIP_ROWS as u32)?;
+            for rows in img.as_raw().chunks(strip_len(img)) {
+                image.write_strip(&pixel::widen(rows))?;
+            }
+            image.finish()?;
+        }
+    }
+    Ok(())
+}
+
+struct GifEncoder;
+
+impl Encoder for GifEncoder {
+    fn indexed(&self) -> bool {
+        true
+    }
+
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        _dpi: Option<(f64, f64)>,
+        options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        let quantize = options.quantize.as_ref().unwrap_or(&GIF_DEFAULT_QUANTIZE);
+        let (Ok(width), Ok(height)) = (u16::try_from(img.width()), u16::try_from(img.height()))
+        else {
+            bail!("GIF 尺寸不能超过 65535x65535：{:?}", img.dimensions());
+        };
+        let quantized = quantize::quantize(img, quantize, true);
+        let palette: Vec<u8> = quantized
+            .palette
+            .iter()
+            .flat_map(|c| &c[..3])
+            .copied()
+            .collect();
+        let mut encoder = gif::Encoder::new(writer, width, height, &palette)?;
+        let frame = gif::Frame::from_indexed_pixels(
+            width,
+            height,
+            quantized.indices,
+            quantized.transparent,
+        );
+        encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+struct PdfEncoder;
+
+impl Encoder for PdfEncoder {
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        dpi: Option<(f64, f64)>,
+        _options: &EncodeOptions,
+        writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        // 单页 PDF，页面物理尺寸按文档分辨率计算
+        let mut pdf = PdfWriter::new(writer)?;
+        pdf.add_page(&Page::new(img, dpi)?)?;
+        pdf.finish()?;
+        Ok(())
+    }
+}
+
+/// 没有额外设置的格式直接交给 image crate 编码
+struct ImageEncoder(ImageFormat);
+
+impl Encoder for ImageEncoder {
+    fn encode(
+        &self,
+        img: &RgbaImage,
+        _dpi: Option<(f64, f64)>,
+        _options: &EncodeOptions,
+        mut writer: &mut dyn WriteSeek,
+    ) -> Result<()> {
+        img.write_to(&mut writer, self.0)?;
+        Ok(())
+    }
+}
+
+/// 一个条带的字节数（至少为 1，空图像时 `chunks` 也不会出错）
+fn strip_len(img: &RgbaImage) -> usize {
+    (img.width() as usize * 4 * STRIP_ROWS).max(1)
+}
+
+/// 将 RGBA 图像按 alpha 合成到纯色背景上，各条带并行处理
+pub fn flatten(img: &RgbaImage, background: [u8; 3]) -> RgbImage {
+    let mut out = RgbImage::new(img.width(), img.height());
+    let strip_rgb = (img.width() as usize * 3 * STRIP_ROWS).max(1);
+    img.as_raw()
+        .par_chunks(strip_len(img))
+        .zip(out.par_chunks_mut(strip_rgb))
+        .for_each(|(rgba, rgb)| pixel::flatten(rgba, background, rgb));
+    out
+}
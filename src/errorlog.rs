@@ -0,0 +1,48 @@
+//! 只记录导出失败的错误日志（`--error-log`）：每次失败追加时间、源文件与完整的错误链，
+//! 长时间监听后可以直接查看这个文件，而不必在全部日志中查找。
+
+use std::{
+    fmt::Write as _,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::absolute;
+
+static ERROR_LOG: OnceLock<PathBuf> = OnceLock::new();
+
+/// 之后的失败追加到 `path`
+pub fn set(path: &Path) {
+    _ = ERROR_LOG.set(path.to_path_buf());
+}
+
+/// 记录一次失败，未设置错误日志时什么也不做
+pub fn record(source: &Path, error: &anyhow::Error) {
+    let Some(path) = ERROR_LOG.get() else {
+        return;
+    };
+    let mut text = format!(
+        "{}  {}\n",
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        absolute(source).display()
+    );
+    for (i, cause) in error.chain().enumerate() {
+        for (j, line) in cause.to_string().lines().enumerate() {
+            let prefix = if i > 0 && j == 0 { "原因：" } else { "" };
+            _ = writeln!(text, "    {}{}", prefix, line);
+        }
+    }
+    // 多个导出线程可能同时失败，每条记录整体写入
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+    {
+        log::error!("无法写入错误日志 {:?}：{}", path, e);
+    }
+}
@@ -0,0 +1,117 @@
+//! Windows 事件日志（`--event-log`）：作为服务运行（如通过 NSSM、WinSW 或计划任务）时，
+//! 把导出失败以及开始、结束等生命周期事件写入“应用程序”日志，由现有的监控系统
+//! 像其它服务一样收集。
+//!
+//! 没有注册消息文件，事件查看器会提示找不到事件 ID 的描述，但事件内容中仍包含完整的
+//! 消息文本；监控规则可以按来源 [`SOURCE`] 与事件 ID 过滤。
+
+use anyhow::Result;
+
+/// 事件来源，显示在事件查看器的“来源”一列
+pub const SOURCE: &str = "psd-auto-export";
+
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    /// 开始监听、监听结束、一次性导出完成
+    Lifecycle,
+    /// 单个文件导出失败
+    Failure,
+}
+
+impl Event {
+    /// 事件 ID
+    pub fn id(self) -> u32 {
+        match self {
+            Event::Lifecycle => 1,
+            Event::Failure => 2,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, sync::OnceLock};
+
+    pub const EVENTLOG_ERROR_TYPE: u16 = 0x0001;
+    pub const EVENTLOG_INFORMATION_TYPE: u16 = 0x0004;
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        pub fn RegisterEventSourceW(server: *const u16, source: *const u16) -> *mut c_void;
+        pub fn ReportEventW(
+            log: *mut c_void,
+            kind: u16,
+            category: u16,
+            event_id: u32,
+            user_sid: *mut c_void,
+            num_strings: u16,
+            data_size: u32,
+            strings: *const *const u16,
+            data: *mut c_void,
+        ) -> i32;
+    }
+
+    /// 事件日志句柄，进程退出时由系统关闭
+    pub static HANDLE: OnceLock<usize> = OnceLock::new();
+
+    pub fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+}
+
+/// 注册事件来源，之后的 [`report`] 写入事件日志
+pub fn open() -> Result<()> {
+    #[cfg(windows)]
+    {
+        let source = sys::wide(SOURCE);
+        // SAFETY: 参数是以 0 结尾的 UTF-16 字符串，服务器为空表示本机
+        let handle = unsafe { sys::RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(anyhow::Error::new(std::io::Error::last_os_error())
+                .context("无法注册 Windows 事件日志来源"));
+        }
+        _ = sys::HANDLE.set(handle as usize);
+        Ok(())
+    }
+    #[cfg(not(windows))]
+    {
+        anyhow::bail!("--event-log 仅支持 Windows")
+    }
+}
+
+/// 写入一条事件，未调用 [`open`] 或不是 Windows 时什么也不做
+pub fn report(event: Event, message: &str) {
+    #[cfg(windows)]
+    {
+        let Some(&handle) = sys::HANDLE.get() else {
+            return;
+        };
+        let kind = match event {
+            Event::Lifecycle => sys::EVENTLOG_INFORMATION_TYPE,
+            Event::Failure => sys::EVENTLOG_ERROR_TYPE,
+        };
+        let message = sys::wide(message);
+        let strings = [message.as_ptr()];
+        // SAFETY: 句柄来自 RegisterEventSourceW，字符串数组在调用期间有效
+        let reported = unsafe {
+            sys::ReportEventW(
+                handle as *mut std::ffi::c_void,
+                kind,
+                0,
+                event.id(),
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null_mut(),
+            )
+        };
+        if reported == 0 {
+            log::debug!("无法写入事件日志：{}", std::io::Error::last_os_error());
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        _ = (event, message);
+    }
+}
@@ -0,0 +1,468 @@
+//! 嵌入其它程序时使用的接口。[`Exporter`] 按同一份设置导出单个文件、整个目录，或持续
+//! 监听目录；每个文件开始导出、导出成功与失败时调用注册的回调。子目录配置、预设与
+//! 脚本等命令行功能不在其中，需要时由调用方为每个文件准备 [`ExportOptions`]。
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    ExportFormat, ExportOptions, Exported, budget, encode::EncodeOptions, find_psd_files,
+    is_source_file, longpath, process_psd_file, transform::ResizeSpec,
+};
+
+/// 与命令行 `--debounce` 的默认值相同
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// 监听时检查是否应当停止的间隔
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 收到事件后等待源文件写完的时间
+const WRITE_SETTLE: Duration = Duration::from_millis(10);
+
+type StartHook = Box<dyn Fn(&Path) + Send + Sync>;
+type ExportedHook = Box<dyn Fn(&Path, &Exported) + Send + Sync>;
+type ErrorHook = Box<dyn Fn(&Path, &anyhow::Error) + Send + Sync>;
+
+#[derive(Default)]
+struct Hooks {
+    on_start: Vec<StartHook>,
+    on_exported: Vec<ExportedHook>,
+    on_error: Vec<ErrorHook>,
+}
+
+/// 按文件记录上次事件的时间，忽略防抖间隔内重复的事件（一次保存通常产生多个事件）
+pub struct Debouncer {
+    interval: Duration,
+    last: HashMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    pub fn new(interval: Duration) -> Self {
+        Debouncer {
+            interval,
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// 距离该文件上次被接受的事件已超过防抖间隔（或是第一次）时记录本次时间并返回 `true`
+    pub fn ready(&mut self, path: &Path) -> bool {
+        let now = Instant::now();
+        if let Some(last) = self.last.get(path)
+            && now.duration_since(*last) < self.interval
+        {
+            return false;
+        }
+        self.last.insert(path.to_path_buf(), now);
+        true
+    }
+}
+
+/// 合并一小段时间内的事件：收到第一个事件后等待 `window`，再一次取出期间涉及的不同
+/// 文件。临时文件加重命名式的保存会产生一连串事件，合并后每个文件只处理一次
+pub struct Coalescer {
+    window: Duration,
+    started: Option<Instant>,
+    paths: Vec<PathBuf>,
+}
+
+impl Coalescer {
+    pub fn new(window: Duration) -> Self {
+        Coalescer {
+            window,
+            started: None,
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, path: PathBuf) {
+        self.started.get_or_insert_with(Instant::now);
+        if !self.paths.contains(&path) {
+            self.paths.push(path);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// 距离本批事件到期的时间，不超过 `max`
+    pub fn timeout(&self, max: Duration) -> Duration {
+        self.started.map_or(max, |started| {
+            (started + self.window)
+                .saturating_duration_since(Instant::now())
+                .min(max)
+        })
+    }
+
+    /// 本批事件已到期时按到达顺序取出涉及的文件
+    pub fn take(&mut self) -> Vec<PathBuf> {
+        match self.started {
+            Some(started) if started.elapsed() >= self.window => {
+                self.started = None;
+                std::mem::take(&mut self.paths)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// 限制开始导出的频率：每分钟最多导出 `max_per_minute` 个文件，同一文件两次导出至少
+/// 间隔 `min_interval`。超出限制的文件按到达顺序排队等待，而不是像防抖那样丢弃
+pub struct Throttle {
+    max_per_minute: Option<usize>,
+    min_interval: Option<Duration>,
+    /// 最近一分钟内开始导出的时间
+    started: VecDeque<Instant>,
+    last: HashMap<PathBuf, Instant>,
+    queue: VecDeque<PathBuf>,
+    /// 是否已提示达到每分钟的限制，队列清空后重新提示
+    limited: bool,
+}
+
+impl Throttle {
+    pub fn new(max_per_minute: Option<usize>, min_interval: Option<Duration>) -> Self {
+        Throttle {
+            max_per_minute,
+            min_interval,
+            started: VecDeque::new(),
+            last: HashMap::new(),
+            queue: VecDeque::new(),
+            limited: false,
+        }
+    }
+
+    /// 加入队列，已在队列中的文件不重复加入
+    pub fn push(&mut self, path: PathBuf) {
+        if !self.queue.contains(&path) {
+            self.queue.push_back(path);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// 取出现在可以开始导出的文件，并记录为已开始
+    pub fn take_ready(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        while self
+            .started
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= Duration::from_secs(60))
+        {
+            self.started.pop_front();
+        }
+        let mut ready = Vec::new();
+        let mut waiting = VecDeque::new();
+        while let Some(path) = self.queue.pop_front() {
+            if let Some(max) = self.max_per_minute
+                && self.started.len() >= max
+            {
+                if !self.limited {
+                    log::info!(
+                        "已达到每分钟最多导出 {} 个文件的限制，其余文件排队等待",
+                        max
+                    );
+                    self.limited = true;
+                }
+                waiting.push_back(path);
+                waiting.append(&mut self.queue);
+                break;
+            }
+            if let (Some(interval), Some(last)) = (self.min_interval, self.last.get(&path))
+                && now.duration_since(*last) < interval
+            {
+                waiting.push_back(path);
+                continue;
+            }
+            self.started.push_back(now);
+            self.last.insert(path.clone(), now);
+            ready.push(path);
+        }
+        self.queue = waiting;
+        if self.queue.is_empty() {
+            self.limited = false;
+        }
+        ready
+    }
+}
+
+/// [`Exporter`] 的构建器，未设置的选项与命令行的默认值相同
+pub struct ExporterBuilder {
+    options: ExportOptions,
+    debounce: Duration,
+    hooks: Hooks,
+}
+
+impl ExporterBuilder {
+    /// 替换全部导出设置
+    pub fn options(mut self, options: ExportOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn format(mut self, format: ExportFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    pub fn resize(mut self, spec: ResizeSpec) -> Self {
+        self.options.resize = Some(spec);
+        self
+    }
+
+    pub fn encode(mut self, encode: EncodeOptions) -> Self {
+        self.options.encode = encode;
+        self
+    }
+
+    /// 监听时的防抖间隔
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// 开始导出一个文件时调用
+    pub fn on_start(mut self, hook: impl Fn(&Path) + Send + Sync + 'static) -> Self {
+        self.hooks.on_start.push(Box::new(hook));
+        self
+    }
+
+    /// 导出成功时调用，参数为源文件与导出结果
+    pub fn on_exported(mut self, hook: impl Fn(&Path, &Exported) + Send + Sync + 'static) -> Self {
+        self.hooks.on_exported.push(Box::new(hook));
+        self
+    }
+
+    /// 导出失败时调用
+    pub fn on_error(
+        mut self,
+        hook: impl Fn(&Path, &anyhow::Error) + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_error.push(Box::new(hook));
+        self
+    }
+
+    pub fn build(self) -> Exporter {
+        Exporter {
+            inner: Arc::new(Inner {
+                options: self.options,
+                debounce: self.debounce,
+                hooks: self.hooks,
+            }),
+        }
+    }
+}
+
+struct Inner {
+    options: ExportOptions,
+    debounce: Duration,
+    hooks: Hooks,
+}
+
+/// 导出器。克隆开销很小，所有克隆共享同一份设置与回调
+#[derive(Clone)]
+pub struct Exporter {
+    inner: Arc<Inner>,
+}
+
+impl Exporter {
+    pub fn builder() -> ExporterBuilder {
+        ExporterBuilder {
+            options: ExportOptions::default(),
+            debounce: DEFAULT_DEBOUNCE,
+            hooks: Hooks::default(),
+        }
+    }
+
+    pub fn options(&self) -> &ExportOptions {
+        &self.inner.options
+    }
+
+    /// 查找路径下需要导出的源文件（目录则递归查找）
+    pub fn scan(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        find_psd_files(path, &self.inner.options)
+    }
+
+    /// 导出一个源文件，输出与源文件同目录同名。设置了内存预算时在调用线程上等待预算，
+    /// 因此不要在 rayon 线程池中调用，并行导出使用 [`Exporter::export_all`]
+    pub fn export(&self, psd_path: &Path) -> Result<Exported> {
+        let _permit = self.inner.options.admit(psd_path);
+        self.export_admitted(psd_path)
+    }
+
+    /// 已获取内存预算后导出
+    fn export_admitted(&self, psd_path: &Path) -> Result<Exported> {
+        let hooks = &self.inner.hooks;
+        for hook in &hooks.on_start {
+            hook(psd_path);
+        }
+        match process_psd_file(psd_path, &self.inner.options) {
+            Ok(exported) => {
+                for hook in &hooks.on_exported {
+                    hook(psd_path, &exported);
+                }
+                Ok(exported)
+            }
+            Err(e) => {
+                for hook in &hooks.on_error {
+                    hook(psd_path, &e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 并行导出路径下的全部源文件，按源文件路径顺序返回成功的结果；失败的文件
+    /// 只通过 `on_error` 报告
+    pub fn export_all(&self, path: &Path) -> Result<Vec<(PathBuf, Exported)>> {
+        let mut psd_files = self.scan(path)?;
+        psd_files.sort();
+        Ok(budget::map(
+            self.inner.options.memory.as_ref(),
+            psd_files.into_iter(),
+            |psd_path| budget::estimate(psd_path),
+            |psd_path| {
+                let exported = self.export_admitted(&psd_path).ok()?;
+                Some((psd_path, exported))
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect())
+    }
+
+    /// 监听路径，源文件创建或修改时交给 rayon 线程池导出。阻塞直到 `stop` 被设置为
+    /// `true`，或监听器停止
+    pub fn watch(&self, path: &Path, stop: &AtomicBool) -> Result<()> {
+        let mode = if path.is_dir() {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())
+            .context("无法创建文件系统监听器")?;
+        watcher
+            .watch(&longpath::extended(path), mode)
+            .context(format!("无法监听路径：{:?}", path))?;
+
+        let mut debouncer = Debouncer::new(self.inner.debounce);
+        // 等待写完的文件及其到期时间。等待时间固定，先到的文件先到期
+        let mut settling: VecDeque<(Instant, PathBuf)> = VecDeque::new();
+        while !stop.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            while settling.front().is_some_and(|(due, _)| *due <= now) {
+                let (_, path) = settling.pop_front().unwrap();
+                let exporter = self.clone();
+                // 在监听线程上等待内存预算，导出线程不等待
+                let permit = self.inner.options.admit(&path);
+                rayon::spawn(move || {
+                    let _permit = permit;
+                    // 结果已通过回调报告
+                    _ = exporter.export_admitted(&path);
+                });
+            }
+            let timeout = settling.front().map_or(STOP_POLL_INTERVAL, |(due, _)| {
+                due.saturating_duration_since(now).min(STOP_POLL_INTERVAL)
+            });
+            let event = match rx.recv_timeout(timeout) {
+                Ok(Ok(event)) => event,
+                Ok(Err(e)) => {
+                    log::error!("监听事件错误：{}", e);
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if path.is_file()
+                    && is_source_file(&path, &self.inner.options)
+                    && debouncer.ready(&path)
+                {
+                    // 在监听线程上等待源文件写完，不占用导出线程
+                    settling.push_back((Instant::now() + WRITE_SETTLE, path));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn throttle_limits_per_minute() {
+        let mut throttle = Throttle::new(Some(2), None);
+        for name in ["a", "b", "a", "c", "d"] {
+            throttle.push(PathBuf::from(name));
+        }
+        assert_eq!(throttle.take_ready(), paths(&["a", "b"]));
+        // 超出限制的文件按到达顺序留在队列中
+        assert!(!throttle.is_empty());
+        assert!(throttle.take_ready().is_empty());
+        assert_eq!(throttle.queue, paths(&["c", "d"]));
+        throttle.push(PathBuf::from("c"));
+        assert_eq!(throttle.queue, paths(&["c", "d"]));
+    }
+
+    #[test]
+    fn throttle_spaces_out_the_same_file() {
+        let interval = Duration::from_millis(50);
+        let mut throttle = Throttle::new(None, Some(interval));
+        throttle.push(PathBuf::from("a"));
+        assert_eq!(throttle.take_ready(), paths(&["a"]));
+        throttle.push(PathBuf::from("a"));
+        throttle.push(PathBuf::from("b"));
+        // 等待中的文件不阻塞其它文件
+        assert_eq!(throttle.take_ready(), paths(&["b"]));
+        assert!(!throttle.is_empty());
+        std::thread::sleep(interval);
+        assert_eq!(throttle.take_ready(), paths(&["a"]));
+        assert!(throttle.is_empty());
+    }
+
+    #[test]
+    fn coalescer_waits_for_window() {
+        let window = Duration::from_millis(50);
+        let max = Duration::from_secs(1);
+        let mut coalescer = Coalescer::new(window);
+        assert!(coalescer.is_empty());
+        assert_eq!(coalescer.timeout(max), max);
+        for name in ["b", "a", "b", "c", "a"] {
+            coalescer.push(PathBuf::from(name));
+        }
+        assert!(coalescer.timeout(max) <= window);
+        assert!(coalescer.take().is_empty());
+        std::thread::sleep(window);
+        assert_eq!(coalescer.timeout(max), Duration::ZERO);
+        // 每个文件只取出一次，按到达顺序
+        assert_eq!(coalescer.take(), paths(&["b", "a", "c"]));
+        assert!(coalescer.is_empty());
+        assert_eq!(coalescer.timeout(max), max);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// 画廊页面文件名
+pub const GALLERY_NAME: &str = "index.html";
+
+/// 画廊中的一项
+pub struct GalleryEntry {
+    /// 完整尺寸的导出图像
+    pub image: PathBuf,
+    /// 缩略图，没有时直接用原图缩小显示
+    pub thumbnail: Option<PathBuf>,
+}
+
+/// 在 `root` 下生成按目录分组的静态画廊 `index.html`，返回写入的文件路径
+pub fn write_gallery(root: &Path, entries: &[GalleryEntry]) -> Result<PathBuf> {
+    let mut groups: BTreeMap<PathBuf, Vec<&GalleryEntry>> = BTreeMap::new();
+    for entry in entries {
+        let dir = entry.image.parent().unwrap_or(Path::new(""));
+        let dir = dir.strip_prefix(root).unwrap_or(dir).to_path_buf();
+        groups.entry(dir).or_default().push(entry);
+    }
+
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>PSD Export Gallery</title>\n<style>\n",
+        "body{font-family:sans-serif;background:#202020;color:#e6e6e6;margin:1em}\n",
+        "h2{border-bottom:1px solid #444;padding-bottom:.2em}\n",
+        ".grid{display:flex;flex-wrap:wrap;gap:1em}\n",
+        "figure{margin:0;width:200px;text-align:center}\n",
+        "figure img{max-width:200px;max-height:200px;background:",
+        "repeating-conic-gradient(#555 0 25%,#333 0 50%) 0 0/16px 16px}\n",
+        "figcaption{font-size:.8em;word-break:break-all}\n",
+        "a{color:inherit;text-decoration:none}\n",
+        "</style>\n</head>\n<body>\n",
+    ));
+    for (dir, mut items) in groups {
+        items.sort_by(|a, b| a.image.cmp(&b.image));
+        let title = if dir.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            dir.to_string_lossy().into_owned()
+        };
+        _ = writeln!(html, "<h2>{}</h2>\n<div class=\"grid\">", escape(&title));
+        for item in items {
+            let href = relative_url(root, &item.image);
+            let src = item
+                .thumbnail
+                .as_ref()
+                .map(|t| relative_url(root, t))
+                .unwrap_or_else(|| href.clone());
+            let name = item
+                .image
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            _ = writeln!(
+                html,
+                "<figure><a href=\"{}\"><img src=\"{}\" loading=\"lazy\" alt=\"{}\"></a>\
+                 <figcaption>{}</figcaption></figure>",
+                escape(&href),
+                escape(&src),
+                escape(&name),
+                escape(&name),
+            );
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+
+    let output = root.join(GALLERY_NAME);
+    std::fs::write(&output, html).context(format!("无法写入画廊页面：{:?}", output))?;
+    Ok(output)
+}
+
+/// 计算 `path` 相对 `root` 的 URL，各段做百分号编码
+fn relative_url(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(percent_encode(&s.to_string_lossy())),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            _ = write!(out, "%{:02X}", b);
+        }
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
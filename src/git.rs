@@ -0,0 +1,135 @@
+//! 通过 git 找出有改动的源文件（`--changed-since`、`--git-dirty`、`--staged`），一次性
+//! 模式下只导出这些文件，CI 与 pre-commit 钩子中不必每次导出整个目录。
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result, bail};
+
+/// 返回有改动的文件的完整路径（已规范化）。`since` 与工作区比较，包含该版本之后的
+/// 提交与尚未提交的修改；`dirty` 包含未提交的修改与未跟踪的文件；`staged` 包含已暂存
+/// 的修改。已删除的文件不返回
+pub fn changed_files(
+    path: &Path,
+    since: Option<&str>,
+    dirty: bool,
+    staged: bool,
+) -> Result<HashSet<PathBuf>> {
+    let dir = if path.is_dir() {
+        path
+    } else {
+        path.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+    };
+    // diff 与 status 输出的路径都相对于仓库根目录
+    let toplevel = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim_end());
+
+    let mut files = Vec::new();
+    if let Some(since) = since {
+        let output = git(
+            dir,
+            &[
+                "diff",
+                "--name-only",
+                "-z",
+                "--no-renames",
+                "--diff-filter=d",
+                since,
+                "--",
+            ],
+        )?;
+        files.extend(
+            output
+                .split('\0')
+                .filter(|p| !p.is_empty())
+                .map(String::from),
+        );
+    }
+    if staged {
+        let output = git(
+            dir,
+            &[
+                "diff",
+                "--cached",
+                "--name-only",
+                "-z",
+                "--no-renames",
+                "--diff-filter=d",
+            ],
+        )?;
+        files.extend(
+            output
+                .split('\0')
+                .filter(|p| !p.is_empty())
+                .map(String::from),
+        );
+    }
+    if dirty {
+        let output = git(
+            dir,
+            &[
+                "status",
+                "--porcelain",
+                "-z",
+                "--untracked-files=all",
+                "--no-renames",
+            ],
+        )?;
+        // 每项为 `XY 路径`，X、Y 分别是暂存区与工作区的状态
+        for entry in output.split('\0') {
+            if let (Some(status), Some(path)) = (entry.get(..2), entry.get(3..))
+                && !status.contains('D')
+            {
+                files.push(path.to_string());
+            }
+        }
+    }
+    Ok(files
+        .into_iter()
+        .map(|file| {
+            let path = toplevel.join(file);
+            std::fs::canonicalize(&path).unwrap_or(path)
+        })
+        .collect())
+}
+
+/// 把文件加入暂存区（`--git-add`），使导出的图片与源文件一同提交
+pub fn add(dir: &Path, files: &[PathBuf]) -> Result<()> {
+    let mut args = vec![OsString::from("add"), OsString::from("--")];
+    args.extend(files.iter().map(|file| file.as_os_str().to_owned()));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&args)
+        .output()
+        .context("无法运行 git")?;
+    if !output.status.success() {
+        bail!(
+            "git add 失败：{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("无法运行 git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} 失败：{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
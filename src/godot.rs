@@ -0,0 +1,132 @@
+//! Godot 导入设置（`--godot-import`）：在每个导出文件旁写入 `name.png.import`，团队成员
+//! 重新导入时使用相同的压缩与 mipmap 设置。
+//!
+//! 已有的 `.import` 只更新 `[params]` 中由这里管理的键，`uid` 与 Godot 写入的其它内容
+//! 保持不变；新文件只包含导入器与参数，其余部分由 Godot 导入时补全。Godot 4 的纹理
+//! 过滤方式在项目设置与节点上指定，不属于导入设置。
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+/// `compress/mode` 的取值
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    Lossless,
+    Lossy,
+    VramCompressed,
+    VramUncompressed,
+    Basis,
+}
+
+impl Compression {
+    fn id(self) -> u32 {
+        match self {
+            Compression::Lossless => 0,
+            Compression::Lossy => 1,
+            Compression::VramCompressed => 2,
+            Compression::VramUncompressed => 3,
+            Compression::Basis => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportSettings {
+    pub compression: Compression,
+    pub mipmaps: bool,
+}
+
+impl ImportSettings {
+    /// 由这里管理的 `[params]` 键与值
+    fn params(&self) -> [(&'static str, String); 2] {
+        [
+            ("compress/mode", self.compression.id().to_string()),
+            ("mipmaps/generate", self.mipmaps.to_string()),
+        ]
+    }
+}
+
+/// `.import` 文件的路径：输出文件名后加 `.import`
+pub fn import_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".import");
+    output_path.with_file_name(name)
+}
+
+/// 写入或更新导出文件的 `.import`，返回其路径
+pub fn write_import(output_path: &Path, settings: &ImportSettings) -> Result<PathBuf> {
+    let path = import_path(output_path);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(existing) => update(&existing, settings),
+        Err(_) => {
+            let mut text = String::from(
+                "[remap]\n\nimporter=\"texture\"\ntype=\"CompressedTexture2D\"\n\n[params]\n\n",
+            );
+            for (key, value) in settings.params() {
+                text.push_str(&format!("{}={}\n", key, value));
+            }
+            text
+        }
+    };
+    std::fs::write(&path, text).context(format!("无法写入 Godot 导入设置：{:?}", path))?;
+    Ok(path)
+}
+
+/// 替换 `[params]` 中已有的键，缺少的键追加到该段末尾；没有 `[params]` 段时追加在文件末尾
+fn update(existing: &str, settings: &ImportSettings) -> String {
+    let params = settings.params();
+    let mut written = [false; 2];
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_params = false;
+    let mut seen_params = false;
+    // 把缺少的键插入到 [params] 段最后一个非空行之后
+    let flush = |lines: &mut Vec<String>, written: &mut [bool; 2]| {
+        let at = lines
+            .iter()
+            .rposition(|line| !line.trim().is_empty())
+            .map_or(lines.len(), |i| i + 1);
+        let missing: Vec<String> = params
+            .iter()
+            .zip(written.iter_mut())
+            .filter(|(_, written)| !**written)
+            .map(|((key, value), written)| {
+                *written = true;
+                format!("{}={}", key, value)
+            })
+            .collect();
+        lines.splice(at..at, missing);
+    };
+    for line in existing.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if in_params {
+                flush(&mut lines, &mut written);
+            }
+            in_params = trimmed == "[params]";
+            seen_params |= in_params;
+        } else if in_params
+            && let Some((key, _)) = trimmed.split_once('=')
+            && let Some(i) = params.iter().position(|(k, _)| *k == key.trim())
+        {
+            lines.push(format!("{}={}", params[i].0, params[i].1));
+            written[i] = true;
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if in_params {
+        flush(&mut lines, &mut written);
+    }
+    if !seen_params {
+        lines.push(String::new());
+        lines.push("[params]".to_string());
+        lines.push(String::new());
+        flush(&mut lines, &mut written);
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
@@ -0,0 +1,24 @@
+//! 监听模式下的 SIGHUP：收到信号后重新加载配置（仅 Unix，其它平台上从不触发）。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// 注册 SIGHUP 处理函数。处理函数只设置标志，由监听循环轮询
+pub fn install() {
+    #[cfg(unix)]
+    {
+        extern "C" fn handle(_: libc::c_int) {
+            RECEIVED.store(true, Ordering::Relaxed);
+        }
+        // SAFETY: 处理函数只写入一个原子变量，是异步信号安全的
+        unsafe {
+            libc::signal(libc::SIGHUP, handle as *const () as libc::sighandler_t);
+        }
+    }
+}
+
+/// 自上次调用以来是否收到过 SIGHUP
+pub fn take() -> bool {
+    RECEIVED.swap(false, Ordering::Relaxed)
+}
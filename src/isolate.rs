@@ -0,0 +1,134 @@
+//! 在子进程中导出（`--isolate`）：畸形文件使解码器崩溃、内存耗尽或 panic 时只结束子
+//! 进程，长时间运行的监听进程记录失败后继续。子进程以相同的参数加上 `--isolated <文件>`
+//! 重新运行本程序，日志以 `级别\t内容` 写入标准错误，由父进程转发到自己的日志；结果
+//! 以 `键\t值` 逐行写入标准输出。
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::{
+    Exported,
+    deadline::{self, TimedOut},
+    timing::Timings,
+};
+
+/// 子进程中设置的环境变量，用于选择日志格式
+pub const ENV: &str = "PAE_ISOLATED";
+
+/// 在子进程中导出一个文件，返回值与 [`crate::process_psd_file`] 相同，脚本要求跳过时
+/// 为 `None`。`output` 替换默认的输出路径，设置 `timeout` 时超时的子进程被结束
+pub fn export(
+    psd_path: &Path,
+    output: Option<&Path>,
+    timeout: Option<Duration>,
+) -> Result<Option<Exported>> {
+    let exe = std::env::current_exe().context("无法确定程序路径")?;
+    let mut command = Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg("--isolated")
+        .arg(psd_path);
+    if let Some(output) = output {
+        command.arg("--isolated-output").arg(output);
+    }
+    let child = command
+        .env(ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("无法启动导出子进程")?;
+    let output = deadline::run(timeout, || deadline::wait_with_output(child))?;
+
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        match line
+            .split_once('\t')
+            .and_then(|(level, message)| Some((level.parse::<log::Level>().ok()?, message)))
+        {
+            Some((level, message)) => log::log!(level, "{}", message),
+            None => log::warn!("{}", line),
+        }
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut output_path = None;
+    let mut exported = Exported {
+        output_path: PathBuf::new(),
+        extra_outputs: Vec::new(),
+        thumbnail_path: None,
+        preview: None,
+        fallback: false,
+        timings: Timings::default(),
+    };
+    for line in stdout.lines() {
+        let (key, value) = line.split_once('\t').unwrap_or((line, ""));
+        match key {
+            "skip" => return Ok(None),
+            "timeout" => return Err(TimedOut(timeout.unwrap_or_default()).into()),
+            "error" => return Err(anyhow!("{}", value)),
+            "output" => output_path = Some(PathBuf::from(value)),
+            "extra" => exported.extra_outputs.push(PathBuf::from(value)),
+            "thumbnail" => exported.thumbnail_path = Some(PathBuf::from(value)),
+            "fallback" => exported.fallback = true,
+            "timings" => {
+                let mut stages = value
+                    .split(' ')
+                    .map(|nanos| Duration::from_nanos(nanos.parse().unwrap_or(0)));
+                let mut next = || stages.next().unwrap_or_default();
+                exported.timings = Timings {
+                    decode: next(),
+                    composite: next(),
+                    transform: next(),
+                    encode: next(),
+                    write: next(),
+                };
+            }
+            _ => {}
+        }
+    }
+    exported.output_path =
+        output_path.context(format!("导出子进程异常退出（{}）", output.status))?;
+    Ok(Some(exported))
+}
+
+/// 子进程中调用：把导出结果写入标准输出，返回退出码
+pub fn report(result: &Result<Option<Exported>>) -> i32 {
+    let mut lines = Vec::new();
+    match result {
+        Ok(None) => lines.push("skip".to_string()),
+        Ok(Some(exported)) => {
+            lines.push(format!("output\t{}", exported.output_path.display()));
+            for path in &exported.extra_outputs {
+                lines.push(format!("extra\t{}", path.display()));
+            }
+            if let Some(path) = &exported.thumbnail_path {
+                lines.push(format!("thumbnail\t{}", path.display()));
+            }
+            if exported.fallback {
+                lines.push("fallback".to_string());
+            }
+            let t = &exported.timings;
+            lines.push(format!(
+                "timings\t{} {} {} {} {}",
+                t.decode.as_nanos(),
+                t.composite.as_nanos(),
+                t.transform.as_nanos(),
+                t.encode.as_nanos(),
+                t.write.as_nanos()
+            ));
+        }
+        Err(e) if deadline::timed_out(e) => lines.push("timeout".to_string()),
+        Err(e) => lines.push(format!("error\t{}", format!("{:#}", e).replace('\n', " "))),
+    }
+    let mut stdout = std::io::stdout().lock();
+    for line in lines {
+        _ = writeln!(stdout, "{}", line);
+    }
+    i32::from(result.is_err())
+}
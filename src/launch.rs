@@ -0,0 +1,171 @@
+//! 导出完成后用查看器打开输出文件（`--open`），便于立即确认合并后的效果；或在文件
+//! 管理器中选中输出文件（`--reveal`），输出目录与源文件不同时不必再去找。
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{Context, Result};
+
+/// 打开文件。`program` 为空时使用系统默认程序；否则按空白分隔为程序与参数，参数中的
+/// `{output}` 替换为文件路径，没有该占位符时把路径追加在最后
+pub fn open(path: &Path, program: Option<&str>) -> Result<()> {
+    let path = &full_path(path);
+    let Some(program) = program else {
+        return open_default(path);
+    };
+    let mut parts = program.split_whitespace();
+    let mut command = Command::new(parts.next().context("打开文件的程序为空")?);
+    let args: Vec<String> = parts.map(String::from).collect();
+    if args.iter().any(|arg| arg.contains("{output}")) {
+        command.args(
+            args.iter()
+                .map(|arg| arg.replace("{output}", &path.to_string_lossy())),
+        );
+    } else {
+        command.args(args).arg(path);
+    }
+    spawn(command)
+}
+
+/// 在文件管理器中显示并选中文件
+pub fn reveal(path: &Path) -> Result<()> {
+    let path = &full_path(path);
+    #[cfg(windows)]
+    {
+        // explorer 要求 `/select,` 与路径在同一个参数中
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(path);
+        let mut command = Command::new("explorer");
+        command.arg(select);
+        spawn(command)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        spawn(command)
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        // 支持 FileManager1 接口的文件管理器（Nautilus、Dolphin、Nemo 等）可以选中
+        // 文件，否则只打开所在目录
+        let shown = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--dest=org.freedesktop.FileManager1",
+                "--type=method_call",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+            ])
+            .arg(format!("array:string:{}", file_uri(path)))
+            .arg("string:")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if shown {
+            return Ok(());
+        }
+        spawn(default_opener(path.parent().unwrap_or(Path::new("."))))
+    }
+}
+
+/// `file://` URI，路径中除非保留字符与 `/` 以外的字节按百分号编码
+#[cfg(not(any(windows, target_os = "macos")))]
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// 完整路径。不使用 `canonicalize`：Windows 上它返回的 `\\?\` 路径资源管理器无法识别
+fn full_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// 用系统默认程序打开文件。不经过 `cmd /C start`：cmd 会解释路径中的 `&`、`|` 等字符，
+/// 而不含空格的路径不会被加上引号，`a&calc.png` 这样的文件名就能执行命令
+#[cfg(windows)]
+fn open_default(path: &Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let wide = |s: &std::ffi::OsStr| -> Vec<u16> { s.encode_wide().chain(Some(0)).collect() };
+    let operation = wide("open".as_ref());
+    let file = wide(path.as_os_str());
+    // SAFETY: 参数是以 0 结尾的 UTF-16 字符串，其余指针为空表示使用默认值
+    let result = unsafe {
+        sys::ShellExecuteW(
+            std::ptr::null_mut(),
+            operation.as_ptr(),
+            file.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null(),
+            sys::SW_SHOWNORMAL,
+        )
+    };
+    // 返回值大于 32 表示成功，否则是错误码
+    if result as usize <= 32 {
+        anyhow::bail!("无法打开文件 {:?}：错误码 {}", path, result as usize);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+
+    pub const SW_SHOWNORMAL: i32 = 1;
+
+    #[link(name = "shell32")]
+    unsafe extern "system" {
+        pub fn ShellExecuteW(
+            window: *mut c_void,
+            operation: *const u16,
+            file: *const u16,
+            parameters: *const u16,
+            directory: *const u16,
+            show: i32,
+        ) -> *mut c_void;
+    }
+}
+
+#[cfg(not(windows))]
+fn open_default(path: &Path) -> Result<()> {
+    spawn(default_opener(path))
+}
+
+#[cfg(target_os = "macos")]
+fn default_opener(path: &Path) -> Command {
+    let mut command = Command::new("open");
+    command.arg(path);
+    command
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn default_opener(path: &Path) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(path);
+    command
+}
+
+/// 启动后不等待程序退出，在后台线程中回收子进程
+fn spawn(mut command: Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context(format!("无法运行 {}", program))?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
@@ -0,0 +1,293 @@
+//! 图层蒙版（`--layer-masks`）。psd crate 读取图层记录时跳过了蒙版数据，也不公开用户
+//! 蒙版通道，这里自己解析图层与蒙版信息段，取出每个图层的用户蒙版（通道 ID -2）。
+//!
+//! 只支持 PSD（非 PSB）以及未压缩或 RLE 压缩的蒙版通道；矢量蒙版与 ZIP 压缩的通道
+//! 不会被读取。
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::{GrayImage, Luma, RgbaImage};
+
+/// 用户蒙版的通道 ID
+const USER_MASK_CHANNEL: i16 = -2;
+
+/// 文件头固定长度
+const HEADER_LEN: usize = 26;
+
+/// 如何处理图层蒙版
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskMode {
+    /// 把蒙版另存为整幅画布大小的灰度图
+    Export,
+    /// 把蒙版乘到图层的透明度上
+    Apply,
+    /// 同时另存与应用
+    Both,
+}
+
+impl MaskMode {
+    pub fn exports(self) -> bool {
+        matches!(self, MaskMode::Export | MaskMode::Both)
+    }
+
+    pub fn applies(self) -> bool {
+        matches!(self, MaskMode::Apply | MaskMode::Both)
+    }
+}
+
+/// 一个图层的用户蒙版，像素只覆盖蒙版范围，范围外为默认颜色
+#[derive(Clone, Debug)]
+pub struct LayerMask {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+    /// 范围外的值，0 或 255
+    pub default_color: u8,
+    /// 蒙版在 Photoshop 中被停用
+    pub disabled: bool,
+    pub pixels: Vec<u8>,
+}
+
+impl LayerMask {
+    /// 画布坐标处的蒙版值
+    pub fn get(&self, x: u32, y: u32) -> u8 {
+        let (dx, dy) = (x as i64 - self.left as i64, y as i64 - self.top as i64);
+        if dx < 0 || dy < 0 || dx >= self.width as i64 || dy >= self.height as i64 {
+            return self.default_color;
+        }
+        self.pixels[dy as usize * self.width as usize + dx as usize]
+    }
+
+    /// 整幅画布大小的灰度图
+    pub fn to_canvas(&self, width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| Luma([self.get(x, y)]))
+    }
+
+    /// 把蒙版乘到位于画布 (x, y) 处的图层像素的透明度上
+    pub fn apply(&self, pixels: &mut RgbaImage, x: u32, y: u32) {
+        for (px, py, pixel) in pixels.enumerate_pixels_mut() {
+            let value = self.get(x + px, y + py) as u32;
+            pixel[3] = (pixel[3] as u32 * value / 255) as u8;
+        }
+    }
+}
+
+/// 图层记录中与蒙版相关的部分
+struct Record {
+    mask_rect: Option<(i32, i32, i32, i32)>,
+    default_color: u8,
+    disabled: bool,
+    /// 各通道的 (ID, 数据长度)
+    channels: Vec<(i16, u32)>,
+    /// 分组的开始或结束标记，psd crate 不把它们计入图层
+    divider: bool,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let data = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .context("图层与蒙版信息段数据不完整")?;
+        self.pos += len;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+}
+
+/// 读取各图层的用户蒙版，顺序与 psd crate 的 `Psd::layers()` 一致（自顶向下，不含分组
+/// 标记）；没有蒙版或蒙版无法读取的图层为 `None`
+pub fn layer_masks(bytes: &[u8]) -> Result<Vec<Option<LayerMask>>> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let header = reader.take(HEADER_LEN)?;
+    if header[4..6] != [0, 1] {
+        anyhow::bail!("只支持读取 PSD 文件的图层蒙版，不支持 PSB");
+    }
+    let color_mode_len = reader.u32()? as usize;
+    reader.take(color_mode_len)?;
+    let resources_len = reader.u32()? as usize;
+    reader.take(resources_len)?;
+
+    if reader.u32()? == 0 {
+        return Ok(Vec::new());
+    }
+    if reader.u32()? == 0 {
+        return Ok(Vec::new());
+    }
+    // 负数表示第一个 Alpha 通道是合并结果的透明度，图层数取绝对值
+    let count = reader.i16()?.unsigned_abs();
+
+    let mut records = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        records.push(read_record(&mut reader)?);
+    }
+
+    let mut masks = Vec::with_capacity(records.len());
+    for record in &records {
+        let mut mask = None;
+        for &(id, len) in &record.channels {
+            let compression = reader.u16()?;
+            let data = reader.take(len.saturating_sub(2) as usize)?;
+            if id != USER_MASK_CHANNEL {
+                continue;
+            }
+            let Some((top, left, bottom, right)) = record.mask_rect else {
+                continue;
+            };
+            let (Some(width), Some(height)) = (right.checked_sub(left), bottom.checked_sub(top))
+            else {
+                log::warn!("图层蒙版范围无效，已忽略");
+                continue;
+            };
+            let width = width.max(0) as u32;
+            let height = height.max(0) as u32;
+            let pixels = match compression {
+                0 => data.to_vec(),
+                1 => unpack_rle(data, width as usize, height as usize).unwrap_or_default(),
+                _ => {
+                    log::warn!("不支持 ZIP 压缩的图层蒙版，已忽略");
+                    continue;
+                }
+            };
+            if pixels.len() != width as usize * height as usize {
+                log::warn!("图层蒙版数据不完整，已忽略");
+                continue;
+            }
+            mask = Some(LayerMask {
+                left,
+                top,
+                width,
+                height,
+                default_color: record.default_color,
+                disabled: record.disabled,
+                pixels,
+            });
+        }
+        if !record.divider {
+            masks.push(mask);
+        }
+    }
+    // Photoshop 按自底向上的顺序存储图层
+    masks.reverse();
+    Ok(masks)
+}
+
+fn read_record(reader: &mut Reader) -> Result<Record> {
+    // 图层范围
+    reader.take(16)?;
+    let channel_count = reader.u16()?;
+    let mut channels = Vec::with_capacity(channel_count as usize);
+    for _ in 0..channel_count {
+        let id = reader.i16()?;
+        let len = reader.u32()?;
+        channels.push((id, len));
+    }
+    // 混合模式签名与键、不透明度、剪贴、标志、填充
+    reader.take(12)?;
+    let extra_len = reader.u32()? as usize;
+    let extra_end = reader.pos + extra_len;
+
+    let mut record = Record {
+        mask_rect: None,
+        default_color: 0,
+        disabled: false,
+        channels,
+        divider: false,
+    };
+    let mask_len = reader.u32()? as usize;
+    let mask_end = reader.pos + mask_len;
+    if mask_len >= 18 {
+        let top = reader.i32()?;
+        let left = reader.i32()?;
+        let bottom = reader.i32()?;
+        let right = reader.i32()?;
+        record.mask_rect = Some((top, left, bottom, right));
+        record.default_color = reader.u8()?;
+        record.disabled = reader.u8()? & 0b10 != 0;
+    }
+    reader.pos = mask_end;
+    let blending_len = reader.u32()? as usize;
+    reader.take(blending_len)?;
+    let name_len = reader.u8()? as usize;
+    // 名称补齐到 4 字节的倍数（含长度字节）
+    reader.take((name_len + 1).div_ceil(4) * 4 - 1)?;
+
+    while reader.pos + 12 <= extra_end {
+        let signature = reader.take(4)?;
+        if signature != b"8BIM" && signature != b"8B64" {
+            break;
+        }
+        let key = reader.take(4)?;
+        let len = reader.u32()? as usize;
+        let data = reader.take(len)?;
+        // 与 psd crate 一致，只识别 lsct
+        if key == b"lsct" && data.len() >= 4 {
+            let kind = u32::from_be_bytes(data[..4].try_into()?);
+            record.divider |= (1..=3).contains(&kind);
+        }
+    }
+    reader.pos = extra_end;
+    Ok(record)
+}
+
+/// 每个 PackBits 重复段最多用 2 字节表示 128 个像素
+const MAX_RLE_RATIO: usize = 64;
+
+/// 解压 PackBits 编码的通道数据，开头是每行压缩后长度的列表。范围来自文件内容，
+/// 数据不可能解压出这么多像素时直接返回 `None`，不按范围分配内存
+fn unpack_rle(data: &[u8], width: usize, height: usize) -> Option<Vec<u8>> {
+    let mut pos = height.checked_mul(2)?;
+    let area = width.checked_mul(height)?;
+    if area > data.len().checked_sub(pos)?.saturating_mul(MAX_RLE_RATIO) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(area);
+    while out.len() < area {
+        let n = *data.get(pos)? as i8;
+        pos += 1;
+        match n {
+            0.. => {
+                let len = n as usize + 1;
+                out.extend_from_slice(data.get(pos..pos + len)?);
+                pos += len;
+            }
+            -127..=-1 => {
+                out.extend(std::iter::repeat_n(
+                    *data.get(pos)?,
+                    (1 - n as isize) as usize,
+                ));
+                pos += 1;
+            }
+            // -128 不表示任何数据
+            _ => {}
+        }
+    }
+    out.truncate(area);
+    Some(out)
+}
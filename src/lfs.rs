@@ -0,0 +1,84 @@
+//! 识别尚未下载的 Git LFS 指针文件。克隆时跳过了 LFS 下载（或 smudge 过滤失败）时，
+//! 工作区里的 “PSD” 只是一个一百多字节的文本文件，直接解析只会得到难以理解的错误。
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::git::git;
+
+/// 指针文件的第一行
+const POINTER_PREFIX: &[u8] = b"version https://git-lfs.github.com/spec/v1\n";
+
+/// 规范规定指针文件小于 1024 字节
+const MAX_POINTER_SIZE: u64 = 1024;
+
+pub fn is_pointer(path: &Path) -> bool {
+    if path
+        .metadata()
+        .map_or(true, |m| m.len() >= MAX_POINTER_SIZE)
+    {
+        return false;
+    }
+    let mut head = [0; POINTER_PREFIX.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut head))
+        .is_ok_and(|()| head == POINTER_PREFIX)
+}
+
+/// 去掉指针文件并逐个给出提示。`pull` 为真时先用 `git lfs pull` 下载，下载后仍是
+/// 指针的文件同样跳过
+pub fn prepare(files: Vec<PathBuf>, pull: bool) -> Vec<PathBuf> {
+    let pointers: Vec<&PathBuf> = files.iter().filter(|path| is_pointer(path)).collect();
+    if pointers.is_empty() {
+        return files;
+    }
+    if pull && let Err(e) = pull_files(&pointers) {
+        log::warn!("git lfs pull 失败：{}", e);
+    }
+    let (pointers, files): (Vec<PathBuf>, Vec<PathBuf>) =
+        files.into_iter().partition(|path| is_pointer(path));
+    for path in &pointers {
+        if pull {
+            log::warn!("跳过 Git LFS 指针文件（下载后仍不是实际文件）：{:?}", path);
+        } else {
+            log::warn!(
+                "跳过 Git LFS 指针文件：{:?}，请运行 git lfs pull 或使用 --lfs-pull",
+                path
+            );
+        }
+    }
+    files
+}
+
+/// 按所在仓库分组，每个仓库只运行一次 `git lfs pull --include`
+fn pull_files(files: &[&PathBuf]) -> Result<()> {
+    let mut repositories: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for file in files {
+        let path = std::fs::canonicalize(file).context(format!("无法访问文件：{:?}", file))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let toplevel = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim_end());
+        let toplevel = std::fs::canonicalize(&toplevel).unwrap_or(toplevel);
+        let relative = path.strip_prefix(&toplevel).unwrap_or(&path);
+        // --include 的模式使用 `/` 分隔
+        let pattern = relative.to_string_lossy().replace('\\', "/");
+        repositories.entry(toplevel).or_default().push(pattern);
+    }
+    for (toplevel, patterns) in repositories {
+        log::info!(
+            "正在下载 {} 个 Git LFS 文件：{:?}",
+            patterns.len(),
+            toplevel
+        );
+        git(
+            &toplevel,
+            &["lfs", "pull", "--include", &patterns.join(",")],
+        )?;
+    }
+    Ok(())
+}
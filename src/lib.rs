@@ -0,0 +1,1061 @@
+//! 把 PSD 等源文件导出为图片。`pae` 命令行程序只负责解析参数与配置文件，扫描、
+//! 防抖、解码与编码都在这个库中，其它程序可以通过 [`Exporter`] 嵌入：
+//!
+//! ```no_run
+//! use psd_auto_export::{ExportFormat, Exporter};
+//!
+//! let exporter = Exporter::builder()
+//!     .format(ExportFormat::Webp)
+//!     .on_exported(|source, exported| println!("{:?} -> {:?}", source, exported.output_path))
+//!     .on_error(|source, e| eprintln!("{:?}: {:#}", source, e))
+//!     .build();
+//! exporter.export_all("art".as_ref())?;
+//! # anyhow::Ok(())
+//! ```
+
+use std::{
+    any::Any,
+    io::Write,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+use psd::Psd;
+
+pub use crate::exporter::{Coalescer, Debouncer, Exporter, ExporterBuilder, Throttle};
+use crate::{
+    budget::MemoryBudget,
+    encode::EncodeOptions,
+    godot::ImportSettings,
+    layermask::{LayerMask, MaskMode},
+    mipmap::MipmapMode,
+    pipeline::Step,
+    sheet::SheetOptions,
+    stamp::Stamp,
+    timing::{TimedWriter, Timings},
+    transform::{Flip, PadSpec, ResizeFilter, ResizeSpec, Rotation},
+    unity::TextureType,
+    upload::Upload,
+};
+
+pub mod archive;
+pub mod atlas;
+pub mod budget;
+pub mod busy;
+pub mod checkpoint;
+pub mod clock;
+pub mod collision;
+mod composite;
+pub mod config;
+pub mod dashboard;
+pub mod deadline;
+pub mod dedupe;
+pub mod diff;
+pub mod encode;
+pub mod errorlog;
+pub mod eventlog;
+mod exporter;
+mod font;
+pub mod gallery;
+pub mod git;
+pub mod godot;
+pub mod hangup;
+pub mod isolate;
+pub mod launch;
+pub mod layermask;
+pub mod lfs;
+pub mod lock;
+pub mod longpath;
+pub mod mipmap;
+mod mmap;
+pub mod naming;
+mod ora;
+pub mod order;
+mod parallel_png;
+pub mod pdf;
+mod photoshop;
+pub mod pipeline;
+mod pixel;
+mod plugin;
+pub mod priority;
+pub mod quantize;
+pub mod quarantine;
+pub mod report;
+mod resources;
+pub mod roots;
+mod scan;
+pub mod script;
+mod sha256;
+pub mod sheet;
+pub mod source;
+#[cfg(feature = "clip")]
+mod sqlite;
+pub mod stamp;
+pub mod sync;
+pub mod timing;
+pub mod transform;
+#[cfg(feature = "turbojpeg")]
+mod turbojpeg;
+pub mod unity;
+pub mod upload;
+pub mod validate;
+#[cfg(feature = "vips")]
+mod vips;
+pub mod watchlist;
+#[cfg(feature = "xcf")]
+mod xcf;
+mod zip;
+
+// 定义支持的导出格式
+#[derive(ValueEnum, Clone, Debug, Default, PartialEq)] // 派生 ValueEnum, Clone, Debug, Default, PartialEq
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Jpg,
+    Bmp,
+    Webp,
+    Tiff,
+    Avif,
+    Ico,
+    Gif,
+    Pdf,
+    /// OpenRaster，保留图层
+    Ora,
+}
+
+impl ExportFormat {
+    // 获取对应的文件扩展名列表
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Jpg => "jpg",
+            ExportFormat::Bmp => "bmp",
+            ExportFormat::Webp => "webp",
+            ExportFormat::Tiff => "tiff",
+            ExportFormat::Avif => "avif",
+            ExportFormat::Ico => "ico",
+            ExportFormat::Gif => "gif",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Ora => "ora",
+        }
+    }
+
+    /// 上传时使用的 Content-Type
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "image/png",
+            ExportFormat::Jpg => "image/jpeg",
+            ExportFormat::Bmp => "image/bmp",
+            ExportFormat::Webp => "image/webp",
+            ExportFormat::Tiff => "image/tiff",
+            ExportFormat::Avif => "image/avif",
+            ExportFormat::Ico => "image/vnd.microsoft.icon",
+            ExportFormat::Gif => "image/gif",
+            ExportFormat::Pdf => "application/pdf",
+            ExportFormat::Ora => "image/openraster",
+        }
+    }
+}
+
+/// 单个文件导出时需要的全部参数
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub rotate: Option<Rotation>,
+    pub flip: Vec<Flip>,
+    pub resize: Option<ResizeSpec>,
+    pub filter: ResizeFilter,
+    pub pad_to: Option<PadSpec>,
+    pub pad_color: Rgba<u8>,
+    pub encode: EncodeOptions,
+    pub stamp: Option<Stamp>,
+    /// 设置后取代上面固定顺序的旋转、缩放、填充与水印
+    pub steps: Vec<Step>,
+    pub thumbnail: Option<u32>,
+    pub sheet: Option<SheetOptions>,
+    pub fallback: Option<String>,
+    pub photoshop: bool,
+    #[cfg(feature = "vips")]
+    pub vips: bool,
+    pub memory: Option<Arc<MemoryBudget>>,
+    pub script: Option<String>,
+    pub upload: Option<Upload>,
+    /// 脚本指定的输出路径，为空时与源文件同目录同名
+    pub output: Option<PathBuf>,
+    /// 替换输出文件名中 Windows 与 macOS 上无效字符的字符串，见 [`naming::sanitize`]
+    pub name_replacement: String,
+    /// 配置文件中的 `[[target]]`，为空时按上面的设置导出一个文件
+    pub targets: Vec<Target>,
+    /// 作为源文件的扩展名（小写），为空时为内置解码器支持的全部扩展名
+    pub extensions: Vec<String>,
+    /// 源文件大小的下限与上限（字节），范围外的文件不导出
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// 单个文件的导出时限，见 [`deadline`]
+    pub timeout: Option<Duration>,
+    /// 只读取 PSD 内嵌的缩略图，代替完整解码
+    pub preview: bool,
+    /// 另外把每个通道保存为灰度图
+    pub channels: bool,
+    /// 另外把透明度保存为灰度图 `name_alpha.png`
+    pub export_alpha: bool,
+    /// 另外生成 mipmap 链，见 [`mipmap`]
+    pub mipmaps: Option<MipmapMode>,
+    /// 另外写入 Unity 的 `.meta` 导入设置，见 [`unity`]
+    pub unity_meta: Option<TextureType>,
+    /// 另外写入 Godot 的 `.import` 导入设置，见 [`godot`]
+    pub godot_import: Option<ImportSettings>,
+    /// ORA 导出时如何处理图层蒙版，见 [`layermask`]
+    pub layer_masks: Option<MaskMode>,
+}
+
+/// 与不带任何参数运行 `pae` 时相同的设置
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            format: ExportFormat::default(),
+            rotate: None,
+            flip: Vec::new(),
+            resize: None,
+            filter: ResizeFilter::default(),
+            pad_to: None,
+            pad_color: Rgba([0, 0, 0, 0]),
+            encode: EncodeOptions::default(),
+            stamp: None,
+            steps: Vec::new(),
+            thumbnail: None,
+            sheet: None,
+            fallback: None,
+            photoshop: false,
+            #[cfg(feature = "vips")]
+            vips: false,
+            memory: None,
+            script: None,
+            upload: None,
+            output: None,
+            name_replacement: "_".to_string(),
+            targets: Vec::new(),
+            extensions: Vec::new(),
+            min_size: None,
+            max_size: None,
+            timeout: None,
+            preview: false,
+            channels: false,
+            export_alpha: false,
+            mipmaps: None,
+            unity_meta: None,
+            godot_import: None,
+            layer_masks: None,
+        }
+    }
+}
+
+/// 同一源文件的一个输出目标，各自带有完整的导出设置
+#[derive(Clone, Debug)]
+pub struct Target {
+    /// 输出目录，相对路径相对于源文件所在目录；不指定时与源文件同目录
+    pub dir: Option<PathBuf>,
+    pub options: ExportOptions,
+}
+
+impl Target {
+    /// 输出路径：目录下与源文件同名的文件，目录不存在时创建
+    fn output_path(&self, psd_path: &Path) -> Result<PathBuf> {
+        let path = self.planned_path(psd_path);
+        if self.dir.is_some()
+            && let Some(dir) = path.parent()
+        {
+            std::fs::create_dir_all(dir).context(format!("无法创建输出目录：{:?}", dir))?;
+        }
+        Ok(path)
+    }
+
+    /// 输出路径，不创建目录
+    fn planned_path(&self, psd_path: &Path) -> PathBuf {
+        let path = self.options.derived_path(psd_path);
+        let Some(dir) = &self.dir else {
+            return path;
+        };
+        let dir = psd_path.parent().unwrap_or(Path::new(".")).join(dir);
+        dir.join(path.file_name().unwrap_or_default())
+    }
+}
+
+impl ExportOptions {
+    /// 是否设置了任何会改变像素的处理
+    pub fn has_transforms(&self) -> bool {
+        self.rotate.is_some()
+            || !self.flip.is_empty()
+            || self.resize.is_some()
+            || self.pad_to.is_some()
+            || self.encode.quantize.is_some()
+            || self.stamp.is_some()
+            || self.thumbnail.is_some()
+            || !self.steps.is_empty()
+    }
+
+    /// 导出源文件会写入的主要输出（不含缩略图）
+    pub fn outputs(&self, psd_path: &Path) -> Vec<PathBuf> {
+        if !self.targets.is_empty() {
+            return self
+                .targets
+                .iter()
+                .map(|target| target.planned_path(psd_path))
+                .collect();
+        }
+        vec![
+            self.output
+                .clone()
+                .unwrap_or_else(|| self.derived_path(psd_path)),
+        ]
+    }
+
+    /// 与源文件同目录同名的输出路径，文件名中的无效字符已被替换
+    pub fn derived_path(&self, psd_path: &Path) -> PathBuf {
+        naming::sanitize_path(
+            &psd_path.with_extension(self.format.extension()),
+            &self.name_replacement,
+        )
+    }
+
+    /// 是否需要重新导出：有输出不存在，或比源文件（及其旁配置文件）旧
+    pub fn is_stale(&self, psd_path: &Path) -> bool {
+        let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+        let Some(source) = modified(psd_path) else {
+            return false;
+        };
+        let source = modified(&config::sidecar_path(psd_path)).map_or(source, |s| s.max(source));
+        self.outputs(psd_path)
+            .iter()
+            .any(|output| modified(output).is_none_or(|output| output < source))
+    }
+
+    /// 是否会在主输出之外另外写入文件：缩略图、各通道、mipmap、引擎的导入设置与图层蒙版
+    pub fn writes_extra_files(&self) -> bool {
+        self.thumbnail.is_some()
+            || self.channels
+            || self.export_alpha
+            || self.mipmaps.is_some()
+            || self.unity_meta.is_some()
+            || self.godot_import.is_some()
+            || self.layer_masks.is_some_and(MaskMode::exports)
+    }
+
+    /// 按内存预算等待开始导出，返回的凭证在导出完成后释放。需要在把任务交给线程池
+    /// 之前调用，见 [`budget`]
+    pub fn admit(&self, psd_path: &Path) -> Option<budget::Permit> {
+        self.memory
+            .as_ref()
+            .map(|budget| budget.acquire(budget::estimate(psd_path)))
+    }
+}
+
+/// 单个文件的导出结果
+pub struct Exported {
+    pub output_path: PathBuf,
+    /// 有多个输出目标时除第一个以外的输出文件，以及各通道的灰度图
+    pub extra_outputs: Vec<PathBuf>,
+    pub thumbnail_path: Option<PathBuf>,
+    /// 总览图使用的预览图，仅在启用总览图时生成
+    pub preview: Option<RgbaImage>,
+    /// 是否由外部转换器解码
+    pub fallback: bool,
+    pub timings: Timings,
+}
+
+impl Exported {
+    /// 本次写入的全部文件：主输出、其它目标的输出与缩略图
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.output_path.clone()];
+        files.extend(self.extra_outputs.iter().cloned());
+        files.extend(self.thumbnail_path.clone());
+        files
+    }
+}
+
+/// 解码后（或应用所有变换后）的图像
+pub struct Rendered {
+    pub image: RgbaImage,
+    pub dpi: Option<(f64, f64)>,
+    /// 是否由外部转换器解码
+    pub fallback: bool,
+    /// 解码、合成与变换的耗时
+    pub timings: Timings,
+}
+
+/// 转换为绝对路径，便于比较监听根目录与事件中的路径
+pub fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// 是否是需要导出的源文件：扩展名（不区分大小写）在 `--ext` 中，未设置时为内置解码器
+/// 支持的扩展名，且大小在 `--min-size` 与 `--max-size` 之间。与导出格式扩展名相同的
+/// 文件（如导出 ORA 时生成的 `.ora`）视为导出结果而不是源文件，避免循环导出
+pub fn is_source_file(path: &Path, options: &ExportOptions) -> bool {
+    let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+    let selected = if options.extensions.is_empty() {
+        source::is_supported(path)
+    } else {
+        options
+            .extensions
+            .iter()
+            .any(|selected| selected.eq_ignore_ascii_case(ext))
+    };
+    if !selected || ext.eq_ignore_ascii_case(options.format.extension()) {
+        return false;
+    }
+    if options.min_size.is_none() && options.max_size.is_none() {
+        return true;
+    }
+    path.metadata().is_ok_and(|metadata| {
+        let size = metadata.len();
+        options.min_size.is_none_or(|min| size >= min)
+            && options.max_size.is_none_or(|max| size <= max)
+    })
+}
+
+/// 查找指定路径下的所有源文件（如果是目录则递归查找）
+pub fn find_psd_files(path: &Path, options: &ExportOptions) -> Result<Vec<PathBuf>> {
+    let mut psd_files = Vec::new();
+
+    if path.is_file() {
+        if is_source_file(path, options) {
+            psd_files.push(path.to_path_buf());
+        }
+    } else if path.is_dir() {
+        psd_files = scan::files(path, &|path| is_source_file(path, options));
+    }
+    // 如果路径不存在或不是文件/目录，find_psd_files 会返回空 Vec，这在 main
+    // 中已经处理了路径不存在的情况
+
+    Ok(psd_files)
+}
+
+/// 与 [`find_psd_files`] 相同，但每读完一个目录就把其中的源文件交给 `found`，不等待
+/// 遍历结束。`found` 在当前 rayon 线程池中并发调用，遍历完成后返回
+pub fn scan_psd_files(path: &Path, options: &ExportOptions, found: &(dyn Fn(Vec<PathBuf>) + Sync)) {
+    if path.is_file() {
+        if is_source_file(path, options) {
+            found(vec![path.to_path_buf()]);
+        }
+    } else if path.is_dir() {
+        scan::each(path, &|path| is_source_file(path, options), found);
+    }
+}
+
+/// 将指定的源文件转换为同名的指定格式图像文件。设置了内存预算时，调用方需要先用
+/// [`ExportOptions::admit`] 获取预算
+pub fn process_psd_file(psd_path: &Path, options: &ExportOptions) -> Result<Exported> {
+    catch_panic(|| deadline::run(options.timeout, || export_file(psd_path, options)))
+}
+
+/// 把导出过程中的 panic 转换为错误，一个异常的文件不会使工作线程或整个程序退出
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(anyhow!(
+            "导出时发生内部错误（panic）：{}",
+            panic_message(payload.as_ref())
+        ))
+    })
+}
+
+/// panic 的消息，`panic!` 的参数通常是 `&str` 或 `String`
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("未知错误")
+}
+
+fn export_file(psd_path: &Path, options: &ExportOptions) -> Result<Exported> {
+    let format = &options.format;
+    if !options.targets.is_empty() {
+        return export_targets(psd_path, options);
+    }
+
+    // 构建输出文件的路径，使用指定的扩展名
+    let output_path = options
+        .output
+        .clone()
+        .unwrap_or_else(|| options.derived_path(psd_path));
+
+    if let ExportFormat::Ora = format {
+        let mut exported = export_ora(psd_path, &output_path, options)?;
+        upload_output(&output_path, options, &mut exported.timings)?;
+        return Ok(exported);
+    }
+
+    #[cfg(feature = "vips")]
+    if options.vips && vips::supports(psd_path, options) {
+        // libvips 一次完成读取、缩放与编码，全部计入编码耗时
+        let mut timings = Timings::default();
+        match timing::measure(&mut timings.encode, || {
+            dedupe::unlink_shared(&output_path)?;
+            vips::export(psd_path, &output_path, options)
+        }) {
+            Ok(()) => {
+                upload_output(&output_path, options, &mut timings)?;
+                return Ok(Exported {
+                    output_path,
+                    extra_outputs: Vec::new(),
+                    thumbnail_path: None,
+                    preview: None,
+                    fallback: false,
+                    timings,
+                });
+            }
+            Err(e) => warn!("libvips 导出失败，改用默认路径：{}", e),
+        }
+    }
+
+    let Rendered {
+        image: img_buffer,
+        dpi,
+        fallback,
+        mut timings,
+    } = render_psd_file(psd_path, options)?;
+    save_image(&img_buffer, &output_path, options, dpi, &mut timings)?;
+    upload_output(&output_path, options, &mut timings)?;
+    let mut extra_outputs = if options.channels {
+        save_channels(&img_buffer, &output_path, &mut timings)?
+    } else {
+        Vec::new()
+    };
+    if options.export_alpha {
+        extra_outputs.push(save_alpha(&img_buffer, &output_path, &mut timings)?);
+    }
+    if let Some(mode) = options.mipmaps {
+        extra_outputs.extend(save_mipmaps(
+            &img_buffer,
+            mode,
+            &output_path,
+            options,
+            &mut timings,
+        )?);
+    }
+    if let Some(kind) = options.unity_meta {
+        extra_outputs.push(timing::measure(&mut timings.write, || {
+            unity::write_meta(&output_path, &img_buffer, kind)
+        })?);
+    }
+    if let Some(settings) = &options.godot_import {
+        extra_outputs.push(timing::measure(&mut timings.write, || {
+            godot::write_import(&output_path, settings)
+        })?);
+    }
+
+    let preview = options
+        .sheet
+        .map(|sheet| sheet.preview(&img_buffer, options.filter));
+
+    // 同一次解码中顺便生成缩略图
+    let thumbnail_path = save_thumbnail(img_buffer, &output_path, options, &mut timings)?;
+
+    Ok(Exported {
+        output_path,
+        extra_outputs,
+        thumbnail_path,
+        preview,
+        fallback,
+        timings,
+    })
+}
+
+/// 把 R、G、B、A 各通道分别保存为灰度 PNG `name.r.png` 等。始终使用 PNG，有损格式会
+/// 破坏通道中存放的遮罩数据
+fn save_channels(
+    img: &RgbaImage,
+    output_path: &Path,
+    timings: &mut Timings,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for (index, name) in ["r", "g", "b", "a"].into_iter().enumerate() {
+        let path = output_path.with_extension(format!("{}.png", name));
+        save_channel(img, index, &path, timings)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// 把透明度保存为灰度 PNG `name_alpha.png`，供视频合成软件作为遮罩使用
+fn save_alpha(img: &RgbaImage, output_path: &Path, timings: &mut Timings) -> Result<PathBuf> {
+    let mut name = output_path.file_stem().unwrap_or_default().to_os_string();
+    name.push("_alpha.png");
+    let path = output_path.with_file_name(name);
+    save_channel(img, 3, &path, timings)?;
+    Ok(path)
+}
+
+/// 生成 mipmap 链并按 `mode` 保存为 `name.mip{级别}.<ext>` 或 `name.dds`
+fn save_mipmaps(
+    img: &RgbaImage,
+    mode: MipmapMode,
+    output_path: &Path,
+    options: &ExportOptions,
+    timings: &mut Timings,
+) -> Result<Vec<PathBuf>> {
+    if !mipmap::is_power_of_two(img) {
+        warn!(
+            "{:?} 的尺寸 {}x{} 不是 2 的幂，mipmap 各级边长向下取整",
+            output_path,
+            img.width(),
+            img.height()
+        );
+    }
+    let levels = timing::measure(&mut timings.transform, || {
+        mipmap::chain(img, options.filter)
+    });
+    match mode {
+        MipmapMode::Files => {
+            let mut paths = Vec::new();
+            for (index, level) in levels.iter().enumerate() {
+                let path = output_path.with_extension(format!(
+                    "mip{}.{}",
+                    index + 1,
+                    options.format.extension()
+                ));
+                save_image(level, &path, options, None, timings)?;
+                paths.push(path);
+            }
+            Ok(paths)
+        }
+        MipmapMode::Dds => {
+            let path = output_path.with_extension("dds");
+            let all: Vec<&RgbaImage> = std::iter::once(img).chain(&levels).collect();
+            let mut buffer = Vec::new();
+            timing::measure(&mut timings.encode, || mipmap::write_dds(&all, &mut buffer))
+                .context(format!("无法编码 DDS 纹理：{:?}", path))?;
+            timing::measure(&mut timings.write, || {
+                dedupe::unlink_shared(&path)?;
+                std::fs::write(&path, buffer)
+            })
+            .context(format!("无法写入输出文件：{:?}", path))?;
+            Ok(vec![path])
+        }
+    }
+}
+
+/// 把第 `index` 个通道保存为灰度 PNG
+fn save_channel(img: &RgbaImage, index: usize, path: &Path, timings: &mut Timings) -> Result<()> {
+    let channel = image::GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        image::Luma([img.get_pixel(x, y)[index]])
+    });
+    write_output(path, timings, "无法编码通道图像", |writer| {
+        Ok(channel.write_to(writer, image::ImageFormat::Png)?)
+    })
+}
+
+/// 把图层蒙版保存为整幅画布大小的灰度 PNG `name.mask{序号}.png`，序号与 ORA 中的
+/// `data/layer{序号}.png` 对应，没有蒙版的图层不生成文件
+fn save_layer_masks(
+    masks: &[Option<LayerMask>],
+    (width, height): (u32, u32),
+    output_path: &Path,
+    timings: &mut Timings,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for (index, mask) in masks.iter().enumerate() {
+        let Some(mask) = mask else {
+            continue;
+        };
+        let path = output_path.with_extension(format!("mask{}.png", index));
+        write_output(&path, timings, "无法编码图层蒙版", |writer| {
+            Ok(mask
+                .to_canvas(width, height)
+                .write_to(writer, image::ImageFormat::Png)?)
+        })?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// 按 `[[target]]` 导出：源文件只解码一次，每个目标各自变换、编码并写入。
+/// 总览图使用第一个目标的图像
+fn export_targets(psd_path: &Path, options: &ExportOptions) -> Result<Exported> {
+    let Rendered {
+        image,
+        dpi,
+        fallback,
+        mut timings,
+    } = decode_psd_file(psd_path, options)?;
+
+    let mut outputs = Vec::new();
+    let mut thumbnail_path = None;
+    let mut preview = None;
+    let mut export = |image: RgbaImage, target: &Target| -> Result<()> {
+        let path = target.output_path(psd_path)?;
+        let (image, dpi) = transform_image(image, dpi, psd_path, &target.options, &mut timings)?;
+        save_image(&image, &path, &target.options, dpi, &mut timings)?;
+        upload_output(&path, &target.options, &mut timings)?;
+        if outputs.is_empty() {
+            preview = options
+                .sheet
+                .map(|sheet| sheet.preview(&image, options.filter));
+        }
+        let thumbnail = save_thumbnail(image, &path, &target.options, &mut timings)?;
+        thumbnail_path = thumbnail_path.take().or(thumbnail);
+        outputs.push(path);
+        Ok(())
+    };
+    // 最后一个目标直接使用解码结果，其余目标各复制一份
+    if let Some((last, rest)) = options.targets.split_last() {
+        for target in rest {
+            export(image.clone(), target)?;
+        }
+        export(image, last)?;
+    }
+
+    let mut outputs = outputs.into_iter();
+    Ok(Exported {
+        output_path: outputs.next().context("没有输出目标")?,
+        extra_outputs: outputs.collect(),
+        thumbnail_path,
+        preview,
+        fallback,
+        timings,
+    })
+}
+
+/// 按设置上传导出文件，耗时计入写入
+fn upload_output(path: &Path, options: &ExportOptions, timings: &mut Timings) -> Result<()> {
+    let Some(upload) = &options.upload else {
+        return Ok(());
+    };
+    let url = timing::measure(&mut timings.write, || {
+        upload::upload(upload, path, options.format.mime_type())
+    })?;
+    info!("已上传：{:?} -> {}", path, url);
+    Ok(())
+}
+
+/// 按设置生成缩略图 `name.thumb.<ext>`，返回其路径
+fn save_thumbnail(
+    image: RgbaImage,
+    output_path: &Path,
+    options: &ExportOptions,
+    timings: &mut Timings,
+) -> Result<Option<PathBuf>> {
+    let Some(size) = options.thumbnail else {
+        return Ok(None);
+    };
+    let thumbnail = timing::measure(&mut timings.transform, || {
+        transform::resize(image, ResizeSpec::Fit(size, size), options.filter)
+    });
+    let path = thumbnail_path(output_path, &options.format);
+    save_image(&thumbnail, &path, options, None, timings)?;
+    Ok(Some(path))
+}
+
+/// 导出保留图层的 ORA 文件。图层按原始画布保存，不应用旋转、缩放等变换
+fn export_ora(psd_path: &Path, output_path: &Path, options: &ExportOptions) -> Result<Exported> {
+    let mut timings = Timings::default();
+    dedupe::unlink_shared(output_path).context(format!("无法替换输出文件：{:?}", output_path))?;
+    let file = std::fs::File::create(output_path)
+        .context(format!("无法创建输出文件：{:?}", output_path))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let (merged, masks) = encode_ora(psd_path, options, &mut writer, &mut timings)
+        .context(format!("无法保存 ORA 文件：{:?}", output_path))?;
+    timing::measure(&mut timings.encode, || writer.flush())
+        .context(format!("无法保存 ORA 文件：{:?}", output_path))?;
+
+    let extra_outputs = if options.layer_masks.is_some_and(MaskMode::exports) {
+        save_layer_masks(&masks, merged.dimensions(), output_path, &mut timings)?
+    } else {
+        Vec::new()
+    };
+
+    Ok(Exported {
+        output_path: output_path.to_path_buf(),
+        extra_outputs,
+        thumbnail_path: None,
+        preview: options
+            .sheet
+            .map(|sheet| sheet.preview(&merged, options.filter)),
+        fallback: false,
+        timings,
+    })
+}
+
+/// 把 PSD 的图层编码为 ORA 写入 `writer`，返回合并后的图像与读取的图层蒙版
+fn encode_ora(
+    psd_path: &Path,
+    options: &ExportOptions,
+    writer: impl Write,
+    timings: &mut Timings,
+) -> Result<(RgbaImage, Vec<Option<LayerMask>>)> {
+    if !source::is_psd(psd_path) {
+        anyhow::bail!("ORA 导出只支持 PSD 源文件：{:?}", psd_path);
+    }
+    let decode_start = Instant::now();
+    let psd_bytes = mmap::read(psd_path).context(format!("无法读取 PSD 文件：{:?}", psd_path))?;
+    let psd = Psd::from_bytes(&psd_bytes).context(format!("无法解析 PSD 文件：{:?}", psd_path))?;
+    let masks = match options.layer_masks {
+        Some(_) => layermask::layer_masks(&psd_bytes).unwrap_or_else(|e| {
+            warn!("无法读取图层蒙版，已忽略：{:?}：{:#}", psd_path, e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    timings.decode = decode_start.elapsed();
+
+    // 图层 PNG 的编码与写入交错进行，全部计入编码耗时
+    let encode_start = Instant::now();
+    let merged = ora::write_ora(
+        &psd,
+        resources::resolution(&psd_bytes),
+        options.filter,
+        if options.layer_masks.is_some_and(MaskMode::applies) {
+            &masks
+        } else {
+            &[]
+        },
+        writer,
+    )?;
+    timings.encode = encode_start.elapsed();
+    Ok((merged, masks))
+}
+
+/// 解码源文件并依次应用旋转、缩放、填充和水印，返回最终图像与其分辨率
+pub fn render_psd_file(psd_path: &Path, options: &ExportOptions) -> Result<Rendered> {
+    catch_panic(|| {
+        deadline::run(options.timeout, || {
+            let Rendered {
+                image,
+                dpi,
+                fallback,
+                mut timings,
+            } = decode_psd_file(psd_path, options)?;
+            let (image, dpi) = transform_image(image, dpi, psd_path, options, &mut timings)?;
+            Ok(Rendered {
+                image,
+                dpi,
+                fallback,
+                timings,
+            })
+        })
+    })
+}
+
+/// 从内存中的源文件内容导出，编码结果同样留在内存中，不读写任何文件（用于读取
+/// 标准输入）。`name` 用于选择解码器与错误信息。ORA 格式、Photoshop、外部转换器、
+/// `[[target]]`、缩略图与上传都需要文件，这里不支持
+pub fn export_bytes(
+    bytes: &[u8],
+    name: &Path,
+    options: &ExportOptions,
+) -> Result<(Vec<u8>, Timings)> {
+    if let ExportFormat::Ora = options.format {
+        anyhow::bail!("ORA 格式需要读取源文件，不能从内存导出");
+    }
+    catch_panic(|| {
+        deadline::run(options.timeout, || {
+            let mut timings = Timings::default();
+            let decode_start = Instant::now();
+            let source::Decoded {
+                image,
+                dpi,
+                composite,
+            } = source::decode_bytes(bytes, name)?;
+            timings.composite = composite;
+            timings.decode = decode_start.elapsed().saturating_sub(composite);
+            deadline::check()?;
+            let (image, dpi) = transform_image(image, dpi, name, options, &mut timings)?;
+            let mut buffer = std::io::Cursor::new(Vec::new());
+            timing::measure(&mut timings.encode, || {
+                encode::write_image(&image, &options.format, dpi, &options.encode, &mut buffer)
+            })
+            .context(format!("无法编码图像：{:?}", name))?;
+            Ok((buffer.into_inner(), timings))
+        })
+    })
+}
+
+/// 把源文件导出到内存中，不创建任何文件（用于 `--stdout`）。与 [`export_bytes`] 不同，
+/// 源文件可以交给 Photoshop 或外部转换器解码，也可以导出 ORA。只生成主输出：
+/// `[[target]]`、上传与 [`ExportOptions::writes_extra_files`] 中的输出需要文件，这里不支持
+pub fn export_to_memory(psd_path: &Path, options: &ExportOptions) -> Result<(Vec<u8>, Timings)> {
+    if !options.targets.is_empty() || options.upload.is_some() || options.writes_extra_files() {
+        anyhow::bail!("导出到内存时只能生成主输出，不支持 [[target]]、上传、缩略图与附加输出");
+    }
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    if let ExportFormat::Ora = options.format {
+        let mut timings = Timings::default();
+        catch_panic(|| {
+            deadline::run(options.timeout, || {
+                encode_ora(psd_path, options, &mut buffer, &mut timings)
+            })
+        })
+        .context(format!("无法编码 ORA 文件：{:?}", psd_path))?;
+        return Ok((buffer.into_inner(), timings));
+    }
+    let Rendered {
+        image,
+        dpi,
+        mut timings,
+        ..
+    } = render_psd_file(psd_path, options)?;
+    timing::measure(&mut timings.encode, || {
+        encode::write_image(&image, &options.format, dpi, &options.encode, &mut buffer)
+    })
+    .context(format!("无法编码图像：{:?}", psd_path))?;
+    Ok((buffer.into_inner(), timings))
+}
+
+/// 解码源文件，返回未经变换的图像与其分辨率
+fn decode_psd_file(psd_path: &Path, options: &ExportOptions) -> Result<Rendered> {
+    // 解码合并后的图像 (RGBA 格式) 与文档分辨率，缩放时同比例换算以保持物理尺寸不变。
+    // 内置解码器失败时按需交给 Photoshop 或外部转换器
+    let mut timings = Timings::default();
+    let decode_start = Instant::now();
+    let preview = options
+        .preview
+        .then(|| source::decode_preview(psd_path))
+        .flatten();
+    let (decoded, fallback) = if let Some(decoded) = preview {
+        (decoded, false)
+    } else if options.photoshop && needs_photoshop(psd_path) {
+        info!("{:?} 包含智能对象或图层效果，交给 Photoshop 导出", psd_path);
+        (photoshop::convert(psd_path)?, true)
+    } else {
+        match source::decode(psd_path) {
+            Ok(decoded) => (decoded, false),
+            Err(e) if options.photoshop => {
+                warn!("无法解码 {:?}，改用 Photoshop：{:#}", psd_path, e);
+                (photoshop::convert(psd_path)?, true)
+            }
+            Err(e) => match &options.fallback {
+                Some(command) => {
+                    warn!("无法解码 {:?}，改用外部转换器：{:#}", psd_path, e);
+                    (source::convert_external(command, psd_path)?, true)
+                }
+                None => return Err(e),
+            },
+        }
+    };
+    let source::Decoded {
+        image,
+        dpi,
+        composite,
+    } = decoded;
+    timings.composite = composite;
+    timings.decode = decode_start.elapsed().saturating_sub(composite);
+    deadline::check()?;
+    Ok(Rendered {
+        image,
+        dpi,
+        fallback,
+        timings,
+    })
+}
+
+/// 依次应用旋转、缩放、填充和水印（或 `--steps` 中的步骤），分辨率随缩放同比例换算
+fn transform_image(
+    img_buffer: RgbaImage,
+    mut dpi: Option<(f64, f64)>,
+    psd_path: &Path,
+    options: &ExportOptions,
+    timings: &mut Timings,
+) -> Result<(RgbaImage, Option<(f64, f64)>)> {
+    let transform_start = Instant::now();
+    if !options.steps.is_empty() {
+        let result = pipeline::run(img_buffer, dpi, psd_path, options);
+        timings.transform += transform_start.elapsed();
+        return result;
+    }
+
+    // 先纠正方向，旋转 90/270 度时交换水平与垂直分辨率
+    let img_buffer = transform::orient(img_buffer, options.rotate, &options.flip);
+    if matches!(options.rotate, Some(Rotation::Deg90 | Rotation::Deg270)) {
+        dpi = dpi.map(|(x, y)| (y, x));
+    }
+    let source_width = img_buffer.width();
+
+    // 按需缩放
+    let mut img_buffer = match options.resize {
+        Some(spec) => transform::resize(img_buffer, spec, options.filter),
+        None => img_buffer,
+    };
+    if let Some((x, y)) = &mut dpi {
+        let ratio = img_buffer.width() as f64 / source_width as f64;
+        *x *= ratio;
+        *y *= ratio;
+    }
+
+    // 按需填充画布，填充到指定尺寸时原图的缩放同样换算到分辨率
+    if let Some(spec) = options.pad_to {
+        let (padded, ratio) = transform::pad(img_buffer, spec, options.pad_color, options.filter)?;
+        img_buffer = padded;
+        dpi = dpi.map(|(x, y)| (x * ratio, y * ratio));
+    }
+
+    // 绘制水印
+    if let Some(stamp) = &options.stamp {
+        stamp.apply(&mut img_buffer, psd_path);
+    }
+    timings.transform += transform_start.elapsed();
+
+    deadline::check()?;
+    Ok((img_buffer, dpi))
+}
+
+/// 是否是需要 Photoshop 才能准确还原的 PSD
+fn needs_photoshop(psd_path: &Path) -> bool {
+    source::is_psd(psd_path)
+        && mmap::read(psd_path).is_ok_and(|bytes| photoshop::needs_photoshop(&bytes))
+}
+
+/// 缩略图的输出路径：与导出文件同目录的 `name.thumb.<ext>`
+fn thumbnail_path(output_path: &Path, format: &ExportFormat) -> PathBuf {
+    output_path.with_extension(format!("thumb.{}", format.extension()))
+}
+
+/// 将图像按导出设置编码并写入文件
+fn save_image(
+    img: &RgbaImage,
+    output_path: &Path,
+    options: &ExportOptions,
+    dpi: Option<(f64, f64)>,
+    timings: &mut Timings,
+) -> Result<()> {
+    // image crate 的 save 方法可以根据文件扩展名自动选择格式，
+    // 但为了明确控制格式和元数据（特别是 DPI），我们自己选择编码器。
+    deadline::check()?;
+    write_output(output_path, timings, "无法保存图像文件", |writer| {
+        encode::write_image(img, &options.format, dpi, &options.encode, writer)
+    })
+}
+
+type OutputWriter = std::io::BufWriter<TimedWriter<std::fs::File>>;
+
+/// 把 `encode` 的结果直接写入输出文件，不在内存中另存一份编码后的数据。
+/// 编码与写入交替进行，写入的耗时由 [`TimedWriter`] 单独统计
+fn write_output(
+    path: &Path,
+    timings: &mut Timings,
+    failed: &str,
+    encode: impl FnOnce(&mut OutputWriter) -> Result<()>,
+) -> Result<()> {
+    let file = timing::measure(&mut timings.write, || {
+        dedupe::unlink_shared(path)?;
+        std::fs::File::create(path)
+    })
+    .context(format!("无法创建输出文件：{:?}", path))?;
+    let mut writer = std::io::BufWriter::new(TimedWriter::new(file));
+    let start = Instant::now();
+    encode(&mut writer)
+        .and_then(|()| Ok(writer.flush()?))
+        .context(format!("{}：{:?}", failed, path))?;
+    let written = writer.get_ref().elapsed();
+    timings.write += written;
+    timings.encode += start.elapsed().saturating_sub(written);
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! 导出结果的哈希锁文件（`--lock`）与校验（`pae verify`）。锁文件记录每个输出及其
+//! 源文件的 SHA-256；校验时重新计算，找出被改动或损坏的输出，以及导出后又被修改
+//! （输出已过期）的源文件。
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use rayon::prelude::*;
+
+use crate::sha256;
+
+/// 锁文件名
+pub const LOCK_NAME: &str = "psd-export.lock";
+
+const HEADER: &str = "# psd-auto-export 锁文件：输出文件与源文件的 SHA-256，由 `pae verify` 校验\n";
+
+struct Entry {
+    output_hash: String,
+    source: PathBuf,
+    source_hash: String,
+}
+
+/// 重新计算各文件哈希后的结果，路径均为完整路径
+#[derive(Default)]
+pub struct Verification {
+    /// 校验通过的输出数
+    pub ok: usize,
+    /// 内容与记录不符的输出
+    pub modified: Vec<PathBuf>,
+    pub missing: Vec<PathBuf>,
+    /// 导出后又被修改的源文件
+    pub drifted: Vec<PathBuf>,
+    pub missing_sources: Vec<PathBuf>,
+}
+
+impl Verification {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty()
+            && self.missing.is_empty()
+            && self.drifted.is_empty()
+            && self.missing_sources.is_empty()
+    }
+}
+
+/// 按本次导出结果更新 `root` 下的锁文件，返回记录的输出数。导出失败或被跳过的源文件
+/// 保留原有记录，已不存在的源文件的记录被删除
+pub fn write(
+    root: &Path,
+    sources: &[PathBuf],
+    exported: &[(PathBuf, Vec<PathBuf>)],
+) -> Result<usize> {
+    let lock_path = root.join(LOCK_NAME);
+    let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+    let found: HashSet<PathBuf> = sources.iter().map(|path| relative(path)).collect();
+    let done: HashSet<PathBuf> = exported.iter().map(|(path, _)| relative(path)).collect();
+    let mut entries: BTreeMap<PathBuf, Entry> = if lock_path.exists() {
+        load(&lock_path)?
+            .into_iter()
+            .filter(|(_, entry)| found.contains(&entry.source) && !done.contains(&entry.source))
+            .collect()
+    } else {
+        BTreeMap::new()
+    };
+
+    let hashed: Vec<(PathBuf, Entry)> = exported
+        .par_iter()
+        .map(|(source, outputs)| {
+            let source_hash = hash(source)?;
+            outputs
+                .iter()
+                .map(|output| {
+                    let entry = Entry {
+                        output_hash: hash(output)?,
+                        source: relative(source),
+                        source_hash: source_hash.clone(),
+                    };
+                    Ok((relative(output), entry))
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    entries.extend(hashed);
+
+    let mut text = String::from(HEADER);
+    for (output, entry) in &entries {
+        text.push_str(&format!(
+            "{}\t{}\t{}\t{}\n",
+            entry.output_hash,
+            entry.source_hash,
+            entry.source.to_string_lossy(),
+            output.to_string_lossy()
+        ));
+    }
+    std::fs::write(&lock_path, text).context(format!("无法写入锁文件：{:?}", lock_path))?;
+    Ok(entries.len())
+}
+
+/// 校验锁文件。`path` 可以是锁文件本身或它所在的目录
+pub fn verify(path: &Path) -> Result<Verification> {
+    let lock_path = if path.is_dir() {
+        path.join(LOCK_NAME)
+    } else {
+        path.to_path_buf()
+    };
+    let root = lock_path.parent().unwrap_or(Path::new("."));
+    let entries = load(&lock_path)?;
+
+    // 同一源文件的多个输出只计算一次源文件的哈希
+    let sources: BTreeMap<&Path, &str> = entries
+        .values()
+        .map(|entry| (entry.source.as_path(), entry.source_hash.as_str()))
+        .collect();
+    let (source_states, output_states) = rayon::join(
+        || {
+            sources
+                .par_iter()
+                .map(|(source, expected)| (root.join(source), state(&root.join(source), expected)))
+                .collect::<Vec<_>>()
+        },
+        || {
+            entries
+                .par_iter()
+                .map(|(output, entry)| {
+                    let path = root.join(output);
+                    let state = state(&path, &entry.output_hash);
+                    (path, state)
+                })
+                .collect::<Vec<_>>()
+        },
+    );
+
+    let mut verification = Verification::default();
+    for (path, state) in output_states {
+        match state? {
+            State::Match => verification.ok += 1,
+            State::Mismatch => verification.modified.push(path),
+            State::Missing => verification.missing.push(path),
+        }
+    }
+    for (path, state) in source_states {
+        match state? {
+            State::Match => {}
+            State::Mismatch => verification.drifted.push(path),
+            State::Missing => verification.missing_sources.push(path),
+        }
+    }
+    Ok(verification)
+}
+
+enum State {
+    Match,
+    Mismatch,
+    Missing,
+}
+
+fn state(path: &Path, expected: &str) -> Result<State> {
+    if !path.exists() {
+        return Ok(State::Missing);
+    }
+    Ok(if hash(path)? == expected {
+        State::Match
+    } else {
+        State::Mismatch
+    })
+}
+
+fn hash(path: &Path) -> Result<String> {
+    sha256::hash_file(path).context(format!("无法读取文件：{:?}", path))
+}
+
+/// 每行一个输出：输出哈希、源文件哈希、源文件与输出文件，以制表符分隔
+fn load(path: &Path) -> Result<BTreeMap<PathBuf, Entry>> {
+    let text = std::fs::read_to_string(path).context(format!("无法读取锁文件：{:?}", path))?;
+    let mut entries = BTreeMap::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.splitn(4, '\t').collect();
+        let [output_hash, source_hash, source, output] = fields[..] else {
+            bail!("锁文件 {:?} 第 {} 行格式错误", path, number + 1);
+        };
+        let entry = Entry {
+            output_hash: output_hash.to_string(),
+            source: PathBuf::from(source),
+            source_hash: source_hash.to_string(),
+        };
+        entries.insert(PathBuf::from(output), entry);
+    }
+    Ok(entries)
+}
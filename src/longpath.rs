@@ -0,0 +1,33 @@
+//! Windows 长路径：超过 `MAX_PATH`（260 个字符）的路径需要写成 `\\?\C:\...` 或
+//! `\\?\UNC\server\share\...` 的形式才能交给 Win32 API。标准库的文件操作会自动转换，
+//! 但文件系统监听器（`ReadDirectoryChangesW`）与 libvips 等 C 库直接使用传入的路径，
+//! 项目目录层级较深时会报告“系统找不到指定的路径”。
+//!
+//! 程序清单（`pae.manifest`）同时声明了 `longPathAware`，在系统启用长路径时其余 API
+//! 也不再受此限制。
+
+use std::path::{Path, PathBuf};
+
+/// 超过该长度时转换。创建目录的限制比文件少 12 个字符（需留出 8.3 文件名的空间）
+#[cfg(windows)]
+const LIMIT: usize = 248;
+
+/// 需要时把路径转换为 Win32 API 可以接受的长路径形式，其它平台与较短的路径原样返回。
+/// 较短的路径不转换，日志与事件中的路径保持用户熟悉的形式
+pub fn extended(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let long = path.as_os_str().len() >= LIMIT;
+        let text = path.to_string_lossy();
+        if long && !text.starts_with(r"\\?\") {
+            // 前缀路径不再处理 `.`、`..` 与 `/`，需要先转为规范的绝对路径
+            let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+            let absolute = absolute.to_string_lossy();
+            return match absolute.strip_prefix(r"\\") {
+                Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+                None => PathBuf::from(format!(r"\\?\{}", absolute)),
+            };
+        }
+    }
+    path.to_path_buf()
+}
@@ -1,91 +1,810 @@
 use std::{
     collections::HashMap,
+    ffi::OsString,
+    io::{Read, Write},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, mpsc},
-    thread,
-    time::{Duration, Instant},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use image::{ImageBuffer, ImageFormat, Rgba};
-use log::{LevelFilter, error, info};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use image::{Rgba, RgbaImage};
+use log::{LevelFilter, error, info, warn};
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use psd::Psd;
 use rayon::prelude::*;
-use walkdir::WalkDir;
 
-// 定义防抖间隔，这里是 100 毫秒 (0.1 秒)
-const DEBOUNCE_DURATION: Duration = Duration::from_millis(100);
+use psd_auto_export::{
+    Coalescer, Debouncer, ExportFormat, ExportOptions, Exported, Rendered, Target, Throttle,
+    absolute,
+    archive::Archive,
+    atlas::{self, AtlasFormat},
+    budget::{self, MemoryBudget},
+    busy,
+    checkpoint::Checkpoint,
+    clock,
+    collision::{self, Claims, Planned, Resolution},
+    config,
+    dashboard::{self, Dashboard},
+    deadline, dedupe, diff,
+    encode::{
+        AvifOptions, BitDepth, EncodeOptions, JpegOptions, MultiPageTiff, PngCompression,
+        PngOptions, TiffCompression, TiffOptions,
+    },
+    errorlog,
+    eventlog::{self, Event},
+    find_psd_files, gallery,
+    gallery::GalleryEntry,
+    git,
+    godot::{Compression, ImportSettings},
+    hangup, is_source_file, isolate, launch,
+    layermask::MaskMode,
+    lfs, lock, longpath,
+    mipmap::MipmapMode,
+    naming::{self, Conventions},
+    order,
+    order::{Order, Schedule},
+    panic_message, pdf, pipeline,
+    pipeline::Step,
+    priority, process_psd_file,
+    quantize::{self, Dither, QuantizeOptions},
+    quarantine, render_psd_file, report,
+    roots::Roots,
+    scan_psd_files, script, sheet,
+    sheet::{SheetJson, SheetOptions},
+    source,
+    stamp::{Stamp, StampPosition},
+    sync,
+    transform::{self, Flip, PadSpec, ResizeFilter, ResizeSpec, Rotation},
+    unity::TextureType,
+    upload::{S3Options, Upload},
+    validate, watchlist,
+};
+
+// 一次性模式结束时列出的最慢文件数
+const SLOWEST_FILES: usize = 5;
+
+/// 边扫描边导出时每次从扫描结果中取出并筛选的最大文件数，隔离清单与 `git lfs pull`
+/// 按批处理
+const STREAM_BATCH: usize = 256;
+
+/// 源文件仍被占用时第一次推迟的时间，之后每次加倍
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_BUSY_DELAY: Duration = Duration::from_secs(30);
+/// 推迟的次数超过该值后不再等待，直接导出
+const MAX_BUSY_ATTEMPTS: u32 = 20;
+/// 内存预算不足时，监听模式下再次尝试开始导出的间隔
+const MEMORY_RETRY_DELAY: Duration = Duration::from_millis(200);
 
-// 定义支持的导出格式
-#[derive(ValueEnum, Clone, Debug)] // 派生 ValueEnum, Clone, Debug
-enum ExportFormat {
-    Png,
-    Jpg,
-    Bmp,
-    Webp,
-    Tiff,
-    Avif,
-    Ico,
+// 监听模式下检查 SIGHUP 的间隔
+const HANGUP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 配置文件相关操作
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// 按锁文件重新计算哈希，检查输出是否被改动或损坏、源文件在导出后是否被修改
+    Verify {
+        /// 锁文件或它所在的目录
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+    /// 解析路径下的全部源文件而不写入输出，报告无法解析的文件及原因，以及使用了
+    /// 渲染器无法还原的功能的文件。有文件失败时退出状态为 1，只有无法还原的功能时为 2
+    Validate {
+        /// 文件夹或单个源文件
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+    /// 渲染两个版本（源文件或已导出的图片）并逐像素比较，写入标出差异的图像并报告
+    /// 变化的像素比例；有差异时以非零状态退出
+    Diff {
+        a: PathBuf,
+        b: PathBuf,
+
+        /// 差异图的输出路径，格式按扩展名选择
+        #[arg(short, long, default_value = "diff.png")]
+        output: PathBuf,
+
+        /// 任一通道相差超过该值才视为变化，用于忽略压缩带来的细微差异
+        #[arg(long, default_value_t = 0)]
+        threshold: u8,
+    },
 }
 
-impl ExportFormat {
-    // 获取对应的文件扩展名列表
-    fn extension(&self) -> &'static str {
-        match self {
-            ExportFormat::Png => "png",
-            ExportFormat::Jpg => "jpg",
-            ExportFormat::Bmp => "bmp",
-            ExportFormat::Webp => "webp",
-            ExportFormat::Tiff => "tiff",
-            ExportFormat::Avif => "avif",
-            ExportFormat::Ico => "ico",
-        }
-    }
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// 生成列出全部选项（含说明与默认值）的配置文件，选项均已注释
+    Init {
+        /// 输出路径
+        #[arg(default_value = config::FILE_NAME)]
+        file: PathBuf,
 
-    // 获取对应的 image crate 输出格式
-    fn image_format(&self) -> ImageFormat {
-        match self {
-            ExportFormat::Png => ImageFormat::Png,
-            ExportFormat::Jpg => ImageFormat::Jpeg,
-            ExportFormat::Bmp => ImageFormat::Bmp,
-            ExportFormat::Webp => ImageFormat::WebP,
-            ExportFormat::Tiff => ImageFormat::Tiff,
-            ExportFormat::Avif => ImageFormat::Avif,
-            ExportFormat::Ico => ImageFormat::Ico,
-        }
-    }
+        /// 覆盖已存在的文件
+        #[arg(long)]
+        force: bool,
+    },
+    /// 检查配置文件的语法与各项取值
+    Validate {
+        /// 配置文件路径
+        #[arg(default_value = config::FILE_NAME)]
+        file: PathBuf,
+    },
 }
 
-/// 监听指定路径下的 PSD 文件变化（支持文件夹递归或单文件）并自动导出为指定格式
+/// 监听指定路径下的 PSD / ORA / KRA / CLIP / XCF 文件变化（支持文件夹递归或单文件）并自动导出为指定格式
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_override_self = true,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 struct Cli {
-    /// 要监听的文件夹路径（递归监听）或单个 PSD 文件路径
-    path: PathBuf,
+    /// 要监听的文件夹路径（递归监听）或单个 PSD / ORA / KRA / CLIP / XCF 文件路径；
+    /// 为 `-` 时从标准输入读取 PSD（需要 `--stdout` 或 `-o`）
+    #[arg(required_unless_present = "watchlist")]
+    path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// 配置文件路径，不指定时读取监听根目录下的 `psd-export.toml`（如果存在）。
+    /// 键名与命令行长参数相同，命令行参数优先于配置文件
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-    /// 导出图像的格式 (png 或 jpg)
+    /// 使用配置文件中 `[profile.NAME]` 定义的预设（如 `web`、`print`），
+    /// 覆盖配置文件的顶层设置
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// 监听模式下同一文件两次导出之间的最短间隔，例如 `100ms`、`2s`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "100ms")]
+    debounce: Duration,
+
+    /// 监听模式下合并事件的时间窗口：收到事件后等待这段时间，再把期间涉及的每个文件
+    /// 各处理一次，`0s` 时逐个处理事件
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "200ms")]
+    coalesce: Duration,
+
+    /// 导出图像的格式
     #[arg(short, long, value_enum, default_value_t = ExportFormat::Png)]
     format: ExportFormat,
 
     /// 只导出一次现有的 PSD 文件，不持续监听
     #[arg(long)]
     once: bool,
+
+    /// 一次性模式下把单个源文件的导出结果写入标准输出（日志始终写入标准错误），
+    /// 便于在管道中使用
+    #[arg(
+        long,
+        requires = "once",
+        conflicts_with_all = [
+            "contact_sheet", "combine", "gallery", "sync", "archive", "dedupe", "lock",
+            "git_add", "upload", "thumbnail", "report", "atlas", "channels", "export_alpha",
+            "mipmaps", "unity_meta", "godot_import",
+        ]
+    )]
+    stdout: bool,
+
+    /// 一次性模式下单个源文件（或 `-` 标准输入）的输出路径，取代默认的同目录同名文件
+    #[arg(
+        short,
+        long,
+        requires = "once",
+        conflicts_with_all = [
+            "stdout", "contact_sheet", "combine", "gallery", "sync", "archive", "dedupe",
+            "lock", "git_add", "upload", "thumbnail", "report", "atlas",
+        ]
+    )]
+    output: Option<PathBuf>,
+
+    /// 一次性模式下，为每个目录额外生成一张带文件名的总览图 `contact-sheet.png`
+    #[arg(long, requires = "once")]
+    contact_sheet: bool,
+
+    /// 一次性模式下，把每个目录中导出的图片裁掉透明边缘后排进 `atlas.png`，并按
+    /// TexturePacker 的 JSON（Hash）或通用 XML 格式写入 `atlas.json` / `atlas.xml`
+    #[arg(long, value_enum, requires = "once")]
+    atlas: Option<AtlasFormat>,
+
+    /// 一次性模式下，把所有 PSD 按路径顺序合并为一个多页文件（每个文件一页，
+    /// 可配合 `--resize` 缩小），支持 `--format pdf` 与 `--format tiff`（多页 TIFF）；
+    /// 不指定路径时写入 `combined.pdf` 或 `combined.tiff`，路径没有扩展名时按格式补上
+    #[arg(long, requires = "once", num_args = 0..=1, default_missing_value = "combined")]
+    combine: Option<PathBuf>,
+
+    /// 一次性模式下，在根目录生成可直接浏览的静态画廊 `index.html`，
+    /// 按目录分组（配合 `--thumbnail` 使用缩略图）
+    #[arg(long, requires = "once")]
+    gallery: bool,
+
+    /// 一次性模式下，把导出结果作为源文件树的镜像：删除源文件已不存在或不再生成的
+    /// 旧输出，并报告新增、更新与删除的文件。只删除此前以该模式生成并记录在
+    /// 根目录 `.psd-export-sync` 中的文件
+    #[arg(long, requires = "once", conflicts_with = "combine")]
+    sync: bool,
+
+    /// 一次性模式下，把全部导出结果（含缩略图与多目标输出）在导出完成时依次写入
+    /// 一个 zip 文件，导出的文件本身照常保留
+    #[arg(long, requires = "once", conflicts_with = "combine")]
+    archive: Option<PathBuf>,
+
+    /// 在 `--archive` 的 zip 中保留相对于根目录的目录结构，默认全部放在 zip 根目录
+    #[arg(long, requires = "archive")]
+    archive_tree: bool,
+
+    /// 一次性模式下，把内容完全相同的导出文件改为硬链接，只保存一份数据
+    #[arg(long, requires = "once", conflicts_with = "combine")]
+    dedupe: bool,
+
+    /// 一次性模式下，在根目录写入锁文件 `psd-export.lock`，记录每个输出与其源文件的
+    /// SHA-256，之后可用 `pae verify` 校验
+    #[arg(long, requires = "once", conflicts_with = "combine")]
+    lock: bool,
+
+    /// 把导出失败（时间、源文件与完整的错误链）另外追加到这个文件
+    #[arg(long)]
+    error_log: Option<PathBuf>,
+
+    /// （仅 Windows）把导出失败以及开始、结束等生命周期事件写入 Windows 事件日志
+    /// （来源为 `psd-auto-export`），作为服务运行时由监控系统收集
+    #[arg(long)]
+    event_log: bool,
+
+    /// 一次性模式结束时写入运行报告，列出每个文件的状态、错误信息、各阶段耗时以及
+    /// 源文件与输出的大小和 SHA-256；扩展名为 `.csv` 时写入 CSV，否则写入 JSON
+    #[arg(long, requires = "once", conflicts_with = "combine")]
+    report: Option<PathBuf>,
+
+    /// 一次性模式下只导出自该 git 版本（提交、分支或标签）以来有改动的源文件，
+    /// 包括尚未提交的修改
+    #[arg(long, value_name = "REV", requires = "once")]
+    changed_since: Option<String>,
+
+    /// 一次性模式下只导出 git 工作区中有未提交修改或未跟踪的源文件，
+    /// 可与 `--changed-since` 同时使用
+    #[arg(long, requires = "once")]
+    git_dirty: bool,
+
+    /// 一次性模式下只导出 git 暂存区中有修改的源文件，供 pre-commit 钩子使用；
+    /// 有文件导出失败时以非零状态退出，从而中止提交
+    #[arg(long, requires = "once")]
+    staged: bool,
+
+    /// 一次性模式下导出完成后用 `git add` 把生成的文件加入暂存区，
+    /// 使导出结果与源文件一同提交
+    #[arg(long, requires = "once", conflicts_with = "combine")]
+    git_add: bool,
+
+    /// 一次性模式下只导出在该时间之后修改过的源文件，例如 `2024-01-01`（UTC）
+    #[arg(long, value_name = "TIME", value_parser = clock::parse_time, requires = "once")]
+    since: Option<SystemTime>,
+
+    /// 一次性模式下只导出最近这段时间内修改过的源文件，例如 `7d`、`12h`
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, requires = "once")]
+    newer_than: Option<Duration>,
+
+    /// 单个文件的导出时限，例如 `300s`。超时的导出记为失败，期间运行的外部程序被结束；
+    /// 内置解码器无法中途停止，只在阶段之间检查，一个很慢的阶段会远超时限。监听模式下
+    /// 需要严格的时限时同时使用 `--isolate`，超时的子进程被直接结束
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// 隔离清单文件：导出超时的源文件记录在其中，之后不再导出，删除对应的行后恢复
+    #[arg(long, value_name = "FILE", requires = "timeout")]
+    quarantine: Option<PathBuf>,
+
+    /// 监听模式下在子进程中导出每个文件，解码器崩溃或内存耗尽时只有该文件失败，
+    /// 不会使监听进程退出
+    #[arg(long, conflicts_with = "once")]
+    isolate: bool,
+
+    /// 由 `--isolate` 启动的子进程只导出该文件
+    #[arg(long, hide = true, value_name = "FILE")]
+    isolated: Option<PathBuf>,
+
+    /// `--isolated` 的子进程改用的输出路径，由父进程按输出冲突的处理结果传入
+    #[arg(long, hide = true, value_name = "FILE", requires = "isolated")]
+    isolated_output: Option<PathBuf>,
+
+    /// 以低于正常的 CPU 优先级（以及支持时的 IO 优先级）运行导出线程，
+    /// 后台导出时不影响正在使用的绘图软件
+    #[arg(long)]
+    low_priority: bool,
+
+    /// 遇到尚未下载的 Git LFS 指针文件时先运行 `git lfs pull` 下载再导出，
+    /// 默认跳过这些文件并给出提示
+    #[arg(long)]
+    lfs_pull: bool,
+
+    /// 监听模式下每分钟最多开始导出的文件数，大量文件同时更新（如批量同步）时超出的
+    /// 文件排队等待，不会丢弃
+    #[arg(long, value_name = "N", conflicts_with = "once", value_parser = clap::value_parser!(u32).range(1..))]
+    max_per_minute: Option<u32>,
+
+    /// 监听模式下同一文件两次导出的最小间隔，例如 `30s`；间隔内的修改排队到间隔结束后导出
+    #[arg(long, value_name = "DURATION", conflicts_with = "once", value_parser = humantime::parse_duration)]
+    min_interval_per_file: Option<Duration>,
+
+    /// 监听模式下超过这段时间没有新的事件、且没有待导出的文件时自动退出，例如 `10m`
+    #[arg(long, value_name = "DURATION", conflicts_with = "once", value_parser = humantime::parse_duration)]
+    exit_after_idle: Option<Duration>,
+
+    /// 监听模式只运行这段时间，例如 `2h`：到时后不再接收新的事件，等已收到的文件导出
+    /// 完成后退出
+    #[arg(long, value_name = "DURATION", conflicts_with = "once", value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    /// 监听模式下每隔这段时间重新扫描一次监听的路径，导出输出不存在或比源文件旧的
+    /// 文件，例如 `15m`，用于补上负载较高或某些文件系统上丢失的事件
+    #[arg(long, value_name = "DURATION", conflicts_with = "once", value_parser = humantime::parse_duration)]
+    rescan_interval: Option<Duration>,
+
+    /// 监听模式开始前先导出输出不存在或比源文件旧的文件，补上程序未运行期间的修改
+    #[arg(long, conflicts_with = "once")]
+    export_existing: bool,
+
+    /// 监听列表文件：每行一个路径或通配符模式（`*`、`?`、`**`），代替 PATH 作为监听的
+    /// 路径，列表修改后自动增减监听的路径。此时 PATH 可以省略，只用于查找配置文件
+    #[arg(long, value_name = "FILE", conflicts_with = "once")]
+    watchlist: Option<PathBuf>,
+
+    /// 监听模式下显示终端界面：队列、正在导出的文件、最近的失败与吞吐量，
+    /// 可按键暂停、重试失败的文件或退出
+    #[arg(long, conflicts_with = "once")]
+    tui: bool,
+
+    /// 监听单个文件时，每次导出成功后用系统默认程序打开输出文件
+    #[arg(long, conflicts_with = "once")]
+    open: bool,
+
+    /// 用指定程序打开输出文件（隐含 `--open`），参数中的 `{output}` 替换为文件路径，
+    /// 例如 `"gimp {output}"`
+    #[arg(long, value_name = "PROGRAM", conflicts_with = "once")]
+    open_with: Option<String>,
+
+    /// 监听模式下每次导出成功后在文件管理器（资源管理器、访达等）中显示输出文件
+    #[arg(long, conflicts_with = "once")]
+    reveal: bool,
+
+    /// 作为源文件处理的扩展名，逗号分隔、不区分大小写，例如 `psd,psb,pdd`；默认为内置
+    /// 解码器支持的全部扩展名。没有内置解码器的扩展名（如 `psb`）需配合 `--fallback`
+    /// 或 `--photoshop` 使用
+    #[arg(long, value_delimiter = ',', value_name = "EXT")]
+    ext: Vec<String>,
+
+    /// 跳过小于该大小的源文件，例如 `4K`，用于排除占位文件
+    #[arg(long, value_parser = budget::parse_size, value_name = "SIZE")]
+    min_size: Option<u64>,
+
+    /// 跳过大于该大小的源文件，例如 `2G`，超大文件可以留给其它机器导出
+    #[arg(long, value_parser = budget::parse_size, value_name = "SIZE", visible_alias = "max-size-bytes")]
+    max_size: Option<u64>,
+
+    /// 一次性模式下的处理顺序，不指定时按目录遍历顺序
+    #[arg(long, value_enum, requires = "once")]
+    order: Option<Order>,
+
+    /// 一次性模式下线程池的调度策略：按处理顺序、小文件优先或大小文件交替
+    #[arg(long, value_enum, requires = "once", default_value_t = Schedule::default())]
+    schedule: Schedule,
+
+    /// 一次性模式下边扫描边导出：找到的源文件立即交给线程池，不等待扫描结束，也不在
+    /// 内存中保存完整的文件列表。需要全部源文件的同步、锁文件、图集、排序、调度、git
+    /// 筛选与合并输出不能同时使用；输出冲突时先开始导出的源文件保留原来的输出
+    #[arg(
+        long,
+        requires = "once",
+        conflicts_with_all = [
+            "sync", "lock", "atlas", "order", "schedule", "combine", "changed_since", "git_dirty",
+            "staged",
+        ]
+    )]
+    stream: bool,
+
+    /// 一次性模式下从上次被中断的运行继续，跳过根目录 `.psd-export-progress` 中记录的
+    /// 已完成的文件。进度在每个文件导出后记录，正常结束时删除；请使用与上次相同的参数。
+    /// 上次完成的文件会计入同步记录与锁文件，但不计入运行报告、画廊与总览图
+    #[arg(long, requires = "once", conflicts_with_all = ["combine", "archive"])]
+    resume: bool,
+
+    /// 总览图每行的格子数
+    #[arg(long, default_value_t = 6)]
+    sheet_columns: u32,
+
+    /// 总览图格子的边长（像素）
+    #[arg(long, default_value_t = 256)]
+    sheet_cell: u32,
+
+    /// 另外在总览图旁写入 `contact-sheet.json`，按 Aseprite 的格式（`hash` 或 `array`）
+    /// 列出每一帧在总览图中的位置
+    #[arg(long, value_enum, requires = "contact_sheet")]
+    sheet_json: Option<SheetJson>,
+
+    /// 导出前将图像顺时针旋转指定角度
+    #[arg(long, value_enum)]
+    rotate: Option<Rotation>,
+
+    /// 导出前翻转图像（h 水平，v 垂直），可重复指定，在旋转之后应用
+    #[arg(long, value_enum)]
+    flip: Vec<Flip>,
+
+    /// 缩放导出图像：`0.5x`/`2x` 按倍数，`1024` 限制最长边，`1920x1080`
+    /// 限制在范围内
+    #[arg(long, value_parser = transform::parse_resize)]
+    resize: Option<ResizeSpec>,
+
+    /// 缩放时使用的重采样滤波器
+    #[arg(long, value_enum, default_value_t = ResizeFilter::default())]
+    filter: ResizeFilter,
+
+    /// 将画布填充到指定宽高比（如 `16:9`）或固定尺寸（如 `1920x1080`，
+    /// 图像过大时先等比缩小），图像居中
+    #[arg(long, value_parser = transform::parse_pad)]
+    pad_to: Option<PadSpec>,
+
+    /// 填充区域的颜色：`transparent` 或 `#RRGGBB` / `#RRGGBBAA`
+    #[arg(long, value_parser = transform::parse_color, default_value = "transparent")]
+    pad_color: Rgba<u8>,
+
+    /// 量化为指定颜色数 (2-256) 的调色板图像；PNG 输出为索引色 PNG，
+    /// GIF 未指定时默认 256 色
+    #[arg(long, value_parser = quantize::parse_colors)]
+    colors: Option<u16>,
+
+    /// 调色板量化时使用的抖动算法
+    #[arg(long, value_enum, default_value_t = Dither::default())]
+    dither: Dither,
+
+    /// 抖动强度 (0.0-1.0)
+    #[arg(long, value_parser = quantize::parse_strength, default_value_t = 1.0)]
+    dither_strength: f32,
+
+    /// JPEG / AVIF 的压缩质量 (1-100)，默认 JPEG 75、AVIF 80
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// AVIF 编码速度，1 最慢但压缩率最高，10 最快
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=10), default_value_t = 4)]
+    avif_speed: u8,
+
+    /// PNG 的压缩级别
+    #[arg(long, value_enum, default_value_t = PngCompression::default())]
+    png_compression: PngCompression,
+
+    /// 相同的输入总是得到逐字节相同的输出：zip 与水印中的时间固定为
+    /// `SOURCE_DATE_EPOCH`（未设置时为 1970-01-01），大图 PNG 的编码方式不随 CPU
+    /// 核数变化，`--archive` 中的文件按路径排序
+    #[arg(long)]
+    deterministic: bool,
+
+    /// TIFF 的压缩方式
+    #[arg(long, value_enum, default_value_t = TiffCompression::default())]
+    tiff_compression: TiffCompression,
+
+    /// PNG / TIFF 每个通道的位数（索引色 PNG 始终为 8 位）
+    #[arg(long, value_enum, default_value_t = BitDepth::default())]
+    bit_depth: BitDepth,
+
+    /// 内置解码器无法解析源文件时调用的外部转换命令，`{input}` 替换为源文件，
+    /// `{output}` 替换为转换器应写入的临时 PNG，例如 `"magick {input}[0] {output}"`
+    /// （按空白拆分参数，不经过 shell）
+    #[arg(long)]
+    fallback: Option<String>,
+
+    /// 每个源文件导出前运行的脚本命令，脚本向标准输出写入 TOML 来跳过文件
+    /// （`skip = true`）、指定输出路径（`output = "..."`）或覆盖任意设置；
+    /// 源文件路径与图层名在环境变量 `PAE_SOURCE`、`PAE_LAYERS` 中
+    #[arg(long)]
+    script: Option<String>,
+
+    /// 导出后同时上传到该地址，支持 `{name}`、`{ext}`、`{file}`、`{date}` 占位符，
+    /// 例如 `s3://bucket/renders/{file}`、`sftp://host/delivery/{file}`（需要系统安装 curl）
+    #[arg(long)]
+    upload: Option<String>,
+
+    /// S3 区域，凭证取自 `AWS_ACCESS_KEY_ID` 与 `AWS_SECRET_ACCESS_KEY`
+    #[arg(long, default_value = "us-east-1")]
+    s3_region: String,
+
+    /// S3 兼容服务的地址（如 MinIO、Cloudflare R2），不指定时使用 AWS
+    #[arg(long)]
+    s3_endpoint: Option<String>,
+
+    /// 上传对象的预设 ACL，例如 `public-read`
+    #[arg(long)]
+    s3_acl: Option<String>,
+
+    /// SFTP、FTP 与 WebDAV 上传的用户名
+    #[arg(long)]
+    upload_user: Option<String>,
+
+    /// SFTP、FTP 与 WebDAV 上传的密码，建议写在配置文件或 `PSD_EXPORT_UPLOAD_PASSWORD` 环境变量中
+    #[arg(long)]
+    upload_password: Option<String>,
+
+    /// 上传遇到连接失败、超时等临时错误时的重试次数
+    #[arg(long, default_value_t = 3)]
+    upload_retries: u32,
+
+    /// （仅 Windows）通过 COM 调用本机安装的 Photoshop 导出包含智能对象或图层效果的
+    /// PSD，以及内置解码器无法解析的文件，结果与 Photoshop 完全一致但速度较慢
+    #[arg(long)]
+    photoshop: bool,
+
+    /// 使用 libvips 读取、缩放并编码 PSD（需要带 ImageMagick 支持的 libvips），
+    /// 设置了旋转、填充、水印等 libvips 路径不支持的处理时自动使用默认路径
+    #[cfg(feature = "vips")]
+    #[arg(long)]
+    vips: bool,
+
+    /// 同时进行的导出任务占用的内存上限，例如 `8G`、`512M`。每个任务按源文件头部
+    /// 估算所需内存，预算不足时排队等待
+    #[arg(long, value_parser = budget::parse_size)]
+    max_memory: Option<u64>,
+
+    /// 额外生成最长边不超过该像素值的缩略图 `name.thumb.<ext>`
+    #[arg(long)]
+    thumbnail: Option<u32>,
+
+    /// 在导出图像角落绘制文字水印，支持 `{name}`、`{date}`、`{time}`、`{git}` 占位符，
+    /// 例如 `"{name} {date} {git}"`（仅支持 ASCII 字符）
+    #[arg(long)]
+    stamp: Option<String>,
+
+    /// 按顺序执行的处理步骤，取代默认的旋转、翻转、缩放、填充、水印顺序，可重复指定：
+    /// `trim`、`rotate:90`、`flip:h`、`resize:2048`、`pad:16:9`、`stamp:{name}`、
+    /// `watermark:logo.png`、`plugin:命令`（交给外部程序处理）、`encode:webp`（指定输出格式）
+    #[arg(long, value_parser = pipeline::parse_step)]
+    steps: Vec<Step>,
+
+    /// 只读取 PSD 内嵌的缩略图（最长边通常为 160 像素）快速导出小图，不完整解码；
+    /// 没有内嵌缩略图的文件照常完整解码
+    #[arg(long)]
+    preview: bool,
+
+    /// 另外把 R、G、B、A 各通道分别保存为灰度 PNG（`name.r.png`、`name.g.png` 等），
+    /// 用于拆分打包在通道中的遮罩
+    #[arg(long)]
+    channels: bool,
+
+    /// 另外把透明度保存为灰度 PNG `name_alpha.png`，供视频合成软件作为遮罩使用
+    #[arg(long)]
+    export_alpha: bool,
+
+    /// 另外生成逐级缩小一半直到 1×1 的 mipmap 链：`files`（默认）保存为
+    /// `name.mip1.<ext>`、`name.mip2.<ext>` 等，`dds` 与原图一起打包为未压缩的 `name.dds`
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "files")]
+    mipmaps: Option<MipmapMode>,
+
+    /// 另外在导出文件旁写入或刷新 Unity 的导入设置 `name.png.meta`（纹理类型、sRGB、
+    /// 按不透明像素范围计算的精灵范围），已有文件的 guid 保持不变
+    #[arg(long, value_enum)]
+    unity_meta: Option<TextureType>,
+
+    /// 另外在导出文件旁写入或更新 Godot 的导入设置 `name.png.import`，
+    /// 使用 `--godot-compress` 与 `--godot-mipmaps` 指定的参数，已有文件的 uid 保持不变
+    #[arg(long)]
+    godot_import: bool,
+
+    /// Godot 导入设置中的压缩方式
+    #[arg(long, value_enum, default_value_t = Compression::default())]
+    godot_compress: Compression,
+
+    /// Godot 导入设置中生成 mipmap
+    #[arg(long)]
+    godot_mipmaps: bool,
+
+    /// 导出 ORA 时处理图层蒙版：`export` 把每个图层的蒙版另存为整幅画布大小的灰度 PNG
+    /// （`name.mask0.png` 等，序号与 ORA 中的图层对应），`apply` 把蒙版乘到图层的透明度上，
+    /// `both` 两者都做
+    #[arg(long, value_enum)]
+    layer_masks: Option<MaskMode>,
+
+    /// 多个源文件会写入同一输出时的处理方式：`rename` 让排在后面的源文件改为输出
+    /// `name-2.png` 等，`fail` 让它们导出失败。冲突在导出前（监听模式下为启动时）检查
+    #[arg(long, value_enum, default_value_t = collision::Policy::default())]
+    on_collision: collision::Policy,
+
+    /// 按文件名约定调整单个文件的设置，优先于命令行与配置文件：`icon@2x.psd` 按 2 倍
+    /// 缩放，`photo.q80.jpg.psd` 导出为质量 80 的 JPEG 文件 `photo.jpg`
+    #[arg(long)]
+    filename_options: bool,
+
+    /// 替换输出文件名中 Windows 与 macOS 上无效的字符（`:`、`?`、`/` 等）的字符串，
+    /// 文件名结尾的 `.` 与空格会被去掉，`CON` 等保留名后会加上该字符串
+    #[arg(long, value_parser = naming::parse_replacement, default_value = "_")]
+    name_replacement: String,
+
+    /// 水印所在的角落
+    #[arg(long, value_enum, default_value_t = StampPosition::default())]
+    stamp_position: StampPosition,
+
+    /// 水印字形放大倍数，不指定时根据图像尺寸自动选择
+    #[arg(long)]
+    stamp_scale: Option<u32>,
+}
+
+/// 由解析后的参数构建导出设置
+fn export_options(args: &Cli) -> ExportOptions {
+    ExportOptions {
+        format: pipeline::output_format(&args.steps).unwrap_or_else(|| args.format.clone()),
+        rotate: args.rotate,
+        flip: args.flip.clone(),
+        resize: args.resize,
+        filter: args.filter,
+        pad_to: args.pad_to,
+        pad_color: args.pad_color,
+        encode: EncodeOptions {
+            quantize: args.colors.map(|colors| QuantizeOptions {
+                colors,
+                dither: args.dither,
+                strength: args.dither_strength,
+            }),
+            png: PngOptions {
+                compression: args.png_compression,
+                bit_depth: args.bit_depth,
+            },
+            jpeg: JpegOptions {
+                quality: args.quality.unwrap_or(JpegOptions::default().quality),
+            },
+            avif: AvifOptions {
+                quality: args.quality.unwrap_or(AvifOptions::default().quality),
+                speed: args.avif_speed,
+            },
+            tiff: TiffOptions {
+                compression: args.tiff_compression,
+                bit_depth: args.bit_depth,
+            },
+            deterministic: args.deterministic,
+        },
+        stamp: args.stamp.clone().map(|template| Stamp {
+            template,
+            position: args.stamp_position,
+            scale: args.stamp_scale,
+        }),
+        steps: args
+            .steps
+            .iter()
+            .map(|step| step.clone().placed(args.stamp_position, args.stamp_scale))
+            .collect(),
+        thumbnail: args.thumbnail,
+        sheet: args.contact_sheet.then_some(SheetOptions {
+            columns: args.sheet_columns.max(1),
+            cell: args.sheet_cell.max(16),
+            json: args.sheet_json,
+        }),
+        fallback: args.fallback.clone(),
+        photoshop: args.photoshop,
+        #[cfg(feature = "vips")]
+        vips: args.vips,
+        memory: args
+            .max_memory
+            .map(|limit| Arc::new(MemoryBudget::new(limit))),
+        targets: Vec::new(),
+        script: args.script.clone(),
+        upload: args.upload.clone().map(|url| Upload {
+            url,
+            s3: S3Options {
+                region: args.s3_region.clone(),
+                endpoint: args.s3_endpoint.clone(),
+                acl: args.s3_acl.clone(),
+            },
+            user: args.upload_user.clone(),
+            password: args.upload_password.clone(),
+            retries: args.upload_retries,
+        }),
+        output: None,
+        name_replacement: args.name_replacement.clone(),
+        extensions: args
+            .ext
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect(),
+        min_size: args.min_size,
+        max_size: args.max_size,
+        timeout: args.timeout,
+        preview: args.preview,
+        channels: args.channels,
+        export_alpha: args.export_alpha,
+        mipmaps: args.mipmaps,
+        unity_meta: args.unity_meta,
+        godot_import: args.godot_import.then_some(ImportSettings {
+            compression: args.godot_compress,
+            mipmaps: args.godot_mipmaps,
+        }),
+        layer_masks: args.layer_masks,
+    }
 }
 
 fn main() -> Result<()> {
-    _ = pretty_env_logger::formatted_builder()
-        .filter_level(LevelFilter::Info)
-        .format_timestamp_secs()
-        .parse_default_env()
-        .try_init();
+    let mut builder = pretty_env_logger::formatted_builder();
+    if std::env::var_os(isolate::ENV).is_some() {
+        // 导出子进程的日志由父进程转发，只保留警告与错误
+        builder
+            .filter_level(LevelFilter::Warn)
+            .format(|buf, record| writeln!(buf, "{}\t{}", record.level(), record.args()));
+    } else {
+        builder
+            .filter_level(LevelFilter::Info)
+            .format_timestamp_secs();
+    }
+    let logger = builder.parse_default_env().build();
+    let max_level = logger.filter();
+    dashboard::install_logger(Box::new(logger), max_level);
 
     // 解析命令行参数
-    let args = Cli::parse();
-    let watch_path = args.path;
-    let export_format = args.format;
+    let (args, argv, document) = parse_args()?;
+    match &args.command {
+        Some(Command::Config { action }) => return run_config(action),
+        Some(Command::Verify { path }) => return run_verify(path),
+        Some(Command::Validate { path }) => return run_validate(path),
+        Some(Command::Diff {
+            a,
+            b,
+            output,
+            threshold,
+        }) => return run_diff(a, b, output, *threshold),
+        None => {}
+    }
+    let watch_path = args.watch_path().to_path_buf();
+    let config_file = config_location(&args);
+    let mut debounce = args.debounce;
     let run_once = args.once;
+    // 导出本身的 panic 已转换为错误，这里处理其余代码中的 panic：默认会使整个进程退出
+    let low_priority = args.low_priority;
+    rayon::ThreadPoolBuilder::new()
+        .start_handler(move |_| {
+            if low_priority {
+                priority::lower_current_thread();
+            }
+        })
+        .panic_handler(|payload| {
+            error!(
+                "导出线程发生内部错误（panic）：{}",
+                panic_message(payload.as_ref())
+            )
+        })
+        .build_global()
+        .context("无法创建导出线程池")?;
+    let mut settings = Arc::new(Settings::new(argv, &args, &watch_path, document)?);
+    let options = settings.options.clone();
+
+    if let Some(psd_path) = &args.isolated {
+        let result = settings.options_for(psd_path).and_then(|options| {
+            options
+                .map(|mut options| {
+                    if let Some(output) = &args.isolated_output {
+                        options.output = Some(output.clone());
+                    }
+                    process_psd_file(psd_path, &options)
+                })
+                .transpose()
+        });
+        std::process::exit(isolate::report(&result));
+    }
+
+    if watch_path == Path::new(STDIN_PATH) {
+        return export_stdin(args.stdout, args.output.as_deref(), &options);
+    }
+
+    if let Some(path) = &args.error_log {
+        errorlog::set(path);
+    }
 
     // 检查监听路径是否存在
     if !watch_path.exists() {
@@ -93,199 +812,1734 @@ fn main() -> Result<()> {
         std::process::exit(1);
     }
 
+    if options.photoshop && !cfg!(windows) {
+        error!("错误：--photoshop 仅支持 Windows");
+        std::process::exit(1);
+    }
+
+    if args.event_log {
+        if !cfg!(windows) {
+            error!("错误：--event-log 仅支持 Windows");
+            std::process::exit(1);
+        }
+        if let Err(e) = eventlog::open() {
+            warn!("{:#}", e);
+        }
+    }
+
+    if options.layer_masks.is_some() && !matches!(options.format, ExportFormat::Ora) {
+        warn!("--layer-masks 只在导出 ORA 格式时生效");
+    }
+
+    if matches!(options.format, ExportFormat::Ora) && options.has_transforms() {
+        warn!("ORA 格式保留原始图层，旋转、缩放、填充、量化、水印与缩略图设置将被忽略");
+    }
+
+    // 检查合并输出与格式是否匹配
+    if args.combine.is_some() && !matches!(options.format, ExportFormat::Pdf | ExportFormat::Tiff) {
+        error!("错误：--combine 不支持 {:?} 格式", options.format);
+        std::process::exit(1);
+    }
+
+    if args.stdout || args.output.is_some() {
+        return export_single(&watch_path, &settings, args.output.as_deref());
+    }
+
     // 如果是一次性模式
     if run_once {
         info!("以一次性模式运行，导出现有文件...");
-        let psd_files = find_psd_files(&watch_path)?;
-        info!("找到 {} 个 .psd 文件。", psd_files.len());
+        // 同时设置时取较晚的时间
+        let cutoff = [
+            args.since,
+            args.newer_than
+                .and_then(|age| SystemTime::now().checked_sub(age)),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        // 边扫描边导出时源文件在导出过程中逐批找到并筛选
+        let (sources, psd_files) = if args.stream {
+            (Vec::new(), Vec::new())
+        } else {
+            // 同步与锁文件需要全部源文件，只导出 git 报告有改动的部分时也是如此
+            let sources = find_psd_files(&watch_path, &options)?;
+            info!("找到 {} 个源文件。", sources.len());
+            settings.detect_collisions(&sources);
+            let psd_files = if args.changed_since.is_some() || args.git_dirty || args.staged {
+                let changed = git::changed_files(
+                    &watch_path,
+                    args.changed_since.as_deref(),
+                    args.git_dirty,
+                    args.staged,
+                )?;
+                let psd_files: Vec<PathBuf> = sources
+                    .iter()
+                    .filter(|path| changed.contains(&absolute(path)))
+                    .cloned()
+                    .collect();
+                info!("其中 {} 个有 git 改动。", psd_files.len());
+                psd_files
+            } else {
+                sources.clone()
+            };
+            let psd_files = match cutoff {
+                Some(cutoff) => {
+                    let psd_files: Vec<PathBuf> = psd_files
+                        .into_iter()
+                        .filter(|path| modified_after(path, cutoff))
+                        .collect();
+                    info!(
+                        "其中 {} 个在 {} 之后修改过。",
+                        psd_files.len(),
+                        humantime::format_rfc3339_seconds(cutoff)
+                    );
+                    psd_files
+                }
+                None => psd_files,
+            };
+            (sources, exclude_unavailable(psd_files, &args))
+        };
 
-        if psd_files.is_empty() {
-            info!("没有找到需要导出的 .psd 文件。");
+        if psd_files.is_empty() && !args.stream {
+            info!("没有找到需要导出的源文件。");
+            // 源文件全部删除后，之前的输出同样需要清理
+            if args.sync {
+                sync_outputs(&watch_path, &sources, &[])?;
+            }
+            if args.lock {
+                lock::write(output_root(&watch_path), &sources, &[])?;
+            }
+            if let Some(path) = &args.report {
+                report::write(path, &[])?;
+            }
+        } else if let Some(combined_path) = &args.combine {
+            let mut combined_path = combined_path.clone();
+            if combined_path.extension().is_none() {
+                combined_path.set_extension(options.format.extension());
+            }
+            let mut psd_files = psd_files;
+            psd_files.sort();
+            let count = if let ExportFormat::Tiff = options.format {
+                write_combined_tiff(&combined_path, &psd_files, &settings)?
+            } else {
+                // 合并模式：并行渲染并压缩每一页，再按路径顺序写入同一个文件
+                let pages: Vec<pdf::Page> = budget::map(
+                    settings.options.memory.as_ref(),
+                    psd_files.iter(),
+                    |psd_path| budget::estimate(psd_path),
+                    |psd_path| {
+                        let rendered = render_page(&settings, psd_path)?;
+                        match pdf::Page::new(&rendered.image, rendered.dpi) {
+                            Ok(page) => Some(page),
+                            Err(e) => {
+                                error!("渲染文件失败 {:?}: 无法压缩 PDF 页面数据：{}", psd_path, e);
+                                record_failure(
+                                    psd_path,
+                                    &anyhow::Error::new(e).context("无法压缩 PDF 页面数据"),
+                                );
+                                None
+                            }
+                        }
+                    },
+                )
+                .into_iter()
+                .flatten()
+                .collect();
+                write_combined_pdf(&combined_path, &pages)?;
+                pages.len()
+            };
+            info!("已合并 {} 页到：{:?}", count, combined_path);
         } else {
-            // 使用 rayon 的并行迭代器处理文件
-            psd_files.par_iter().for_each(|psd_path| {
+            // 使用 rayon 的并行迭代器处理文件。par_bridge 按顺序取出任务，
+            // 使开始导出的顺序与 `--order` 一致
+            let mut psd_files = psd_files;
+            if let Some(order) = args.order {
+                order::sort(&mut psd_files, order);
+            }
+            let psd_files = order::schedule(psd_files, args.schedule);
+            let archive = args
+                .archive
+                .as_deref()
+                .map(|path| {
+                    let root = output_root(&watch_path);
+                    Archive::create(path, root, args.archive_tree, args.deterministic)
+                })
+                .transpose()?;
+            let checkpoint = match Checkpoint::open(output_root(&watch_path), args.resume) {
+                Ok(checkpoint) => Some(checkpoint),
+                Err(e) if !args.resume => {
+                    warn!("{:#}，中断后将无法继续", e);
+                    None
+                }
+                Err(e) => return Err(e),
+            };
+            let resumed = AtomicUsize::new(0);
+            let failed = AtomicUsize::new(0);
+            let entries = Mutex::new(Vec::new());
+            let record = |entry| {
+                if args.report.is_some() {
+                    entries.lock().unwrap().push(entry);
+                }
+            };
+            let export = |index: usize, psd_path: PathBuf| -> Option<(usize, PathBuf, Exported)> {
+                let psd_path = &psd_path;
+                if checkpoint.as_ref().is_some_and(|c| c.is_done(psd_path)) {
+                    resumed.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
                 info!("正在导出文件：{:?}", psd_path);
-                match process_psd_file(psd_path, &export_format) {
-                    Ok(_) => info!(
-                        "成功导出：{:?} -> {:?}",
-                        psd_path,
-                        psd_path.with_extension(export_format.extension())
-                    ),
-                    Err(e) => error!("导出文件失败 {:?}: {}", psd_path, e),
+                if args.stream {
+                    settings.claim_outputs(psd_path);
                 }
-            });
+                match settings.options_for(psd_path).and_then(|options| {
+                    options
+                        .map(|options| process_psd_file(psd_path, &options))
+                        .transpose()
+                }) {
+                    Ok(None) => {
+                        info!("脚本要求跳过：{:?}", psd_path);
+                        record(report::Entry::skipped(psd_path));
+                        if let Some(checkpoint) = &checkpoint {
+                            checkpoint.record(psd_path, &[]);
+                        }
+                        None
+                    }
+                    Ok(Some(exported)) => {
+                        info!(
+                            "成功导出：{:?} -> {:?}{}{}，{}",
+                            psd_path,
+                            exported.output_path,
+                            extra_outputs_note(&exported),
+                            fallback_note(&exported),
+                            exported.timings
+                        );
+                        record(report::Entry::exported(psd_path, &exported));
+                        if let Some(archive) = &archive {
+                            for file in exported.files() {
+                                if let Err(e) = archive.add(&file) {
+                                    error!("写入 zip 失败 {:?}: {}", file, e);
+                                }
+                            }
+                        }
+                        if let Some(checkpoint) = &checkpoint {
+                            checkpoint.record(psd_path, &exported.files());
+                        }
+                        Some((index, psd_path.clone(), exported))
+                    }
+                    Err(e) => {
+                        error!("导出文件失败 {:?}: {}", psd_path, e);
+                        record_failure(psd_path, &e);
+                        record(report::Entry::failed(psd_path, &e));
+                        quarantine_timed_out(args.quarantine.as_deref(), psd_path, &e);
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        None
+                    }
+                }
+            };
+            // 上次已完成的文件直接跳过，不需要等待内存预算
+            let estimate = |(_, psd_path): &(usize, PathBuf)| {
+                if checkpoint.as_ref().is_some_and(|c| c.is_done(psd_path)) {
+                    0
+                } else {
+                    budget::estimate(psd_path)
+                }
+            };
+            let mut exported: Vec<(usize, PathBuf, Exported)> = if args.stream {
+                export_streamed(&watch_path, &args, &settings, cutoff, &estimate, &export)?
+            } else {
+                budget::map(
+                    settings.options.memory.as_ref(),
+                    psd_files.into_iter().enumerate(),
+                    estimate,
+                    |(index, psd_path)| export(index, psd_path),
+                )
+                .into_iter()
+                .flatten()
+                .collect()
+            };
+            exported.sort_by_key(|(index, _, _)| *index);
+            let (psd_files, exported): (Vec<PathBuf>, Vec<Exported>) = exported
+                .into_iter()
+                .map(|(_, psd_path, exported)| (psd_path, exported))
+                .unzip();
+            let resumed = resumed.into_inner();
+            if resumed > 0 {
+                info!("跳过了 {} 个上次已完成的文件。", resumed);
+            }
             info!("一次性导出完成。");
-        }
-        Ok(()) // 一次性模式完成后退出
-    } else {
-        // 持续监听模式
+            eventlog::report(
+                Event::Lifecycle,
+                &format!(
+                    "一次性导出完成：{:?}，成功 {} 个，失败 {} 个",
+                    absolute(&watch_path),
+                    exported.len(),
+                    failed.load(Ordering::Relaxed)
+                ),
+            );
+            if let (Some(archive), Some(path)) = (archive, &args.archive) {
+                let count = archive.finish()?;
+                info!("已打包 {} 个文件到：{:?}", count, path);
+            }
+
+            // 列出最慢的几个文件及其瓶颈阶段，便于找出异常的 PSD
+            if exported.len() > 1 {
+                let mut slowest: Vec<&Exported> = exported.iter().collect();
+                slowest.sort_by_key(|e| std::cmp::Reverse(e.timings.total()));
+                info!("耗时最长的文件：");
+                for e in slowest.iter().take(SLOWEST_FILES) {
+                    info!(
+                        "  {:?}：{}，瓶颈：{}",
+                        e.output_path,
+                        e.timings,
+                        e.timings.bottleneck()
+                    );
+                }
+            }
 
-        // 根据路径类型确定监听模式
-        let recursive_mode = if watch_path.is_dir() {
-            info!("开始递归监听目录：{:?}", watch_path);
-            RecursiveMode::Recursive
-        } else if watch_path.is_file() {
-            // 如果是文件，检查是否是 .psd 文件
-            if watch_path.extension().and_then(|ext| ext.to_str()) != Some("psd") {
-                error!(
-                    "错误：指定的路径是一个文件，但不是 .psd 文件：{:?}",
-                    watch_path
+            let fallbacks: Vec<&Exported> = exported.iter().filter(|e| e.fallback).collect();
+            if !fallbacks.is_empty() {
+                warn!("其中 {} 个文件由外部转换器导出：", fallbacks.len());
+                for e in fallbacks {
+                    warn!("  {:?}", e.output_path);
+                }
+            }
+
+            if args.sync {
+                let outputs: Vec<(PathBuf, Vec<PathBuf>)> = psd_files
+                    .iter()
+                    .zip(&exported)
+                    .map(|(psd_path, e)| (psd_path.clone(), e.files()))
+                    .chain(checkpoint.iter().flat_map(Checkpoint::previous))
+                    .collect();
+                sync_outputs(&watch_path, &sources, &outputs)?;
+            }
+
+            if args.dedupe {
+                let files: Vec<PathBuf> = exported.iter().flat_map(Exported::files).collect();
+                let report = dedupe::dedupe(&files)?;
+                info!(
+                    "去重完成：{} 个文件改为硬链接，节省 {:.1} MiB",
+                    report.linked,
+                    report.saved as f64 / (1 << 20) as f64
+                );
+            }
+
+            if args.lock {
+                let outputs: Vec<(PathBuf, Vec<PathBuf>)> = psd_files
+                    .iter()
+                    .zip(&exported)
+                    .map(|(psd_path, e)| (psd_path.clone(), e.files()))
+                    .chain(checkpoint.iter().flat_map(Checkpoint::previous))
+                    .collect();
+                let root = output_root(&watch_path);
+                let count = lock::write(root, &sources, &outputs)?;
+                info!(
+                    "已写入锁文件：{:?}（{} 个输出）",
+                    root.join(lock::LOCK_NAME),
+                    count
                 );
+            }
+
+            if args.git_add && !exported.is_empty() {
+                let files: Vec<PathBuf> = exported
+                    .iter()
+                    .flat_map(Exported::files)
+                    .map(|file| absolute(&file))
+                    .collect();
+                git::add(output_root(&watch_path), &files)?;
+                info!("已将 {} 个导出文件加入 git 暂存区", files.len());
+            }
+
+            if args.gallery && !exported.is_empty() {
+                let root = output_root(&watch_path);
+                let entries: Vec<GalleryEntry> = exported
+                    .iter()
+                    .map(|e| GalleryEntry {
+                        image: e.output_path.clone(),
+                        thumbnail: e.thumbnail_path.clone(),
+                    })
+                    .collect();
+                let path = gallery::write_gallery(root, &entries)?;
+                info!("已生成画廊页面：{:?}", path);
+            }
+
+            if let Some(format) = args.atlas {
+                // 包括本次没有重新导出的文件，图集始终完整
+                let images: Vec<PathBuf> = sources
+                    .iter()
+                    .filter_map(|path| settings.options_for(path).ok().flatten().map(|o| (path, o)))
+                    .filter_map(|(path, options)| options.outputs(path).into_iter().next())
+                    .filter(|output| output.is_file())
+                    .collect();
+                for path in atlas::write_atlases(&images, format)? {
+                    info!("已生成图集：{:?}", path);
+                }
+            }
+
+            if let Some(sheet) = &options.sheet {
+                let previews: Vec<(PathBuf, RgbaImage)> = exported
+                    .into_iter()
+                    .filter_map(|e| e.preview.map(|p| (e.output_path, p)))
+                    .collect();
+                if !previews.is_empty() {
+                    for path in sheet::write_contact_sheets(previews, sheet)? {
+                        info!("已生成总览图：{:?}", path);
+                    }
+                }
+            }
+
+            if let Some(path) = &args.report {
+                report::write(path, &entries.into_inner().unwrap())?;
+                info!("已写入运行报告：{:?}", path);
+            }
+
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.finish()?;
+            }
+
+            let failed = failed.into_inner();
+            if args.staged && failed > 0 {
+                error!("{} 个文件导出失败，中止提交", failed);
                 std::process::exit(1);
             }
-            info!("开始监听单个文件：{:?}", watch_path);
-            RecursiveMode::NonRecursive // 监听单个文件不需要递归
-        } else {
-            // 既不是文件也不是目录，报错退出
-            error!("错误：指定的路径既不是文件也不是目录：{:?}", watch_path);
-            std::process::exit(1);
-        };
+        }
+        Ok(()) // 一次性模式完成后退出
+    } else {
+        // 持续监听模式
 
         // 创建一个通道用于接收文件系统事件
         let (tx, rx) = mpsc::channel();
 
-        // 创建一个文件系统监听器
+        // 监听列表中的路径使用单独的监听器，增减时不影响配置文件所在目录的监听
+        let mut roots = Roots::new(tx.clone())?;
+        let mut watchlist_text = None;
+        if let Some(list) = &args.watchlist {
+            watchlist_text = std::fs::read_to_string(list).ok();
+            let paths = watchlist::load(list)?;
+            info!("使用监听列表 {:?}，共 {} 个路径", list, paths.len());
+            roots.set(paths);
+        } else {
+            // 目录递归监听，单个文件不需要递归
+            if watch_path.is_dir() {
+                info!("开始递归监听目录：{:?}", watch_path);
+            } else if watch_path.is_file() {
+                // 如果是文件，检查是否是支持的源文件
+                if !is_source_file(&watch_path, &options) {
+                    error!(
+                        "错误：指定的路径是一个文件，但不是支持的源文件（{}）：{:?}",
+                        if options.extensions.is_empty() {
+                            source::extensions().join(" / ")
+                        } else {
+                            options.extensions.join(" / ")
+                        },
+                        watch_path
+                    );
+                    std::process::exit(1);
+                }
+                info!("开始监听单个文件：{:?}", watch_path);
+            } else {
+                // 既不是文件也不是目录，报错退出
+                error!("错误：指定的路径既不是文件也不是目录：{:?}", watch_path);
+                std::process::exit(1);
+            }
+            roots.watch(&watch_path)?;
+        }
+
+        // 配置文件与监听列表所在的目录由另一个监听器监听
         let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())
             .context("无法创建文件系统监听器")?;
 
-        // 开始监听指定的路径，根据类型使用不同的模式
-        watcher
-            .watch(&watch_path, recursive_mode)
-            .context(format!("无法监听路径：{:?}", watch_path))?;
+        // 根配置文件不在递归监听范围内时，单独监听它所在的目录（编辑器保存时
+        // 常常先删除再重建文件，直接监听文件本身会丢失后续事件）
+        let config_dir = config_file
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let covered = args.watchlist.is_none()
+            && watch_path.is_dir()
+            && absolute(config_dir).starts_with(absolute(&watch_path));
+        if !covered && config_dir.is_dir() {
+            watcher
+                .watch(&longpath::extended(config_dir), RecursiveMode::NonRecursive)
+                .context(format!("无法监听配置文件所在目录：{:?}", config_dir))?;
+        }
+        // 监听列表同样监听所在目录，与配置文件在同一目录时重复监听没有影响
+        if let Some(list) = &args.watchlist {
+            let list_dir = list
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            watcher
+                .watch(&longpath::extended(list_dir), RecursiveMode::NonRecursive)
+                .context(format!("无法监听监听列表所在目录：{:?}", list_dir))?;
+        }
+        let mut config_text = std::fs::read_to_string(&config_file).ok();
+        hangup::install();
 
-        info!("监听器已启动。等待 .psd 文件创建或修改...");
-        info!("导出格式：{:?}", export_format);
-        info!("防抖间隔设置为：{:?}", DEBOUNCE_DURATION);
+        let dashboard = args
+            .tui
+            .then(|| Dashboard::new(&absolute(&watch_path), format!("{:?}", options.format)));
+        let _terminal = dashboard.as_ref().map(dashboard::start);
+        let open = (args.open || args.open_with.is_some()).then(|| args.open_with.clone());
+        if open.is_some() && !watch_path.is_file() {
+            warn!("--open 只在监听单个文件时生效");
+        }
+        let context = Arc::new(WatchContext {
+            lfs_pull: args.lfs_pull,
+            open: open.filter(|_| watch_path.is_file()),
+            reveal: args.reveal,
+            quarantine: args.quarantine.clone(),
+            isolate: args.isolate,
+            deferred: Mutex::new(Vec::new()),
+            running: AtomicUsize::new(0),
+            dashboard: dashboard.clone(),
+        });
+
+        info!("监听器已启动。等待源文件创建或修改...");
+        eventlog::report(
+            Event::Lifecycle,
+            &format!("开始监听：{:?}", absolute(&watch_path)),
+        );
+        info!("导出格式：{:?}", options.format);
+        info!("防抖间隔设置为：{:?}", debounce);
+
+        // 记录每个文件上次导出的时间
+        let mut debouncer = Debouncer::new(debounce);
+        let mut coalescer = Coalescer::new(args.coalesce);
+        let mut throttle = Throttle::new(
+            args.max_per_minute.map(|max| max as usize),
+            args.min_interval_per_file,
+        );
+
+        // 启动时检查已有源文件的输出冲突，之后新增的文件不再检查
+        let sources: Vec<PathBuf> = roots
+            .paths()
+            .iter()
+            .flat_map(|root| find_psd_files(root, &options).unwrap_or_default())
+            .collect();
+        settings.detect_collisions(&sources);
 
-        // 使用 Arc<Mutex<HashMap>>
-        // 来存储每个文件上次导出的时间，以便在多个线程间安全共享
-        let last_processed_times: Arc<Mutex<HashMap<PathBuf, Instant>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+        // 最近一次收到源文件事件的时间
+        let mut last_event = Instant::now();
+        if args.export_existing {
+            let stale = stale_files(roots.paths(), &settings);
+            info!("启动前有 {} 个源文件需要导出", stale.len());
+            for path in stale {
+                throttle.push(path);
+            }
+        }
+
+        let started = Instant::now();
+        let mut rescanned_at = Instant::now();
+        // 已到 `--duration` 的时间，只等待剩余的导出完成
+        let mut draining = false;
 
         // 在主线程中导出接收到的事件
-        for res in rx {
+        loop {
+            if let Some(dashboard) = &dashboard {
+                if dashboard.quit_requested() {
+                    break;
+                }
+                for path in dashboard.take_pending() {
+                    throttle.push(path);
+                }
+            }
+            for path in roots.check(&settings.options) {
+                if draining {
+                    continue;
+                }
+                last_event = Instant::now();
+                throttle.push(path);
+            }
+            if let Some(interval) = args.rescan_interval
+                && rescanned_at.elapsed() >= interval
+                && !draining
+            {
+                let stale = stale_files(roots.paths(), &settings);
+                if !stale.is_empty() {
+                    info!("定期扫描发现 {} 个需要重新导出的源文件", stale.len());
+                    last_event = Instant::now();
+                }
+                for path in stale {
+                    throttle.push(path);
+                }
+                rescanned_at = Instant::now();
+            }
+            for path in coalescer.take() {
+                // 临时文件重命名后，事件中的部分路径可能已不存在
+                if !path.is_file() {
+                    continue;
+                }
+                // 如果距离上次导出时间小于防抖间隔，则忽略此事件
+                if !debouncer.ready(&path) {
+                    info!("文件 {:?} 在防抖间隔内，忽略事件。", path);
+                    continue; // 跳过当前路径的导出
+                }
+                info!("检测到源文件事件：{:?}", path);
+
+                match &dashboard {
+                    Some(dashboard) if dashboard.paused() => dashboard.hold(&path),
+                    _ => throttle.push(path),
+                }
+            }
+            for path in throttle.take_ready() {
+                // 新的事件取代仍在等待的推迟任务
+                context.deferred.lock().unwrap().retain(|d| d.path != path);
+                spawn_export(path, 0, None, &settings, &context);
+            }
+            for deferred in context.take_due() {
+                spawn_export(
+                    deferred.path,
+                    deferred.attempt,
+                    deferred.stamp,
+                    &settings,
+                    &context,
+                );
+            }
+            let drained = coalescer.is_empty() && throttle.is_empty() && context.is_idle();
+            if let Some(idle) = args.exit_after_idle
+                && last_event.elapsed() >= idle
+                && drained
+            {
+                info!(
+                    "已有 {} 没有新的事件，退出监听",
+                    humantime::format_duration(idle)
+                );
+                break;
+            }
+            if !draining
+                && let Some(duration) = args.duration
+                && started.elapsed() >= duration
+            {
+                info!(
+                    "已运行 {}，不再接收新的事件，等待剩余的导出完成",
+                    humantime::format_duration(duration)
+                );
+                draining = true;
+            }
+            if draining && drained {
+                info!("剩余的导出已完成，退出监听");
+                break;
+            }
+            // 定时醒来检查 SIGHUP
+            // 推迟的任务到期时也要醒来
+            let timeout = coalescer.timeout(HANGUP_POLL_INTERVAL);
+            let timeout = context.next_due().map_or(timeout, |due| due.min(timeout));
+            let res = match rx.recv_timeout(timeout) {
+                Ok(res) => res,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if hangup::take() {
+                        info!("收到 SIGHUP，重新加载配置");
+                        config_text = std::fs::read_to_string(&config_file).ok();
+                        reload_settings(&mut settings, &mut debounce, &watch_path);
+                        debouncer.set_interval(debounce);
+                        if let Some(list) = &args.watchlist {
+                            watchlist_text = std::fs::read_to_string(list).ok();
+                            reload_watchlist(list, &mut roots);
+                        }
+                    }
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
             match res {
                 Ok(event) => {
+                    // 事件队列溢出等情况下丢失了事件，需要重新扫描
+                    if event.need_rescan() {
+                        roots.mark_failed();
+                    }
                     // 只处理创建和修改事件
                     if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
                         // 遍历事件中涉及的所有路径
                         for path in event.paths {
-                            // 检查路径是否是文件且以 .psd 结尾
-                            if path.is_file()
-                                && path.extension().and_then(|ext| ext.to_str()) == Some("psd")
+                            if same_file(&path, &config_file) {
+                                // 一次保存会产生多个事件，内容不变时不重复加载；
+                                // 空文件通常是编辑器截断后尚未写完
+                                let text = std::fs::read_to_string(&config_file)
+                                    .ok()
+                                    .filter(|text| !text.trim().is_empty());
+                                if text.is_some() && text != config_text {
+                                    info!("配置文件已修改：{:?}", config_file);
+                                    config_text = text;
+                                    reload_settings(&mut settings, &mut debounce, &watch_path);
+                                    debouncer.set_interval(debounce);
+                                }
+                                continue;
+                            }
+                            if let Some(list) = &args.watchlist
+                                && same_file(&path, list)
                             {
-                                // 获取当前时间
-                                let now = Instant::now();
-
-                                // 获取互斥锁，访问 last_processed_times map
-                                let mut map = last_processed_times.lock().unwrap();
-
-                                // 检查该文件上次导出的时间
-                                if let Some(last_time) = map.get(&path) {
-                                    // 如果距离上次导出时间小于防抖间隔，则忽略此事件
-                                    if now.duration_since(*last_time) < DEBOUNCE_DURATION {
-                                        info!("文件 {:?} 在防抖间隔内，忽略事件。", path);
-                                        continue; // 跳过当前路径的导出
-                                    }
+                                // 与配置文件相同，空文件视为尚未写完
+                                let text = std::fs::read_to_string(list)
+                                    .ok()
+                                    .filter(|text| !text.trim().is_empty());
+                                if text.is_some() && text != watchlist_text {
+                                    info!("监听列表已修改：{:?}", list);
+                                    watchlist_text = text;
+                                    reload_watchlist(list, &mut roots);
                                 }
-
-                                // 如果是第一次导出，或者距离上次导出时间已超过防抖间隔
-                                info!("检测到 .psd 文件事件：{:?}", path);
-
-                                // 更新该文件的导出时间
-                                map.insert(path.clone(), now);
-
-                                // 释放互斥锁，避免在导出过程中阻塞其他事件的导出
-                                drop(map);
-
-                                // 克隆路径和格式参数，因为新线程需要拥有它们
-                                let psd_path_clone = path.clone();
-                                let export_format_clone = export_format.clone();
-
-                                // 在新线程中处理 PSD 到 PNG 的转换
-                                thread::spawn(move || {
-                                    std::thread::sleep(Duration::from_millis(10)); // 避免 psd 还未写入就开始读取，然后失败。
-                                    info!("正在导出文件：{:?}", psd_path_clone);
-                                    match process_psd_file(&psd_path_clone, &export_format_clone) {
-                                        Ok(_) => info!(
-                                            "成功导出：{:?} -> {:?}",
-                                            psd_path_clone,
-                                            psd_path_clone
-                                                .with_extension(export_format_clone.extension())
-                                        ),
-                                        Err(e) => {
-                                            error!("导出文件失败 {:?}: {}", psd_path_clone, e)
-                                        }
-                                    }
-                                });
+                                continue;
+                            }
+                            // 旁配置文件修改后重新导出对应的源文件
+                            let path = config::sidecar_source(&path)
+                                .filter(|source| is_source_file(source, &settings.options))
+                                .unwrap_or(path);
+                            // 检查路径是否是文件且是支持的源文件
+                            if !draining
+                                && path.is_file()
+                                && is_source_file(&path, &settings.options)
+                            {
+                                last_event = Instant::now();
+                                coalescer.push(path);
                             }
                         }
                     }
                 }
-                Err(e) => error!("监听事件错误：{}", e),
+                Err(e) => {
+                    error!("监听事件错误：{}", e);
+                    roots.mark_failed();
+                }
             }
         }
 
         // 如果 rx 循环结束（通常不会发生，除非监听器停止），程序退出
         info!("监听器停止。");
+        eventlog::report(
+            Event::Lifecycle,
+            &format!("监听结束：{:?}", absolute(&watch_path)),
+        );
 
         Ok(())
     }
 }
 
-/// 查找指定路径下的所有 .psd 文件（如果是目录则递归查找）
-fn find_psd_files(path: &Path) -> Result<Vec<PathBuf>> {
-    let mut psd_files = Vec::new();
+/// 监听的路径下输出不存在或比源文件旧的源文件，按各文件适用的设置判断
+fn stale_files<'a>(
+    roots: impl IntoIterator<Item = &'a PathBuf>,
+    settings: &Settings,
+) -> Vec<PathBuf> {
+    roots
+        .into_iter()
+        .flat_map(|root| find_psd_files(root, &settings.options).unwrap_or_default())
+        .filter(|path| {
+            settings
+                .options_for(path)
+                .is_ok_and(|options| options.is_some_and(|options| options.is_stale(path)))
+        })
+        .collect()
+}
+
+/// 重新读取监听列表并更新监听的路径，读取失败时保持不变
+fn reload_watchlist(list: &Path, roots: &mut Roots) {
+    match watchlist::load(list) {
+        Ok(paths) => roots.set(paths),
+        Err(e) => error!("重新读取监听列表失败，保留当前监听的路径：{:#}", e),
+    }
+}
+
+/// 从标准输入读取源文件时使用的路径参数
+const STDIN_PATH: &str = "-";
+
+/// 标准输入的内容按 PSD 解码，这个名称只用于选择解码器、水印与日志
+const STDIN_NAME: &str = "stdin.psd";
 
-    if path.is_file() {
-        if path.extension().and_then(|ext| ext.to_str()) == Some("psd") {
-            psd_files.push(path.to_path_buf());
+/// 路径为 `-`：从标准输入读取 PSD，在内存中导出后写入 `output`，或在 `stdout` 时写入
+/// 标准输出，全程不创建临时文件
+fn export_stdin(stdout: bool, output: Option<&Path>, options: &ExportOptions) -> Result<()> {
+    if !stdout && output.is_none() {
+        anyhow::bail!("从标准输入读取时需要用 --stdout 或 -o 指定输出");
+    }
+    if !options.targets.is_empty() {
+        anyhow::bail!("从标准输入读取时不能使用配置文件中的 [[target]]");
+    }
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut bytes)
+        .context("无法读取标准输入")?;
+    let name = Path::new(STDIN_NAME);
+    let (encoded, timings) = psd_auto_export::export_bytes(&bytes, name, options)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, &encoded).context(format!("无法写入输出文件：{:?}", path))?
         }
-    } else if path.is_dir() {
-        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-            let entry_path = entry.path();
-            if entry_path.is_file()
-                && entry_path.extension().and_then(|ext| ext.to_str()) == Some("psd")
-            {
-                psd_files.push(entry_path.to_path_buf());
+        None => write_stdout(&encoded)?,
+    }
+    info!("成功导出标准输入，{}", timings);
+    Ok(())
+}
+
+/// `--stdout` 与 `-o`：把单个源文件导出到 `output`，未指定时写入标准输出。写入标准
+/// 输出时在内存中导出，不创建临时文件；只能输出一个文件，附加输出不可用
+fn export_single(psd_path: &Path, settings: &Settings, output: Option<&Path>) -> Result<()> {
+    if !psd_path.is_file() {
+        anyhow::bail!("--stdout 与 -o 只能用于单个源文件：{:?}", psd_path);
+    }
+    let Some(options) = settings.options_for(psd_path)? else {
+        anyhow::bail!("脚本要求跳过：{:?}", psd_path);
+    };
+    if !options.targets.is_empty() {
+        anyhow::bail!("--stdout 与 -o 不能与配置文件中的 [[target]] 一起使用");
+    }
+    if let Some(output) = output {
+        let options = ExportOptions {
+            output: Some(output.to_path_buf()),
+            ..options
+        };
+        let exported = process_psd_file(psd_path, &options)?;
+        info!(
+            "成功导出：{:?} -> {:?}，{}",
+            psd_path, exported.output_path, exported.timings
+        );
+        return Ok(());
+    }
+    if options.writes_extra_files() {
+        anyhow::bail!("--stdout 只能输出一个文件，不能同时生成缩略图、各通道、mipmap 等附加输出");
+    }
+    let (encoded, timings) = psd_auto_export::export_to_memory(psd_path, &options)?;
+    write_stdout(&encoded)?;
+    info!("成功导出：{:?}，{}", psd_path, timings);
+    Ok(())
+}
+
+fn write_stdout(bytes: &[u8]) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    stdout
+        .write_all(bytes)
+        .and_then(|()| stdout.flush())
+        .context("无法写入标准输出")
+}
+
+/// 监听模式下导出任务共用的、导出设置以外的参数
+struct WatchContext {
+    lfs_pull: bool,
+    /// 导出成功后打开输出文件的程序，内层为 `None` 时使用系统默认程序
+    open: Option<Option<String>>,
+    reveal: bool,
+    quarantine: Option<PathBuf>,
+    isolate: bool,
+    dashboard: Option<Arc<Dashboard>>,
+    /// 等待确认写完、因文件仍被占用或内存预算不足而推迟的任务
+    deferred: Mutex<Vec<Deferred>>,
+    /// 已交给线程池、尚未结束的导出任务数
+    running: AtomicUsize,
+}
+
+struct Deferred {
+    path: PathBuf,
+    /// 已推迟的次数
+    attempt: u32,
+    due: Instant,
+    /// 推迟前记下的大小与修改时间，到期时与当前比较判断是否写完；为空时重新记录
+    stamp: Option<busy::Stamp>,
+}
+
+impl WatchContext {
+    /// 取出已到时间的推迟任务
+    fn take_due(&self) -> Vec<Deferred> {
+        let now = Instant::now();
+        let mut deferred = self.deferred.lock().unwrap();
+        let (due, waiting) = std::mem::take(&mut *deferred)
+            .into_iter()
+            .partition(|d| d.due <= now);
+        *deferred = waiting;
+        due
+    }
+
+    /// 最早到期的推迟任务还需等待的时间
+    fn next_due(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.deferred
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|d| d.due.saturating_duration_since(now))
+            .min()
+    }
+
+    /// 没有正在进行或推迟的导出任务
+    fn is_idle(&self) -> bool {
+        self.running.load(Ordering::Relaxed) == 0 && self.deferred.lock().unwrap().is_empty()
+    }
+}
+
+/// 离开作用域时减少正在进行的任务数，任务提前返回或 panic 时同样生效
+struct Running<'a>(&'a AtomicUsize);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 把导出失败另外记入 `--error-log` 与 Windows 事件日志
+fn record_failure(psd_path: &Path, e: &anyhow::Error) {
+    errorlog::record(psd_path, e);
+    eventlog::report(
+        Event::Failure,
+        &format!("导出文件失败 {}：{:#}", psd_path.display(), e),
+    );
+}
+
+/// 导出超时时按 `--quarantine` 记入隔离清单
+fn quarantine_timed_out(list: Option<&Path>, psd_path: &Path, e: &anyhow::Error) {
+    if let Some(list) = list
+        && deadline::timed_out(e)
+    {
+        match quarantine::add(list, psd_path) {
+            Ok(()) => warn!("已隔离超时的文件：{:?}", psd_path),
+            Err(e) => error!("{:#}", e),
+        }
+    }
+}
+
+/// 交给 rayon 线程池导出：同时进行的任务数不超过线程数，大量文件同时保存时
+/// 不会为每个文件各开一个线程
+///
+/// 避免源文件还未写完就开始读取，然后失败：第一次调用只记下大小与修改时间，推迟
+/// [`busy::SETTLE`] 后带着 `stamp` 再次调用时比较。等待都在事件循环中安排，不占用导出线程
+fn spawn_export(
+    psd_path: PathBuf,
+    attempt: u32,
+    stamp: Option<busy::Stamp>,
+    settings: &Arc<Settings>,
+    context: &Arc<WatchContext>,
+) {
+    if let Some(list) = &context.quarantine
+        && quarantine::filter(list, vec![psd_path.clone()]).is_empty()
+    {
+        return;
+    }
+    let defer = |attempt, delay, stamp| {
+        context.deferred.lock().unwrap().push(Deferred {
+            path: psd_path.clone(),
+            attempt,
+            due: Instant::now() + delay,
+            stamp,
+        });
+    };
+    let busy = match stamp {
+        Some(before) => busy::check(&psd_path, before),
+        None => match busy::stamp(&psd_path) {
+            Some(stamp) => return defer(attempt, busy::SETTLE, Some(stamp)),
+            None => None,
+        },
+    };
+    if let Some(reason) = busy {
+        if attempt < MAX_BUSY_ATTEMPTS {
+            let delay = (BUSY_RETRY_DELAY * 2u32.pow(attempt.min(6))).min(MAX_BUSY_DELAY);
+            info!("文件{}，{:?} 后再导出：{:?}", reason, delay, psd_path);
+            return defer(attempt + 1, delay, None);
+        }
+        warn!("文件一直{}，仍然尝试导出：{:?}", reason, psd_path);
+    }
+    // 内存预算不足时稍后再试，事件循环与导出线程都不等待预算。已确认写完的文件不再
+    // 重新确认
+    let permit = match &settings.options.memory {
+        Some(budget) => match budget.try_acquire(budget::estimate(&psd_path)) {
+            Some(permit) => Some(permit),
+            None => return defer(attempt, MEMORY_RETRY_DELAY, stamp),
+        },
+        None => None,
+    };
+    let settings = Arc::clone(settings);
+    let context = Arc::clone(context);
+    if let Some(dashboard) = &context.dashboard {
+        dashboard.queued(&psd_path);
+    }
+    context.running.fetch_add(1, Ordering::Relaxed);
+    rayon::spawn(move || {
+        let _permit = permit;
+        let _running = Running(&context.running);
+        let dashboard = &context.dashboard;
+        if let Some(dashboard) = dashboard {
+            dashboard.started(&psd_path);
+        }
+        let error = if lfs::prepare(vec![psd_path.clone()], context.lfs_pull).is_empty() {
+            None
+        } else {
+            info!("正在导出文件：{:?}", psd_path);
+            let result = if context.isolate {
+                // 内存预算由父进程统一管理
+                settings.collision_output(&psd_path).and_then(|output| {
+                    isolate::export(&psd_path, output.as_deref(), settings.options.timeout)
+                })
+            } else {
+                settings.options_for(&psd_path).and_then(|options| {
+                    options
+                        .map(|options| process_psd_file(&psd_path, &options))
+                        .transpose()
+                })
+            };
+            match result {
+                Ok(None) => {
+                    info!("脚本要求跳过：{:?}", psd_path);
+                    None
+                }
+                Ok(Some(exported)) => {
+                    info!(
+                        "成功导出：{:?} -> {:?}{}{}，{}",
+                        psd_path,
+                        exported.output_path,
+                        extra_outputs_note(&exported),
+                        fallback_note(&exported),
+                        exported.timings
+                    );
+                    if let Some(program) = &context.open
+                        && let Err(e) = launch::open(&exported.output_path, program.as_deref())
+                    {
+                        warn!("无法打开输出文件：{:#}", e);
+                    }
+                    if context.reveal
+                        && let Err(e) = launch::reveal(&exported.output_path)
+                    {
+                        warn!("无法在文件管理器中显示输出文件：{:#}", e);
+                    }
+                    None
+                }
+                Err(e) => {
+                    error!("导出文件失败 {:?}: {}", psd_path, e);
+                    record_failure(&psd_path, &e);
+                    quarantine_timed_out(context.quarantine.as_deref(), &psd_path, &e);
+                    Some(format!("{:#}", e))
+                }
+            }
+        };
+        if let Some(dashboard) = &dashboard {
+            dashboard.finished(&psd_path, error);
+        }
+    });
+}
+
+/// 解析命令行参数。找到配置文件时，把其中的设置转换为参数放在命令行参数之前重新解析。
+/// 同时返回最终使用的参数列表，供子目录配置在其基础上叠加
+fn parse_args() -> Result<(Cli, Vec<OsString>, config::Document)> {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let args = parse_from(&argv).unwrap_or_else(|e| e.exit());
+    if args.command.is_some() {
+        return Ok((args, argv, config::Document::default()));
+    }
+    let Some(path) = args
+        .config
+        .clone()
+        .or_else(|| config::discover(args.watch_path()))
+    else {
+        if let Some(profile) = &args.profile {
+            anyhow::bail!("没有配置文件，无法使用预设 {}", profile);
+        }
+        return Ok((args, argv, config::Document::default()));
+    };
+    info!("使用配置文件：{:?}", path);
+
+    let document = config::load(&path)?;
+    let mut table = root_table(&path, &document, args.profile.as_deref())?;
+    if let Some(profile) = &args.profile {
+        info!("使用预设：{}", profile);
+    }
+    // 环境变量优先于配置文件：已由环境变量设置的键不再从配置文件读取
+    table
+        .entries
+        .retain(|(key, _)| std::env::var_os(config::env_name(key)).is_none());
+    let mut combined = argv[..1].to_vec();
+    combined.extend(table.to_args());
+    combined.extend_from_slice(&argv[1..]);
+    match parse_from(&combined) {
+        Ok(args) => Ok((args, combined, document)),
+        Err(e) => Err(anyhow::anyhow!(
+            "配置文件 {:?} 中的设置无效：{}",
+            path,
+            clap_message(&e)
+        )),
+    }
+}
+
+impl Cli {
+    /// 监听路径。只有执行子命令或使用监听列表时才可能为空
+    fn watch_path(&self) -> &Path {
+        self.path.as_deref().unwrap_or(Path::new("."))
+    }
+}
+
+/// `verify` 子命令
+fn run_verify(path: &Path) -> Result<()> {
+    let verification = lock::verify(path)?;
+    for path in &verification.modified {
+        error!("输出已被改动或损坏：{:?}", path);
+    }
+    for path in &verification.missing {
+        error!("输出不存在：{:?}", path);
+    }
+    for path in &verification.drifted {
+        warn!("源文件在导出后被修改，输出已过期：{:?}", path);
+    }
+    for path in &verification.missing_sources {
+        warn!("源文件不存在：{:?}", path);
+    }
+    info!(
+        "校验完成：{} 个输出通过，{} 个被改动，{} 个缺失；{} 个源文件已修改，{} 个缺失",
+        verification.ok,
+        verification.modified.len(),
+        verification.missing.len(),
+        verification.drifted.len(),
+        verification.missing_sources.len()
+    );
+    if !verification.is_clean() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `validate` 子命令
+fn run_validate(path: &Path) -> Result<()> {
+    let checked = validate::check_all(path)?;
+    let mut failed = 0;
+    let mut unsupported = 0;
+    for file in &checked {
+        if let Some(error) = &file.error {
+            error!("无法解析 {:?}：{}", file.path, error);
+            failed += 1;
+        }
+        if !file.unsupported.is_empty() {
+            warn!(
+                "{:?} 包含渲染器无法还原的{}，导出结果会与 Photoshop 不同",
+                file.path,
+                file.unsupported.join("、")
+            );
+            unsupported += 1;
+        }
+    }
+    info!(
+        "检查完成：共 {} 个源文件，{} 个无法解析，{} 个包含无法还原的功能",
+        checked.len(),
+        failed,
+        unsupported
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    if unsupported > 0 {
+        std::process::exit(2);
+    }
+    Ok(())
+}
+
+/// `diff` 子命令
+fn run_diff(a: &Path, b: &Path, output: &Path, threshold: u8) -> Result<()> {
+    let (image_a, image_b) = rayon::join(|| diff::load(a), || diff::load(b));
+    let (image_a, image_b) = (image_a?, image_b?);
+    if image_a.dimensions() != image_b.dimensions() {
+        warn!(
+            "两张图像尺寸不同：{}x{} 与 {}x{}",
+            image_a.width(),
+            image_a.height(),
+            image_b.width(),
+            image_b.height()
+        );
+    }
+    let diff = diff::compare(&image_a, &image_b, threshold);
+    diff.image
+        .save(output)
+        .context(format!("无法写入差异图：{:?}", output))?;
+    info!(
+        "{} / {} 个像素有变化（{:.2}%），差异图：{:?}",
+        diff.changed,
+        diff.total,
+        diff.percentage(),
+        output
+    );
+    if diff.changed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `config` 子命令
+fn run_config(action: &ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Init { file, force } => {
+            if file.exists() && !force {
+                anyhow::bail!("文件已存在：{:?}，使用 --force 覆盖", file);
+            }
+            std::fs::write(file, config::template(&Cli::command()))
+                .context(format!("无法写入配置文件：{:?}", file))?;
+            info!("已生成配置文件：{:?}", file);
+        }
+        ConfigAction::Validate { file } => {
+            let document = config::load(file)?;
+            // 顶层设置与每个预设分别检查
+            let profiles = document.profiles().map(|(name, _)| Some(name));
+            for profile in std::iter::once(None).chain(profiles) {
+                let mut argv: Vec<OsString> = vec!["pae".into()];
+                argv.extend(root_table(file, &document, profile)?.to_args());
+                // 监听路径只是为了满足必填参数，不检查是否存在
+                argv.push(".".into());
+                parse_from(&argv).map_err(|e| {
+                    let scope = profile.map_or_else(String::new, |p| format!("预设 {} ", p));
+                    anyhow::anyhow!(
+                        "配置文件 {:?} 中{}的设置无效：{}",
+                        file,
+                        scope,
+                        clap_message(&e)
+                    )
+                })?;
+                build_targets(&argv, &document).context(format!("配置文件 {:?} 无效", file))?;
             }
+            info!(
+                "配置文件有效：{:?}（{} 项设置，{} 个预设，{} 个输出目标）",
+                file,
+                document.root.entries.len(),
+                document.profiles().count(),
+                document.targets().count()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 解析参数。每个参数都可以用 `PSD_EXPORT_<参数名>` 环境变量设置，
+/// 例如 `PSD_EXPORT_FORMAT=jpg`、`PSD_EXPORT_ONCE=true`，命令行参数优先
+fn parse_from<I, T>(argv: I) -> Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let command = Cli::command().mut_args(|arg| {
+        let name = config::env_name(arg.get_id().as_str());
+        arg.env(name)
+    });
+    Cli::from_arg_matches(&command.try_get_matches_from(argv)?)
+}
+
+/// clap 错误信息的第一行，去掉 `error: ` 前缀
+fn clap_message(e: &clap::Error) -> String {
+    let message = e.render().to_string();
+    let message = message.lines().next().unwrap_or_default();
+    message.trim_start_matches("error: ").to_string()
+}
+
+/// 根配置文件的位置：`--config` 指定的文件，或监听根目录下的 `psd-export.toml`
+/// （可能尚不存在，创建后同样会被加载）
+fn config_location(args: &Cli) -> PathBuf {
+    args.config.clone().unwrap_or_else(|| {
+        let path = args.watch_path();
+        let root = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(Path::new("."))
+        };
+        root.join(config::FILE_NAME)
+    })
+}
+
+/// 两个路径是否指向同一个文件。事件中的文件可能已被删除或替换，因此比较
+/// 所在目录的绝对路径与文件名
+fn same_file(a: &Path, b: &Path) -> bool {
+    a.file_name() == b.file_name() && a.parent().map(absolute) == b.parent().map(absolute)
+}
+
+/// 重新加载配置并替换之后新任务使用的设置；失败时保留原设置继续运行
+fn reload_settings(settings: &mut Arc<Settings>, debounce: &mut Duration, watch_path: &Path) {
+    match settings.reload(watch_path) {
+        Ok((reloaded, reloaded_debounce)) => {
+            *settings = Arc::new(reloaded);
+            *debounce = reloaded_debounce;
+            info!(
+                "配置已重新加载，导出格式：{:?}，防抖间隔：{:?}",
+                settings.options.format, debounce
+            );
         }
+        Err(e) => error!("重新加载配置失败，继续使用原设置：{:#}", e),
     }
-    // 如果路径不存在或不是文件/目录，find_psd_files 会返回空 Vec，这在 main
-    // 中已经处理了路径不存在的情况
+}
 
-    Ok(psd_files)
+/// 读取子目录或源文件旁的配置文件，叠加其引用的预设，转换为命令行参数。能在监听目录中
+/// 写文件的人不一定能运行命令，这些文件（连同引用的预设）只接受不运行命令的导出设置
+fn restricted_args(path: &Path, root: &config::Document) -> Result<Vec<OsString>> {
+    let table = config::load(path)?
+        .resolve_in(root)
+        .context(format!("配置文件 {:?} 无效", path))?;
+    table
+        .check_overridable()
+        .map_err(|e| anyhow::anyhow!("配置文件 {:?} 无效：{}", path, e))?;
+    Ok(table.to_args())
 }
 
-/// 将指定的 PSD 文件转换为同名的指定格式图像文件
-fn process_psd_file(psd_path: &Path, format: &ExportFormat) -> Result<()> {
-    // 构建输出文件的路径，使用指定的扩展名
-    let output_path = psd_path.with_extension(format.extension());
+/// 由根配置文件中的 `[[target]]` 构建输出目标。目标中的设置追加在 `argv`
+/// 之后，优先级最高；`dir` 键指定输出目录
+fn build_targets(argv: &[OsString], document: &config::Document) -> Result<Vec<Target>> {
+    let mut targets: Vec<Target> = Vec::new();
+    for (i, table) in document.targets().enumerate() {
+        let mut table = table.clone();
+        let dir = table.take_string("dir")?.map(PathBuf::from);
+        let args = parse_from(argv.iter().cloned().chain(table.to_args()))
+            .map_err(|e| anyhow::anyhow!("第 {} 个输出目标无效：{}", i + 1, clap_message(&e)))?;
+        let target = Target {
+            dir,
+            options: ExportOptions {
+                memory: None,
+                ..export_options(&args)
+            },
+        };
+        if let ExportFormat::Ora = target.options.format {
+            anyhow::bail!("第 {} 个输出目标：ORA 格式不能作为输出目标", i + 1);
+        }
+        if targets.iter().any(|t| {
+            t.dir == target.dir && t.options.format.extension() == target.options.format.extension()
+        }) {
+            anyhow::bail!(
+                "第 {} 个输出目标与之前的目标写入同一文件，请设置不同的 dir 或 format",
+                i + 1
+            );
+        }
+        targets.push(target);
+    }
+    Ok(targets)
+}
+
+/// 根配置文件叠加预设后的设置
+fn root_table(
+    path: &Path,
+    document: &config::Document,
+    profile: Option<&str>,
+) -> Result<config::Table> {
+    document
+        .check_tables()
+        .and_then(|_| document.resolve(profile))
+        .context(format!("配置文件 {:?} 无效", path))
+}
+
+/// 全局导出设置，以及按子目录配置重新解析参数所需的信息
+struct Settings {
+    /// 根配置文件转换出的参数与命令行参数
+    argv: Vec<OsString>,
+    /// 监听根目录的绝对路径
+    root: PathBuf,
+    /// 根配置文件，子目录配置从中查找引用的预设
+    document: config::Document,
+    options: ExportOptions,
+    /// 按文件名约定调整单个文件的设置，见 [`naming`]
+    filename_options: bool,
+    collision_policy: collision::Policy,
+    /// 检测到的输出冲突，以源文件绝对路径为键。重新加载设置时沿用
+    collisions: Arc<RwLock<HashMap<PathBuf, Resolution>>>,
+    /// 边扫描边导出时已被占用的输出，见 [`Settings::claim_outputs`]
+    claims: Mutex<Claims>,
+    /// 规划输出时缓存的脚本决定，见 [`Settings::script_decision`]
+    decisions: Mutex<HashMap<PathBuf, CachedDecision>>,
+}
 
-    // 读取 PSD 文件内容
-    let psd_bytes =
-        std::fs::read(psd_path).context(format!("无法读取 PSD 文件：{:?}", psd_path))?;
+/// 脚本对某个版本的源文件（按大小与修改时间区分）的决定，失败时为错误信息
+type CachedDecision = (
+    Option<busy::Stamp>,
+    std::result::Result<script::Decision, String>,
+);
 
-    // 解析 PSD 数据
-    let psd = Psd::from_bytes(&psd_bytes).context(format!("无法解析 PSD 文件：{:?}", psd_path))?;
+impl Settings {
+    fn new(
+        argv: Vec<OsString>,
+        args: &Cli,
+        watch_path: &Path,
+        document: config::Document,
+    ) -> Result<Self> {
+        let root = if watch_path.is_dir() {
+            watch_path
+        } else {
+            watch_path.parent().unwrap_or(Path::new("."))
+        };
+        let options = ExportOptions {
+            targets: build_targets(&argv, &document)?,
+            ..export_options(args)
+        };
+        clock::set_deterministic(args.deterministic)?;
+        Ok(Settings {
+            argv,
+            root: absolute(root),
+            document,
+            options,
+            filename_options: args.filename_options,
+            collision_policy: args.on_collision,
+            collisions: Arc::default(),
+            claims: Mutex::new(Claims::new(args.on_collision)),
+            decisions: Mutex::default(),
+        })
+    }
 
-    // 获取合并后的最终图像数据 (RGBA 格式)
-    let final_image_data: Vec<u8> = psd.rgba();
+    /// 检查这些源文件的输出是否冲突，记录处理方式，之后的 [`Settings::options_for`]
+    /// 按其调整输出
+    fn detect_collisions(&self, sources: &[PathBuf]) {
+        let planned: Vec<Planned> = sources
+            .par_iter()
+            .filter_map(|path| self.planned(path))
+            .collect();
+        let collisions = collision::detect(planned, self.collision_policy);
+        if !collisions.is_empty() {
+            warn!("{} 个源文件的输出与其它源文件冲突", collisions.len());
+        }
+        *self.collisions.write().unwrap_or_else(|e| e.into_inner()) = collisions;
+    }
 
-    // 创建 ImageBuffer
-    let img_buffer =
-        ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(psd.width(), psd.height(), final_image_data)
-            .context("无法创建 ImageBuffer，可能是图像数据或尺寸问题")?;
+    /// 边扫描边导出时，在导出前占用源文件的输出，与先开始导出的源文件冲突时记录处理方式
+    fn claim_outputs(&self, path: &Path) {
+        let Some(plan) = self.planned(path) else {
+            return;
+        };
+        let resolution = self
+            .claims
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .claim(&plan);
+        if let Some(resolution) = resolution {
+            self.collisions
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(absolute(path), resolution);
+        }
+    }
 
-    // 保存为指定格式的图像文件
-    // image crate 的 save 方法可以根据文件扩展名自动选择格式，
-    // 但为了明确控制格式（特别是 JPEG 质量），我们使用 write_to
-    let mut file = std::fs::File::create(&output_path)
-        .context(format!("无法创建输出文件：{:?}", output_path))?;
+    /// 源文件计划写入的输出，脚本要求跳过或设置无效时为 `None`
+    fn planned(&self, path: &Path) -> Option<Planned> {
+        let options = self.configured_options(path, false).ok().flatten()?;
+        Some(Planned {
+            source: path.to_path_buf(),
+            outputs: options.outputs(path),
+            renamable: options.targets.is_empty(),
+        })
+    }
 
-    img_buffer
-        .write_to(&mut file, format.image_format())
-        .context(format!("无法保存图像文件：{:?}", output_path))?;
+    /// 源文件因输出冲突改用的输出路径；按 `--on-collision fail` 不导出时返回错误
+    fn collision_output(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let collisions = self.collisions.read().unwrap_or_else(|e| e.into_inner());
+        match collisions.get(&absolute(path)) {
+            None => Ok(None),
+            Some(Resolution::Rename(output)) => Ok(Some(output.clone())),
+            Some(Resolution::Fail { output, winner }) => {
+                anyhow::bail!("输出 {:?} 与 {:?} 冲突，不导出", output, winner)
+            }
+        }
+    }
 
+    /// 源文件适用的导出设置，见 [`Settings::configured_options`]；输出与其它源文件冲突时
+    /// 按 `--on-collision` 改名或返回错误
+    fn options_for(&self, path: &Path) -> Result<Option<ExportOptions>> {
+        let Some(mut options) = self.configured_options(path, true)? else {
+            return Ok(None);
+        };
+        if let Some(output) = self.collision_output(path)? {
+            options.output = Some(output);
+        }
+        Ok(Some(options))
+    }
+
+    /// 源文件适用的导出设置：从监听根目录到文件所在目录，依次叠加各级
+    /// `.psd-export.toml`，越深的目录优先级越高（高于命令行参数），然后是文件名约定与
+    /// 源文件旁的 `file.psd.toml`，最后是 `--script` 的输出。脚本要求跳过时返回 `None`。
+    /// `take` 见 [`Settings::script_decision`]
+    fn configured_options(&self, path: &Path, take: bool) -> Result<Option<ExportOptions>> {
+        let absolute_path = absolute(path);
+        let mut dirs: Vec<&Path> = absolute_path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .collect();
+        dirs.reverse();
+
+        let mut overrides = Vec::new();
+        for dir in dirs {
+            let file = dir.join(config::DIR_FILE_NAME);
+            if file.is_file() {
+                overrides.extend(restricted_args(&file, &self.document)?);
+            }
+        }
+        let conventions = if self.filename_options {
+            Conventions::parse(path)
+        } else {
+            Conventions::default()
+        };
+        overrides.extend(conventions.to_args());
+        let sidecar = config::sidecar_path(path);
+        if sidecar.is_file() {
+            overrides.extend(restricted_args(&sidecar, &self.document)?);
+        }
+        let mut options = self
+            .options_with(&overrides)
+            .map_err(|e| anyhow::anyhow!("子目录或源文件旁的配置无效：{}", e))?;
+        // 去掉文件名中的格式与质量部分：`photo.q80.jpg.psd` 输出 `photo.jpg`
+        let named_output = conventions.stem.map(|stem| {
+            naming::sanitize_path(
+                &path.with_file_name(format!("{}.{}", stem, options.format.extension())),
+                &options.name_replacement,
+            )
+        });
+        options.output = named_output.clone();
+
+        let Some(command) = options.script.clone() else {
+            return Ok(Some(options));
+        };
+        let decision = self.script_decision(&command, path, take)?;
+        if decision.skip {
+            return Ok(None);
+        }
+        if !decision.overrides.entries.is_empty() {
+            // 脚本的输出可能受图层名等文件内容影响，与子目录配置一样不能设置运行命令的键
+            decision
+                .overrides
+                .check_overridable()
+                .map_err(|e| anyhow::anyhow!("脚本 {} 的输出无效：{}", command, e))?;
+            overrides.extend(decision.overrides.to_args());
+            options = self
+                .options_with(&overrides)
+                .map_err(|e| anyhow::anyhow!("脚本 {} 的输出无效：{}", command, e))?;
+        }
+        options.output = decision
+            .output
+            .map(|output| naming::sanitize_path(&output, &options.name_replacement))
+            .or(named_output);
+        Ok(Some(options))
+    }
+
+    /// 脚本对源文件的决定。规划输出时运行脚本并缓存结果，导出时（`take`）取出使用，
+    /// 每个版本的源文件只运行一次脚本；两者之间源文件被修改时重新运行
+    fn script_decision(&self, command: &str, path: &Path, take: bool) -> Result<script::Decision> {
+        let key = absolute(path);
+        let stamp = busy::stamp(path);
+        let cached = self
+            .decisions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&key)
+            .filter(|(cached, _)| cached.is_some() && *cached == stamp)
+            .map(|(_, decision)| decision);
+        // 运行脚本时不持有锁，规划时各文件的脚本并行运行
+        let decision =
+            cached.unwrap_or_else(|| script::run(command, path).map_err(|e| format!("{:#}", e)));
+        if !take {
+            self.decisions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key, (stamp, decision.clone()));
+        }
+        decision.map_err(anyhow::Error::msg)
+    }
+
+    /// 在全局参数之后追加 `overrides` 重新解析出的导出设置
+    fn options_with(&self, overrides: &[OsString]) -> Result<ExportOptions> {
+        if overrides.is_empty() {
+            return Ok(self.options.clone());
+        }
+        let argv: Vec<OsString> = self.argv.iter().chain(overrides).cloned().collect();
+        let args = parse_from(&argv).map_err(|e| anyhow::anyhow!("{}", clap_message(&e)))?;
+        Ok(ExportOptions {
+            // 内存预算在所有任务间共享
+            memory: self.options.memory.clone(),
+            targets: build_targets(&argv, &self.document)?,
+            ..export_options(&args)
+        })
+    }
+
+    /// 重新读取命令行与根配置文件。内存预算沿用原来的实例，正在进行的任务
+    /// 与新任务共享同一份预算
+    fn reload(&self, watch_path: &Path) -> Result<(Settings, Duration)> {
+        let (args, argv, document) = parse_args()?;
+        let mut settings = Settings::new(argv, &args, watch_path, document)?;
+        settings.options.memory = self.options.memory.clone();
+        settings.collisions = Arc::clone(&self.collisions);
+        Ok((settings, args.debounce))
+    }
+}
+
+/// 一次性模式的根目录：监听目录本身，或单个文件所在的目录
+fn output_root(watch_path: &Path) -> &Path {
+    if watch_path.is_dir() {
+        watch_path
+    } else {
+        watch_path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+/// 按本次导出结果同步输出并报告变化
+fn sync_outputs(
+    watch_path: &Path,
+    sources: &[PathBuf],
+    outputs: &[(PathBuf, Vec<PathBuf>)],
+) -> Result<()> {
+    let report = sync::sync(output_root(watch_path), sources, outputs)?;
+    info!(
+        "同步完成：新增 {} 个，更新 {} 个，未变化 {} 个，删除 {} 个",
+        report.added.len(),
+        report.updated.len(),
+        report.unchanged,
+        report.removed.len()
+    );
+    for path in &report.added {
+        info!("  新增：{:?}", path);
+    }
+    for path in &report.updated {
+        info!("  更新：{:?}", path);
+    }
+    for path in &report.removed {
+        info!("  删除：{:?}", path);
+    }
     Ok(())
 }
+
+/// 合并模式下渲染一页，跳过或失败时记录日志并返回 `None`。内存预算由调用方获取
+fn render_page(settings: &Settings, psd_path: &Path) -> Option<Rendered> {
+    info!("正在渲染文件：{:?}", psd_path);
+    let rendered = settings.options_for(psd_path).and_then(|options| {
+        let Some(options) = options else {
+            return Ok(None);
+        };
+        render_psd_file(psd_path, &options).map(Some)
+    });
+    match rendered {
+        Ok(Some(rendered)) => Some(rendered),
+        Ok(None) => {
+            info!("脚本要求跳过：{:?}", psd_path);
+            None
+        }
+        Err(e) => {
+            error!("渲染文件失败 {:?}: {}", psd_path, e);
+            record_failure(psd_path, &e);
+            None
+        }
+    }
+}
+
+/// 按路径顺序把所有文件写入一个多页 TIFF，返回页数。TIFF 页面只能在写入时压缩，
+/// 每次并行渲染与线程数相同的页数后依次写入，避免同时保存所有页面的像素
+fn write_combined_tiff(path: &Path, psd_files: &[PathBuf], settings: &Settings) -> Result<usize> {
+    let file = std::fs::File::create(path).context(format!("无法创建输出文件：{:?}", path))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let mut tiff = MultiPageTiff::new(&mut writer, &settings.options.encode)?;
+    let mut count = 0;
+    for chunk in psd_files.chunks(rayon::current_num_threads()) {
+        let pages: Vec<Rendered> = budget::map(
+            settings.options.memory.as_ref(),
+            chunk.iter(),
+            |psd_path| budget::estimate(psd_path),
+            |psd_path| render_page(settings, psd_path),
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+        for page in pages {
+            tiff.add(&page.image, page.dpi)
+                .context(format!("无法保存 TIFF 文件：{:?}", path))?;
+            count += 1;
+        }
+    }
+    writer
+        .flush()
+        .context(format!("无法保存 TIFF 文件：{:?}", path))?;
+    Ok(count)
+}
+
+/// 将多页写入同一个 PDF 文件
+fn write_combined_pdf(path: &Path, pages: &[pdf::Page]) -> Result<()> {
+    let file = std::fs::File::create(path).context(format!("无法创建输出文件：{:?}", path))?;
+    let mut writer = pdf::PdfWriter::new(std::io::BufWriter::new(file))?;
+    for page in pages {
+        writer.add_page(page)?;
+    }
+    writer
+        .finish()
+        .context(format!("无法保存 PDF 文件：{:?}", path))?;
+    Ok(())
+}
+
+/// 源文件是否在 `cutoff` 之后修改过
+fn modified_after(path: &Path, cutoff: SystemTime) -> bool {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified > cutoff)
+}
+
+/// 去掉尚未下载的 Git LFS 指针文件与隔离清单中的文件
+fn exclude_unavailable(psd_files: Vec<PathBuf>, args: &Cli) -> Vec<PathBuf> {
+    let psd_files = lfs::prepare(psd_files, args.lfs_pull);
+    match &args.quarantine {
+        Some(list) => quarantine::filter(list, psd_files),
+        None => psd_files,
+    }
+}
+
+/// 边扫描边导出（`--stream`）：扫描在单独的线程池中进行，导出线程池等待新文件时不会
+/// 占住扫描任务。找到的源文件每次最多取 [`STREAM_BATCH`] 个，筛选后立即交给 `export`
+fn export_streamed<T: Send>(
+    watch_path: &Path,
+    args: &Cli,
+    settings: &Settings,
+    cutoff: Option<SystemTime>,
+    estimate: &dyn Fn(&(usize, PathBuf)) -> u64,
+    export: &(dyn Fn(usize, PathBuf) -> Option<T> + Sync),
+) -> Result<Vec<T>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .thread_name(|i| format!("pae-scan-{}", i))
+        .build()
+        .context("无法创建扫描线程池")?;
+    let (tx, rx) = mpsc::channel::<PathBuf>();
+    let found = AtomicUsize::new(0);
+    let exported = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let tx = tx;
+            pool.install(|| {
+                scan_psd_files(watch_path, &settings.options, &|files| {
+                    found.fetch_add(files.len(), Ordering::Relaxed);
+                    for file in files {
+                        _ = tx.send(file);
+                    }
+                })
+            });
+            info!(
+                "扫描完成，共找到 {} 个源文件。",
+                found.load(Ordering::Relaxed)
+            );
+        });
+        // 通道在扫描结束、发送端释放后关闭
+        let batches = std::iter::from_fn(move || {
+            let first = rx.recv().ok()?;
+            let batch: Vec<PathBuf> = std::iter::once(first)
+                .chain(rx.try_iter().take(STREAM_BATCH - 1))
+                .collect();
+            Some(batch)
+        });
+        let found = batches.flat_map(|batch| {
+            let batch = match cutoff {
+                Some(cutoff) => batch
+                    .into_iter()
+                    .filter(|path| modified_after(path, cutoff))
+                    .collect(),
+                None => batch,
+            };
+            exclude_unavailable(batch, args)
+        });
+        budget::map(
+            settings.options.memory.as_ref(),
+            found.enumerate(),
+            estimate,
+            |(index, psd_path)| export(index, psd_path),
+        )
+        .into_iter()
+        .flatten()
+        .collect()
+    });
+    Ok(exported)
+}
+
+/// 成功日志中列出其它输出目标的文件
+fn extra_outputs_note(exported: &Exported) -> String {
+    exported
+        .extra_outputs
+        .iter()
+        .map(|path| format!("、{:?}", path))
+        .collect()
+}
+
+/// 成功日志中标注使用了外部转换器
+fn fallback_note(exported: &Exported) -> &'static str {
+    if exported.fallback {
+        "（外部转换器）"
+    } else {
+        ""
+    }
+}
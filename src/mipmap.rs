@@ -0,0 +1,101 @@
+//! Mipmap 链（`--mipmaps`）：把导出的图像依次缩小一半直到 1×1，分别保存为
+//! `name.mip1.<ext>`、`name.mip2.<ext>` 等，或与原图一起打包为未压缩的 DDS 纹理，
+//! 引擎可以直接使用美术绘制的最高级别。
+
+use std::io::Write;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use image::RgbaImage;
+
+use crate::transform::ResizeFilter;
+
+/// Mipmap 的保存方式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipmapMode {
+    /// 每一级保存为单独的文件，格式与主输出相同
+    #[default]
+    Files,
+    /// 与原图一起写入 `name.dds`（未压缩 RGBA）
+    Dds,
+}
+
+/// 从原图缩小一半开始直到 1×1 的各级图像，不含原图。每一级由上一级缩小得到，
+/// 奇数边长向下取整
+pub fn chain(image: &RgbaImage, filter: ResizeFilter) -> Vec<RgbaImage> {
+    let mut levels: Vec<RgbaImage> = Vec::new();
+    let (mut width, mut height) = image.dimensions();
+    while width > 1 || height > 1 {
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+        let previous = levels.last().unwrap_or(image);
+        levels.push(image::imageops::resize(
+            previous,
+            width,
+            height,
+            filter.filter_type(),
+        ));
+    }
+    levels
+}
+
+/// 边长是否都是 2 的幂，否则部分引擎无法使用完整的 mipmap 链
+pub fn is_power_of_two(image: &RgbaImage) -> bool {
+    image.width().is_power_of_two() && image.height().is_power_of_two()
+}
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+/// 写入未压缩 32 位 RGBA 的 DDS 文件，`levels` 从最大的一级开始
+pub fn write_dds<W: Write>(levels: &[&RgbaImage], mut writer: W) -> Result<()> {
+    let Some(top) = levels.first() else {
+        anyhow::bail!("没有可写入 DDS 的图像");
+    };
+    let mut header = Vec::with_capacity(128);
+    let mut put = |value: u32| header.extend_from_slice(&value.to_le_bytes());
+    put(u32::from_le_bytes(*b"DDS "));
+    put(124);
+    put(DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT);
+    put(top.height());
+    put(top.width());
+    put(top.width() * 4);
+    // 深度
+    put(0);
+    put(levels.len() as u32);
+    for _ in 0..11 {
+        put(0);
+    }
+    // 像素格式：内存中按 R、G、B、A 的字节顺序存放
+    put(32);
+    put(DDPF_RGB | DDPF_ALPHAPIXELS);
+    put(0);
+    put(32);
+    put(0x0000_00ff);
+    put(0x0000_ff00);
+    put(0x00ff_0000);
+    put(0xff00_0000);
+    let caps = if levels.len() > 1 {
+        DDSCAPS_COMPLEX | DDSCAPS_TEXTURE | DDSCAPS_MIPMAP
+    } else {
+        DDSCAPS_TEXTURE
+    };
+    put(caps);
+    for _ in 0..4 {
+        put(0);
+    }
+    writer.write_all(&header)?;
+    for level in levels {
+        writer.write_all(level.as_raw())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! 读取源文件内容。开启 `mmap` feature 后在 Unix 上使用内存映射，GB 级的 PSD
+//! 不必先整体复制到堆上再解析；映射失败或其它平台上退回 `std::fs::read`。
+//!
+//! 注意：映射期间文件被其它程序截断会导致进程收到 SIGBUS，因此默认不开启。
+
+use std::{fs, io, ops::Deref, path::Path};
+
+/// 源文件的全部字节，可能是内存映射，也可能是堆上的副本
+pub enum FileBytes {
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped {
+        ptr: *mut libc::c_void,
+        len: usize,
+    },
+    Owned(Vec<u8>),
+}
+
+// SAFETY: 映射为只读的私有映射，不会被本进程修改
+unsafe impl Send for FileBytes {}
+unsafe impl Sync for FileBytes {}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(all(feature = "mmap", unix))]
+            FileBytes::Mapped { ptr, len } => unsafe {
+                std::slice::from_raw_parts(ptr.cast::<u8>(), *len)
+            },
+            FileBytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Drop for FileBytes {
+    fn drop(&mut self) {
+        if let FileBytes::Mapped { ptr, len } = *self {
+            unsafe { libc::munmap(ptr, len) };
+        }
+    }
+}
+
+/// 读取整个文件
+pub fn read(path: &Path) -> io::Result<FileBytes> {
+    #[cfg(all(feature = "mmap", unix))]
+    if let Some(mapped) = map(path)? {
+        return Ok(mapped);
+    }
+    fs::read(path).map(FileBytes::Owned)
+}
+
+/// 以只读方式映射文件；空文件或映射失败时返回 `None`，由调用方改为普通读取
+#[cfg(all(feature = "mmap", unix))]
+fn map(path: &Path) -> io::Result<Option<FileBytes>> {
+    use std::os::fd::AsRawFd;
+
+    let file = fs::File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len == 0 {
+        return Ok(None);
+    }
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        log::debug!("无法映射文件 {:?}：{}", path, io::Error::last_os_error());
+        return Ok(None);
+    }
+    // 解析时基本是顺序读取
+    unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+    Ok(Some(FileBytes::Mapped { ptr, len }))
+}
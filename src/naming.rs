@@ -0,0 +1,258 @@
+//! 由文件名约定指定的单个文件的导出设置（`--filename-options`），美术无需配置文件即可
+//! 控制单个文件的输出：
+//!
+//! - `icon@2x.psd`：按 2 倍缩放导出，输出 `icon@2x.png`
+//! - `photo.q80.jpg.psd`：导出为质量 80 的 JPEG，输出 `photo.jpg`
+//!
+//! 格式与质量写作文件名中源文件扩展名之前以 `.` 分隔的部分，顺序不限。
+//!
+//! 由源文件名、文件名约定或脚本得到的输出文件名还会经过 [`sanitize`]，去掉 Windows 与
+//! macOS 上不能出现在文件名中的字符，美术随意起的名字不会导致导出失败。
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+
+use crate::ExportFormat;
+
+/// 从文件名中读出的设置
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Conventions {
+    /// 去掉格式与质量部分后的文件名（不含扩展名），没有这些部分时为 `None`
+    pub stem: Option<String>,
+    pub scale: Option<f32>,
+    pub format: Option<ExportFormat>,
+    pub quality: Option<u8>,
+}
+
+impl Conventions {
+    /// 读取源文件名中的约定
+    pub fn parse(path: &Path) -> Self {
+        let mut conventions = Conventions::default();
+        let Some(stem) = path.file_stem().map(|s| s.to_string_lossy()) else {
+            return conventions;
+        };
+        let mut parts: Vec<&str> = stem.split('.').collect();
+        while parts.len() > 1 {
+            let part = parts[parts.len() - 1];
+            if conventions.format.is_none()
+                && let Ok(format) = ExportFormat::from_str(part, true)
+            {
+                conventions.format = Some(format);
+            } else if conventions.quality.is_none()
+                && let Some(quality) = part
+                    .strip_prefix(['q', 'Q'])
+                    .and_then(|q| q.parse().ok())
+                    .filter(|q| (1..=100).contains(q))
+            {
+                conventions.quality = Some(quality);
+            } else {
+                break;
+            }
+            parts.pop();
+        }
+        let base = parts.join(".");
+        conventions.scale = base
+            .rsplit_once('@')
+            .and_then(|(_, scale)| scale.strip_suffix(['x', 'X']))
+            .and_then(|scale| scale.parse::<f32>().ok())
+            .filter(|scale| *scale > 0.0 && scale.is_finite());
+        if base != stem {
+            conventions.stem = Some(base);
+        }
+        conventions
+    }
+
+    /// 转换为追加在命令行参数之后的参数
+    pub fn to_args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        if let Some(scale) = self.scale {
+            args.push("--resize".into());
+            args.push(format!("{}x", scale).into());
+        }
+        if let Some(format) = &self.format {
+            args.push("--format".into());
+            args.push(format.extension().into());
+        }
+        if let Some(quality) = self.quality {
+            args.push("--quality".into());
+            args.push(quality.to_string().into());
+        }
+        args
+    }
+}
+
+/// Windows 与 macOS 上不能出现在文件名中的字符
+const INVALID: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Windows 保留的设备名，不区分大小写，带扩展名时同样不可用
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 解析 `--name-replacement`：替换字符串本身不能包含无效字符
+pub fn parse_replacement(s: &str) -> Result<String> {
+    if s.contains(INVALID) || s.contains(char::is_control) {
+        bail!("替换字符串不能包含 {:?} 或控制字符", INVALID);
+    }
+    Ok(s.to_string())
+}
+
+/// 把文件名中的无效字符与控制字符替换为 `replacement`，去掉结尾的 `.` 与空格，并在
+/// Windows 保留的设备名后加上 `replacement`
+pub fn sanitize(name: &str, replacement: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if INVALID.contains(&c) || c.is_control() {
+            out.push_str(replacement);
+        } else {
+            out.push(c);
+        }
+    }
+    out.truncate(out.trim_end_matches(['.', ' ']).len());
+    let device = out.split('.').next().unwrap_or_default();
+    if RESERVED
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(device.trim_end()))
+    {
+        out.insert_str(
+            device.len(),
+            if replacement.is_empty() {
+                "_"
+            } else {
+                replacement
+            },
+        );
+    }
+    if out.is_empty() {
+        out.push('_');
+    }
+    out
+}
+
+/// 清理路径中的文件名，目录部分保持不变
+pub fn sanitize_path(path: &Path, replacement: &str) -> PathBuf {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return path.to_path_buf();
+    };
+    let sanitized = sanitize(name, replacement);
+    if sanitized == name {
+        path.to_path_buf()
+    } else {
+        path.with_file_name(sanitized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> Conventions {
+        Conventions::parse(Path::new(name))
+    }
+
+    #[test]
+    fn parses_scale_format_and_quality() {
+        assert_eq!(
+            parse("icon@2x.psd"),
+            Conventions {
+                scale: Some(2.0),
+                ..Conventions::default()
+            }
+        );
+        assert_eq!(
+            parse("art/photo.q80.jpg.psd"),
+            Conventions {
+                stem: Some("photo".to_string()),
+                format: Some(ExportFormat::Jpg),
+                quality: Some(80),
+                ..Conventions::default()
+            }
+        );
+        // 格式与质量的顺序不限，扩展名不区分大小写
+        assert_eq!(parse("photo.JPG.Q80.psd"), parse("photo.q80.jpg.psd"));
+        assert_eq!(
+            parse("logo@0.5x.webp.psd"),
+            Conventions {
+                stem: Some("logo@0.5x".to_string()),
+                scale: Some(0.5),
+                format: Some(ExportFormat::Webp),
+                ..Conventions::default()
+            }
+        );
+        assert_eq!(
+            parse("logo@0.5x.webp.psd").to_args(),
+            ["--resize", "0.5x", "--format", "webp"].map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn ignores_ordinary_names() {
+        for name in [
+            "plain.psd",
+            "v1.2.psd",
+            "photo.q0.psd",
+            "photo.q101.psd",
+            "a@x.psd",
+            "a@-1x.psd",
+            "a@infx.psd",
+        ] {
+            let conventions = parse(name);
+            assert_eq!(conventions, Conventions::default(), "{}", name);
+            assert!(conventions.to_args().is_empty());
+        }
+        // 同一种设置只取最后出现的一个，其余部分留在文件名中
+        assert_eq!(
+            parse("a.png.jpg.psd"),
+            Conventions {
+                stem: Some("a.png".to_string()),
+                format: Some(ExportFormat::Jpg),
+                ..Conventions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn sanitize_replaces_invalid_characters() {
+        assert_eq!(sanitize("a<b>c:d.png", "_"), "a_b_c_d.png");
+        assert_eq!(sanitize("a/b\\c|d?e*\"f", "-"), "a-b-c-d-e--f");
+        assert_eq!(sanitize("tab\there\u{7}", "_"), "tab_here_");
+        assert_eq!(sanitize("a:b", ""), "ab");
+        assert_eq!(sanitize("正常的名字.png", "_"), "正常的名字.png");
+    }
+
+    #[test]
+    fn sanitize_handles_reserved_and_empty_names() {
+        assert_eq!(sanitize("CON.png", "_"), "CON_.png");
+        assert_eq!(sanitize("con", "-"), "con-");
+        assert_eq!(sanitize("lpt9.tar.gz", ""), "lpt9_.tar.gz");
+        assert_eq!(sanitize("COM10.png", "_"), "COM10.png");
+        assert_eq!(sanitize("CONSOLE.png", "_"), "CONSOLE.png");
+        // 结尾的点与空格在 Windows 上会被去掉
+        assert_eq!(sanitize("name. . ", "_"), "name");
+        assert_eq!(sanitize("nul. ", "_"), "nul_");
+        assert_eq!(sanitize("", "_"), "_");
+        assert_eq!(sanitize("...", "_"), "_");
+        assert_eq!(sanitize("???", ""), "_");
+    }
+
+    #[test]
+    fn sanitize_path_keeps_directories() {
+        assert_eq!(
+            sanitize_path(Path::new("out/a:b.png"), "_"),
+            PathBuf::from("out/a_b.png")
+        );
+        assert_eq!(
+            sanitize_path(Path::new("a:dir/ok.png"), "_"),
+            PathBuf::from("a:dir/ok.png")
+        );
+        assert!(parse_replacement("-").is_ok());
+        assert!(parse_replacement("/").is_err());
+        assert!(parse_replacement("\n").is_err());
+    }
+}
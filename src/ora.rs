@@ -0,0 +1,204 @@
+//! OpenRaster (.ora) 导出：每个图层保存为 PNG，并写入描述图层结构的 stack.xml，
+//! GIMP / Krita 打开后仍然可以编辑各个图层。
+
+use std::{
+    fmt::Write as _,
+    io::{Cursor, Write},
+};
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use psd::{Psd, PsdLayer};
+
+use crate::{
+    ExportFormat,
+    encode::{self, EncodeOptions},
+    layermask::LayerMask,
+    transform::{self, ResizeFilter, ResizeSpec},
+    zip::ZipWriter,
+};
+
+/// ORA 规范建议的缩略图最大边长
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// 将 PSD 的图层写入 ORA 文件，返回合并后的图像。`masks` 不为空时把其中启用的图层蒙版
+/// 乘到对应图层的透明度上，顺序与 `psd.layers()` 一致
+pub fn write_ora<W: Write>(
+    psd: &Psd,
+    dpi: Option<(f64, f64)>,
+    filter: ResizeFilter,
+    masks: &[Option<LayerMask>],
+    writer: W,
+) -> Result<RgbaImage> {
+    let (width, height) = (psd.width(), psd.height());
+    let merged = RgbaImage::from_raw(width, height, psd.rgba())
+        .context("无法创建 ImageBuffer，可能是图像数据或尺寸问题")?;
+
+    let mut zip = ZipWriter::new(writer);
+    // mimetype 必须是第一个且不压缩的文件
+    zip.add_stored("mimetype", b"image/openraster")?;
+
+    let (xres, yres) = dpi.unwrap_or((72.0, 72.0));
+    let mut stack = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <image version=\"0.0.3\" w=\"{}\" h=\"{}\" xres=\"{}\" yres=\"{}\">\n<stack>\n",
+        width,
+        height,
+        xres.round(),
+        yres.round()
+    );
+
+    // psd crate 的图层顺序为自顶向下，与 stack.xml 一致。
+    // 同一分组的图层是连续的，按祖先分组链的变化来打开/关闭嵌套的 stack
+    let mut open_groups: Vec<u32> = Vec::new();
+    for (index, layer) in psd.layers().iter().enumerate() {
+        let chain = group_chain(psd, layer);
+        let common = open_groups
+            .iter()
+            .zip(&chain)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for _ in common..open_groups.len() {
+            stack.push_str("</stack>\n");
+        }
+        open_groups.truncate(common);
+        for &id in &chain[common..] {
+            let group = &psd.groups()[&id];
+            _ = writeln!(
+                stack,
+                "<stack name=\"{}\" visibility=\"{}\" opacity=\"{:.3}\" \
+                 composite-op=\"{}\" isolation=\"{}\">",
+                escape(group.name()),
+                visibility(group.visible()),
+                group.opacity() as f32 / 255.0,
+                composite_op(&format!("{:?}", group.blend_mode())),
+                if format!("{:?}", group.blend_mode()) == "PassThrough" {
+                    "auto"
+                } else {
+                    "isolate"
+                },
+            );
+            open_groups.push(id);
+        }
+
+        let (x, y, mut pixels) = crop_layer(layer, width, height)?;
+        if let Some(Some(mask)) = masks.get(index)
+            && !mask.disabled
+        {
+            mask.apply(&mut pixels, x, y);
+        }
+        let src = format!("data/layer{}.png", index);
+        zip.add_stored(&src, &encode_png(&pixels)?)?;
+        _ = writeln!(
+            stack,
+            "<layer name=\"{}\" src=\"{}\" x=\"{}\" y=\"{}\" visibility=\"{}\" \
+             opacity=\"{:.3}\" composite-op=\"{}\"/>",
+            escape(layer.name()),
+            src,
+            x,
+            y,
+            visibility(layer.visible()),
+            layer.opacity() as f32 / 255.0,
+            composite_op(&format!("{:?}", layer.blend_mode())),
+        );
+    }
+    for _ in &open_groups {
+        stack.push_str("</stack>\n");
+    }
+
+    // 没有图层信息的 PSD（只有合并图像）把合并图像作为唯一图层
+    if psd.layers().is_empty() {
+        zip.add_stored("data/layer0.png", &encode_png(&merged)?)?;
+        stack.push_str("<layer name=\"Background\" src=\"data/layer0.png\" x=\"0\" y=\"0\"/>\n");
+    }
+    stack.push_str("</stack>\n</image>\n");
+
+    zip.add_deflated("stack.xml", stack.as_bytes())?;
+    zip.add_stored("mergedimage.png", &encode_png(&merged)?)?;
+    let thumbnail = transform::resize(
+        merged.clone(),
+        ResizeSpec::Fit(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        filter,
+    );
+    zip.add_stored("Thumbnails/thumbnail.png", &encode_png(&thumbnail)?)?;
+    zip.finish()?;
+
+    Ok(merged)
+}
+
+/// 返回图层所在的分组链，从最外层到最内层
+fn group_chain(psd: &Psd, layer: &PsdLayer) -> Vec<u32> {
+    let mut chain = Vec::new();
+    let mut parent = layer.parent_id();
+    while let Some(id) = parent {
+        let Some(group) = psd.groups().get(&id) else {
+            break;
+        };
+        chain.push(id);
+        parent = group.parent_id();
+    }
+    chain.reverse();
+    chain
+}
+
+/// 将整幅画布大小的图层像素裁剪到图层范围内，返回 (x, y, 像素)
+fn crop_layer(layer: &PsdLayer, width: u32, height: u32) -> Result<(u32, u32, RgbaImage)> {
+    let canvas = RgbaImage::from_raw(width, height, layer.rgba())
+        .context(format!("无法读取图层像素：{}", layer.name()))?;
+    let left = layer.layer_left().clamp(0, width as i32) as u32;
+    let top = layer.layer_top().clamp(0, height as i32) as u32;
+    let right = layer.layer_right().clamp(0, width as i32) as u32;
+    let bottom = layer.layer_bottom().clamp(0, height as i32) as u32;
+    if right <= left || bottom <= top {
+        return Ok((0, 0, RgbaImage::new(1, 1)));
+    }
+    let cropped =
+        image::imageops::crop_imm(&canvas, left, top, right - left, bottom - top).to_image();
+    Ok((left, top, cropped))
+}
+
+fn encode_png(img: &RgbaImage) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    encode::write_image(
+        img,
+        &ExportFormat::Png,
+        None,
+        &EncodeOptions::default(),
+        &mut buffer,
+    )?;
+    Ok(buffer.into_inner())
+}
+
+fn visibility(visible: bool) -> &'static str {
+    if visible { "visible" } else { "hidden" }
+}
+
+/// 将 Photoshop 混合模式映射为 ORA 的 composite-op，无对应模式时退回正常混合。
+/// psd crate 没有公开 `BlendMode` 类型的路径，只能按其 Debug 名称匹配
+fn composite_op(mode: &str) -> &'static str {
+    match mode {
+        "Multiply" => "svg:multiply",
+        "Screen" => "svg:screen",
+        "Overlay" => "svg:overlay",
+        "Darken" => "svg:darken",
+        "Lighten" => "svg:lighten",
+        "ColorDodge" => "svg:color-dodge",
+        "ColorBurn" => "svg:color-burn",
+        "HardLight" => "svg:hard-light",
+        "SoftLight" => "svg:soft-light",
+        "Difference" => "svg:difference",
+        "Hue" => "svg:hue",
+        "Saturation" => "svg:saturation",
+        "Color" => "svg:color",
+        "Luminosity" => "svg:luminosity",
+        "LinearDodge" => "svg:plus",
+        _ => "svg:src-over",
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
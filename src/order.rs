@@ -0,0 +1,85 @@
+//! 一次性模式下源文件的处理顺序（`--order`）与按文件大小的调度策略（`--schedule`）。
+
+use std::{
+    collections::{VecDeque, hash_map::RandomState},
+    hash::BuildHasher,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Order {
+    /// 按路径字母顺序，日志可复现
+    Path,
+    /// 最近修改的文件优先
+    Mtime,
+    /// 小文件优先
+    Size,
+    /// 随机顺序
+    Random,
+}
+
+/// 线程池取任务的策略，在 `--order` 排序之后应用
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Schedule {
+    /// 直接按处理顺序
+    #[default]
+    Fifo,
+    /// 小文件优先，避免大量小图排在一个巨大的文件后面
+    SmallestFirst,
+    /// 大小文件交替，大文件尽早开始，其余线程同时处理小文件
+    Interleave,
+}
+
+/// 按指定顺序排列文件；无法读取元数据的文件排在最后
+pub fn sort(files: &mut [PathBuf], order: Order) {
+    match order {
+        Order::Path => files.sort(),
+        Order::Mtime => files.sort_by_cached_key(|path| {
+            let modified = path.metadata().and_then(|m| m.modified()).ok();
+            std::cmp::Reverse(modified.unwrap_or(SystemTime::UNIX_EPOCH))
+        }),
+        Order::Size => {
+            files.sort_by_cached_key(|path| path.metadata().map_or(u64::MAX, |m| m.len()))
+        }
+        Order::Random => shuffle(files),
+    }
+}
+
+/// Fisher–Yates 洗牌，随机数来自标准库 HashMap 的随机种子
+fn shuffle(files: &mut [PathBuf]) {
+    let mut state = RandomState::new().hash_one(0u64) | 1;
+    for i in (1..files.len()).rev() {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        files.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// 按调度策略重新排列文件。排序是稳定的，大小相同的文件保持原有顺序
+pub fn schedule(files: Vec<PathBuf>, schedule: Schedule) -> Vec<PathBuf> {
+    if matches!(schedule, Schedule::Fifo) {
+        return files;
+    }
+    let mut sized: Vec<(u64, PathBuf)> = files
+        .into_iter()
+        .map(|path| (path.metadata().map_or(u64::MAX, |m| m.len()), path))
+        .collect();
+    sized.sort_by_key(|(size, _)| *size);
+    let mut sized: VecDeque<PathBuf> = sized.into_iter().map(|(_, path)| path).collect();
+    match schedule {
+        Schedule::Interleave => {
+            let mut files = Vec::with_capacity(sized.len());
+            while let Some(largest) = sized.pop_back() {
+                files.push(largest);
+                files.extend(sized.pop_front());
+            }
+            files
+        }
+        _ => sized.into(),
+    }
+}
@@ -0,0 +1,207 @@
+//! 大图 PNG 的多线程编码：按行分块，各块在线程池中独立完成滤波与 deflate 压缩，
+//! 块尾做一次同步刷新（与 pigz 相同的做法），这样压缩结果可以直接按顺序拼接成
+//! 一个 zlib 流写入 IDAT。只处理 RGBA 8/16 位，索引色仍由 png crate 编码。
+
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use flate2::{Compress, Compression, FlushCompress, Status};
+use rayon::prelude::*;
+
+/// 像素数达到该值时才使用多线程编码，小图分块只会降低压缩率
+pub const MIN_PIXELS: u64 = 16 << 20;
+
+/// 每块未压缩数据的目标大小
+const CHUNK_BYTES: usize = 4 << 20;
+
+/// 编码一张 RGBA PNG。`rows(range)` 返回指定行范围的原始像素（16 位为大端），
+/// `pixels_per_meter` 写入 pHYs 块
+pub fn encode(
+    writer: &mut dyn Write,
+    (width, height): (u32, u32),
+    bit_depth: u8,
+    level: u32,
+    pixels_per_meter: Option<(u32, u32)>,
+    rows: impl Fn(Range<usize>) -> Vec<u8> + Sync,
+) -> io::Result<()> {
+    let bpp = 4 * bit_depth as usize / 8;
+    let row_len = width as usize * bpp;
+    let height = height as usize;
+    let chunk_rows = (CHUNK_BYTES / row_len.max(1)).max(1);
+    let chunks: Vec<Range<usize>> = (0..height)
+        .step_by(chunk_rows)
+        .map(|start| start..(start + chunk_rows).min(height))
+        .collect();
+
+    let compressed: Vec<(Vec<u8>, u32, usize)> = chunks
+        .par_iter()
+        .enumerate()
+        .map(|(i, range)| {
+            // 滤波需要上一行，块的第一行从前一块借用
+            let first = range.start.saturating_sub(1);
+            let data = rows(first..range.end);
+            let mut previous = if first < range.start {
+                &data[..row_len]
+            } else {
+                &[][..]
+            };
+            let mut filtered = Vec::with_capacity(range.len() * (row_len + 1));
+            for row in data[(range.start - first) * row_len..].chunks_exact(row_len) {
+                filter_row(row, previous, bpp, &mut filtered);
+                previous = row;
+            }
+            let last = i + 1 == chunks.len();
+            let deflated = deflate(&filtered, Compression::new(level), last)?;
+            Ok((deflated, adler32(&filtered), filtered.len()))
+        })
+        .collect::<io::Result<_>>()?;
+
+    writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 位深、颜色类型 6 (RGBA)、压缩、滤波、隔行
+    ihdr.extend_from_slice(&[bit_depth, 6, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &ihdr)?;
+    if let Some((x, y)) = pixels_per_meter {
+        let mut phys = Vec::with_capacity(9);
+        phys.extend_from_slice(&x.to_be_bytes());
+        phys.extend_from_slice(&y.to_be_bytes());
+        phys.push(1); // 单位：米
+        write_chunk(writer, b"pHYs", &phys)?;
+    }
+
+    // zlib 头（deflate、32K 窗口、默认级别）+ 各块 + 整体的 Adler-32
+    let mut checksum = 1;
+    let count = compressed.len();
+    for (i, (deflated, adler, len)) in compressed.into_iter().enumerate() {
+        checksum = adler32_combine(checksum, adler, len);
+        let mut idat = if i == 0 { vec![0x78, 0x9C] } else { Vec::new() };
+        idat.extend_from_slice(&deflated);
+        if i + 1 == count {
+            idat.extend_from_slice(&checksum.to_be_bytes());
+        }
+        write_chunk(writer, b"IDAT", &idat)?;
+    }
+    write_chunk(writer, b"IEND", &[])
+}
+
+/// 写入一行：依次尝试五种滤波器，选绝对值之和最小的一种（libpng 的启发式）
+fn filter_row(row: &[u8], previous: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    let up = |i: usize| previous.get(i).copied().unwrap_or(0);
+    let left = |i: usize| if i >= bpp { row[i - bpp] } else { 0 };
+    let upper_left = |i: usize| if i >= bpp { up(i - bpp) } else { 0 };
+    let filtered = |filter: u8, i: usize| {
+        let predictor = match filter {
+            0 => 0,
+            1 => left(i),
+            2 => up(i),
+            3 => ((left(i) as u16 + up(i) as u16) / 2) as u8,
+            _ => paeth(left(i), up(i), upper_left(i)),
+        };
+        row[i].wrapping_sub(predictor)
+    };
+
+    let best = (0..5u8)
+        .min_by_key(|&filter| {
+            (0..row.len())
+                .map(|i| (filtered(filter, i) as i8).unsigned_abs() as u64)
+                .sum::<u64>()
+        })
+        .unwrap_or(0);
+    out.push(best);
+    out.extend((0..row.len()).map(|i| filtered(best, i)));
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// 压缩为原始 deflate 数据。中间块以同步刷新结束（对齐到字节且不设置结束标记），
+/// 最后一块正常结束
+fn deflate(data: &[u8], level: Compression, last: bool) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    let flush = if last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+    let mut out = Vec::with_capacity(data.len() / 2 + 64);
+    loop {
+        let consumed = compress.total_in() as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], &mut out, flush)
+            .map_err(io::Error::other)?;
+        let done = match status {
+            Status::StreamEnd => true,
+            // 输出缓冲还有剩余空间说明刷新已经完成
+            _ => !last && compress.total_in() as usize == data.len() && out.len() < out.capacity(),
+        };
+        if done {
+            return Ok(out);
+        }
+        out.reserve(out.capacity().max(64));
+    }
+}
+
+const ADLER_BASE: u32 = 65521;
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 是保证 b 不溢出 u32 的最大块长
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= ADLER_BASE;
+        b %= ADLER_BASE;
+    }
+    (b << 16) | a
+}
+
+/// 由两段数据各自的 Adler-32 得到拼接后的校验和（zlib 的 adler32_combine）
+fn adler32_combine(first: u32, second: u32, second_len: usize) -> u32 {
+    let rem = (second_len % ADLER_BASE as usize) as u32;
+    let mut a = first & 0xFFFF;
+    let mut b = rem * a % ADLER_BASE;
+    a += (second & 0xFFFF) + ADLER_BASE - 1;
+    b += (first >> 16) + (second >> 16) + ADLER_BASE - rem;
+    if a >= ADLER_BASE {
+        a -= ADLER_BASE;
+    }
+    if a >= ADLER_BASE {
+        a -= ADLER_BASE;
+    }
+    if b >= ADLER_BASE * 2 {
+        b -= ADLER_BASE * 2;
+    }
+    if b >= ADLER_BASE {
+        b -= ADLER_BASE;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(writer: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&hasher.finalize().to_be_bytes())
+}
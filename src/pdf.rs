@@ -0,0 +1,191 @@
+//! 极简 PDF 写入：每页放一张栅格图像，支持透明通道 (SMask)，不依赖外部库。
+
+use std::io::{self, Write};
+
+use flate2::{Compression, write::ZlibEncoder};
+use image::RgbaImage;
+
+use crate::pixel;
+
+/// PDF 的默认用户空间单位：1/72 英寸
+const POINTS_PER_INCH: f64 = 72.0;
+
+/// 未知分辨率时按 72 DPI 计算页面尺寸（1 像素 = 1 pt）
+const DEFAULT_DPI: (f64, f64) = (72.0, 72.0);
+
+/// 已压缩好的一页，可以在多个线程中并行生成后再按顺序写入
+pub struct Page {
+    width: u32,
+    height: u32,
+    /// 页面尺寸（pt）
+    size: (f64, f64),
+    /// zlib 压缩后的 RGB 数据
+    rgb: Vec<u8>,
+    /// zlib 压缩后的 alpha 数据，图像完全不透明时为空
+    alpha: Option<Vec<u8>>,
+}
+
+impl Page {
+    /// 按图像与分辨率生成一页，页面物理尺寸 = 像素 / DPI
+    pub fn new(img: &RgbaImage, dpi: Option<(f64, f64)>) -> io::Result<Self> {
+        let (dpi_x, dpi_y) = dpi.unwrap_or(DEFAULT_DPI);
+        let mut rgb = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut alpha = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut opaque = true;
+        for row in img.as_raw().chunks(img.width() as usize * 4) {
+            let mut rgb_row = Vec::new();
+            let mut alpha_row = Vec::new();
+            opaque &= pixel::split_alpha(row, &mut rgb_row, &mut alpha_row);
+            rgb.write_all(&rgb_row)?;
+            alpha.write_all(&alpha_row)?;
+        }
+        Ok(Page {
+            width: img.width(),
+            height: img.height(),
+            size: (
+                img.width() as f64 * POINTS_PER_INCH / dpi_x,
+                img.height() as f64 * POINTS_PER_INCH / dpi_y,
+            ),
+            rgb: rgb.finish()?,
+            alpha: if opaque { None } else { Some(alpha.finish()?) },
+        })
+    }
+}
+
+/// 逐页写入的 PDF 文件
+pub struct PdfWriter<W: Write> {
+    out: W,
+    offset: u64,
+    /// 每个对象的字节偏移，下标为对象编号 - 1
+    objects: Vec<u64>,
+    pages: Vec<u32>,
+}
+
+/// 目录对象与页面树对象的编号是固定的
+const CATALOG_ID: u32 = 1;
+const PAGES_ID: u32 = 2;
+
+impl<W: Write> PdfWriter<W> {
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut writer = PdfWriter {
+            out,
+            offset: 0,
+            objects: vec![0; 2],
+            pages: Vec::new(),
+        };
+        writer.write(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        writer.begin_object(CATALOG_ID)?;
+        writer
+            .write(format!("<< /Type /Catalog /Pages {} 0 R >>\nendobj\n", PAGES_ID).as_bytes())?;
+        Ok(writer)
+    }
+
+    /// 追加一页
+    pub fn add_page(&mut self, page: &Page) -> io::Result<()> {
+        let smask_id = match &page.alpha {
+            Some(alpha) => {
+                let id = self.next_id();
+                self.begin_object(id)?;
+                self.write_image_stream(page, alpha, "/DeviceGray", "")?;
+                Some(id)
+            }
+            None => None,
+        };
+
+        let image_id = self.next_id();
+        self.begin_object(image_id)?;
+        let smask = smask_id
+            .map(|id| format!(" /SMask {} 0 R", id))
+            .unwrap_or_default();
+        self.write_image_stream(page, &page.rgb, "/DeviceRGB", &smask)?;
+
+        let (w, h) = page.size;
+        let content = format!("q {:.4} 0 0 {:.4} 0 0 cm /Im0 Do Q\n", w, h);
+        let content_id = self.next_id();
+        self.begin_object(content_id)?;
+        self.write(format!("<< /Length {} >>\nstream\n", content.len()).as_bytes())?;
+        self.write(content.as_bytes())?;
+        self.write(b"endstream\nendobj\n")?;
+
+        let page_id = self.next_id();
+        self.begin_object(page_id)?;
+        self.write(
+            format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.4} {:.4}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>\nendobj\n",
+                PAGES_ID, w, h, image_id, content_id
+            )
+            .as_bytes(),
+        )?;
+        self.pages.push(page_id);
+        Ok(())
+    }
+
+    /// 写入页面树、交叉引用表与文件尾
+    pub fn finish(mut self) -> io::Result<W> {
+        self.begin_object(PAGES_ID)?;
+        let kids: Vec<String> = self.pages.iter().map(|id| format!("{} 0 R", id)).collect();
+        self.write(
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>\nendobj\n",
+                kids.join(" "),
+                self.pages.len()
+            )
+            .as_bytes(),
+        )?;
+
+        let xref_offset = self.offset;
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in &self.objects {
+            xref.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        xref.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1,
+            CATALOG_ID,
+            xref_offset
+        ));
+        self.write(xref.as_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_image_stream(
+        &mut self,
+        page: &Page,
+        data: &[u8],
+        color_space: &str,
+        extra: &str,
+    ) -> io::Result<()> {
+        self.write(
+            format!(
+                "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} \
+                 /BitsPerComponent 8 /Filter /FlateDecode /Length {}{} >>\nstream\n",
+                page.width,
+                page.height,
+                color_space,
+                data.len(),
+                extra
+            )
+            .as_bytes(),
+        )?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.objects.push(0);
+        self.objects.len() as u32
+    }
+
+    fn begin_object(&mut self, id: u32) -> io::Result<()> {
+        self.objects[id as usize - 1] = self.offset;
+        self.write(format!("{} 0 obj\n", id).as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! 通过 COM 调用本机安装的 Photoshop 完成合并与导出（仅 Windows）。psd crate 无法
+//! 还原智能对象与图层效果，最终交付需要与 Photoshop 完全一致时使用。
+//!
+//! 由 Windows 自带的 `cscript` 运行一段 JScript，经 `Photoshop.Application`
+//! 打开文档并另存为 PNG，再按普通流程继续处理。
+
+use std::{path::Path, process::Command, sync::Mutex};
+
+use anyhow::{Context, Result, bail};
+
+use crate::source::{self, Decoded};
+
+/// 打开文档、另存为 PNG 并关闭，不显示任何对话框
+const SCRIPT: &str = r#"var app = new ActiveXObject("Photoshop.Application");
+app.DisplayDialogs = 3;
+var doc = app.Open(WScript.Arguments(0));
+try {
+    doc.SaveAs(WScript.Arguments(1), new ActiveXObject("Photoshop.PNGSaveOptions"), true);
+} finally {
+    doc.Close(2);
+}
+"#;
+
+/// 附加图层信息中表示智能对象与图层效果的键，以及对应的功能名称
+const LIVE_KEYS: &[(&[u8; 4], &str)] = &[
+    (b"SoLd", "智能对象"),
+    (b"SoLE", "智能对象"),
+    (b"PlLd", "智能对象"),
+    (b"lfx2", "图层效果"),
+    (b"lmfx", "图层效果"),
+    (b"lrFX", "图层效果"),
+];
+
+/// 文档是否包含 psd crate 无法还原的智能对象或图层效果
+pub fn needs_photoshop(bytes: &[u8]) -> bool {
+    !live_features(bytes).is_empty()
+}
+
+/// 文档中 psd crate 无法还原的功能名称，不重复
+pub fn live_features(bytes: &[u8]) -> Vec<&'static str> {
+    let mut features = Vec::new();
+    for w in bytes.windows(8).filter(|w| &w[..4] == b"8BIM") {
+        if let Some((_, name)) = LIVE_KEYS.iter().find(|(key, _)| &w[4..] == *key)
+            && !features.contains(name)
+        {
+            features.push(*name);
+        }
+    }
+    features
+}
+
+/// 用 Photoshop 把源文件转换为图像
+pub fn convert(path: &Path) -> Result<Decoded> {
+    if !cfg!(windows) {
+        bail!("Photoshop 自动化仅支持 Windows");
+    }
+    // Photoshop 同一时间只能可靠地处理一个脚本请求
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let script = std::env::temp_dir().join(format!("pae-photoshop-{}.js", std::process::id()));
+    std::fs::write(&script, SCRIPT).context(format!("无法写入脚本：{:?}", script))?;
+    // COM 需要绝对路径
+    let input = std::path::absolute(path).context(format!("无法解析路径：{:?}", path))?;
+    let decoded = source::run_converter(path, |output| {
+        let mut command = Command::new("cscript");
+        command
+            .args(["//nologo", "//E:JScript"])
+            .arg(&script)
+            .arg(&input)
+            .arg(output);
+        command
+    })
+    .context("Photoshop 导出失败");
+    _ = std::fs::remove_file(&script);
+    decoded
+}
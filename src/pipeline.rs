@@ -0,0 +1,149 @@
+//! 声明式的处理流程（`steps`）：按顺序执行的变换步骤，取代默认固定的
+//! 旋转 → 翻转 → 缩放 → 填充 → 水印顺序，使导出方案可以写在配置文件里共享。
+//!
+//! 每一步写作 `名称` 或 `名称:参数`，例如
+//! `steps = ["trim", "resize:2048", "watermark:logo.png", "encode:webp"]`。
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use image::RgbaImage;
+
+use crate::{
+    ExportFormat, ExportOptions, plugin,
+    stamp::{self, Stamp, StampPosition},
+    transform::{self, Flip, PadSpec, ResizeSpec, Rotation},
+};
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// 裁掉透明边缘
+    Trim,
+    Rotate(Rotation),
+    Flip(Flip),
+    Resize(ResizeSpec),
+    /// 填充画布，颜色取 `--pad-color`
+    Pad(PadSpec),
+    /// 文字水印，位置与大小取 `--stamp-position` 与 `--stamp-scale`
+    Stamp(Stamp),
+    /// 图片水印，位置取 `--stamp-position`
+    Watermark(PathBuf, StampPosition),
+    /// 交给外部程序处理，见 [`crate::plugin`]
+    Plugin(String),
+    /// 输出格式，覆盖 `--format`，不改变图像
+    Encode(ExportFormat),
+}
+
+/// 解析一个步骤
+pub fn parse_step(s: &str) -> Result<Step> {
+    let s = s.trim();
+    let (name, arg) = match s.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg.trim())),
+        None => (s, None),
+    };
+    let arg = || {
+        arg.filter(|a| !a.is_empty())
+            .context(format!("步骤 {} 缺少参数", name))
+    };
+    Ok(match name {
+        "trim" => Step::Trim,
+        "rotate" => Step::Rotate(parse_enum(arg()?)?),
+        "flip" => Step::Flip(parse_enum(arg()?)?),
+        "resize" => Step::Resize(transform::parse_resize(arg()?)?),
+        "pad" => Step::Pad(transform::parse_pad(arg()?)?),
+        "stamp" => Step::Stamp(Stamp {
+            template: arg()?.to_string(),
+            position: StampPosition::default(),
+            scale: None,
+        }),
+        "watermark" => Step::Watermark(PathBuf::from(arg()?), StampPosition::default()),
+        "plugin" => Step::Plugin(arg()?.to_string()),
+        "encode" => Step::Encode(parse_enum(arg()?)?),
+        _ => bail!(
+            "未知的步骤 {}，可用：trim、rotate、flip、resize、pad、stamp、watermark、plugin、encode",
+            name
+        ),
+    })
+}
+
+impl Step {
+    /// 水印步骤使用 `--stamp-position` 与 `--stamp-scale` 的设置
+    pub fn placed(self, position: StampPosition, scale: Option<u32>) -> Step {
+        match self {
+            Step::Stamp(stamp) => Step::Stamp(Stamp {
+                position,
+                scale,
+                ..stamp
+            }),
+            Step::Watermark(path, _) => Step::Watermark(path, position),
+            step => step,
+        }
+    }
+}
+
+fn parse_enum<T: ValueEnum>(arg: &str) -> Result<T> {
+    T::from_str(arg, true).map_err(|_| {
+        let values: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect();
+        anyhow::anyhow!("无效的参数 {}，可选：{}", arg, values.join(", "))
+    })
+}
+
+/// 流程中最后一个 `encode` 步骤指定的输出格式
+pub fn output_format(steps: &[Step]) -> Option<ExportFormat> {
+    steps.iter().rev().find_map(|step| match step {
+        Step::Encode(format) => Some(format.clone()),
+        _ => None,
+    })
+}
+
+/// 依次执行各步骤，分辨率随缩放同比例换算，旋转 90/270 度时交换
+pub fn run(
+    mut img: RgbaImage,
+    mut dpi: Option<(f64, f64)>,
+    source: &Path,
+    options: &ExportOptions,
+) -> Result<(RgbaImage, Option<(f64, f64)>)> {
+    for step in &options.steps {
+        let width = img.width();
+        img = match step {
+            Step::Trim => transform::trim(img),
+            Step::Rotate(rotation) => {
+                if matches!(rotation, Rotation::Deg90 | Rotation::Deg270) {
+                    dpi = dpi.map(|(x, y)| (y, x));
+                }
+                transform::orient(img, Some(*rotation), &[])
+            }
+            Step::Flip(flip) => transform::orient(img, None, &[*flip]),
+            Step::Resize(spec) => {
+                let img = transform::resize(img, *spec, options.filter);
+                let ratio = img.width() as f64 / width as f64;
+                dpi = dpi.map(|(x, y)| (x * ratio, y * ratio));
+                img
+            }
+            Step::Pad(spec) => {
+                let (img, ratio) = transform::pad(img, *spec, options.pad_color, options.filter)?;
+                dpi = dpi.map(|(x, y)| (x * ratio, y * ratio));
+                img
+            }
+            Step::Stamp(stamp) => {
+                stamp.apply(&mut img, source);
+                img
+            }
+            Step::Watermark(path, position) => {
+                let mark = image::open(path)
+                    .context(format!("无法读取水印图片：{:?}", path))?
+                    .into_rgba8();
+                stamp::watermark(&mut img, &mark, *position);
+                img
+            }
+            Step::Plugin(command) => plugin::run(command, img, dpi, source)?,
+            Step::Encode(_) => img,
+        };
+    }
+    Ok((img, dpi))
+}
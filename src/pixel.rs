@@ -0,0 +1,141 @@
+//! 大画布上的逐像素转换：背景合成、8 位扩展到 16 位、拆分 RGB 与 alpha。
+//! x86_64 上使用 SSE2（该架构必定支持，无需运行时检测）一次处理 4 个像素，
+//! 剩余不足 4 个的像素以及其它架构走标量实现，两条路径的结果完全一致。
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// 将 RGBA 像素按 alpha 合成到纯色背景上，写入 `rgb`（长度必须为像素数 × 3）
+pub fn flatten(rgba: &[u8], background: [u8; 3], rgb: &mut [u8]) {
+    assert_eq!(rgba.len() / 4 * 3, rgb.len());
+    let done = flatten_simd(rgba, background, rgb);
+    for (src, dst) in rgba[done * 4..]
+        .chunks_exact(4)
+        .zip(rgb[done * 3..].chunks_exact_mut(3))
+    {
+        let a = src[3] as u32;
+        for c in 0..3 {
+            let v = src[c] as u32 * a + background[c] as u32 * (255 - a);
+            dst[c] = ((v + 127) / 255) as u8;
+        }
+    }
+}
+
+/// 将 8 位通道按比例扩展到 16 位（0xAB -> 0xABAB）
+pub fn widen(data: &[u8]) -> Vec<u16> {
+    let mut out = vec![0; data.len()];
+    let done = widen_simd(data, &mut out);
+    for (src, dst) in data[done..].iter().zip(&mut out[done..]) {
+        *dst = *src as u16 * 257;
+    }
+    out
+}
+
+/// 将 RGBA 像素拆分为 RGB 与 alpha 两部分追加到输出中，返回这些像素是否全部不透明
+pub fn split_alpha(rgba: &[u8], rgb: &mut Vec<u8>, alpha: &mut Vec<u8>) -> bool {
+    rgb.reserve(rgba.len() / 4 * 3);
+    alpha.reserve(rgba.len() / 4);
+    for px in rgba.chunks_exact(4) {
+        rgb.extend_from_slice(&px[..3]);
+    }
+    let start = alpha.len();
+    let done = alpha_simd(rgba, alpha);
+    alpha.extend(rgba[done * 4..].chunks_exact(4).map(|px| px[3]));
+    alpha[start..].iter().all(|&a| a == 255)
+}
+
+/// 处理完整的 4 像素块，返回已处理的像素数
+#[cfg(target_arch = "x86_64")]
+fn flatten_simd(rgba: &[u8], background: [u8; 3], rgb: &mut [u8]) -> usize {
+    let blocks = rgba.len() / 16;
+    // SAFETY: SSE2 是 x86_64 的基础指令集；读写都在切片范围内，并使用非对齐访问
+    unsafe {
+        let zero = _mm_setzero_si128();
+        let full = _mm_set1_epi16(255);
+        let round = _mm_set1_epi16(128);
+        let [r, g, b] = background.map(|v| v as i16);
+        let bg = _mm_setr_epi16(r, g, b, 0, r, g, b, 0);
+        let mut out = [0u8; 16];
+        for i in 0..blocks {
+            let px = _mm_loadu_si128(rgba.as_ptr().add(i * 16).cast());
+            let lo = blend(_mm_unpacklo_epi8(px, zero), bg, full, round);
+            let hi = blend(_mm_unpackhi_epi8(px, zero), bg, full, round);
+            _mm_storeu_si128(out.as_mut_ptr().cast(), _mm_packus_epi16(lo, hi));
+            let dst = &mut rgb[i * 12..i * 12 + 12];
+            for p in 0..4 {
+                dst[p * 3..p * 3 + 3].copy_from_slice(&out[p * 4..p * 4 + 3]);
+            }
+        }
+    }
+    blocks * 4
+}
+
+/// 两个像素（8 个 16 位通道）的 `(src * a + bg * (255 - a)) / 255`，四舍五入
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn blend(px: __m128i, bg: __m128i, full: __m128i, round: __m128i) -> __m128i {
+    unsafe {
+        // 把每个像素的 alpha 广播到该像素的 4 个通道
+        let a = _mm_shufflehi_epi16(_mm_shufflelo_epi16(px, 0xFF), 0xFF);
+        // 乘积之和不超过 255 * 255，不会溢出 16 位
+        let v = _mm_add_epi16(
+            _mm_mullo_epi16(px, a),
+            _mm_mullo_epi16(bg, _mm_sub_epi16(full, a)),
+        );
+        // x / 255 的整数近似：(x + 128 + ((x + 128) >> 8)) >> 8，对 0..=65025 精确
+        let v = _mm_add_epi16(v, round);
+        _mm_srli_epi16(_mm_add_epi16(v, _mm_srli_epi16(v, 8)), 8)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn widen_simd(data: &[u8], out: &mut [u16]) -> usize {
+    let blocks = data.len() / 16;
+    // SAFETY: 同上；`out` 与 `data` 长度相同
+    unsafe {
+        for i in 0..blocks {
+            let v = _mm_loadu_si128(data.as_ptr().add(i * 16).cast());
+            // 与自身交错即得到 v << 8 | v = v * 257
+            let dst = out.as_mut_ptr().add(i * 16);
+            _mm_storeu_si128(dst.cast(), _mm_unpacklo_epi8(v, v));
+            _mm_storeu_si128(dst.add(8).cast(), _mm_unpackhi_epi8(v, v));
+        }
+    }
+    blocks * 16
+}
+
+/// 每次取 16 个像素的 alpha，返回已处理的像素数
+#[cfg(target_arch = "x86_64")]
+fn alpha_simd(rgba: &[u8], alpha: &mut Vec<u8>) -> usize {
+    let blocks = rgba.len() / 64;
+    // SAFETY: 同上
+    unsafe {
+        let mut out = [0u8; 16];
+        for i in 0..blocks {
+            let load = |n: usize| {
+                let px = _mm_loadu_si128(rgba.as_ptr().add(i * 64 + n * 16).cast());
+                _mm_srli_epi32(px, 24)
+            };
+            let lo = _mm_packs_epi32(load(0), load(1));
+            let hi = _mm_packs_epi32(load(2), load(3));
+            _mm_storeu_si128(out.as_mut_ptr().cast(), _mm_packus_epi16(lo, hi));
+            alpha.extend_from_slice(&out);
+        }
+    }
+    blocks * 16
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn flatten_simd(_rgba: &[u8], _background: [u8; 3], _rgb: &mut [u8]) -> usize {
+    0
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn widen_simd(_data: &[u8], _out: &mut [u16]) -> usize {
+    0
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn alpha_simd(_rgba: &[u8], _alpha: &mut Vec<u8>) -> usize {
+    0
+}
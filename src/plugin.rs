@@ -0,0 +1,84 @@
+//! 外部程序插件（`plugin:命令` 处理步骤）。插件从标准输入读取 RGBA 像素，可以
+//! 检查图像（非零退出码使导出失败）、自行写出额外的文件，或把处理后的像素写回
+//! 标准输出，用来实现调色板检查、命名规则等各工作室特有的处理。
+//!
+//! 元数据通过环境变量传递：`PAE_SOURCE`（源文件路径）、`PAE_WIDTH`、`PAE_HEIGHT`，
+//! 以及已知分辨率时的 `PAE_DPI`（如 `300x300`）。标准输出为空表示不修改图像，
+//! 否则应以一行 `宽x高` 开头，随后是该尺寸的 RGBA 数据。命令按空白拆分参数，
+//! 不经过 shell，`{input}` 替换为源文件路径；插件的标准错误直接输出到终端。
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{Context, Result, bail};
+use image::RgbaImage;
+
+use crate::deadline;
+
+/// 运行插件，返回插件输出的图像（未输出时返回原图）
+pub fn run(
+    command: &str,
+    img: RgbaImage,
+    dpi: Option<(f64, f64)>,
+    source: &Path,
+) -> Result<RgbaImage> {
+    let mut parts = command.split_whitespace();
+    let program = parts.next().context("插件命令为空")?;
+    let mut command = Command::new(program);
+    command
+        .args(parts.map(|arg| arg.replace("{input}", &source.to_string_lossy())))
+        .env("PAE_SOURCE", source)
+        .env("PAE_WIDTH", img.width().to_string())
+        .env("PAE_HEIGHT", img.height().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    if let Some((x, y)) = dpi {
+        command.env("PAE_DPI", format!("{}x{}", x.round(), y.round()));
+    }
+    let mut child = command
+        .spawn()
+        .context(format!("无法运行插件：{}", program))?;
+
+    // 写入与读取同时进行，避免双方都等在管道缓冲区上；
+    // 只检查图像的插件可能不读完标准输入，写入失败不算错误
+    let mut stdin = child.stdin.take().context("无法写入插件的标准输入")?;
+    let output = thread::scope(|scope| {
+        scope.spawn(|| {
+            _ = stdin.write_all(img.as_raw());
+            drop(stdin);
+        });
+        deadline::wait_with_output(child)
+    })
+    .context(format!("插件执行失败：{}", program))?;
+    if !output.status.success() {
+        bail!("插件 {} 执行失败（{}）", program, output.status);
+    }
+    if output.stdout.is_empty() {
+        return Ok(img);
+    }
+
+    let newline = output
+        .stdout
+        .iter()
+        .position(|&b| b == b'\n')
+        .context(format!("插件 {} 的输出缺少 `宽x高` 头部", program))?;
+    let header = String::from_utf8_lossy(&output.stdout[..newline]);
+    let (width, height) = header
+        .trim()
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)))
+        .context(format!(
+            "插件 {} 的输出头部无效：{}",
+            program,
+            header.trim()
+        ))?;
+    RgbaImage::from_raw(width, height, output.stdout[newline + 1..].to_vec()).context(format!(
+        "插件 {} 输出的像素数据与尺寸 {}x{} 不符",
+        program, width, height
+    ))
+}
@@ -0,0 +1,70 @@
+//! 降低导出线程的优先级（`--low-priority`），使后台导出不会让正在使用的绘图软件卡顿。
+//! 只影响调用的线程，由 rayon 线程池在每个工作线程启动时调用；工作线程启动的子进程
+//! （Photoshop 回退、脚本等）继承降低后的优先级。
+
+/// nice 值，越大优先级越低
+#[cfg(all(unix, not(target_os = "macos")))]
+const NICE: libc::c_int = 10;
+
+/// 降低当前线程的 CPU 优先级，并在支持时降低 IO 优先级。失败时只记录日志
+pub fn lower_current_thread() {
+    #[cfg(target_os = "linux")]
+    {
+        // Linux 的 nice 值与 IO 优先级都按线程设置
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_BE: libc::c_int = 2;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        // SAFETY: 只传入整数参数的系统调用
+        unsafe {
+            let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+            if libc::setpriority(libc::PRIO_PROCESS, tid, NICE) != 0 {
+                log::debug!("无法降低线程优先级：{}", std::io::Error::last_os_error());
+            }
+            // 尽力而为类别中的最低级别；空闲类别在磁盘持续繁忙时可能一直得不到执行
+            let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7;
+            if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) != 0 {
+                log::debug!("无法降低 IO 优先级：{}", std::io::Error::last_os_error());
+            }
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // 后台服务质量同时降低 CPU 与 IO 优先级
+        // SAFETY: 只设置当前线程的服务质量类别
+        unsafe {
+            let result =
+                libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_BACKGROUND, 0);
+            if result != 0 {
+                log::debug!(
+                    "无法降低线程优先级：{}",
+                    std::io::Error::from_raw_os_error(result)
+                );
+            }
+        }
+    }
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+    {
+        // 其它 Unix 上 nice 值按进程设置，对整个进程生效
+        // SAFETY: 只传入整数参数
+        unsafe {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, NICE) != 0 {
+                log::debug!("无法降低进程优先级：{}", std::io::Error::last_os_error());
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        // 后台模式同时降低 CPU、IO 与内存优先级
+        const THREAD_MODE_BACKGROUND_BEGIN: i32 = 0x0001_0000;
+        unsafe extern "system" {
+            fn GetCurrentThread() -> *mut std::ffi::c_void;
+            fn SetThreadPriority(thread: *mut std::ffi::c_void, priority: i32) -> i32;
+        }
+        // SAFETY: GetCurrentThread 返回的伪句柄始终有效
+        unsafe {
+            if SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN) == 0 {
+                log::debug!("无法降低线程优先级：{}", std::io::Error::last_os_error());
+            }
+        }
+    }
+}
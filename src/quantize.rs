@@ -0,0 +1,201 @@
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use color_quant::NeuQuant;
+use image::RgbaImage;
+
+/// NeuQuant 采样因子，1 最精确，30 最快
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// 8x8 Bayer 矩阵，用于有序抖动
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// 调色板量化时使用的抖动算法
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Dither {
+    /// 不抖动，适合纯色 UI 素材
+    None,
+    /// 有序抖动 (Bayer 8x8)，图案规则、压缩率较好
+    Ordered,
+    /// Floyd–Steinberg 误差扩散，适合渐变与照片
+    #[default]
+    FloydSteinberg,
+}
+
+/// 调色板量化设置
+#[derive(Clone, Copy, Debug)]
+pub struct QuantizeOptions {
+    /// 调色板颜色数 (2-256)
+    pub colors: u16,
+    pub dither: Dither,
+    /// 抖动强度 (0.0-1.0)
+    pub strength: f32,
+}
+
+/// 量化结果：RGBA 调色板与每个像素的索引
+pub struct Quantized {
+    pub palette: Vec<[u8; 4]>,
+    pub indices: Vec<u8>,
+    /// 完全透明像素使用的索引（仅在 `binary_alpha` 模式下存在）
+    pub transparent: Option<u8>,
+}
+
+impl Quantized {
+    /// 展开回 RGBA 像素
+    pub fn to_rgba(&self, width: u32, height: u32) -> RgbaImage {
+        let raw = self
+            .indices
+            .iter()
+            .flat_map(|&i| self.palette[i as usize])
+            .collect();
+        RgbaImage::from_raw(width, height, raw).expect("像素数量与尺寸一致")
+    }
+}
+
+/// 解析 `--colors` 参数
+pub fn parse_colors(s: &str) -> Result<u16> {
+    let colors: u16 = s.trim().parse()?;
+    if !(2..=256).contains(&colors) {
+        bail!("调色板颜色数必须在 2 到 256 之间：{}", s);
+    }
+    Ok(colors)
+}
+
+/// 解析 `--dither-strength` 参数
+pub fn parse_strength(s: &str) -> Result<f32> {
+    let strength: f32 = s.trim().parse()?;
+    if !(0.0..=1.0).contains(&strength) {
+        bail!("抖动强度必须在 0.0 到 1.0 之间：{}", s);
+    }
+    Ok(strength)
+}
+
+/// 将图像量化为调色板图像。`binary_alpha` 为 true 时 alpha 只保留透明/不透明两级，
+/// 并单独保留一个透明索引（用于 GIF）
+pub fn quantize(img: &RgbaImage, options: &QuantizeOptions, binary_alpha: bool) -> Quantized {
+    let mut pixels = img.as_raw().clone();
+    let mut has_transparent = false;
+    for px in pixels.chunks_exact_mut(4) {
+        if binary_alpha {
+            px[3] = if px[3] < 128 { 0 } else { 255 };
+        }
+        // 完全透明的像素颜色没有意义，统一后可以节省调色板
+        if px[3] == 0 {
+            px.copy_from_slice(&[0, 0, 0, 0]);
+            has_transparent = true;
+        }
+    }
+
+    // binary_alpha 模式下只用不透明像素训练，并为透明色预留最后一个索引
+    let reserve = binary_alpha && has_transparent;
+    let colors = options.colors as usize - usize::from(reserve);
+    let training: Vec<u8> = if reserve {
+        pixels
+            .chunks_exact(4)
+            .filter(|px| px[3] != 0)
+            .flatten()
+            .copied()
+            .collect()
+    } else {
+        pixels.clone()
+    };
+    let training = if training.is_empty() {
+        vec![0, 0, 0, 255]
+    } else {
+        training
+    };
+    let nq = NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, colors, &training);
+    let mut palette: Vec<[u8; 4]> = nq
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .collect();
+    let transparent = reserve.then(|| {
+        palette.push([0, 0, 0, 0]);
+        (palette.len() - 1) as u8
+    });
+
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let strength = options.strength;
+    let mut indices = vec![0u8; width * height];
+    let lookup = |px: [f32; 4]| -> usize {
+        let clamped = px.map(|v| v.round().clamp(0.0, 255.0) as u8);
+        nq.index_of(&clamped)
+    };
+
+    match options.dither {
+        Dither::None | Dither::Ordered => {
+            // 有序抖动的幅度取调色板相邻颜色的大致间距
+            let spread = 255.0 / (colors as f32).cbrt() * strength;
+            for y in 0..height {
+                for x in 0..width {
+                    let i = y * width + x;
+                    let px = &pixels[i * 4..i * 4 + 4];
+                    if let (Some(t), 0) = (transparent, px[3]) {
+                        indices[i] = t;
+                        continue;
+                    }
+                    let offset = match options.dither {
+                        Dither::Ordered => (BAYER_8X8[y % 8][x % 8] as f32 / 64.0 - 0.5) * spread,
+                        _ => 0.0,
+                    };
+                    let color = [
+                        px[0] as f32 + offset,
+                        px[1] as f32 + offset,
+                        px[2] as f32 + offset,
+                        px[3] as f32,
+                    ];
+                    indices[i] = lookup(color) as u8;
+                }
+            }
+        }
+        Dither::FloydSteinberg => {
+            // 只保留当前行与下一行的误差
+            let mut current = vec![[0f32; 3]; width + 2];
+            let mut next = vec![[0f32; 3]; width + 2];
+            for y in 0..height {
+                for x in 0..width {
+                    let i = y * width + x;
+                    let px = &pixels[i * 4..i * 4 + 4];
+                    if let (Some(t), 0) = (transparent, px[3]) {
+                        indices[i] = t;
+                        continue;
+                    }
+                    let err = current[x + 1];
+                    let color = [
+                        px[0] as f32 + err[0],
+                        px[1] as f32 + err[1],
+                        px[2] as f32 + err[2],
+                        px[3] as f32,
+                    ];
+                    let index = lookup(color);
+                    indices[i] = index as u8;
+                    let chosen = palette[index];
+                    for c in 0..3 {
+                        let e = (color[c].clamp(0.0, 255.0) - chosen[c] as f32) * strength;
+                        current[x + 2][c] += e * 7.0 / 16.0;
+                        next[x][c] += e * 3.0 / 16.0;
+                        next[x + 1][c] += e * 5.0 / 16.0;
+                        next[x + 2][c] += e / 16.0;
+                    }
+                }
+                std::mem::swap(&mut current, &mut next);
+                next.iter_mut().for_each(|e| *e = [0.0; 3]);
+            }
+        }
+    }
+
+    Quantized {
+        palette,
+        indices,
+        transparent,
+    }
+}
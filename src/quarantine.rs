@@ -0,0 +1,66 @@
+//! 隔离清单（`--quarantine`）：导出超时的源文件记录在清单文件中，之后不再自动导出，
+//! 避免同一个异常的文件反复占用导出线程。从清单中删除对应的行即可恢复。
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+
+use crate::absolute;
+
+/// 读取清单中的文件（完整路径），清单不存在时为空
+pub fn load(list: &Path) -> HashSet<PathBuf> {
+    std::fs::read_to_string(list)
+        .map(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| absolute(Path::new(line)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 把文件追加到清单
+pub fn add(list: &Path, file: &Path) -> Result<()> {
+    // 多个导出线程可能同时超时
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if load(list).contains(&absolute(file)) {
+        return Ok(());
+    }
+    let mut text = absolute(file).to_string_lossy().into_owned();
+    text.push('\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(list)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .context(format!("无法写入隔离清单：{:?}", list))
+}
+
+/// 去掉清单中的文件并逐个给出提示
+pub fn filter(list: &Path, files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let quarantined = load(list);
+    if quarantined.is_empty() {
+        return files;
+    }
+    files
+        .into_iter()
+        .filter(|path| {
+            let skip = quarantined.contains(&absolute(path));
+            if skip {
+                log::warn!(
+                    "跳过已隔离的文件：{:?}，从 {:?} 中删除后恢复导出",
+                    path,
+                    list
+                );
+            }
+            !skip
+        })
+        .collect()
+}
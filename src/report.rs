@@ -0,0 +1,273 @@
+//! 一次性模式结束时写入的运行报告（`--report`），逐个列出源文件的导出状态、是否改用了
+//! 外部转换器、错误信息、
+//! 各阶段耗时以及源文件与输出文件的大小和 SHA-256，供构建看板等程序读取。扩展名为
+//! `.csv` 时写入 CSV，否则写入 JSON。
+
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+
+use crate::{Exported, sha256, timing::Timings};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Exported,
+    /// 脚本要求跳过
+    Skipped,
+    Failed,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Exported => "exported",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+/// 一个源文件的导出结果
+pub struct Entry {
+    source: PathBuf,
+    status: Status,
+    /// 是否由外部转换器解码
+    fallback: bool,
+    error: Option<String>,
+    timings: Option<Timings>,
+    outputs: Vec<PathBuf>,
+}
+
+impl Entry {
+    pub fn exported(source: &Path, exported: &Exported) -> Self {
+        Entry {
+            source: source.to_path_buf(),
+            status: Status::Exported,
+            fallback: exported.fallback,
+            error: None,
+            timings: Some(exported.timings),
+            outputs: exported.files(),
+        }
+    }
+
+    pub fn skipped(source: &Path) -> Self {
+        Entry {
+            source: source.to_path_buf(),
+            status: Status::Skipped,
+            fallback: false,
+            error: None,
+            timings: None,
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn failed(source: &Path, error: &anyhow::Error) -> Self {
+        Entry {
+            source: source.to_path_buf(),
+            status: Status::Failed,
+            fallback: false,
+            error: Some(format!("{:#}", error)),
+            timings: None,
+            outputs: Vec::new(),
+        }
+    }
+}
+
+/// 文件的大小与 SHA-256，文件无法读取时为 `None`
+struct FileInfo {
+    path: PathBuf,
+    size: Option<u64>,
+    hash: Option<String>,
+}
+
+impl FileInfo {
+    fn read(path: &Path) -> Self {
+        FileInfo {
+            path: path.to_path_buf(),
+            size: path.metadata().ok().map(|metadata| metadata.len()),
+            hash: sha256::hash_file(path).ok(),
+        }
+    }
+}
+
+struct Row<'a> {
+    entry: &'a Entry,
+    source: FileInfo,
+    outputs: Vec<FileInfo>,
+}
+
+/// 计算各文件的大小与哈希并写入报告，条目按源文件路径排序
+pub fn write(path: &Path, entries: &[Entry]) -> Result<()> {
+    let mut rows: Vec<Row> = entries
+        .par_iter()
+        .map(|entry| Row {
+            entry,
+            source: FileInfo::read(&entry.source),
+            outputs: entry.outputs.iter().map(|p| FileInfo::read(p)).collect(),
+        })
+        .collect();
+    rows.sort_by(|a, b| a.entry.source.cmp(&b.entry.source));
+    let csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let text = if csv { to_csv(&rows) } else { to_json(&rows) };
+    std::fs::write(path, text).context(format!("无法写入运行报告：{:?}", path))
+}
+
+fn to_json(rows: &[Row]) -> String {
+    let count = |status| rows.iter().filter(|r| r.entry.status == status).count();
+    let mut out = String::from("{\n");
+    _ = writeln!(out, "  \"exported\": {},", count(Status::Exported));
+    _ = writeln!(out, "  \"skipped\": {},", count(Status::Skipped));
+    _ = writeln!(out, "  \"failed\": {},", count(Status::Failed));
+    out.push_str("  \"files\": [");
+    for (i, row) in rows.iter().enumerate() {
+        let entry = row.entry;
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str("    {\n");
+        _ = writeln!(out, "      \"source\": {},", json_path(&entry.source));
+        _ = writeln!(out, "      \"status\": \"{}\",", entry.status.name());
+        _ = writeln!(out, "      \"fallback\": {},", entry.fallback);
+        _ = writeln!(
+            out,
+            "      \"error\": {},",
+            entry
+                .error
+                .as_deref()
+                .map_or("null".to_string(), json_string)
+        );
+        match &entry.timings {
+            Some(timings) => {
+                out.push_str("      \"seconds\": {");
+                for (j, (name, duration)) in stages(timings).into_iter().enumerate() {
+                    let separator = if j == 0 { "" } else { ", " };
+                    _ = write!(out, "{}\"{}\": {}", separator, name, seconds(duration));
+                }
+                out.push_str("},\n");
+            }
+            None => out.push_str("      \"seconds\": null,\n"),
+        }
+        _ = writeln!(
+            out,
+            "      \"source_size\": {},",
+            json_size(row.source.size)
+        );
+        _ = writeln!(
+            out,
+            "      \"source_sha256\": {},",
+            json_hash(&row.source.hash)
+        );
+        out.push_str("      \"outputs\": [");
+        for (j, output) in row.outputs.iter().enumerate() {
+            let separator = if j == 0 { "" } else { ", " };
+            _ = write!(
+                out,
+                "{}{{\"path\": {}, \"size\": {}, \"sha256\": {}}}",
+                separator,
+                json_path(&output.path),
+                json_size(output.size),
+                json_hash(&output.hash)
+            );
+        }
+        out.push_str("]\n    }");
+    }
+    out.push_str(if rows.is_empty() {
+        "]\n}\n"
+    } else {
+        "\n  ]\n}\n"
+    });
+    out
+}
+
+/// 每个源文件一行；有多个输出时只列出主输出
+fn to_csv(rows: &[Row]) -> String {
+    let mut out = String::from(
+        "source,status,fallback,error,total,decode,composite,transform,encode,write,\
+         source_size,source_sha256,output,output_size,output_sha256\n",
+    );
+    for row in rows {
+        let entry = row.entry;
+        let mut fields = vec![
+            csv_field(&entry.source.to_string_lossy()),
+            entry.status.name().to_string(),
+            entry.fallback.to_string(),
+            csv_field(entry.error.as_deref().unwrap_or_default()),
+        ];
+        match &entry.timings {
+            Some(timings) => fields.extend(stages(timings).map(|(_, d)| seconds(d))),
+            None => fields.extend(std::iter::repeat_n(String::new(), 6)),
+        }
+        let size = |size: Option<u64>| size.map(|s| s.to_string()).unwrap_or_default();
+        fields.push(size(row.source.size));
+        fields.push(row.source.hash.clone().unwrap_or_default());
+        match row.outputs.first() {
+            Some(output) => {
+                fields.push(csv_field(&output.path.to_string_lossy()));
+                fields.push(size(output.size));
+                fields.push(output.hash.clone().unwrap_or_default());
+            }
+            None => fields.extend(std::iter::repeat_n(String::new(), 3)),
+        }
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn stages(timings: &Timings) -> [(&'static str, Duration); 6] {
+    [
+        ("total", timings.total()),
+        ("decode", timings.decode),
+        ("composite", timings.composite),
+        ("transform", timings.transform),
+        ("encode", timings.encode),
+        ("write", timings.write),
+    ]
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+fn json_size(size: Option<u64>) -> String {
+    size.map_or("null".to_string(), |size| size.to_string())
+}
+
+fn json_hash(hash: &Option<String>) -> String {
+    hash.as_deref().map_or("null".to_string(), json_string)
+}
+
+fn json_path(path: &Path) -> String {
+    json_string(&path.to_string_lossy())
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => _ = write!(out, "\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 含逗号、引号或换行的字段加引号，其中的引号重复一次
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
@@ -0,0 +1,84 @@
+//! PSD 图像资源段 (Image Resources) 的简单解析。psd crate 只解析了其中的切片信息，
+//! 分辨率、缩略图等资源需要我们自己读取。
+
+/// 分辨率信息资源 ID
+const RESOURCE_RESOLUTION_INFO: u16 = 0x03ED;
+
+/// 缩略图资源 ID（Photoshop 5.0 起，RGB 顺序的 JFIF 数据）
+const RESOURCE_THUMBNAIL: u16 = 0x040C;
+
+/// 缩略图资源中 JFIF 数据之前的描述部分长度
+const THUMBNAIL_HEADER_LEN: usize = 28;
+
+/// 文件头固定长度
+const HEADER_LEN: usize = 26;
+
+/// 遍历 PSD 图像资源段，返回 (资源 ID, 资源数据) 列表。数据不完整时返回已解析的部分
+pub fn image_resources(bytes: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut resources = Vec::new();
+
+    // 跳过文件头与颜色模式数据段
+    let Some(color_mode_len) = read_u32(bytes, HEADER_LEN) else {
+        return resources;
+    };
+    let section_start = HEADER_LEN + 4 + color_mode_len as usize;
+    let Some(section_len) = read_u32(bytes, section_start) else {
+        return resources;
+    };
+    let mut pos = section_start + 4;
+    let section_end = (pos + section_len as usize).min(bytes.len());
+
+    while pos + 12 <= section_end {
+        if &bytes[pos..pos + 4] != b"8BIM" {
+            break;
+        }
+        let id = u16::from_be_bytes([bytes[pos + 4], bytes[pos + 5]]);
+        // 名称是补齐到偶数长度的 Pascal 字符串
+        let name_len = bytes[pos + 6] as usize;
+        let name_total = (name_len + 1 + 1) & !1;
+        let len_pos = pos + 6 + name_total;
+        let Some(data_len) = read_u32(bytes, len_pos) else {
+            break;
+        };
+        let data_start = len_pos + 4;
+        let data_end = data_start + data_len as usize;
+        if data_end > section_end {
+            break;
+        }
+        resources.push((id, &bytes[data_start..data_end]));
+        // 资源数据同样补齐到偶数长度
+        pos = data_end + (data_len as usize & 1);
+    }
+
+    resources
+}
+
+/// 读取文档分辨率 (水平 DPI, 垂直 DPI)。Photoshop 始终以像素/英寸存储该值，
+/// 单位字段只影响界面显示
+pub fn resolution(bytes: &[u8]) -> Option<(f64, f64)> {
+    let (_, data) = image_resources(bytes)
+        .into_iter()
+        .find(|(id, _)| *id == RESOURCE_RESOLUTION_INFO)?;
+    let h_res = read_u32(data, 0)? as f64 / 65536.0;
+    let v_res = read_u32(data, 8)? as f64 / 65536.0;
+    (h_res > 0.0 && v_res > 0.0).then_some((h_res, v_res))
+}
+
+/// 内嵌缩略图的 JFIF 数据。只支持 JPEG 格式的缩略图（格式字段为 1），Photoshop
+/// 保存时默认生成这种缩略图
+pub fn thumbnail(bytes: &[u8]) -> Option<&[u8]> {
+    let (_, data) = image_resources(bytes)
+        .into_iter()
+        .find(|(id, _)| *id == RESOURCE_THUMBNAIL)?;
+    (read_u32(data, 0)? == 1).then(|| data.get(THUMBNAIL_HEADER_LEN..))?
+}
+
+/// 文件头中的文档宽度（像素）
+pub fn width(bytes: &[u8]) -> Option<u32> {
+    read_u32(bytes, 18)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    let b = bytes.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
@@ -0,0 +1,183 @@
+//! 监听模式下监听的根路径（PATH 或监听列表中的路径）及其监听器。监听器报告错误、要求
+//! 重新扫描（事件队列溢出），或根路径被替换（编辑器、同步工具删除后重建目录，旧的
+//! 监听随之失效而不会报错）时重新创建监听器，并补充导出期间修改过的源文件。
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{ExportOptions, find_psd_files, longpath};
+
+/// 检查根路径是否被替换的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+type EventSender = Sender<notify::Result<notify::Event>>;
+
+pub struct Roots {
+    sender: EventSender,
+    watcher: RecommendedWatcher,
+    /// 应当监听的路径
+    desired: BTreeSet<PathBuf>,
+    /// 成功开始监听的路径
+    watched: BTreeSet<PathBuf>,
+    /// 开始监听时各路径的标识，不存在的路径为 `None`
+    identities: HashMap<PathBuf, Option<Identity>>,
+    checked_at: Instant,
+    /// 上次确认监听正常的时间，重新创建监听器后补充导出这之后修改的文件
+    healthy_at: SystemTime,
+    failed: bool,
+}
+
+impl Roots {
+    pub fn new(sender: EventSender) -> Result<Self> {
+        let watcher = RecommendedWatcher::new(sender.clone(), notify::Config::default())
+            .context("无法创建文件系统监听器")?;
+        Ok(Roots {
+            sender,
+            watcher,
+            desired: BTreeSet::new(),
+            watched: BTreeSet::new(),
+            identities: HashMap::new(),
+            checked_at: Instant::now(),
+            healthy_at: SystemTime::now(),
+            failed: false,
+        })
+    }
+
+    /// 开始监听一个路径，失败时返回错误
+    pub fn watch(&mut self, path: &Path) -> Result<()> {
+        self.watcher
+            .watch(&longpath::extended(path), mode(path))
+            .context(format!("无法监听路径：{:?}", path))?;
+        self.desired.insert(path.to_path_buf());
+        self.watched.insert(path.to_path_buf());
+        self.identities.insert(path.to_path_buf(), identity(path));
+        Ok(())
+    }
+
+    /// 成功开始监听的路径
+    pub fn paths(&self) -> &BTreeSet<PathBuf> {
+        &self.watched
+    }
+
+    /// 改为监听 `paths`：停止监听已去掉的路径，开始监听新增的路径
+    pub fn set(&mut self, paths: BTreeSet<PathBuf>) {
+        for path in self.watched.difference(&paths) {
+            log::info!("停止监听：{:?}", path);
+            // 路径已被删除时监听已经失效
+            _ = self.watcher.unwatch(&longpath::extended(path));
+        }
+        let mut watched = BTreeSet::new();
+        let mut identities = HashMap::new();
+        for path in &paths {
+            identities.insert(path.clone(), identity(path));
+            if self.watched.contains(path) {
+                watched.insert(path.clone());
+                continue;
+            }
+            match self.watcher.watch(&longpath::extended(path), mode(path)) {
+                Ok(()) => {
+                    log::info!("开始监听：{:?}", path);
+                    watched.insert(path.clone());
+                }
+                Err(e) => log::error!("无法监听路径 {:?}：{}", path, e),
+            }
+        }
+        self.desired = paths;
+        self.watched = watched;
+        self.identities = identities;
+    }
+
+    /// 监听器报告了错误或要求重新扫描，下次检查时重新创建
+    pub fn mark_failed(&mut self) {
+        self.failed = true;
+    }
+
+    /// 定期检查监听是否仍然有效。需要时重新创建监听器，返回期间可能错过事件的源文件
+    pub fn check(&mut self, options: &ExportOptions) -> Vec<PathBuf> {
+        if !self.failed && self.checked_at.elapsed() < CHECK_INTERVAL {
+            return Vec::new();
+        }
+        self.checked_at = Instant::now();
+        let replaced: Vec<&PathBuf> = self
+            .desired
+            .iter()
+            .filter(|path| self.identities.get(*path) != Some(&identity(path)))
+            .collect();
+        if !self.failed && replaced.is_empty() {
+            self.healthy_at = SystemTime::now();
+            return Vec::new();
+        }
+        if self.failed {
+            log::warn!("监听器出错，重新创建监听器");
+        } else {
+            log::warn!("监听的路径已被替换，重新创建监听器：{:?}", replaced);
+        }
+        self.failed = false;
+
+        let watcher = match RecommendedWatcher::new(self.sender.clone(), notify::Config::default())
+        {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log::error!("无法重新创建文件系统监听器：{}", e);
+                self.failed = true;
+                return Vec::new();
+            }
+        };
+        self.watcher = watcher;
+        self.watched.clear();
+        let desired = std::mem::take(&mut self.desired);
+        self.set(desired);
+
+        // 补充导出上次确认正常之后修改过的文件
+        let since = self.healthy_at;
+        self.healthy_at = SystemTime::now();
+        let missed: Vec<PathBuf> = self
+            .watched
+            .iter()
+            .flat_map(|root| find_psd_files(root, options).unwrap_or_default())
+            .filter(|path| {
+                path.metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified >= since)
+            })
+            .collect();
+        if !missed.is_empty() {
+            log::info!("补充导出 {} 个期间修改过的源文件", missed.len());
+        }
+        missed
+    }
+}
+
+fn mode(path: &Path) -> RecursiveMode {
+    if path.is_dir() {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    }
+}
+
+/// 路径的标识：Unix 上为设备号与 inode，其它平台上为创建时间
+#[cfg(unix)]
+type Identity = (u64, u64);
+
+#[cfg(not(unix))]
+type Identity = SystemTime;
+
+#[cfg(unix)]
+fn identity(path: &Path) -> Option<Identity> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = path.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(path: &Path) -> Option<Identity> {
+    path.metadata().and_then(|metadata| metadata.created()).ok()
+}
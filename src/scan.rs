@@ -0,0 +1,57 @@
+//! 并行遍历目录：每个子目录作为一个 rayon 任务读取，数十万个文件的网络共享目录也能
+//! 很快扫描完。文件先按文件名筛选，只有通过筛选的符号链接才需要额外读取元数据。
+//!
+//! 与之前使用的 `WalkDir` 相同：不进入指向目录的符号链接，无法读取的目录跳过。
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+type Accept<'a> = &'a (dyn Fn(&Path) -> bool + Sync);
+
+/// 找出 `root` 下所有 `accept` 返回 `true` 的文件，按路径排序
+pub fn files(root: &Path, accept: Accept) -> Vec<PathBuf> {
+    let found = Mutex::new(Vec::new());
+    each(root, accept, &|files| {
+        found
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(files)
+    });
+    let mut found = found.into_inner().unwrap_or_else(|e| e.into_inner());
+    found.sort();
+    found
+}
+
+/// 遍历 `root`，每读完一个目录就把其中 `accept` 返回 `true` 的文件交给 `found`，不等待
+/// 遍历结束。`found` 在当前 rayon 线程池中并发调用，遍历完成后返回
+pub fn each(root: &Path, accept: Accept, found: &(dyn Fn(Vec<PathBuf>) + Sync)) {
+    rayon::scope(|scope| visit(scope, root.to_path_buf(), accept, found));
+}
+
+fn visit<'scope>(
+    scope: &rayon::Scope<'scope>,
+    dir: PathBuf,
+    accept: Accept<'scope>,
+    found: &'scope (dyn Fn(Vec<PathBuf>) + Sync),
+) {
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return;
+    };
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            scope.spawn(move |scope| visit(scope, path, accept, found));
+        } else if accept(&path) && (file_type.is_file() || path.is_file()) {
+            files.push(path);
+        }
+    }
+    if !files.is_empty() {
+        found(files);
+    }
+}
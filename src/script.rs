@@ -0,0 +1,79 @@
+//! 逐文件的脚本钩子（`--script`）。每个源文件导出前运行一次脚本，由脚本决定
+//! 是否跳过、输出到哪里以及使用哪些设置，逻辑写在任意语言的脚本里，无需重新编译。
+//!
+//! 脚本通过环境变量得到 `PAE_SOURCE`（源文件路径）与 `PAE_LAYERS`（PSD 的图层名，
+//! 每行一个，与 Photoshop 的图层面板相同，按从上到下的顺序），并向标准输出写入
+//! TOML：键名与配置文件相同，另外 `skip = true` 表示跳过该文件，`output = "路径"` 指定输出文件（相对路径相对于
+//! 源文件所在目录）。命令按空白拆分参数，不经过 shell，`{input}` 替换为源文件路径。
+//! 规划输出与导出共用同一次运行的结果，调用方负责缓存 [`Decision`]。
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+use psd::Psd;
+
+use crate::{
+    config::{self, Table, Value},
+    mmap,
+};
+
+/// 脚本对一个源文件的决定
+#[derive(Clone)]
+pub struct Decision {
+    pub skip: bool,
+    pub output: Option<PathBuf>,
+    /// 覆盖的导出设置
+    pub overrides: Table,
+}
+
+pub fn run(command: &str, source: &Path) -> Result<Decision> {
+    let mut parts = command.split_whitespace();
+    let program = parts.next().context("脚本命令为空")?;
+    let output = Command::new(program)
+        .args(parts.map(|arg| arg.replace("{input}", &source.to_string_lossy())))
+        .env("PAE_SOURCE", source)
+        .env("PAE_LAYERS", layer_names(source).join("\n"))
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context(format!("无法运行脚本：{}", program))?;
+    if !output.status.success() {
+        bail!("脚本 {} 执行失败（{}）", program, output.status);
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let document =
+        config::parse(&text).context(format!("脚本 {} 的输出不是有效的 TOML", program))?;
+    if let Some((name, _)) = document.tables.first() {
+        bail!("脚本 {} 的输出中不支持表 [{}]", program, name);
+    }
+    let mut overrides = document.root;
+    let skip = match overrides.remove("skip") {
+        None | Some(Value::Boolean(false)) => false,
+        Some(Value::Boolean(true)) => true,
+        Some(_) => bail!("脚本 {} 的输出中 skip 必须是布尔值", program),
+    };
+    let output = overrides
+        .take_string("output")?
+        .map(|output| source.parent().unwrap_or(Path::new(".")).join(output));
+    Ok(Decision {
+        skip,
+        output,
+        overrides,
+    })
+}
+
+/// PSD 的图层名；其它格式或无法解析时为空
+fn layer_names(source: &Path) -> Vec<String> {
+    if !crate::source::is_psd(source) {
+        return Vec::new();
+    }
+    mmap::read(source)
+        .ok()
+        .and_then(|bytes| Psd::from_bytes(&bytes).ok())
+        .map(|psd| psd.layers().iter().map(|l| l.name().to_string()).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    atlas::{self, Frame},
+    font,
+    transform::{self, ResizeFilter, ResizeSpec},
+};
+
+/// 总览图文件名
+pub const CONTACT_SHEET_NAME: &str = "contact-sheet.png";
+
+/// 总览图的帧信息文件名
+pub const CONTACT_SHEET_JSON_NAME: &str = "contact-sheet.json";
+
+/// 格子之间以及四周的留白
+const GAP: u32 = 8;
+/// 背景色
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+/// 标题文字颜色
+const CAPTION_COLOR: Rgba<u8> = Rgba([230, 230, 230, 255]);
+
+/// 总览图设置
+#[derive(Clone, Copy, Debug)]
+pub struct SheetOptions {
+    /// 每行格子数
+    pub columns: u32,
+    /// 格子边长（像素）
+    pub cell: u32,
+    /// 另外按 Aseprite 的格式写入各帧在总览图中的位置
+    pub json: Option<SheetJson>,
+}
+
+/// Aseprite 导出精灵表时的 JSON 格式，许多引擎工具可以直接读取
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SheetJson {
+    /// `frames` 是以文件名为键的对象
+    Hash,
+    /// `frames` 是带 `filename` 字段的数组
+    Array,
+}
+
+impl SheetOptions {
+    /// 为单个导出结果生成放入格子的预览图
+    pub fn preview(&self, img: &RgbaImage, filter: ResizeFilter) -> RgbaImage {
+        transform::resize(img.clone(), ResizeSpec::Fit(self.cell, self.cell), filter)
+    }
+
+    fn caption_scale(&self) -> u32 {
+        (self.cell / 256).max(1)
+    }
+}
+
+/// 按所在目录分组，为每个目录生成一张带文件名标题的总览图，返回写入的文件列表
+pub fn write_contact_sheets(
+    entries: Vec<(PathBuf, RgbaImage)>,
+    options: &SheetOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut groups: BTreeMap<PathBuf, Vec<(PathBuf, RgbaImage)>> = BTreeMap::new();
+    for (path, preview) in entries {
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        groups.entry(dir).or_default().push((path, preview));
+    }
+
+    let mut written = Vec::new();
+    for (dir, mut items) in groups {
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        let (sheet, frames) = render_sheet(&items, options);
+        let output = dir.join(CONTACT_SHEET_NAME);
+        sheet
+            .save(&output)
+            .context(format!("无法保存总览图：{:?}", output))?;
+        written.push(output);
+        if let Some(json) = options.json {
+            let output = dir.join(CONTACT_SHEET_JSON_NAME);
+            let text = atlas::frames_json(
+                &frames,
+                json == SheetJson::Array,
+                CONTACT_SHEET_NAME,
+                sheet.dimensions(),
+            );
+            std::fs::write(&output, text).context(format!("无法写入总览图帧信息：{:?}", output))?;
+            written.push(output);
+        }
+    }
+    Ok(written)
+}
+
+fn render_sheet(items: &[(PathBuf, RgbaImage)], options: &SheetOptions) -> (RgbaImage, Vec<Frame>) {
+    let scale = options.caption_scale();
+    let caption_h = font::GLYPH_HEIGHT * scale + GAP / 2;
+    let columns = options.columns.min(items.len() as u32).max(1);
+    let rows = (items.len() as u32).div_ceil(columns);
+    let cell_w = options.cell + GAP;
+    let cell_h = options.cell + caption_h + GAP;
+    let mut sheet = RgbaImage::from_pixel(columns * cell_w + GAP, rows * cell_h + GAP, BACKGROUND);
+    let mut frames = Vec::with_capacity(items.len());
+
+    for (i, (path, preview)) in items.iter().enumerate() {
+        let col = i as u32 % columns;
+        let row = i as u32 / columns;
+        let x = GAP + col * cell_w;
+        let y = GAP + row * cell_h;
+
+        // 预览图在格子中居中
+        let px = x + (options.cell - preview.width()) / 2;
+        let py = y + (options.cell - preview.height()) / 2;
+        image::imageops::overlay(&mut sheet, preview, px as i64, py as i64);
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        frames.push(Frame {
+            name: name.clone(),
+            x: px,
+            y: py,
+            width: preview.width(),
+            height: preview.height(),
+            offset: (0, 0),
+            source: preview.dimensions(),
+        });
+        let caption = fit_caption(&name, options.cell, scale);
+        let (text_w, _) = font::text_size(&caption, scale);
+        font::draw_text(
+            &mut sheet,
+            &caption,
+            (x + (options.cell - text_w) / 2) as i64,
+            (y + options.cell + GAP / 2) as i64,
+            scale,
+            CAPTION_COLOR,
+        );
+    }
+    (sheet, frames)
+}
+
+/// 文件名过长时截断并加上 `..`
+fn fit_caption(name: &str, width: u32, scale: u32) -> String {
+    if font::text_size(name, scale).0 <= width {
+        return name.to_string();
+    }
+    let mut chars: Vec<char> = name.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let caption: String = chars.iter().chain(['.', '.'].iter()).collect();
+        if font::text_size(&caption, scale).0 <= width {
+            return caption;
+        }
+    }
+    String::new()
+}
@@ -0,0 +1,395 @@
+//! 读取各种源文件，统一解码为合并后的 RGBA 图像与文档元数据。
+//!
+//! 每种输入格式实现一个 [`Decoder`]，并在 [`DECODERS`] 中按扩展名注册；除 PSD
+//! 以外的格式都可以通过同名 cargo feature 关闭。
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+#[cfg(any(feature = "ora", feature = "kra"))]
+use image::ImageFormat;
+use image::RgbaImage;
+use psd::Psd;
+
+#[cfg(feature = "clip")]
+use crate::sqlite::Database;
+#[cfg(any(feature = "ora", feature = "kra"))]
+use crate::zip::ZipReader;
+use crate::{composite, deadline, mmap, resources, timing};
+
+/// 解码结果
+pub struct Decoded {
+    /// 合并后的图像
+    pub image: RgbaImage,
+    /// 文档分辨率 (DPI)
+    pub dpi: Option<(f64, f64)>,
+    /// 合成图层的耗时，解码器直接读取合并图像时为 0
+    pub composite: Duration,
+}
+
+/// 一种输入格式的解码器
+pub trait Decoder: Sync {
+    /// 处理的文件扩展名（小写）
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// 从文件内容解码，`path` 仅用于错误信息
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded>;
+}
+
+/// 已注册的解码器，同一扩展名先注册的优先
+static DECODERS: &[&dyn Decoder] = &[
+    &PsdDecoder,
+    #[cfg(feature = "ora")]
+    &OraDecoder,
+    #[cfg(feature = "kra")]
+    &KraDecoder,
+    #[cfg(feature = "clip")]
+    &ClipDecoder,
+    #[cfg(feature = "xcf")]
+    &XcfDecoder,
+];
+
+/// 所有支持作为输入的文件扩展名
+pub fn extensions() -> Vec<&'static str> {
+    DECODERS
+        .iter()
+        .flat_map(|d| d.extensions())
+        .copied()
+        .collect()
+}
+
+/// 查找处理该路径扩展名的解码器
+fn decoder_for(path: &Path) -> Option<&'static dyn Decoder> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    DECODERS
+        .iter()
+        .find(|d| d.extensions().contains(&ext.as_str()))
+        .copied()
+}
+
+/// 是否是可以导出的源文件
+pub fn is_supported(path: &Path) -> bool {
+    decoder_for(path).is_some()
+}
+
+/// 是否是 PSD 格式的文件（`.psd` 或 `.pdd`）
+pub fn is_psd(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("psd") || ext.eq_ignore_ascii_case("pdd"))
+}
+
+/// 读取并解码源文件
+pub fn decode(path: &Path) -> Result<Decoded> {
+    let decoder = supported_decoder(path)?;
+    let bytes = mmap::read(path).context(format!("无法读取源文件：{:?}", path))?;
+    decoder.decode(&bytes, path)
+}
+
+/// 解码已读入内存的源文件内容，按 `path` 的扩展名选择解码器
+pub fn decode_bytes(bytes: &[u8], path: &Path) -> Result<Decoded> {
+    supported_decoder(path)?.decode(bytes, path)
+}
+
+fn supported_decoder(path: &Path) -> Result<&'static dyn Decoder> {
+    let Some(decoder) = decoder_for(path) else {
+        // `--ext` 可以加入没有内置解码器的扩展名（如 PSB），交给外部转换器或 Photoshop
+        bail!(
+            "没有支持该文件类型的内置解码器，请使用 --fallback 或 --photoshop：{:?}",
+            path
+        );
+    };
+    Ok(decoder)
+}
+
+/// 读取 PSD 内嵌的缩略图，分辨率按缩略图与文档的宽度之比换算。不是 PSD、没有内嵌
+/// 缩略图或无法解码时返回 `None`
+pub fn decode_preview(path: &Path) -> Option<Decoded> {
+    if !is_psd(path) {
+        return None;
+    }
+    let bytes = mmap::read(path).ok()?;
+    let decoded = resources::thumbnail(&bytes)
+        .and_then(|jpeg| image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg).ok());
+    let Some(image) = decoded else {
+        log::info!("{:?} 没有可用的内嵌缩略图，改为完整解码", path);
+        return None;
+    };
+    let image = image.into_rgba8();
+    let ratio = resources::width(&bytes)
+        .filter(|width| *width > 0)
+        .map_or(1.0, |width| image.width() as f64 / width as f64);
+    let dpi = resources::resolution(&bytes).map(|(x, y)| (x * ratio, y * ratio));
+    Some(Decoded {
+        image,
+        dpi,
+        composite: Duration::ZERO,
+    })
+}
+
+/// 调用外部转换命令把源文件转换为临时 PNG 再读取。`command` 按空白拆分为参数，
+/// 其中的 `{input}` / `{output}` 替换为源文件与临时文件路径
+pub fn convert_external(command: &str, path: &Path) -> Result<Decoded> {
+    let mut parts = command.split_whitespace();
+    let program = parts.next().context("外部转换命令为空")?;
+    run_converter(path, |output| {
+        let mut command = Command::new(program);
+        command.args(parts.map(|arg| {
+            arg.replace("{input}", &path.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+        }));
+        command
+    })
+}
+
+/// 运行 `build` 构造的转换命令，命令应把结果写入传入的临时 PNG 路径
+pub fn run_converter(path: &Path, build: impl FnOnce(&Path) -> Command) -> Result<Decoded> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let output = std::env::temp_dir().join(format!(
+        "pae-{}-{}.png",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut command = build(&output);
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let result = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("无法运行外部转换器：{}", program))
+        .and_then(deadline::wait_with_output);
+    let decoded = result.and_then(|out| {
+        if !out.status.success() {
+            bail!(
+                "外部转换器执行失败（{}）：{}",
+                out.status,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        let image = image::open(&output)
+            .context(format!("无法读取外部转换器的输出：{:?}", output))?
+            .into_rgba8();
+        // 即使 psd crate 无法解析，分辨率资源通常仍然可读
+        let dpi = mmap::read(path)
+            .ok()
+            .and_then(|bytes| resources::resolution(&bytes));
+        Ok(Decoded {
+            image,
+            dpi,
+            composite: Duration::ZERO,
+        })
+    });
+    _ = std::fs::remove_file(&output);
+    decoded
+}
+
+struct PsdDecoder;
+
+impl Decoder for PsdDecoder {
+    fn extensions(&self) -> &'static [&'static str] {
+        // PDD（PhotoDeluxe）与 PSD 格式相同
+        &["psd", "pdd"]
+    }
+
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded> {
+        decode_psd(bytes, path)
+    }
+}
+
+#[cfg(feature = "ora")]
+struct OraDecoder;
+
+#[cfg(feature = "ora")]
+impl Decoder for OraDecoder {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ora"]
+    }
+
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded> {
+        decode_ora(bytes, path)
+    }
+}
+
+#[cfg(feature = "kra")]
+struct KraDecoder;
+
+#[cfg(feature = "kra")]
+impl Decoder for KraDecoder {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["kra"]
+    }
+
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded> {
+        decode_kra(bytes, path)
+    }
+}
+
+#[cfg(feature = "clip")]
+struct ClipDecoder;
+
+#[cfg(feature = "clip")]
+impl Decoder for ClipDecoder {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["clip"]
+    }
+
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded> {
+        decode_clip(bytes, path)
+    }
+}
+
+#[cfg(feature = "xcf")]
+struct XcfDecoder;
+
+#[cfg(feature = "xcf")]
+impl Decoder for XcfDecoder {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["xcf"]
+    }
+
+    fn decode(&self, bytes: &[u8], path: &Path) -> Result<Decoded> {
+        let (image, dpi) =
+            crate::xcf::decode(bytes).context(format!("无法解析 XCF 文件：{:?}", path))?;
+        Ok(Decoded {
+            image,
+            dpi,
+            composite: Duration::ZERO,
+        })
+    }
+}
+
+fn decode_psd(bytes: &[u8], path: &Path) -> Result<Decoded> {
+    let psd = Psd::from_bytes(bytes).context(format!("无法解析 PSD 文件：{:?}", path))?;
+    let mut composite = Duration::ZERO;
+    let rgba = timing::measure(&mut composite, || {
+        composite::rgba(bytes).unwrap_or_else(|| psd.rgba())
+    });
+    let img = RgbaImage::from_raw(psd.width(), psd.height(), rgba)
+        .context("无法创建 ImageBuffer，可能是图像数据或尺寸问题")?;
+    Ok(Decoded {
+        image: img,
+        dpi: resources::resolution(bytes),
+        composite,
+    })
+}
+
+#[cfg(feature = "ora")]
+/// ORA 是一个 zip 文件，直接读取其中由编辑器保存的 `mergedimage.png`，
+/// 分辨率取自 `stack.xml` 的 `xres` / `yres`
+fn decode_ora(bytes: &[u8], path: &Path) -> Result<Decoded> {
+    decode_merged_zip(bytes, path, "ORA", ("stack.xml", "<image", "xres", "yres"))
+}
+
+#[cfg(feature = "kra")]
+/// KRA 与 ORA 结构相同，分辨率取自 `maindoc.xml` 中 `<IMAGE>` 的 `x-res` / `y-res`
+fn decode_kra(bytes: &[u8], path: &Path) -> Result<Decoded> {
+    decode_merged_zip(
+        bytes,
+        path,
+        "KRA",
+        ("maindoc.xml", "<IMAGE", "x-res", "y-res"),
+    )
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+/// 读取 zip 容器中的 `mergedimage.png`。`resolution` 为
+/// (XML 文件名, 元素起始, 水平分辨率属性, 垂直分辨率属性)
+fn decode_merged_zip(
+    bytes: &[u8],
+    path: &Path,
+    kind: &str,
+    resolution: (&str, &str, &str, &str),
+) -> Result<Decoded> {
+    let zip = ZipReader::new(bytes).context(format!("无法解析 {} 文件：{:?}", kind, path))?;
+    let merged = zip
+        .read("mergedimage.png")
+        .context(format!("无法解析 {} 文件：{:?}", kind, path))?
+        .context(format!("{} 文件中没有 mergedimage.png：{:?}", kind, path))?;
+    let img = image::load_from_memory_with_format(&merged, ImageFormat::Png)
+        .context(format!("无法解码 {} 合并图像：{:?}", kind, path))?
+        .into_rgba8();
+
+    let (document, element, xres, yres) = resolution;
+    let dpi = zip.read(document).ok().flatten().and_then(|xml| {
+        let xml = String::from_utf8_lossy(&xml);
+        let start = xml.find(element)?;
+        let tag = &xml[start..start + xml[start..].find('>')?];
+        let x = attribute(tag, xres)?.parse::<f64>().ok()?;
+        let y = attribute(tag, yres)?.parse::<f64>().ok()?;
+        (x > 0.0 && y > 0.0).then_some((x, y))
+    });
+    Ok(Decoded {
+        image: img,
+        dpi,
+        composite: Duration::ZERO,
+    })
+}
+
+#[cfg(feature = "clip")]
+/// Clip Studio 文件由 `CSFCHUNK` 文件头和一串 `CHNK` 数据块组成，其中 `CHNKSQLi`
+/// 块是一个 SQLite 数据库。图层像素保存在私有格式的外部数据块中，这里读取数据库
+/// `CanvasPreview` 表里 CSP 保存时生成的合并预览 PNG（尺寸可能小于画布），
+/// 分辨率按预览与画布 (`Canvas` 表) 的宽度比例换算
+fn decode_clip(bytes: &[u8], path: &Path) -> Result<Decoded> {
+    if !bytes.starts_with(b"CSFCHUNK") {
+        bail!("不是 Clip Studio 文件：{:?}", path);
+    }
+    let database = clip_chunk(bytes, b"CHNKSQLi")
+        .context(format!("Clip Studio 文件中没有 SQLite 数据块：{:?}", path))?;
+    let database =
+        Database::new(database).context(format!("无法解析 Clip Studio 文件：{:?}", path))?;
+
+    let preview = database
+        .table("CanvasPreview")
+        .context(format!("无法解析 Clip Studio 文件：{:?}", path))?
+        .context(format!("Clip Studio 文件中没有预览图：{:?}", path))?;
+    let data = preview
+        .get(0, "ImageData")
+        .and_then(|v| v.as_blob())
+        .context(format!("Clip Studio 文件中没有预览图：{:?}", path))?;
+    let img = image::load_from_memory(data)
+        .context(format!("无法解码 Clip Studio 预览图：{:?}", path))?
+        .into_rgba8();
+
+    let dpi = database.table("Canvas").ok().flatten().and_then(|canvas| {
+        let resolution = canvas.get(0, "CanvasResolution")?.as_f64()?;
+        let width = canvas.get(0, "CanvasWidth")?.as_f64()?;
+        let dpi = resolution * img.width() as f64 / width;
+        (dpi > 0.0 && dpi.is_finite()).then_some((dpi, dpi))
+    });
+    Ok(Decoded {
+        image: img,
+        dpi,
+        composite: Duration::ZERO,
+    })
+}
+
+#[cfg(feature = "clip")]
+/// 查找指定类型的数据块：8 字节类型 + 8 字节大端长度 + 数据，首个块位于偏移 24
+fn clip_chunk<'a>(bytes: &'a [u8], kind: &[u8; 8]) -> Option<&'a [u8]> {
+    let mut pos = 24;
+    while let Some(header) = bytes.get(pos..pos + 16) {
+        let len = u64::from_be_bytes(header[8..].try_into().ok()?) as usize;
+        let data = bytes.get(pos + 16..(pos + 16).checked_add(len)?)?;
+        if &header[..8] == kind {
+            return Some(data);
+        }
+        pos += 16 + len;
+    }
+    None
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
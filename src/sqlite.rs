@@ -0,0 +1,528 @@
+//! 极简只读 SQLite 解析：按表名读取整张表的所有行，用于读取 Clip Studio 文件中
+//! 嵌入的数据库。只支持普通的 rowid 表，不支持 WAL 与索引查询。
+
+use std::{collections::HashSet, io};
+
+/// 单元格的值
+#[derive(Clone, Debug)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(v) => Some(*v as f64),
+            Value::Real(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_blob(&self) -> Option<&[u8]> {
+        match self {
+            Value::Blob(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// 读取出的整张表
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    /// 按列名取某一行的值，列不存在时返回 `None`
+    pub fn get(&self, row: usize, column: &str) -> Option<&Value> {
+        let index = self
+            .columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(column))?;
+        self.rows.get(row)?.get(index)
+    }
+}
+
+/// 溢出页链表的页数上限，防止损坏文件中的循环引用导致死循环
+const MAX_VISITED_PAGES: usize = 1 << 20;
+
+/// b-tree 的层数上限。正常的数据库不超过二十层，损坏的子页号可能形成很深的链，
+/// 递归读取时会耗尽栈空间，而栈溢出无法被捕获
+const MAX_DEPTH: usize = 64;
+
+pub struct Database<'a> {
+    bytes: &'a [u8],
+    page_size: usize,
+    /// 每页可用字节数（去掉保留区）
+    usable_size: usize,
+}
+
+impl<'a> Database<'a> {
+    pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        if !bytes.starts_with(b"SQLite format 3\0") || bytes.len() < 100 {
+            return Err(invalid("不是 SQLite 数据库"));
+        }
+        let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        let usable_size = page_size.saturating_sub(bytes[20] as usize);
+        if usable_size < 480 {
+            return Err(invalid("SQLite 页面大小无效"));
+        }
+        Ok(Database {
+            bytes,
+            page_size,
+            usable_size,
+        })
+    }
+
+    /// 读取指定名称的表，不存在时返回 `None`
+    pub fn table(&self, name: &str) -> io::Result<Option<Table>> {
+        // sqlite_master：type, name, tbl_name, rootpage, sql
+        let mut master = Vec::new();
+        self.read_btree(1, &mut master, &mut HashSet::new(), 0)?;
+        let Some((root, sql)) = master
+            .into_iter()
+            .find_map(|(_, row)| match row.as_slice() {
+                [
+                    Value::Text(kind),
+                    Value::Text(table),
+                    _,
+                    Value::Integer(root),
+                    Value::Text(sql),
+                ] if kind == "table" && table.eq_ignore_ascii_case(name) => {
+                    Some((*root as u32, sql.clone()))
+                }
+                _ => None,
+            })
+        else {
+            return Ok(None);
+        };
+
+        let (columns, rowid_column) = parse_columns(&sql);
+        let mut rows = Vec::new();
+        self.read_btree(root, &mut rows, &mut HashSet::new(), 0)?;
+        let rows = rows
+            .into_iter()
+            .map(|(rowid, mut row)| {
+                row.resize(columns.len().max(row.len()), Value::Null);
+                // INTEGER PRIMARY KEY 列实际保存在 rowid 中
+                if let Some(index) = rowid_column {
+                    row[index] = Value::Integer(rowid);
+                }
+                row
+            })
+            .collect();
+        Ok(Some(Table { columns, rows }))
+    }
+
+    fn page(&self, number: u32) -> io::Result<&'a [u8]> {
+        let start = (number as usize)
+            .checked_sub(1)
+            .ok_or_else(|| invalid("SQLite 页号无效"))?
+            * self.page_size;
+        self.bytes
+            .get(start..start + self.page_size)
+            .ok_or_else(|| invalid("SQLite 数据不完整"))
+    }
+
+    /// 深度优先读取表 b-tree 上的所有记录 (rowid, 值)。每页只能出现一次，`depth` 为
+    /// 当前页所在的层数
+    fn read_btree(
+        &self,
+        number: u32,
+        rows: &mut Vec<(i64, Vec<Value>)>,
+        visited: &mut HashSet<u32>,
+        depth: usize,
+    ) -> io::Result<()> {
+        if !visited.insert(number) {
+            return Err(invalid("SQLite b-tree 存在循环"));
+        }
+        if depth > MAX_DEPTH {
+            return Err(invalid("SQLite b-tree 层数过多"));
+        }
+        let page = self.page(number)?;
+        // 第一页开头是 100 字节的文件头
+        let header = if number == 1 { 100 } else { 0 };
+        let kind = page[header];
+        let count = read_u16(page, header + 3)? as usize;
+        let cells_start = header + if kind == 0x05 { 12 } else { 8 };
+
+        for i in 0..count {
+            let cell = read_u16(page, cells_start + i * 2)? as usize;
+            match kind {
+                // 内部页：左子页号 + 键
+                0x05 => self.read_btree(read_u32(page, cell)?, rows, visited, depth + 1)?,
+                // 叶子页：负载长度 + rowid + 负载
+                0x0D => {
+                    let (payload_len, n) = varint(page, cell)?;
+                    let (rowid, m) = varint(page, cell + n)?;
+                    let payload_len =
+                        usize::try_from(payload_len).map_err(|_| invalid("SQLite 记录长度无效"))?;
+                    let payload = self.payload(page, cell + n + m, payload_len)?;
+                    rows.push((rowid, parse_record(&payload)?));
+                }
+                _ => return Err(invalid("不支持的 SQLite 页面类型")),
+            }
+        }
+        if kind == 0x05 {
+            self.read_btree(read_u32(page, header + 8)?, rows, visited, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// 读取记录负载，过长的负载保存在溢出页链表中
+    fn payload(&self, page: &[u8], start: usize, len: usize) -> io::Result<Vec<u8>> {
+        // 长度来自文件内容，超过整个数据库时一定是损坏的，不按它分配内存
+        if len > self.bytes.len() {
+            return Err(invalid("SQLite 记录长度无效"));
+        }
+        let usable = self.usable_size;
+        let max_local = usable - 35;
+        let local = if len <= max_local {
+            len
+        } else {
+            let min_local = (usable - 12) * 32 / 255 - 23;
+            let k = min_local + (len - min_local) % (usable - 4);
+            if k <= max_local { k } else { min_local }
+        };
+        let mut payload = Vec::with_capacity(len);
+        payload.extend_from_slice(
+            page.get(start..start + local)
+                .ok_or_else(|| invalid("SQLite 记录不完整"))?,
+        );
+        if local < len {
+            let mut next = read_u32(page, start + local)?;
+            let mut visited = 0;
+            while payload.len() < len {
+                visited += 1;
+                if next == 0 || visited > MAX_VISITED_PAGES {
+                    return Err(invalid("SQLite 溢出页不完整"));
+                }
+                let overflow = self.page(next)?;
+                let take = (len - payload.len()).min(usable - 4);
+                payload.extend_from_slice(&overflow[4..4 + take]);
+                next = read_u32(overflow, 0)?;
+            }
+        }
+        Ok(payload)
+    }
+}
+
+/// 解析记录格式：头部长度 + 各列类型，之后是各列数据
+fn parse_record(payload: &[u8]) -> io::Result<Vec<Value>> {
+    let (header_len, mut pos) = varint(payload, 0)?;
+    let mut types = Vec::new();
+    while pos < header_len as usize {
+        let (kind, n) = varint(payload, pos)?;
+        types.push(kind);
+        pos += n;
+    }
+
+    let mut data = header_len as usize;
+    let mut values = Vec::with_capacity(types.len());
+    for kind in types {
+        let size = match kind {
+            0 | 8 | 9 => 0,
+            1..=4 => kind as usize,
+            5 => 6,
+            6 | 7 => 8,
+            n if n >= 12 => (n as usize - 12) / 2,
+            _ => return Err(invalid("SQLite 记录类型无效")),
+        };
+        let bytes = data
+            .checked_add(size)
+            .and_then(|end| payload.get(data..end))
+            .ok_or_else(|| invalid("SQLite 记录不完整"))?;
+        data += size;
+        values.push(match kind {
+            0 => Value::Null,
+            1..=6 => {
+                // 大端有符号整数，先按符号位扩展
+                let fill = if bytes[0] & 0x80 != 0 { 0xFF } else { 0 };
+                let mut buf = [fill; 8];
+                buf[8 - size..].copy_from_slice(bytes);
+                Value::Integer(i64::from_be_bytes(buf))
+            }
+            7 => Value::Real(f64::from_be_bytes(bytes.try_into().unwrap_or_default())),
+            8 => Value::Integer(0),
+            9 => Value::Integer(1),
+            n if n % 2 == 0 => Value::Blob(bytes.to_vec()),
+            _ => Value::Text(String::from_utf8_lossy(bytes).into_owned()),
+        });
+    }
+    Ok(values)
+}
+
+/// 从 CREATE TABLE 语句中取出列名，以及 INTEGER PRIMARY KEY 列的位置
+fn parse_columns(sql: &str) -> (Vec<String>, Option<usize>) {
+    let (Some(start), Some(end)) = (sql.find('('), sql.rfind(')')) else {
+        return (Vec::new(), None);
+    };
+    let mut columns = Vec::new();
+    let mut rowid_column = None;
+    for definition in sql[start + 1..end].split(',') {
+        let definition = definition.trim();
+        let upper = definition.to_ascii_uppercase();
+        // 表级约束不是列
+        if ["PRIMARY", "UNIQUE", "CHECK", "FOREIGN", "CONSTRAINT"]
+            .iter()
+            .any(|k| upper.starts_with(k))
+        {
+            continue;
+        }
+        let name = definition
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .trim_matches(|c| matches!(c, '"' | '`' | '[' | ']'));
+        if upper.contains("INTEGER PRIMARY KEY") {
+            rowid_column = Some(columns.len());
+        }
+        columns.push(name.to_string());
+    }
+    (columns, rowid_column)
+}
+
+/// SQLite 的大端变长整数，返回 (值, 字节数)
+fn varint(bytes: &[u8], pos: usize) -> io::Result<(i64, usize)> {
+    let mut value: u64 = 0;
+    for i in 0..9 {
+        let byte = *bytes
+            .get(pos + i)
+            .ok_or_else(|| invalid("SQLite 数据不完整"))?;
+        if i == 8 {
+            return Ok((((value << 8) | byte as u64) as i64, 9));
+        }
+        value = (value << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value as i64, i + 1));
+        }
+    }
+    unreachable!()
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> io::Result<u16> {
+    bytes
+        .get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("SQLite 数据不完整"))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> io::Result<u32> {
+    bytes
+        .get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("SQLite 数据不完整"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 512;
+
+    fn encode_varint(value: u64) -> Vec<u8> {
+        let mut groups = vec![(value & 0x7F) as u8];
+        let mut rest = value >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        groups.reverse();
+        groups
+    }
+
+    fn record(values: &[Value]) -> Vec<u8> {
+        let mut types = Vec::new();
+        let mut data = Vec::new();
+        for value in values {
+            let kind = match value {
+                Value::Null => 0,
+                Value::Integer(v) => {
+                    data.extend_from_slice(&v.to_be_bytes());
+                    6
+                }
+                Value::Real(v) => {
+                    data.extend_from_slice(&v.to_be_bytes());
+                    7
+                }
+                Value::Text(s) => {
+                    data.extend_from_slice(s.as_bytes());
+                    13 + 2 * s.len() as u64
+                }
+                Value::Blob(b) => {
+                    data.extend_from_slice(b);
+                    12 + 2 * b.len() as u64
+                }
+            };
+            types.extend(encode_varint(kind));
+        }
+        // 头部长度不超过 127 字节，自身只占一个字节
+        let mut out = encode_varint(types.len() as u64 + 1);
+        out.extend(types);
+        out.extend(data);
+        out
+    }
+
+    /// 把页面写入数据库，页号从 1 开始
+    fn put_page(db: &mut Vec<u8>, number: u32, page: &[u8]) {
+        let start = (number as usize - 1) * PAGE_SIZE;
+        if db.len() < start + PAGE_SIZE {
+            db.resize(start + PAGE_SIZE, 0);
+        }
+        db[start..start + page.len()].copy_from_slice(page);
+    }
+
+    /// 组装 b-tree 页：页头后是单元指针数组，单元内容依次排在后面
+    fn btree_page(header: usize, kind: u8, cells: &[Vec<u8>], right: Option<u32>) -> Vec<u8> {
+        let mut page = vec![0; PAGE_SIZE];
+        page[header] = kind;
+        page[header + 3..header + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        let pointers = header + if right.is_some() { 12 } else { 8 };
+        if let Some(right) = right {
+            page[header + 8..header + 12].copy_from_slice(&right.to_be_bytes());
+        }
+        let mut pos = pointers + cells.len() * 2;
+        for (i, cell) in cells.iter().enumerate() {
+            page[pointers + i * 2..pointers + i * 2 + 2]
+                .copy_from_slice(&(pos as u16).to_be_bytes());
+            page[pos..pos + cell.len()].copy_from_slice(cell);
+            pos += cell.len();
+        }
+        page
+    }
+
+    /// 叶子页单元；负载过长时把多余部分写入从 `overflow` 开始的连续溢出页
+    fn leaf_cell(db: &mut Vec<u8>, rowid: i64, payload: &[u8], overflow: u32) -> Vec<u8> {
+        let mut cell = encode_varint(payload.len() as u64);
+        cell.extend(encode_varint(rowid as u64));
+        let usable = PAGE_SIZE;
+        let max_local = usable - 35;
+        let local = if payload.len() <= max_local {
+            payload.len()
+        } else {
+            let min_local = (usable - 12) * 32 / 255 - 23;
+            let k = min_local + (payload.len() - min_local) % (usable - 4);
+            if k <= max_local { k } else { min_local }
+        };
+        cell.extend_from_slice(&payload[..local]);
+        if local < payload.len() {
+            cell.extend_from_slice(&overflow.to_be_bytes());
+            let chunks: Vec<_> = payload[local..].chunks(usable - 4).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let number = overflow + i as u32;
+                let next = if i + 1 < chunks.len() { number + 1 } else { 0 };
+                let mut page = next.to_be_bytes().to_vec();
+                page.extend_from_slice(chunk);
+                put_page(db, number, &page);
+            }
+        }
+        cell
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    /// 第 1 页为 sqlite_master，`items` 表的根页为第 2 页
+    fn database(root: &[u8]) -> Vec<u8> {
+        let mut db = Vec::new();
+        let master = record(&[
+            text("table"),
+            text("items"),
+            text("items"),
+            Value::Integer(2),
+            text("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, data BLOB, scale REAL)"),
+        ]);
+        let cell = leaf_cell(&mut db, 1, &master, 0);
+        let mut page = btree_page(100, 0x0D, &[cell], None);
+        page[..16].copy_from_slice(b"SQLite format 3\0");
+        page[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        put_page(&mut db, 1, &page);
+        put_page(&mut db, 2, root);
+        db
+    }
+
+    #[test]
+    fn reads_rows_across_pages() {
+        // 第 2 页为内部页，左子页 3，右子页 4；第二行的负载溢出到第 5、6 页
+        let mut db = database(&[]);
+        let big: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let first = record(&[
+            Value::Null,
+            text("a"),
+            Value::Blob(vec![1, 2, 3]),
+            Value::Real(0.5),
+        ]);
+        let second = record(&[
+            Value::Null,
+            text("b"),
+            Value::Blob(big.clone()),
+            Value::Integer(-2),
+        ]);
+        let cell = leaf_cell(&mut db, 1, &first, 0);
+        put_page(&mut db, 3, &btree_page(0, 0x0D, &[cell], None));
+        let cell = leaf_cell(&mut db, 7, &second, 5);
+        put_page(&mut db, 4, &btree_page(0, 0x0D, &[cell], None));
+        let mut key = 3u32.to_be_bytes().to_vec();
+        key.extend(encode_varint(1));
+        put_page(&mut db, 2, &btree_page(0, 0x05, &[key], Some(4)));
+
+        let database = Database::new(&db).unwrap();
+        assert!(database.table("missing").unwrap().is_none());
+        let table = database.table("ITEMS").unwrap().unwrap();
+        assert_eq!(table.columns, ["id", "name", "data", "scale"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.get(0, "id").unwrap().as_f64(), Some(1.0));
+        assert_eq!(table.get(1, "id").unwrap().as_f64(), Some(7.0));
+        assert!(matches!(table.get(0, "name"), Some(Value::Text(s)) if s == "a"));
+        assert_eq!(
+            table.get(0, "data").unwrap().as_blob(),
+            Some(&[1, 2, 3][..])
+        );
+        assert_eq!(table.get(1, "data").unwrap().as_blob(), Some(&big[..]));
+        assert_eq!(table.get(0, "scale").unwrap().as_f64(), Some(0.5));
+        assert_eq!(table.get(1, "scale").unwrap().as_f64(), Some(-2.0));
+        assert!(table.get(0, "nothing").is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(Database::new(b"").is_err());
+        assert!(Database::new(&[0; 512]).is_err());
+
+        let mut key = 2u32.to_be_bytes().to_vec();
+        key.extend(encode_varint(1));
+        let db = database(&btree_page(0, 0x05, &[key], Some(3)));
+        // 第 3 页不存在
+        assert!(Database::new(&db).unwrap().table("items").is_err());
+        // 截断的数据库
+        assert!(Database::new(&db[..600]).unwrap().table("items").is_err());
+
+        // 子页指回自身
+        let db = database(&btree_page(0, 0x05, &[], Some(2)));
+        assert!(Database::new(&db).unwrap().table("items").is_err());
+
+        // 很深的内部页链不能耗尽栈空间
+        let mut db = database(&btree_page(0, 0x05, &[], Some(3)));
+        for number in 3..200 {
+            put_page(&mut db, number, &btree_page(0, 0x05, &[], Some(number + 1)));
+        }
+        put_page(&mut db, 200, &btree_page(0, 0x0D, &[], None));
+        assert!(Database::new(&db).unwrap().table("items").is_err());
+
+        // 未知的页面类型
+        let db = database(&btree_page(0, 0x02, &[vec![0; 8]], None));
+        assert!(Database::new(&db).unwrap().table("items").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::{path::Path, process::Command, time::UNIX_EPOCH};
+
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    clock, font,
+    transform::{self, ResizeFilter, ResizeSpec},
+};
+
+/// 水印所在的角落
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum StampPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// 文字水印设置
+#[derive(Clone, Debug)]
+pub struct Stamp {
+    /// 水印模板，支持 `{name}`、`{date}`、`{time}`、`{git}` 占位符
+    pub template: String,
+    pub position: StampPosition,
+    /// 字形放大倍数，为空时根据图像尺寸自动选择
+    pub scale: Option<u32>,
+}
+
+impl Stamp {
+    /// 展开模板中的占位符
+    pub fn render_text(&self, source: &Path) -> String {
+        let mut text = self.template.clone();
+        if text.contains("{name}") {
+            let name = source
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            text = text.replace("{name}", &name);
+        }
+        if text.contains("{date}") || text.contains("{time}") {
+            let (date, time) = utc_now();
+            text = text.replace("{date}", &date).replace("{time}", &time);
+        }
+        if text.contains("{git}") {
+            text = text.replace("{git}", &git_revision(source));
+        }
+        text
+    }
+
+    /// 将水印绘制到图像的指定角落，文字为白色，底部衬半透明黑色
+    pub fn apply(&self, img: &mut RgbaImage, source: &Path) {
+        let text = self.render_text(source);
+        let scale = self
+            .scale
+            .unwrap_or_else(|| (img.width().min(img.height()) / 300).max(1));
+        let (text_w, text_h) = font::text_size(&text, scale);
+        if text_w == 0 {
+            return;
+        }
+        let padding = 2 * scale;
+        let box_w = text_w + padding * 2;
+        let box_h = text_h + padding * 2;
+        let (x, y) = corner(self.position, img.dimensions(), (box_w, box_h), 0);
+        font::fill_rect(img, x, y, box_w, box_h, Rgba([0, 0, 0, 160]));
+        font::draw_text(
+            img,
+            &text,
+            x + padding as i64,
+            y + padding as i64,
+            scale,
+            Rgba([255, 255, 255, 255]),
+        );
+    }
+}
+
+/// 将图片水印（如 logo）按透明度叠加到指定角落，距边缘留出短边的 2%；
+/// 水印比图像大时等比缩小
+pub fn watermark(img: &mut RgbaImage, mark: &RgbaImage, position: StampPosition) {
+    let margin = img.width().min(img.height()) / 50;
+    let max_w = img.width().saturating_sub(margin * 2).max(1);
+    let max_h = img.height().saturating_sub(margin * 2).max(1);
+    let scaled;
+    let mark = if mark.width() > max_w || mark.height() > max_h {
+        let spec = ResizeSpec::Fit(max_w, max_h);
+        scaled = transform::resize(mark.clone(), spec, ResizeFilter::default());
+        &scaled
+    } else {
+        mark
+    };
+    let (x, y) = corner(position, img.dimensions(), mark.dimensions(), margin);
+    image::imageops::overlay(img, mark, x, y);
+}
+
+/// 宽高为 `size` 的方框放在图像角落时的左上角坐标
+fn corner(
+    position: StampPosition,
+    (width, height): (u32, u32),
+    size: (u32, u32),
+    margin: u32,
+) -> (i64, i64) {
+    let right = width as i64 - size.0 as i64 - margin as i64;
+    let bottom = height as i64 - size.1 as i64 - margin as i64;
+    let margin = margin as i64;
+    match position {
+        StampPosition::TopLeft => (margin, margin),
+        StampPosition::TopRight => (right, margin),
+        StampPosition::BottomLeft => (margin, bottom),
+        StampPosition::BottomRight => (right, bottom),
+    }
+}
+
+/// 获取源文件所在 git 仓库的短版本号，不在仓库中时返回 `unknown`
+fn git_revision(source: &Path) -> String {
+    let dir = source
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// 返回当前 UTC 日期 (`YYYY-MM-DD`) 与时间 (`HH:MM:SS`)
+fn utc_now() -> (String, String) {
+    let secs = clock::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02}:{:02}", rem / 3600, rem % 3600 / 60, rem % 60),
+    )
+}
+
+/// 将 1970-01-01 起的天数转换为公历日期
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
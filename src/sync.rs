@@ -0,0 +1,124 @@
+//! 一次性模式的镜像同步（`--sync`）：在根目录记录每个源文件生成了哪些输出，
+//! 下次运行时删除源文件已不存在、或设置改变后不再生成的旧输出，并报告新增、更新
+//! 与删除的文件。只删除记录中由本程序生成的文件，目录中其它图片不受影响。
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// 同步记录文件名
+pub const MANIFEST_NAME: &str = ".psd-export-sync";
+
+const HEADER: &str = "# psd-auto-export 同步记录，请勿手动修改\n";
+
+/// 一个输出文件的来源与内容校验和，用于区分更新与未变化
+struct Entry {
+    source: PathBuf,
+    checksum: u32,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub added: Vec<PathBuf>,
+    pub updated: Vec<PathBuf>,
+    pub unchanged: usize,
+    pub removed: Vec<PathBuf>,
+}
+
+/// 按本次的导出结果更新 `root` 下的同步记录并删除过期的输出。
+/// `sources` 是本次找到的全部源文件，`exported` 是导出成功的源文件及其输出；
+/// 导出失败或被脚本跳过的源文件保留原有记录与输出
+pub fn sync(
+    root: &Path,
+    sources: &[PathBuf],
+    exported: &[(PathBuf, Vec<PathBuf>)],
+) -> Result<Report> {
+    let manifest_path = root.join(MANIFEST_NAME);
+    let mut previous = load(&manifest_path)?;
+    let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+
+    let mut report = Report::default();
+    let mut entries = BTreeMap::new();
+    for (source, outputs) in exported {
+        for output in outputs {
+            let data = std::fs::read(output).context(format!("无法读取输出文件：{:?}", output))?;
+            let checksum = crc32fast::hash(&data);
+            let key = relative(output);
+            match previous.remove(&key) {
+                None => report.added.push(output.clone()),
+                Some(entry) if entry.checksum != checksum => report.updated.push(output.clone()),
+                Some(_) => report.unchanged += 1,
+            }
+            let source = relative(source);
+            entries.insert(key, Entry { source, checksum });
+        }
+    }
+
+    let found: HashSet<PathBuf> = sources.iter().map(|path| relative(path)).collect();
+    let done: HashSet<PathBuf> = exported.iter().map(|(path, _)| relative(path)).collect();
+    for (output, entry) in previous {
+        if found.contains(&entry.source) && !done.contains(&entry.source) {
+            entries.insert(output, entry);
+            continue;
+        }
+        // 输出可能正好是某个源文件（例如改为导出 ORA 后的 .ora），不能删除
+        if found.contains(&output) {
+            continue;
+        }
+        let path = root.join(&output);
+        match std::fs::remove_file(&path) {
+            Ok(()) => report.removed.push(path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context(format!("无法删除过期的输出：{:?}", path)),
+        }
+    }
+
+    save(&manifest_path, &entries)?;
+    Ok(report)
+}
+
+/// 读取同步记录，文件不存在时视为空记录
+fn load(path: &Path) -> Result<BTreeMap<PathBuf, Entry>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e).context(format!("无法读取同步记录：{:?}", path)),
+    };
+    let mut entries = BTreeMap::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let (Some(checksum), Some(source), Some(output)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            anyhow::bail!("同步记录 {:?} 第 {} 行格式错误", path, number + 1);
+        };
+        let checksum = u32::from_str_radix(checksum, 16).context(format!(
+            "同步记录 {:?} 第 {} 行格式错误",
+            path,
+            number + 1
+        ))?;
+        let source = PathBuf::from(source);
+        entries.insert(PathBuf::from(output), Entry { source, checksum });
+    }
+    Ok(entries)
+}
+
+/// 每行一个输出：校验和、源文件与输出文件，以制表符分隔
+fn save(path: &Path, entries: &BTreeMap<PathBuf, Entry>) -> Result<()> {
+    let mut text = String::from(HEADER);
+    for (output, entry) in entries {
+        text.push_str(&format!(
+            "{:08x}\t{}\t{}\n",
+            entry.checksum,
+            entry.source.to_string_lossy(),
+            output.to_string_lossy()
+        ));
+    }
+    std::fs::write(path, text).context(format!("无法写入同步记录：{:?}", path))
+}
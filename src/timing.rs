@@ -0,0 +1,100 @@
+//! 单个文件各处理阶段的耗时，写入导出日志，并在一次性模式结束时汇总最慢的文件。
+
+use std::{
+    fmt,
+    io::{self, Seek, SeekFrom, Write},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    /// 读取并解析源文件
+    pub decode: Duration,
+    /// 合成图层（由解码器自行合成时）
+    pub composite: Duration,
+    /// 旋转、缩放、填充与水印
+    pub transform: Duration,
+    /// 编码为输出格式
+    pub encode: Duration,
+    /// 写入输出文件
+    pub write: Duration,
+}
+
+impl Timings {
+    fn stages(&self) -> [(&'static str, Duration); 5] {
+        [
+            ("解码", self.decode),
+            ("合成", self.composite),
+            ("变换", self.transform),
+            ("编码", self.encode),
+            ("写入", self.write),
+        ]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages().iter().map(|(_, d)| *d).sum()
+    }
+
+    /// 耗时最长的阶段名称
+    pub fn bottleneck(&self) -> &'static str {
+        self.stages()
+            .into_iter()
+            .max_by_key(|(_, d)| *d)
+            .map_or("解码", |(name, _)| name)
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "耗时 {:.2}s（", self.total().as_secs_f64())?;
+        for (i, (name, duration)) in self.stages().into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "，" };
+            write!(f, "{}{} {:.2}s", separator, name, duration.as_secs_f64())?;
+        }
+        write!(f, "）")
+    }
+}
+
+/// 执行 `f`，并把耗时累加到 `slot`
+pub fn measure<T>(slot: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *slot += start.elapsed();
+    result
+}
+
+/// 统计写入耗时的 `Write` 包装。编码器边编码边写入时，用它从总耗时中分出写入的部分
+pub struct TimedWriter<W> {
+    inner: W,
+    elapsed: Duration,
+}
+
+impl<W> TimedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        TimedWriter {
+            inner,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// 目前为止在写入中花费的时间
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+impl<W: Write> Write for TimedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        measure(&mut self.elapsed, || self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        measure(&mut self.elapsed, || self.inner.flush())
+    }
+}
+
+impl<W: Seek> Seek for TimedWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        measure(&mut self.elapsed, || self.inner.seek(pos))
+    }
+}
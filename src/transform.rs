@@ -0,0 +1,365 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage, imageops::FilterType};
+
+/// 缩放时使用的重采样滤波器
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ResizeFilter {
+    /// 最近邻，适合像素画
+    Nearest,
+    /// 双线性
+    Triangle,
+    /// 双三次 (Catmull-Rom)
+    Catmullrom,
+    /// Lanczos (窗口为 3)，适合照片
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub(crate) fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::Catmullrom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// 缩放方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeSpec {
+    /// 按倍数缩放，例如 `0.5x`、`2x`
+    Scale(f32),
+    /// 等比缩小到不超过 宽x高 的范围内（不放大），例如 `1024` 或 `1920x1080`
+    Fit(u32, u32),
+}
+
+impl ResizeSpec {
+    /// 计算缩放后的尺寸
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match *self {
+            ResizeSpec::Scale(factor) => (
+                ((width as f32 * factor).round() as u32).max(1),
+                ((height as f32 * factor).round() as u32).max(1),
+            ),
+            ResizeSpec::Fit(max_w, max_h) => {
+                if width <= max_w && height <= max_h {
+                    return (width, height);
+                }
+                let ratio = (max_w as f64 / width as f64).min(max_h as f64 / height as f64);
+                (
+                    ((width as f64 * ratio).round() as u32).max(1),
+                    ((height as f64 * ratio).round() as u32).max(1),
+                )
+            }
+        }
+    }
+}
+
+/// 解析 `--resize` 参数：`2x`/`0.5x` 表示倍数，`1024` 表示最长边，`1920x1080`
+/// 表示限定范围
+pub fn parse_resize(s: &str) -> Result<ResizeSpec> {
+    let s = s.trim();
+    if let Some(factor) = s.strip_suffix(['x', 'X']) {
+        let factor: f32 = factor.parse()?;
+        if !(factor > 0.0 && factor.is_finite()) {
+            bail!("缩放倍数必须大于 0：{}", s);
+        }
+        return Ok(ResizeSpec::Scale(factor));
+    }
+    let (w, h) = match s.split_once(['x', 'X']) {
+        Some((w, h)) => (w.parse()?, h.parse()?),
+        None => {
+            let n = s.parse()?;
+            (n, n)
+        }
+    };
+    if w == 0 || h == 0 {
+        bail!("缩放尺寸必须大于 0：{}", s);
+    }
+    Ok(ResizeSpec::Fit(w, h))
+}
+
+/// 按指定方式和滤波器缩放图像；尺寸不变时直接返回原图
+pub fn resize(img: RgbaImage, spec: ResizeSpec, filter: ResizeFilter) -> RgbaImage {
+    let (w, h) = spec.target_size(img.width(), img.height());
+    if (w, h) == img.dimensions() {
+        return img;
+    }
+    image::imageops::resize(&img, w, h, filter.filter_type())
+}
+
+/// 画布填充的目标尺寸
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadSpec {
+    /// 按宽高比填充，例如 `16:9`
+    Aspect(u32, u32),
+    /// 填充到固定尺寸，例如 `1920x1080`；图像过大时先等比缩小
+    Size(u32, u32),
+}
+
+/// 解析 `--pad-to` 参数：`16:9` 表示宽高比，`1920x1080` 表示固定尺寸
+pub fn parse_pad(s: &str) -> Result<PadSpec> {
+    let s = s.trim();
+    let (is_aspect, (w, h)) = match (s.split_once(':'), s.split_once(['x', 'X'])) {
+        (Some(pair), _) => (true, pair),
+        (None, Some(pair)) => (false, pair),
+        (None, None) => bail!("无法解析填充目标，应为 `16:9` 或 `1920x1080`：{}", s),
+    };
+    let (w, h): (u32, u32) = (w.trim().parse()?, h.trim().parse()?);
+    if w == 0 || h == 0 {
+        bail!("填充目标必须大于 0：{}", s);
+    }
+    Ok(if is_aspect {
+        PadSpec::Aspect(w, h)
+    } else {
+        PadSpec::Size(w, h)
+    })
+}
+
+/// 解析颜色：`transparent` 或 `#RRGGBB` / `#RRGGBBAA`（`#` 可省略）
+pub fn parse_color(s: &str) -> Result<Rgba<u8>> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("transparent") {
+        return Ok(Rgba([0, 0, 0, 0]));
+    }
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        bail!(
+            "无法解析颜色，应为 `#RRGGBB`、`#RRGGBBAA` 或 `transparent`：{}",
+            s
+        );
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, alpha]))
+}
+
+/// 将图像居中放到满足目标宽高比或尺寸的画布上，空白处用 `color` 填充。同时返回原图
+/// 的缩放比例：填充到指定尺寸时原图会先缩放到能放入画布，分辨率需要同比例换算。
+/// 画布尺寸超出范围或无法分配时返回错误
+pub fn pad(
+    img: RgbaImage,
+    spec: PadSpec,
+    color: Rgba<u8>,
+    filter: ResizeFilter,
+) -> Result<(RgbaImage, f64)> {
+    let source_width = img.width();
+    let (canvas_w, canvas_h) = canvas_size(img.dimensions(), spec)?;
+    if (canvas_w, canvas_h) == img.dimensions() {
+        return Ok((img, 1.0));
+    }
+    // 先分配画布，过大时在缩放原图之前就返回错误
+    let mut canvas = filled(canvas_w, canvas_h, color)?;
+    let img = match spec {
+        PadSpec::Aspect(..) => img,
+        PadSpec::Size(cw, ch) => resize(img, ResizeSpec::Fit(cw, ch), filter),
+    };
+    let ratio = if source_width == 0 {
+        1.0
+    } else {
+        img.width() as f64 / source_width as f64
+    };
+    if (canvas_w, canvas_h) == img.dimensions() {
+        return Ok((img, ratio));
+    }
+    let x = (canvas_w - img.width()) / 2;
+    let y = (canvas_h - img.height()) / 2;
+    image::imageops::replace(&mut canvas, &img, x as i64, y as i64);
+    Ok((canvas, ratio))
+}
+
+/// 填充后的画布尺寸。按宽高比填充时取能容纳原图的最小画布
+fn canvas_size((width, height): (u32, u32), spec: PadSpec) -> Result<(u32, u32)> {
+    let (aw, ah) = match spec {
+        PadSpec::Size(cw, ch) => return Ok((cw, ch)),
+        PadSpec::Aspect(aw, ah) => (aw as u64, ah as u64),
+    };
+    let (w, h) = (width as u64, height as u64);
+    let (cw, ch) = if w * ah >= h * aw {
+        (w, (w * ah).div_ceil(aw))
+    } else {
+        ((h * aw).div_ceil(ah), h)
+    };
+    match (u32::try_from(cw), u32::try_from(ch)) {
+        (Ok(cw), Ok(ch)) => Ok((cw, ch)),
+        _ => bail!("按宽高比 {}:{} 填充后的画布过大：{}x{}", aw, ah, cw, ch),
+    }
+}
+
+/// 以 `color` 填满的画布，过大时返回错误，而不是使进程中止
+fn filled(width: u32, height: u32, color: Rgba<u8>) -> Result<RgbaImage> {
+    let pixels = (width as usize)
+        .checked_mul(height as usize)
+        .filter(|pixels| pixels.checked_mul(4).is_some())
+        .context(format!("填充后的画布过大：{}x{}", width, height))?;
+    let mut data = Vec::new();
+    data.try_reserve_exact(pixels * 4)
+        .context(format!("填充后的画布过大：{}x{}", width, height))?;
+    data.extend(std::iter::repeat_n(color.0, pixels).flatten());
+    RgbaImage::from_raw(width, height, data)
+        .context(format!("填充后的画布过大：{}x{}", width, height))
+}
+
+/// 裁掉四周完全透明的边缘；整张图都透明时原样返回
+pub fn trim(img: RgbaImage) -> RgbaImage {
+    let Some((left, top, right, bottom)) = opaque_bounds(&img) else {
+        return img;
+    };
+    if (left, top, right, bottom) == (0, 0, img.width(), img.height()) {
+        return img;
+    }
+    image::imageops::crop_imm(&img, left, top, right - left, bottom - top).to_image()
+}
+
+/// 不透明像素的范围 (left, top, right, bottom)，完全透明时为 `None`
+pub fn opaque_bounds(img: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = img.dimensions();
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel[3] != 0 {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+    }
+    (right > left).then_some((left, top, right, bottom))
+}
+
+/// 顺时针旋转角度
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Rotation {
+    #[value(name = "90")]
+    Deg90,
+    #[value(name = "180")]
+    Deg180,
+    #[value(name = "270")]
+    Deg270,
+}
+
+/// 翻转方向
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Flip {
+    /// 水平翻转
+    H,
+    /// 垂直翻转
+    V,
+}
+
+/// 依次应用旋转和翻转
+pub fn orient(img: RgbaImage, rotate: Option<Rotation>, flips: &[Flip]) -> RgbaImage {
+    let mut img = match rotate {
+        Some(Rotation::Deg90) => image::imageops::rotate90(&img),
+        Some(Rotation::Deg180) => image::imageops::rotate180(&img),
+        Some(Rotation::Deg270) => image::imageops::rotate270(&img),
+        None => img,
+    };
+    for flip in flips {
+        match flip {
+            Flip::H => image::imageops::flip_horizontal_in_place(&mut img),
+            Flip::V => image::imageops::flip_vertical_in_place(&mut img),
+        }
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pad_targets() {
+        assert!(matches!(parse_pad("16:9"), Ok(PadSpec::Aspect(16, 9))));
+        assert!(matches!(
+            parse_pad(" 1920x1080 "),
+            Ok(PadSpec::Size(1920, 1080))
+        ));
+        assert!(matches!(parse_pad("64X32"), Ok(PadSpec::Size(64, 32))));
+        for s in ["", "16", "0:9", "16:0", "0x10", "axb", "-1:2", "1:2:3"] {
+            assert!(parse_pad(s).is_err(), "应当拒绝：{:?}", s);
+        }
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("transparent").unwrap(), Rgba([0, 0, 0, 0]));
+        assert_eq!(parse_color("#ff8000").unwrap(), Rgba([255, 128, 0, 255]));
+        assert_eq!(parse_color("10203040").unwrap(), Rgba([16, 32, 48, 64]));
+        for s in ["", "#fff", "#ff80000", "#gggggg", "#ff800０"] {
+            assert!(parse_color(s).is_err(), "应当拒绝：{:?}", s);
+        }
+    }
+
+    #[test]
+    fn canvas_fits_image() {
+        assert_eq!(
+            canvas_size((100, 100), PadSpec::Aspect(16, 9)).unwrap(),
+            (178, 100)
+        );
+        assert_eq!(
+            canvas_size((100, 100), PadSpec::Aspect(9, 16)).unwrap(),
+            (100, 178)
+        );
+        assert_eq!(
+            canvas_size((160, 90), PadSpec::Aspect(16, 9)).unwrap(),
+            (160, 90)
+        );
+        assert_eq!(canvas_size((3, 2), PadSpec::Aspect(1, 1)).unwrap(), (3, 3));
+        assert_eq!(
+            canvas_size((3, 2), PadSpec::Size(10, 20)).unwrap(),
+            (10, 20)
+        );
+        // 超出 u32 时返回错误，而不是截断
+        assert!(canvas_size((2, 1), PadSpec::Aspect(1, 2_147_483_648)).is_err());
+        assert!(canvas_size((u32::MAX, 1), PadSpec::Aspect(1, 2)).is_err());
+    }
+
+    #[test]
+    fn pads_and_centers() {
+        let red = Rgba([255, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let img = RgbaImage::from_pixel(2, 1, red);
+        let (padded, ratio) = pad(
+            img.clone(),
+            PadSpec::Aspect(1, 1),
+            white,
+            ResizeFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(padded.dimensions(), (2, 2));
+        assert_eq!(ratio, 1.0);
+        assert_eq!(*padded.get_pixel(0, 0), red);
+        assert_eq!(*padded.get_pixel(0, 1), white);
+
+        // 固定尺寸时原图先缩小到能放入画布，返回缩放比例
+        let wide = RgbaImage::from_pixel(8, 2, red);
+        let (padded, ratio) =
+            pad(wide, PadSpec::Size(4, 4), white, ResizeFilter::default()).unwrap();
+        assert_eq!(padded.dimensions(), (4, 4));
+        assert_eq!(ratio, 0.5);
+        assert_eq!(*padded.get_pixel(0, 0), white);
+        assert_eq!(*padded.get_pixel(0, 1), red);
+
+        assert!(
+            pad(
+                img.clone(),
+                PadSpec::Aspect(1, 2_147_483_648),
+                white,
+                ResizeFilter::default()
+            )
+            .is_err()
+        );
+        assert!(
+            pad(
+                img,
+                PadSpec::Size(u32::MAX, u32::MAX),
+                white,
+                ResizeFilter::default()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Unity 导入设置（`--unity-meta`）：在每个导出文件旁写入 `name.png.meta`，指定纹理类型、
+//! sRGB 与精灵范围，Unity 导入新渲染的图片时不再套用默认设置。
+//!
+//! 已有的 `.meta` 会被重写，其中的 `guid` 保持不变，场景与预制件中的引用不受影响；
+//! 新文件的 `guid` 由输出文件的绝对路径决定，重复导出时保持一致。
+
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::RgbaImage;
+
+use crate::{absolute, sha256, transform};
+
+/// 纹理类型
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureType {
+    /// 精灵（2D 与 UI），范围为不透明像素的范围
+    #[default]
+    Sprite,
+    /// 普通纹理
+    Default,
+    /// 法线贴图，不使用 sRGB
+    NormalMap,
+}
+
+impl TextureType {
+    /// `TextureImporter` 中 `textureType` 的取值
+    fn id(self) -> u32 {
+        match self {
+            TextureType::Default => 0,
+            TextureType::NormalMap => 1,
+            TextureType::Sprite => 8,
+        }
+    }
+}
+
+/// `.meta` 文件的路径：输出文件名后加 `.meta`
+pub fn meta_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".meta");
+    output_path.with_file_name(name)
+}
+
+/// 写入或刷新导出文件的 `.meta`，返回其路径
+pub fn write_meta(output_path: &Path, image: &RgbaImage, kind: TextureType) -> Result<PathBuf> {
+    let path = meta_path(output_path);
+    let guid = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| existing_guid(&text))
+        .unwrap_or_else(|| {
+            sha256::hash_bytes(absolute(output_path).to_string_lossy().as_bytes())[..32].to_string()
+        });
+    let name = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    std::fs::write(&path, meta(&guid, &name, image, kind))
+        .context(format!("无法写入 Unity 导入设置：{:?}", path))?;
+    Ok(path)
+}
+
+fn existing_guid(text: &str) -> Option<String> {
+    let guid = text
+        .lines()
+        .find_map(|line| line.strip_prefix("guid:"))?
+        .trim();
+    (guid.len() == 32 && guid.bytes().all(|b| b.is_ascii_hexdigit())).then(|| guid.to_string())
+}
+
+fn meta(guid: &str, name: &str, image: &RgbaImage, kind: TextureType) -> String {
+    let sprite = kind == TextureType::Sprite;
+    let srgb = kind != TextureType::NormalMap;
+    let mut out = String::new();
+    _ = writeln!(out, "fileFormatVersion: 2");
+    _ = writeln!(out, "guid: {}", guid);
+    _ = writeln!(out, "TextureImporter:");
+    _ = writeln!(out, "  serializedVersion: 12");
+    _ = writeln!(out, "  mipmaps:");
+    _ = writeln!(out, "    sRGBTexture: {}", srgb as u8);
+    _ = writeln!(out, "    enableMipMap: {}", !sprite as u8);
+    _ = writeln!(out, "  textureType: {}", kind.id());
+    _ = writeln!(out, "  textureShape: 1");
+    _ = writeln!(out, "  alphaUsage: 1");
+    _ = writeln!(out, "  alphaIsTransparency: {}", sprite as u8);
+    _ = writeln!(out, "  npotScale: {}", !sprite as u8);
+    if sprite {
+        // 精灵范围使用 Unity 的坐标系：原点在左下角
+        let (left, top, right, bottom) =
+            transform::opaque_bounds(image).unwrap_or((0, 0, image.width(), image.height()));
+        let id = i64::from_str_radix(&sha256::hash_bytes(name.as_bytes())[..15], 16).unwrap_or(1);
+        _ = writeln!(out, "  spriteMode: 2");
+        _ = writeln!(out, "  spritePixelsToUnits: 100");
+        _ = writeln!(out, "  spriteMeshType: 1");
+        _ = writeln!(out, "  spriteSheet:");
+        _ = writeln!(out, "    serializedVersion: 2");
+        _ = writeln!(out, "    sprites:");
+        _ = writeln!(out, "    - serializedVersion: 2");
+        _ = writeln!(out, "      name: {}", yaml_string(name));
+        _ = writeln!(out, "      rect:");
+        _ = writeln!(out, "        serializedVersion: 2");
+        _ = writeln!(out, "        x: {}", left);
+        _ = writeln!(out, "        y: {}", image.height() - bottom);
+        _ = writeln!(out, "        width: {}", right - left);
+        _ = writeln!(out, "        height: {}", bottom - top);
+        _ = writeln!(out, "      alignment: 0");
+        _ = writeln!(out, "      pivot: {{x: 0.5, y: 0.5}}");
+        _ = writeln!(out, "      border: {{x: 0, y: 0, z: 0, w: 0}}");
+        _ = writeln!(out, "      internalID: {}", id);
+        _ = writeln!(out, "    nameFileIdTable:");
+        _ = writeln!(out, "      {}: {}", yaml_string(name), id);
+    } else {
+        _ = writeln!(out, "  spriteMode: 0");
+    }
+    _ = writeln!(out, "  userData: ");
+    _ = writeln!(out, "  assetBundleName: ");
+    _ = writeln!(out, "  assetBundleVariant: ");
+    out
+}
+
+/// 用单引号包住字符串，其中的单引号重复一次
+fn yaml_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
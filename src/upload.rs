@@ -0,0 +1,396 @@
+//! 把导出结果上传到远程位置（`--upload`），本地文件照常保留。上传通过系统的
+//! `curl` 命令完成，凭证经标准输入传给 curl，不会出现在进程列表中。
+//!
+//! 地址中可以使用占位符：`{name}`（源文件名，不含扩展名）、`{ext}`（导出格式的
+//! 扩展名）、`{file}`（导出文件名）与 `{date}`（UTC 日期 `YYYY-MM-DD`）。
+//!
+//! - `s3://bucket/key`：上传到 S3 兼容的存储，凭证取自 `AWS_ACCESS_KEY_ID`、
+//!   `AWS_SECRET_ACCESS_KEY` 与可选的 `AWS_SESSION_TOKEN`
+//! - `sftp://host/path`、`ftp://host/path`、`ftps://host/path`：上传到文件服务器，
+//!   自动创建缺少的目录。用户名与密码取自 `--upload-user`、`--upload-password`
+//!   （也可写在地址中，同样经标准输入传递，日志中的地址不含凭证），SFTP 未指定密码时
+//!   使用 `~/.ssh` 中的密钥
+//! - `https://`、`http://`（或 `davs://`、`dav://`）：以 WebDAV 方式上传（Nextcloud、
+//!   SharePoint 等），使用同样的用户名与密码，缺少的目录用 MKCOL 创建
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{deadline, stamp};
+
+#[derive(Clone, Debug)]
+pub struct Upload {
+    /// 目标地址模板
+    pub url: String,
+    pub s3: S3Options,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// 连接失败或超时等临时错误的重试次数
+    pub retries: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct S3Options {
+    pub region: String,
+    /// 自定义服务地址（MinIO、R2 等），为空时使用 AWS 的地址
+    pub endpoint: Option<String>,
+    /// 对象的预设 ACL，例如 `public-read`
+    pub acl: Option<String>,
+}
+
+/// 上传一个导出文件，返回上传到的地址
+pub fn upload(upload: &Upload, file: &Path, content_type: &str) -> Result<String> {
+    let url = expand(&upload.url, file);
+    let (scheme, rest) = url
+        .split_once("://")
+        .context(format!("上传地址缺少协议：{}", url))?;
+    let (userinfo, rest) = split_userinfo(rest);
+    let url = format!("{}://{}", scheme, rest);
+    let upload = &with_userinfo(upload, userinfo);
+    let rest = rest.as_str();
+    match scheme {
+        "https" | "davs" => return webdav_upload(upload, file, content_type, "https", rest),
+        "http" | "dav" => return webdav_upload(upload, file, content_type, "http", rest),
+        _ => {}
+    }
+    let mut request = Request::new(file, upload.retries);
+    let target = match scheme {
+        "s3" => s3_request(&mut request, &upload.s3, rest, content_type)?,
+        "sftp" | "ftp" | "ftps" => file_server_request(&mut request, upload, scheme, rest)?,
+        _ => bail!(
+            "不支持的上传协议 {}://，可用：s3、sftp、ftp、ftps、https、http",
+            scheme
+        ),
+    };
+    request.send(&target)?;
+    Ok(url)
+}
+
+/// WebDAV 的 PUT 要求父目录已经存在。服务器返回 409 时逐级 MKCOL 创建目录后重试，
+/// 已经存在的目录返回 405，忽略即可
+fn webdav_upload(
+    upload: &Upload,
+    file: &Path,
+    content_type: &str,
+    scheme: &str,
+    location: &str,
+) -> Result<String> {
+    let (host, path) = split_path(scheme, location)?;
+    let target = format!("{}://{}/{}", scheme, host, percent_encode(path));
+    let put = || {
+        let mut request = Request::new(file, upload.retries);
+        credentials(&mut request, upload);
+        request.header(&format!("Content-Type: {}", content_type));
+        request.status(&target)
+    };
+    let mut status = put()?;
+    if let (409, Some((parents, _))) = (status, path.rsplit_once('/')) {
+        let mut dir = format!("{}://{}", scheme, host);
+        for component in parents.split('/') {
+            dir = format!("{}/{}", dir, percent_encode(component));
+            let mut request = Request::empty(upload.retries);
+            credentials(&mut request, upload);
+            request.arg("--request");
+            request.arg("MKCOL");
+            request.status(&format!("{}/", dir))?;
+        }
+        status = put()?;
+    }
+    if !(200..300).contains(&status) {
+        bail!("上传到 {} 失败：服务器返回 HTTP {}", target, status);
+    }
+    Ok(target)
+}
+
+fn s3_request(
+    request: &mut Request,
+    options: &S3Options,
+    location: &str,
+    content_type: &str,
+) -> Result<String> {
+    let (bucket, key) = location
+        .split_once('/')
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .context(format!("S3 地址应为 s3://bucket/key：s3://{}", location))?;
+    let (Ok(access_key), Ok(secret_key)) = (
+        std::env::var("AWS_ACCESS_KEY_ID"),
+        std::env::var("AWS_SECRET_ACCESS_KEY"),
+    ) else {
+        bail!("缺少 S3 凭证，请设置 AWS_ACCESS_KEY_ID 与 AWS_SECRET_ACCESS_KEY");
+    };
+    request.config("user", &format!("{}:{}", access_key, secret_key));
+    if let Ok(token) = std::env::var("AWS_SESSION_TOKEN") {
+        request.config("header", &format!("x-amz-security-token: {}", token));
+    }
+    request.arg("--aws-sigv4");
+    request.arg(&format!("aws:amz:{}:s3", options.region));
+    request.header(&format!("Content-Type: {}", content_type));
+    if let Some(acl) = &options.acl {
+        request.header(&format!("x-amz-acl: {}", acl));
+    }
+    let endpoint = options
+        .endpoint
+        .clone()
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", options.region));
+    Ok(format!(
+        "{}/{}/{}",
+        endpoint.trim_end_matches('/'),
+        bucket,
+        percent_encode(key)
+    ))
+}
+
+fn file_server_request(
+    request: &mut Request,
+    upload: &Upload,
+    scheme: &str,
+    location: &str,
+) -> Result<String> {
+    let (host, path) = split_path(scheme, location)?;
+    request.arg("--ftp-create-dirs");
+    credentials(request, upload);
+    if scheme == "ftps" {
+        // 隐式 TLS 很少见，ftps:// 按显式 TLS（AUTH TLS）处理
+        request.arg("--ssl-reqd");
+        return Ok(format!("ftp://{}/{}", host, percent_encode(path)));
+    }
+    Ok(format!("{}://{}/{}", scheme, host, percent_encode(path)))
+}
+
+/// 把 `host/path/file` 拆成主机与路径，路径必须以文件名结尾
+fn split_path<'a>(scheme: &str, location: &'a str) -> Result<(&'a str, &'a str)> {
+    location
+        .split_once('/')
+        .filter(|(host, path)| !host.is_empty() && !path.is_empty() && !path.ends_with('/'))
+        .context(format!(
+            "上传地址应为 {0}://host/path/file：{0}://{1}",
+            scheme, location
+        ))
+}
+
+/// 从 `user:password@host/path` 中取出用户信息，返回它与去掉它之后的地址
+fn split_userinfo(location: &str) -> (Option<&str>, String) {
+    let end = location.find('/').unwrap_or(location.len());
+    match location[..end].rsplit_once('@') {
+        Some((userinfo, host)) => (Some(userinfo), format!("{}{}", host, &location[end..])),
+        None => (None, location.to_string()),
+    }
+}
+
+/// 地址中的用户名与密码（百分号编码）补充命令行未设置的部分
+fn with_userinfo(upload: &Upload, userinfo: Option<&str>) -> Upload {
+    let mut upload = upload.clone();
+    if let Some(userinfo) = userinfo {
+        let (user, password) = match userinfo.split_once(':') {
+            Some((user, password)) => (user, Some(password)),
+            None => (userinfo, None),
+        };
+        upload.user.get_or_insert_with(|| percent_decode(user));
+        if let Some(password) = password {
+            upload
+                .password
+                .get_or_insert_with(|| percent_decode(password));
+        }
+    }
+    upload
+}
+
+fn credentials(request: &mut Request, upload: &Upload) {
+    if let Some(user) = &upload.user {
+        let password = upload.password.as_deref().unwrap_or_default();
+        request.config("user", &format!("{}:{}", user, password));
+    }
+}
+
+/// 一次 curl 上传：命令行参数与经标准输入传递的配置（含凭证）
+struct Request {
+    args: Vec<String>,
+    config: String,
+}
+
+impl Request {
+    fn new(file: &Path, retries: u32) -> Self {
+        let mut request = Self::empty(retries);
+        request.arg("--upload-file");
+        request.arg(&file.to_string_lossy());
+        request
+    }
+
+    /// 不带上传文件的请求
+    fn empty(retries: u32) -> Self {
+        let mut request = Request {
+            args: vec!["--silent".into(), "--show-error".into()],
+            config: String::new(),
+        };
+        if retries > 0 {
+            request.arg("--retry");
+            request.arg(&retries.to_string());
+            request.arg("--retry-connrefused");
+        }
+        request
+    }
+
+    fn arg(&mut self, arg: &str) {
+        self.args.push(arg.to_string());
+    }
+
+    fn header(&mut self, header: &str) {
+        self.arg("--header");
+        self.arg(header);
+    }
+
+    /// 写入 curl 配置文件格式的一项，值加引号并转义
+    fn config(&mut self, key: &str, value: &str) {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        self.config.push_str(&format!("{} = \"{}\"\n", key, value));
+    }
+
+    /// 发送请求，服务器返回错误状态时失败
+    fn send(mut self, url: &str) -> Result<()> {
+        self.arg("--fail");
+        self.run(url).map(drop)
+    }
+
+    /// 发送请求并返回 HTTP 状态码，只有连接等 curl 自身的错误才会失败
+    fn status(mut self, url: &str) -> Result<u16> {
+        self.arg("--write-out");
+        self.arg("\n%{http_code}");
+        let stdout = self.run(url)?;
+        let stdout = String::from_utf8_lossy(&stdout);
+        stdout
+            .lines()
+            .last()
+            .and_then(|code| code.trim().parse().ok())
+            .context(format!("无法从 curl 的输出中读取 {} 的状态码", url))
+    }
+
+    /// 运行 curl，返回标准输出
+    fn run(self, url: &str) -> Result<Vec<u8>> {
+        let mut child = Command::new("curl")
+            .args(&self.args)
+            .args(["--config", "-", "--url", url])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("无法运行 curl，上传需要安装 curl")?;
+        child
+            .stdin
+            .take()
+            .context("无法写入 curl 的标准输入")?
+            .write_all(self.config.as_bytes())?;
+        let output = deadline::wait_with_output(child)?;
+        if !output.status.success() {
+            bail!(
+                "上传到 {} 失败：{}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// 展开地址模板中的占位符
+fn expand(template: &str, file: &Path) -> String {
+    let text = |s: Option<&std::ffi::OsStr>| {
+        s.map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let name = text(file.file_stem());
+    let ext = text(file.extension());
+    let mut url = template
+        .replace("{name}", &name)
+        .replace("{ext}", &ext)
+        .replace("{file}", &text(file.file_name()));
+    if url.contains("{date}") {
+        let days = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() / 86400);
+        let (year, month, day) = stamp::civil_from_days(days as i64);
+        url = url.replace("{date}", &format!("{:04}-{:02}-{:02}", year, month, day));
+    }
+    url
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 对路径做百分号编码，保留 `/` 与 RFC 3986 的非保留字符
+fn percent_encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(user: Option<&str>, password: Option<&str>) -> Upload {
+        Upload {
+            url: String::new(),
+            s3: S3Options {
+                region: "us-east-1".to_string(),
+                endpoint: None,
+                acl: None,
+            },
+            user: user.map(str::to_string),
+            password: password.map(str::to_string),
+            retries: 0,
+        }
+    }
+
+    #[test]
+    fn moves_userinfo_out_of_url() {
+        let (userinfo, rest) = split_userinfo("me:p%40ss%3Aw0rd@files.example.com/a@b/c.png");
+        assert_eq!(userinfo, Some("me:p%40ss%3Aw0rd"));
+        assert_eq!(rest, "files.example.com/a@b/c.png");
+        let merged = with_userinfo(&upload(None, None), userinfo);
+        assert_eq!(merged.user.as_deref(), Some("me"));
+        assert_eq!(merged.password.as_deref(), Some("p@ss:w0rd"));
+        // 命令行设置的优先
+        let merged = with_userinfo(&upload(Some("studio"), None), userinfo);
+        assert_eq!(merged.user.as_deref(), Some("studio"));
+        assert_eq!(merged.password.as_deref(), Some("p@ss:w0rd"));
+
+        assert_eq!(
+            split_userinfo("files.example.com/a@b.png"),
+            (None, "files.example.com/a@b.png".to_string())
+        );
+        assert_eq!(split_userinfo("me@host"), (Some("me"), "host".to_string()));
+        assert_eq!(percent_decode("100%25 %zz %4"), "100% %zz %4");
+    }
+}
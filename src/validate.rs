@@ -0,0 +1,42 @@
+//! 检查源文件能否解析（`pae validate`）：逐个解码而不写入任何输出，报告失败的文件与
+//! 原因，以及使用了渲染器无法还原的功能（智能对象、图层效果）的文件，供 CI 使用。
+
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::{ExportOptions, catch_panic, find_psd_files, mmap, photoshop, source};
+
+/// 单个文件的检查结果
+pub struct Checked {
+    pub path: PathBuf,
+    /// 解码失败的原因
+    pub error: Option<String>,
+    /// 渲染器无法还原、导出结果会与 Photoshop 不同的功能
+    pub unsupported: Vec<&'static str>,
+}
+
+/// 检查路径下的全部源文件（目录则递归查找），按路径排序返回
+pub fn check_all(path: &Path) -> anyhow::Result<Vec<Checked>> {
+    let mut files = find_psd_files(path, &ExportOptions::default())?;
+    files.sort();
+    Ok(files.into_par_iter().map(|path| check(&path)).collect())
+}
+
+pub fn check(path: &Path) -> Checked {
+    let error = catch_panic(|| source::decode(path))
+        .err()
+        .map(|e| format!("{:#}", e));
+    let unsupported = if source::is_psd(path) {
+        mmap::read(path)
+            .map(|bytes| photoshop::live_features(&bytes))
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    Checked {
+        path: path.to_path_buf(),
+        error,
+        unsupported,
+    }
+}
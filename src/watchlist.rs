@@ -0,0 +1,180 @@
+//! 监听列表文件（`--watchlist`）：每行一个路径或通配符模式，列表修改后监听模式随之
+//! 增减监听的路径，维护项目列表的人不必改动服务配置或重启服务。
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use walkdir::WalkDir;
+
+/// 读取列表并展开通配符，返回存在的文件与目录
+pub fn load(path: &Path) -> Result<BTreeSet<PathBuf>> {
+    let text = std::fs::read_to_string(path).context(format!("无法读取监听列表：{:?}", path))?;
+    Ok(parse(&text, path.parent().unwrap_or(Path::new("."))))
+}
+
+/// 空行与 `#` 开头的行忽略，相对路径相对于 `base`。通配符支持 `*`、`?` 与匹配任意层
+/// 目录的 `**`，只在读取列表时展开，之后新建的匹配目录要等列表下次修改时才会加入。
+/// 已被列表中其它目录包含的路径不单独返回
+pub fn parse(text: &str, base: &Path) -> BTreeSet<PathBuf> {
+    let mut paths = BTreeSet::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = base.join(line);
+        if !is_pattern(line) {
+            if path.exists() {
+                paths.insert(path);
+            } else {
+                log::warn!("监听列表中的路径不存在：{:?}", path);
+            }
+            continue;
+        }
+        let matched = expand(&path);
+        if matched.is_empty() {
+            log::warn!("监听列表中的模式没有匹配任何路径：{}", line);
+        }
+        paths.extend(matched);
+    }
+    // 按路径排序时目录排在其内容之前
+    let mut outermost = BTreeSet::new();
+    for path in paths {
+        if !outermost
+            .iter()
+            .any(|dir: &PathBuf| dir.is_dir() && path.starts_with(dir))
+        {
+            outermost.insert(path);
+        }
+    }
+    outermost
+}
+
+fn is_pattern(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+/// 从第一个含通配符的部分之前的目录开始遍历，逐级匹配其余部分
+fn expand(pattern: &Path) -> Vec<PathBuf> {
+    let mut base = PathBuf::new();
+    let mut rest: Vec<Vec<char>> = Vec::new();
+    for component in pattern.components() {
+        let text = component.as_os_str().to_string_lossy();
+        if rest.is_empty() && !is_pattern(&text) {
+            base.push(component);
+        } else {
+            rest.push(text.chars().collect());
+        }
+    }
+    if base.as_os_str().is_empty() {
+        base.push(".");
+    }
+    let max_depth = if rest.iter().any(|part| *part == ['*', '*']) {
+        usize::MAX
+    } else {
+        rest.len()
+    };
+    WalkDir::new(&base)
+        .min_depth(1)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let names: Vec<Vec<char>> = entry
+                .path()
+                .strip_prefix(&base)
+                .unwrap_or(entry.path())
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().chars().collect())
+                .collect();
+            matches_path(&rest, &names)
+        })
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+fn matches_path(pattern: &[Vec<char>], names: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => names.is_empty(),
+        Some((first, rest)) if *first == ['*', '*'] => {
+            (0..=names.len()).any(|skip| matches_path(rest, &names[skip..]))
+        }
+        Some((first, rest)) => names
+            .split_first()
+            .is_some_and(|(name, names)| matches_name(first, name) && matches_path(rest, names)),
+    }
+}
+
+fn matches_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_name(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_name(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && matches_name(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        let split = |s: &str| s.split('/').map(chars).collect::<Vec<_>>();
+        matches_path(&split(pattern), &split(path))
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(matches("*.psd", "a.psd"));
+        assert!(matches("*.psd", ".psd"));
+        assert!(!matches("*.psd", "a.kra"));
+        assert!(!matches("*.psd", "dir/a.psd"));
+        assert!(matches("art-?/*", "art-1/a.psd"));
+        assert!(!matches("art-?/*", "art-10/a.psd"));
+        assert!(!matches("art-?/*", "art-1"));
+        // `**` 匹配零层或任意层目录
+        assert!(matches("**/*.psd", "a.psd"));
+        assert!(matches("**/*.psd", "x/y/z/a.psd"));
+        assert!(matches("projects/**/art", "projects/art"));
+        assert!(matches("projects/**/art", "projects/a/b/art"));
+        assert!(!matches("projects/**/art", "projects/a/b/art/c"));
+        assert!(matches("a*b*c", "abc"));
+        assert!(matches("a*b*c", "a-b-b-c"));
+        assert!(!matches("a*b*c", "a-c-b"));
+    }
+
+    #[test]
+    fn parses_list() {
+        let dir = std::env::temp_dir().join(format!("pae-watchlist-{}", std::process::id()));
+        for sub in [
+            "games/a/art",
+            "games/b/art",
+            "games/b/art/ui",
+            "games/c/docs",
+            "solo",
+        ] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        std::fs::write(dir.join("solo/x.psd"), []).unwrap();
+
+        let text = "# 注释\n\n  games/*/art  \ngames/b/art/ui\nsolo/x.psd\nmissing\nnone/*\n";
+        let paths = parse(text, &dir);
+        // 已被 `games/b/art` 包含的 `games/b/art/ui` 不单独返回，不存在的路径忽略
+        assert_eq!(
+            paths.into_iter().collect::<Vec<_>>(),
+            [
+                dir.join("games/a/art"),
+                dir.join("games/b/art"),
+                dir.join("solo/x.psd"),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,678 @@
+//! GIMP XCF 读取。XCF 不保存合并后的图像，这里按图层顺序自行合成：
+//! 支持 RGB / 灰度 / 索引色图层、不透明度、可见性、偏移、分组与图层蒙版，
+//! 混合模式一律按正常模式处理，只支持 8 位精度。
+
+use std::io::Read;
+
+use anyhow::{Context, Result, bail};
+use flate2::read::ZlibDecoder;
+use image::RgbaImage;
+
+const TILE_SIZE: u32 = 64;
+
+const PROP_END: u32 = 0;
+const PROP_COLORMAP: u32 = 1;
+const PROP_OPACITY: u32 = 6;
+const PROP_VISIBLE: u32 = 8;
+const PROP_APPLY_MASK: u32 = 11;
+const PROP_OFFSETS: u32 = 15;
+const PROP_COMPRESSION: u32 = 17;
+const PROP_RESOLUTION: u32 = 19;
+const PROP_GROUP_ITEM: u32 = 29;
+const PROP_ITEM_PATH: u32 = 30;
+const PROP_FLOAT_OPACITY: u32 = 33;
+
+#[derive(Clone, Copy)]
+enum Compression {
+    None,
+    Rle,
+    Zlib,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// 11 版本起指针为 64 位
+    wide_pointers: bool,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let data = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .context("XCF 数据不完整")?;
+        self.pos += len;
+        Ok(data)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn pointer(&mut self) -> Result<usize> {
+        if self.wide_pointers {
+            Ok(u64::from_be_bytes(self.take(8)?.try_into()?) as usize)
+        } else {
+            Ok(self.u32()? as usize)
+        }
+    }
+
+    /// 以 0 结尾的指针列表
+    fn pointers(&mut self) -> Result<Vec<usize>> {
+        let mut pointers = Vec::new();
+        loop {
+            match self.pointer()? {
+                0 => return Ok(pointers),
+                p => pointers.push(p),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let data = self.take(len)?;
+        Ok(String::from_utf8_lossy(data.strip_suffix(b"\0").unwrap_or(data)).into_owned())
+    }
+
+    /// 读取属性列表，返回 (类型, 数据)
+    fn properties(&mut self) -> Result<Vec<(u32, &[u8])>> {
+        let mut properties = Vec::new();
+        loop {
+            let kind = self.u32()?;
+            let len = self.u32()? as usize;
+            if kind == PROP_END {
+                return Ok(properties);
+            }
+            let start = self.pos;
+            self.take(len)?;
+            properties.push((kind, &self.bytes[start..start + len]));
+        }
+    }
+
+    fn at(&self, pos: usize) -> Self {
+        Reader {
+            bytes: self.bytes,
+            pos,
+            wide_pointers: self.wide_pointers,
+        }
+    }
+}
+
+/// 图层的像素：每像素 `channels` 字节
+struct Pixels {
+    width: u32,
+    height: u32,
+    channels: usize,
+    data: Vec<u8>,
+}
+
+struct Layer {
+    pixels: Pixels,
+    kind: u32,
+    offset: (i32, i32),
+    opacity: f32,
+    visible: bool,
+    group: bool,
+    path: Vec<u32>,
+    mask: Option<Pixels>,
+}
+
+/// 解析 XCF 并合成为一张图像，返回图像与分辨率 (DPI)
+pub fn decode(bytes: &[u8]) -> Result<(RgbaImage, Option<(f64, f64)>)> {
+    let version = match bytes.get(..14) {
+        Some(b"gimp xcf file\0") => 0,
+        Some(header) if header.starts_with(b"gimp xcf v") && header[13] == 0 => {
+            std::str::from_utf8(&header[10..13])?.parse::<u32>()?
+        }
+        _ => bail!("不是 XCF 文件"),
+    };
+    let mut reader = Reader {
+        bytes,
+        pos: 14,
+        wide_pointers: version >= 11,
+    };
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let _base_type = reader.u32()?;
+    if version >= 4 {
+        let precision = reader.u32()?;
+        // 7 版本之前 0 表示 8 位；之后 100 / 150 分别为 8 位线性与 8 位 gamma
+        let eight_bit = if version < 7 {
+            precision == 0
+        } else {
+            matches!(precision, 100 | 150)
+        };
+        if !eight_bit {
+            bail!("只支持 8 位精度的 XCF 文件");
+        }
+    }
+
+    let mut compression = Compression::Rle;
+    let mut dpi = None;
+    let mut colormap = Vec::new();
+    for (kind, data) in reader.properties()? {
+        match kind {
+            PROP_COMPRESSION => {
+                compression = match data.first() {
+                    Some(0) => Compression::None,
+                    Some(1) => Compression::Rle,
+                    Some(2) => Compression::Zlib,
+                    _ => bail!("不支持的 XCF 压缩方式"),
+                }
+            }
+            PROP_RESOLUTION if data.len() >= 8 => {
+                let x = f32::from_be_bytes(data[..4].try_into()?) as f64;
+                let y = f32::from_be_bytes(data[4..8].try_into()?) as f64;
+                dpi = (x > 0.0 && y > 0.0).then_some((x, y));
+            }
+            PROP_COLORMAP if data.len() >= 4 => {
+                colormap = data[4..]
+                    .chunks_exact(3)
+                    .map(|c| [c[0], c[1], c[2]])
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    let layers = reader
+        .pointers()?
+        .into_iter()
+        .map(|p| read_layer(&mut reader.at(p), compression))
+        .collect::<Result<Vec<_>>>()?;
+
+    // 图层列表自顶向下，从最底层开始合成
+    let mut canvas = RgbaImage::from_raw(width, height, zeroed(width, height, 4)?)
+        .context("XCF 图像尺寸无效")?;
+    for layer in layers.iter().rev() {
+        if layer.group {
+            continue;
+        }
+        // 分组的可见性与不透明度作用于其中所有图层
+        let mut visible = layer.visible;
+        let mut opacity = layer.opacity;
+        for group in &layers {
+            if group.group
+                && layer.path.starts_with(&group.path)
+                && group.path.len() < layer.path.len()
+            {
+                visible &= group.visible;
+                opacity *= group.opacity;
+            }
+        }
+        if visible && opacity > 0.0 {
+            composite(&mut canvas, layer, opacity, &colormap);
+        }
+    }
+    Ok((canvas, dpi))
+}
+
+fn read_layer(reader: &mut Reader, compression: Compression) -> Result<Layer> {
+    let _width = reader.u32()?;
+    let _height = reader.u32()?;
+    let kind = reader.u32()?;
+    let _name = reader.string()?;
+
+    let mut layer_opacity = 1.0;
+    let mut visible = true;
+    let mut offset = (0, 0);
+    let mut group = false;
+    let mut path = Vec::new();
+    let mut apply_mask = false;
+    for (prop, data) in reader.properties()? {
+        let value = data
+            .get(..4)
+            .map(|d| u32::from_be_bytes(d.try_into().unwrap_or_default()));
+        match (prop, value) {
+            (PROP_OPACITY, Some(v)) => layer_opacity = v.min(255) as f32 / 255.0,
+            (PROP_FLOAT_OPACITY, Some(v)) => layer_opacity = f32::from_bits(v).clamp(0.0, 1.0),
+            (PROP_VISIBLE, Some(v)) => visible = v != 0,
+            (PROP_APPLY_MASK, Some(v)) => apply_mask = v != 0,
+            (PROP_GROUP_ITEM, _) => group = true,
+            (PROP_OFFSETS, Some(x)) if data.len() >= 8 => {
+                offset = (x as i32, i32::from_be_bytes(data[4..8].try_into()?));
+            }
+            (PROP_ITEM_PATH, _) => {
+                path = data
+                    .chunks_exact(4)
+                    .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    let hierarchy = reader.pointer()?;
+    let mask = reader.pointer()?;
+    // 分组图层的像素只是子图层的缓存，不参与合成
+    let pixels = if group || hierarchy == 0 {
+        Pixels {
+            width: 0,
+            height: 0,
+            channels: 1,
+            data: Vec::new(),
+        }
+    } else {
+        let pixels = read_hierarchy(&mut reader.at(hierarchy), compression)?;
+        // 合成时按图层类型读取各通道，通道数必须与类型一致
+        let expected = match kind {
+            0 => 3,
+            1 => 4,
+            2 | 4 => 1,
+            3 | 5 => 2,
+            _ => bail!("不支持的 XCF 图层类型：{}", kind),
+        };
+        if pixels.channels != expected {
+            bail!("XCF 图层的通道数与类型不符");
+        }
+        pixels
+    };
+    let mask = if apply_mask && mask != 0 {
+        // 蒙版是一个通道：宽、高、名称、属性，之后是像素层级指针
+        let mut channel = reader.at(mask);
+        channel.u32()?;
+        channel.u32()?;
+        channel.string()?;
+        channel.properties()?;
+        let hierarchy = channel.pointer()?;
+        Some(read_hierarchy(&mut channel.at(hierarchy), compression)?)
+    } else {
+        None
+    };
+
+    Ok(Layer {
+        pixels,
+        kind,
+        offset,
+        opacity: layer_opacity,
+        visible,
+        group,
+        path,
+        mask,
+    })
+}
+
+/// 读取像素层级中的第一级（原始尺寸），按 64x64 图块拼接
+fn read_hierarchy(reader: &mut Reader, compression: Compression) -> Result<Pixels> {
+    let _width = reader.u32()?;
+    let _height = reader.u32()?;
+    let channels = reader.u32()? as usize;
+    let level = reader.pointer()?;
+    let mut reader = reader.at(level);
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let tiles = reader.pointers()?;
+    if !(1..=4).contains(&channels) {
+        bail!("不支持的 XCF 像素格式");
+    }
+    if width == 0 || height == 0 {
+        bail!("XCF 图层尺寸无效");
+    }
+    // 每个图块都有一个指针，图块数不符的尺寸一定是损坏的；这同时使分配的内存受
+    // 文件大小限制
+    let columns = width.div_ceil(TILE_SIZE);
+    let rows = height.div_ceil(TILE_SIZE);
+    if tiles.len() as u64 != columns as u64 * rows as u64 {
+        bail!("XCF 图块数与图层尺寸不符");
+    }
+    let mut data = zeroed(width, height, channels)?;
+    for (i, &pointer) in tiles.iter().enumerate() {
+        let (tx, ty) = (
+            i as u32 % columns * TILE_SIZE,
+            i as u32 / columns * TILE_SIZE,
+        );
+        if ty >= height {
+            break;
+        }
+        let tile_w = TILE_SIZE.min(width - tx) as usize;
+        let tile_h = TILE_SIZE.min(height - ty) as usize;
+        let tile = read_tile(
+            reader.bytes,
+            pointer,
+            compression,
+            tile_w * tile_h,
+            channels,
+        )?;
+        for row in 0..tile_h {
+            let dst = ((ty as usize + row) * width as usize + tx as usize) * channels;
+            let src = row * tile_w * channels;
+            data[dst..dst + tile_w * channels].copy_from_slice(&tile[src..src + tile_w * channels]);
+        }
+    }
+    Ok(Pixels {
+        width,
+        height,
+        channels,
+        data,
+    })
+}
+
+/// 分配 `width * height * channels` 字节并清零，过大时返回错误，而不是使进程中止
+fn zeroed(width: u32, height: u32, channels: usize) -> Result<Vec<u8>> {
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .context("XCF 图像尺寸无效")?;
+    let mut data = Vec::new();
+    data.try_reserve_exact(len)
+        .context(format!("XCF 图像过大：{}x{}", width, height))?;
+    data.resize(len, 0);
+    Ok(data)
+}
+
+/// 读取一个图块，返回按像素交错排列的数据
+fn read_tile(
+    bytes: &[u8],
+    pos: usize,
+    compression: Compression,
+    pixels: usize,
+    channels: usize,
+) -> Result<Vec<u8>> {
+    let len = pixels * channels;
+    let source = bytes.get(pos..).context("XCF 图块指针无效")?;
+    match compression {
+        Compression::None => Ok(source.get(..len).context("XCF 图块数据不完整")?.to_vec()),
+        Compression::Zlib => {
+            let mut tile = Vec::with_capacity(len);
+            ZlibDecoder::new(source)
+                .take(len as u64)
+                .read_to_end(&mut tile)?;
+            if tile.len() != len {
+                bail!("XCF 图块数据不完整");
+            }
+            Ok(tile)
+        }
+        // RLE 按通道分平面存储
+        Compression::Rle => {
+            let mut tile = vec![0; len];
+            let mut pos = 0;
+            let mut byte = || -> Result<u8> {
+                let b = *source.get(pos).context("XCF 图块数据不完整")?;
+                pos += 1;
+                Ok(b)
+            };
+            for channel in 0..channels {
+                let mut i = 0;
+                while i < pixels {
+                    let op = byte()?;
+                    let (count, literal) = match op {
+                        128 => (u16::from_be_bytes([byte()?, byte()?]) as usize, true),
+                        129.. => (256 - op as usize, true),
+                        127 => (u16::from_be_bytes([byte()?, byte()?]) as usize, false),
+                        _ => (op as usize + 1, false),
+                    };
+                    if i + count > pixels {
+                        bail!("XCF 图块数据损坏");
+                    }
+                    let value = if literal { 0 } else { byte()? };
+                    for _ in 0..count {
+                        tile[i * channels + channel] = if literal { byte()? } else { value };
+                        i += 1;
+                    }
+                }
+            }
+            Ok(tile)
+        }
+    }
+}
+
+/// 按正常模式把图层叠加到画布上
+fn composite(canvas: &mut RgbaImage, layer: &Layer, opacity: f32, colormap: &[[u8; 3]]) {
+    let pixels = &layer.pixels;
+    let (ox, oy) = layer.offset;
+    for y in 0..pixels.height {
+        let cy = oy as i64 + y as i64;
+        if cy < 0 || cy >= canvas.height() as i64 {
+            continue;
+        }
+        for x in 0..pixels.width {
+            let cx = ox as i64 + x as i64;
+            if cx < 0 || cx >= canvas.width() as i64 {
+                continue;
+            }
+            let i = y as usize * pixels.width as usize + x as usize;
+            let px = &pixels.data[i * pixels.channels..(i + 1) * pixels.channels];
+            // 图层类型：0 RGB、1 RGBA、2 灰度、3 灰度+alpha、4 索引、5 索引+alpha
+            let (rgb, alpha) = match layer.kind {
+                0 | 1 => ([px[0], px[1], px[2]], px.get(3).copied()),
+                2 | 3 => ([px[0]; 3], px.get(1).copied()),
+                _ => (
+                    colormap.get(px[0] as usize).copied().unwrap_or_default(),
+                    px.get(1).copied(),
+                ),
+            };
+            let mut alpha = alpha.unwrap_or(255) as f32 / 255.0 * opacity;
+            if let Some(mask) = &layer.mask {
+                let value = mask.data.get(i * mask.channels).copied().unwrap_or(255);
+                alpha *= value as f32 / 255.0;
+            }
+            if alpha <= 0.0 {
+                continue;
+            }
+
+            let dst = canvas.get_pixel_mut(cx as u32, cy as u32);
+            let dst_alpha = dst[3] as f32 / 255.0;
+            let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+            for c in 0..3 {
+                let value =
+                    (rgb[c] as f32 * alpha + dst[c] as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+                dst[c] = value.round() as u8;
+            }
+            dst[3] = (out_alpha * 255.0).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestLayer {
+        kind: u32,
+        width: u32,
+        height: u32,
+        channels: u32,
+        offset: (i32, i32),
+        /// 已按文件的压缩方式编码的图块
+        tiles: Vec<Vec<u8>>,
+    }
+
+    struct Builder {
+        out: Vec<u8>,
+        wide: bool,
+    }
+
+    impl Builder {
+        fn u32(&mut self, value: u32) {
+            self.out.extend_from_slice(&value.to_be_bytes());
+        }
+
+        /// 写入占位指针，返回其位置
+        fn pointer(&mut self, value: usize) -> usize {
+            let at = self.out.len();
+            if self.wide {
+                self.out.extend_from_slice(&(value as u64).to_be_bytes());
+            } else {
+                self.u32(value as u32);
+            }
+            at
+        }
+
+        /// 让 `at` 处的指针指向当前位置
+        fn patch(&mut self, at: usize) {
+            let pos = self.out.len();
+            if self.wide {
+                self.out[at..at + 8].copy_from_slice(&(pos as u64).to_be_bytes());
+            } else {
+                self.out[at..at + 4].copy_from_slice(&(pos as u32).to_be_bytes());
+            }
+        }
+
+        fn property(&mut self, kind: u32, data: &[u8]) {
+            self.u32(kind);
+            self.u32(data.len() as u32);
+            self.out.extend_from_slice(data);
+        }
+    }
+
+    fn build(
+        header: &[u8; 14],
+        precision: Option<u32>,
+        compression: u8,
+        (width, height): (u32, u32),
+        layers: &[TestLayer],
+    ) -> Vec<u8> {
+        let wide = header[10..13] >= b"011"[..] && header.starts_with(b"gimp xcf v");
+        let mut b = Builder {
+            out: header.to_vec(),
+            wide,
+        };
+        b.u32(width);
+        b.u32(height);
+        b.u32(0);
+        if let Some(precision) = precision {
+            b.u32(precision);
+        }
+        b.property(PROP_COMPRESSION, &[compression]);
+        let resolution = [300f32.to_be_bytes(), 300f32.to_be_bytes()].concat();
+        b.property(PROP_RESOLUTION, &resolution);
+        b.property(PROP_END, &[]);
+        let pointers: Vec<_> = layers.iter().map(|_| b.pointer(0)).collect();
+        b.pointer(0);
+        // 通道列表
+        b.pointer(0);
+
+        for (layer, at) in layers.iter().zip(pointers) {
+            b.patch(at);
+            b.u32(layer.width);
+            b.u32(layer.height);
+            b.u32(layer.kind);
+            b.u32(2);
+            b.out.extend_from_slice(b"L\0");
+            let offsets = [layer.offset.0.to_be_bytes(), layer.offset.1.to_be_bytes()].concat();
+            b.property(PROP_OFFSETS, &offsets);
+            b.property(PROP_END, &[]);
+            let hierarchy = b.pointer(0);
+            b.pointer(0);
+
+            b.patch(hierarchy);
+            b.u32(layer.width);
+            b.u32(layer.height);
+            b.u32(layer.channels);
+            let level = b.pointer(0);
+            b.pointer(0);
+
+            b.patch(level);
+            b.u32(layer.width);
+            b.u32(layer.height);
+            let tiles: Vec<_> = layer.tiles.iter().map(|_| b.pointer(0)).collect();
+            b.pointer(0);
+            for (tile, at) in layer.tiles.iter().zip(tiles) {
+                b.patch(at);
+                b.out.extend_from_slice(tile);
+            }
+        }
+        b.out
+    }
+
+    /// 底层 2x2 蓝色 RGB，顶层 1x1 红色 RGBA 偏移到 (1, 1)
+    fn two_layers() -> Vec<TestLayer> {
+        vec![
+            TestLayer {
+                kind: 1,
+                width: 1,
+                height: 1,
+                channels: 4,
+                offset: (1, 1),
+                tiles: vec![vec![255, 0, 0, 255]],
+            },
+            TestLayer {
+                kind: 0,
+                width: 2,
+                height: 2,
+                channels: 3,
+                offset: (0, 0),
+                tiles: vec![[0, 0, 255].repeat(4)],
+            },
+        ]
+    }
+
+    #[test]
+    fn composites_uncompressed_layers() {
+        let bytes = build(b"gimp xcf file\0", None, 0, (2, 2), &two_layers());
+        let (image, dpi) = decode(&bytes).unwrap();
+        assert_eq!(dpi, Some((300.0, 300.0)));
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(1, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_rle_tiles_with_wide_pointers() {
+        // 70 像素宽的灰度图层跨两个图块：第一块为 64 个重复值，第二块为 6 个字面值
+        let layer = TestLayer {
+            kind: 2,
+            width: 70,
+            height: 1,
+            channels: 1,
+            offset: (0, 0),
+            tiles: vec![vec![63, 10], vec![250, 1, 2, 3, 4, 5, 6]],
+        };
+        let bytes = build(b"gimp xcf v011\0", Some(150), 1, (70, 1), &[layer]);
+        let (image, _) = decode(&bytes).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [10, 10, 10, 255]);
+        assert_eq!(image.get_pixel(63, 0).0, [10, 10, 10, 255]);
+        assert_eq!(image.get_pixel(64, 0).0, [1, 1, 1, 255]);
+        assert_eq!(image.get_pixel(69, 0).0, [6, 6, 6, 255]);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(decode(b"").is_err());
+        assert!(decode(b"not an xcf file at all").is_err());
+
+        let bytes = build(b"gimp xcf file\0", None, 0, (2, 2), &two_layers());
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+
+        // 16 位精度
+        assert!(
+            decode(&build(
+                b"gimp xcf v011\0",
+                Some(250),
+                0,
+                (2, 2),
+                &two_layers()
+            ))
+            .is_err()
+        );
+
+        // 通道数与图层类型不符
+        let mut layers = two_layers();
+        layers[0].kind = 0;
+        assert!(decode(&build(b"gimp xcf file\0", None, 0, (2, 2), &layers)).is_err());
+
+        // 图块数与图层尺寸不符
+        let mut layers = two_layers();
+        layers[1].tiles.push(vec![0; 12]);
+        assert!(decode(&build(b"gimp xcf file\0", None, 0, (2, 2), &layers)).is_err());
+
+        // 图层尺寸为 0
+        let mut layers = two_layers();
+        layers[0].width = 0;
+        assert!(decode(&build(b"gimp xcf file\0", None, 0, (2, 2), &layers)).is_err());
+
+        // RLE 游程超出图块
+        let layer = TestLayer {
+            kind: 2,
+            width: 2,
+            height: 1,
+            channels: 1,
+            offset: (0, 0),
+            tiles: vec![vec![5, 10]],
+        };
+        assert!(decode(&build(b"gimp xcf v003\0", None, 1, (2, 1), &[layer])).is_err());
+    }
+}
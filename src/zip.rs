@@ -0,0 +1,560 @@
+//! 极简 zip 读写，支持存储与 deflate 两种方式。超过 4 GiB 或 65535 个条目时使用 zip64 扩展。
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+use std::io::Read;
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+use flate2::read::DeflateDecoder;
+use flate2::{Compression, write::DeflateEncoder};
+
+use crate::{clock, stamp::civil_from_days};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+/// zip64 扩展字段的标识
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+/// 通用标志位：文件名使用 UTF-8 编码
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+/// 解压所需版本 2.0
+const VERSION: u16 = 20;
+/// 使用 zip64 扩展时解压所需版本 4.5
+const VERSION_ZIP64: u16 = 45;
+/// 32 位字段的上限，等于该值表示实际值保存在 zip64 扩展中
+const MAX_U32: u64 = u32::MAX as u64;
+/// deflate 的最大压缩比约为 1032:1，中央目录声称的大小超过它时一定是损坏的
+#[cfg(any(feature = "ora", feature = "kra"))]
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+struct CentralEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// 超出 32 位的值写成 `0xFFFFFFFF`，并把真实值追加到 zip64 扩展字段
+fn zip64_field(value: u64, extra: &mut Vec<u8>) -> u32 {
+    if value >= MAX_U32 {
+        extra.extend_from_slice(&value.to_le_bytes());
+        u32::MAX
+    } else {
+        value as u32
+    }
+}
+
+/// 组装 zip64 扩展字段，没有超出的值时为空
+fn zip64_extra(values: &[u8]) -> Vec<u8> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let mut extra = Vec::with_capacity(4 + values.len());
+    extra.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+    extra.extend_from_slice(&(values.len() as u16).to_le_bytes());
+    extra.extend_from_slice(values);
+    extra
+}
+
+/// 顺序写入的 zip 文件
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    /// DOS 格式的 (时间, 日期)
+    timestamp: (u16, u16),
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        ZipWriter {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+            timestamp: dos_timestamp(clock::now()),
+        }
+    }
+
+    /// 不压缩地写入一个文件（已经压缩过的数据，如 PNG，或 ORA 的 mimetype）
+    pub fn add_stored(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.add_entry(name, METHOD_STORED, data, data)
+    }
+
+    /// 以 deflate 压缩写入一个文件
+    pub fn add_deflated(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        self.add_entry(name, METHOD_DEFLATED, data, &compressed)
+    }
+
+    fn add_entry(&mut self, name: &str, method: u16, data: &[u8], stored: &[u8]) -> io::Result<()> {
+        let size = data.len() as u64;
+        let compressed_size = stored.len() as u64;
+        let offset = self.offset;
+        let crc = crc32fast::hash(data);
+        let (time, date) = self.timestamp;
+
+        // 文件头中的 zip64 扩展必须同时包含原始大小与压缩后大小
+        let zip64 = size >= MAX_U32 || compressed_size >= MAX_U32;
+        let extra = if zip64 {
+            zip64_extra(&[size.to_le_bytes(), compressed_size.to_le_bytes()].concat())
+        } else {
+            Vec::new()
+        };
+        let (size_field, compressed_field) = if zip64 {
+            (u32::MAX, u32::MAX)
+        } else {
+            (size as u32, compressed_size as u32)
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len() + extra.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&if zip64 { VERSION_ZIP64 } else { VERSION }.to_le_bytes());
+        header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&compressed_field.to_le_bytes());
+        header.extend_from_slice(&size_field.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&extra);
+        self.write(&header)?;
+        self.write(stored)?;
+
+        self.entries.push(CentralEntry {
+            name: name.to_string(),
+            method,
+            crc,
+            compressed_size,
+            size,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// 写入中央目录并返回底层 writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let (time, date) = self.timestamp;
+        let directory_offset = self.offset;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            // 中央目录的 zip64 扩展只包含超出的字段，顺序为原始大小、压缩后大小、偏移
+            let mut values = Vec::new();
+            let size = zip64_field(entry.size, &mut values);
+            let compressed_size = zip64_field(entry.compressed_size, &mut values);
+            let offset = zip64_field(entry.offset, &mut values);
+            let extra = zip64_extra(&values);
+            let version = if extra.is_empty() {
+                VERSION
+            } else {
+                VERSION_ZIP64
+            };
+
+            let mut header = Vec::with_capacity(46 + entry.name.len() + extra.len());
+            header.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            header.extend_from_slice(&version.to_le_bytes());
+            header.extend_from_slice(&version.to_le_bytes());
+            header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+            header.extend_from_slice(&entry.method.to_le_bytes());
+            header.extend_from_slice(&time.to_le_bytes());
+            header.extend_from_slice(&date.to_le_bytes());
+            header.extend_from_slice(&entry.crc.to_le_bytes());
+            header.extend_from_slice(&compressed_size.to_le_bytes());
+            header.extend_from_slice(&size.to_le_bytes());
+            header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            // 注释长度，磁盘号，内部属性，外部属性
+            header.extend_from_slice(&[0; 10]);
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(entry.name.as_bytes());
+            header.extend_from_slice(&extra);
+            self.write(&header)?;
+        }
+        let directory_size = self.offset - directory_offset;
+        let count = entries.len() as u64;
+
+        let zip64 =
+            count >= u16::MAX as u64 || directory_size >= MAX_U32 || directory_offset >= MAX_U32;
+        if zip64 {
+            let record_offset = self.offset;
+            let mut record = Vec::with_capacity(56 + 20);
+            record.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+            // 记录剩余部分的长度
+            record.extend_from_slice(&44u64.to_le_bytes());
+            record.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            record.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            // 本磁盘号，中央目录起始磁盘号
+            record.extend_from_slice(&[0; 8]);
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&directory_size.to_le_bytes());
+            record.extend_from_slice(&directory_offset.to_le_bytes());
+
+            record.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&record_offset.to_le_bytes());
+            // 磁盘总数
+            record.extend_from_slice(&1u32.to_le_bytes());
+            self.write(&record)?;
+        }
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        let count = count.min(u16::MAX as u64) as u16;
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(directory_size.min(MAX_U32) as u32).to_le_bytes());
+        end.extend_from_slice(&(directory_offset.min(MAX_U32) as u32).to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// 将时间转换为 zip 使用的 DOS 时间与日期（UTC）
+fn dos_timestamp(time: SystemTime) -> (u16, u16) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    // DOS 日期只能表示 1980 年之后
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let rem = secs % 86400;
+    let time = (((rem / 3600) << 11) | ((rem % 3600 / 60) << 5) | ((rem % 60) / 2)) as u16;
+    let date = ((((year - 1980) as u32) << 9) | (month << 5) | day) as u16;
+    (time, date)
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+/// 从内存中的 zip 文件按名称读取条目
+pub struct ZipReader<'a> {
+    bytes: &'a [u8],
+    entries: Vec<CentralEntry>,
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+impl<'a> ZipReader<'a> {
+    /// 解析中央目录
+    pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        // 文件尾记录至少 22 字节，后面可能跟着最长 65535 字节的注释
+        let end = (0..=bytes.len().saturating_sub(22))
+            .rev()
+            .take(22 + u16::MAX as usize)
+            .find(|&pos| read_u32(bytes, pos) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+            .ok_or_else(|| invalid("找不到 zip 中央目录"))?;
+        let (Some(count), Some(directory_size), Some(directory_offset)) = (
+            read_u16(bytes, end + 10),
+            read_u32(bytes, end + 12),
+            read_u32(bytes, end + 16),
+        ) else {
+            return Err(invalid("zip 文件尾记录不完整"));
+        };
+        let (count, mut pos) =
+            if count == u16::MAX || directory_size == u32::MAX || directory_offset == u32::MAX {
+                // zip64：文件尾记录之前是定位记录，指向 zip64 文件尾记录
+                let locator = end
+                    .checked_sub(20)
+                    .ok_or_else(|| invalid("zip64 定位记录不完整"))?;
+                if read_u32(bytes, locator) != Some(ZIP64_LOCATOR_SIGNATURE) {
+                    return Err(invalid("找不到 zip64 定位记录"));
+                }
+                let record =
+                    read_u64(bytes, locator + 8).ok_or_else(|| invalid("zip64 定位记录不完整"))?;
+                let record =
+                    usize::try_from(record).map_err(|_| invalid("zip64 文件尾记录损坏"))?;
+                if read_u32(bytes, record) != Some(ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE) {
+                    return Err(invalid("zip64 文件尾记录损坏"));
+                }
+                let (Some(count), Some(offset)) =
+                    (read_u64(bytes, record + 32), read_u64(bytes, record + 48))
+                else {
+                    return Err(invalid("zip64 文件尾记录不完整"));
+                };
+                (count, offset as usize)
+            } else {
+                (count as u64, directory_offset as usize)
+            };
+        // 条目数来自文件内容，每个条目至少占 46 字节，不按它预先分配内存
+        if count > (bytes.len() / 46) as u64 {
+            return Err(invalid("zip 中央目录损坏"));
+        }
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if read_u32(bytes, pos) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(invalid("zip 中央目录损坏"));
+            }
+            let field = |offset: usize| read_u32(bytes, pos + offset);
+            let short = |offset: usize| read_u16(bytes, pos + offset).map(usize::from);
+            let (
+                Some(method),
+                Some(crc),
+                Some(compressed_size),
+                Some(size),
+                Some(name_len),
+                Some(extra_len),
+                Some(comment_len),
+                Some(offset),
+            ) = (
+                read_u16(bytes, pos + 10),
+                field(16),
+                field(20),
+                field(24),
+                short(28),
+                short(30),
+                short(32),
+                field(42),
+            )
+            else {
+                return Err(invalid("zip 中央目录损坏"));
+            };
+            let name = bytes
+                .get(pos + 46..pos + 46 + name_len)
+                .ok_or_else(|| invalid("zip 中央目录损坏"))?;
+            let extra = bytes
+                .get(pos + 46 + name_len..pos + 46 + name_len + extra_len)
+                .ok_or_else(|| invalid("zip 中央目录损坏"))?;
+            // 值为 0xFFFFFFFF 的字段按顺序从 zip64 扩展中读取
+            let mut values = zip64_values(extra);
+            let mut field = |value: u32| match value {
+                u32::MAX => values.next().ok_or_else(|| invalid("zip64 扩展字段不完整")),
+                value => Ok(value as u64),
+            };
+            let size = field(size)?;
+            let compressed_size = field(compressed_size)?;
+            let offset = field(offset)?;
+            entries.push(CentralEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method,
+                crc,
+                compressed_size,
+                size,
+                offset,
+            });
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(ZipReader { bytes, entries })
+    }
+
+    /// 读取并解压指定名称的条目，不存在时返回 `None`
+    pub fn read(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entries.iter().find(|e| e.name == name) else {
+            return Ok(None);
+        };
+        let start = usize::try_from(entry.offset).map_err(|_| invalid("zip 文件头损坏"))?;
+        if read_u32(self.bytes, start) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(invalid("zip 文件头损坏"));
+        }
+        let (Some(name_len), Some(extra_len)) = (
+            read_u16(self.bytes, start + 26),
+            read_u16(self.bytes, start + 28),
+        ) else {
+            return Err(invalid("zip 文件头损坏"));
+        };
+        let data_start = start + 30 + name_len as usize + extra_len as usize;
+        let stored = usize::try_from(entry.compressed_size)
+            .ok()
+            .and_then(|len| data_start.checked_add(len))
+            .and_then(|end| self.bytes.get(data_start..end))
+            .ok_or_else(|| invalid("zip 条目数据不完整"))?;
+
+        let data = match entry.method {
+            METHOD_STORED => stored.to_vec(),
+            METHOD_DEFLATED => {
+                // 大小来自文件内容，按压缩数据能解出的上限预分配，并且不多解压
+                let capacity = entry
+                    .size
+                    .min((stored.len() as u64 + 1).saturating_mul(MAX_DEFLATE_RATIO));
+                let mut data = Vec::with_capacity(capacity as usize);
+                DeflateDecoder::new(stored)
+                    .take(entry.size)
+                    .read_to_end(&mut data)?;
+                data
+            }
+            method => {
+                return Err(invalid(&format!("不支持的 zip 压缩方式：{}", method)));
+            }
+        };
+        if data.len() as u64 != entry.size || crc32fast::hash(&data) != entry.crc {
+            return Err(invalid(&format!("zip 条目校验失败：{}", name)));
+        }
+        Ok(Some(data))
+    }
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+fn read_u64(bytes: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(pos..pos + 8)?.try_into().ok()?,
+    ))
+}
+
+/// 扩展字段区中 zip64 扩展包含的 64 位值
+#[cfg(any(feature = "ora", feature = "kra"))]
+fn zip64_values(mut extra: &[u8]) -> impl Iterator<Item = u64> {
+    let mut data: &[u8] = &[];
+    while let (Some(id), Some(len)) = (read_u16(extra, 0), read_u16(extra, 2)) {
+        let Some(field) = extra.get(4..4 + len as usize) else {
+            break;
+        };
+        if id == ZIP64_EXTRA_ID {
+            data = field;
+            break;
+        }
+        extra = &extra[4 + len as usize..];
+    }
+    data.chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap_or_default()))
+}
+
+#[cfg(any(feature = "ora", feature = "kra"))]
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(all(test, any(feature = "ora", feature = "kra")))]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_stored("mimetype", b"image/openraster").unwrap();
+        zip.add_deflated("中文/stack.xml", &b"<stack/>".repeat(100))
+            .unwrap();
+        zip.add_deflated("empty", b"").unwrap();
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample();
+        let reader = ZipReader::new(&bytes).unwrap();
+        assert_eq!(
+            reader.read("mimetype").unwrap().unwrap(),
+            b"image/openraster"
+        );
+        assert_eq!(
+            reader.read("中文/stack.xml").unwrap().unwrap(),
+            b"<stack/>".repeat(100)
+        );
+        assert_eq!(reader.read("empty").unwrap().unwrap(), b"");
+        assert!(reader.read("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn many_entries_use_zip64() {
+        let mut zip = ZipWriter::new(Vec::new());
+        let count = u16::MAX as usize + 10;
+        for i in 0..count {
+            zip.add_stored(&i.to_string(), i.to_string().as_bytes())
+                .unwrap();
+        }
+        let bytes = zip.finish().unwrap();
+        let reader = ZipReader::new(&bytes).unwrap();
+        assert_eq!(reader.entries.len(), count);
+        let last = (count - 1).to_string();
+        assert_eq!(reader.read(&last).unwrap().unwrap(), last.as_bytes());
+    }
+
+    #[test]
+    fn reads_zip64_extra_fields() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_stored("a", b"hello").unwrap();
+        let mut bytes = zip.finish().unwrap();
+        // 把中央目录中的大小与偏移改为 0xFFFFFFFF，真实值放进 zip64 扩展
+        let end = bytes.len() - 22;
+        let directory = read_u32(&bytes, end + 16).unwrap() as usize;
+        let mut central = bytes[directory..end].to_vec();
+        for offset in [20, 24, 42] {
+            central[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        }
+        central[30..32].copy_from_slice(&28u16.to_le_bytes());
+        central.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        central.extend_from_slice(&24u16.to_le_bytes());
+        for value in [5u64, 5, 0] {
+            central.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut tail = bytes[end..].to_vec();
+        tail[12..16].copy_from_slice(&(central.len() as u32).to_le_bytes());
+        bytes.truncate(directory);
+        bytes.extend(central);
+        bytes.extend(tail);
+
+        let reader = ZipReader::new(&bytes).unwrap();
+        assert_eq!(reader.read("a").unwrap().unwrap(), b"hello");
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(ZipReader::new(b"").is_err());
+        assert!(ZipReader::new(b"not a zip file at all, just some text").is_err());
+
+        let bytes = sample();
+        // 截掉文件尾记录
+        assert!(ZipReader::new(&bytes[..bytes.len() - 10]).is_err());
+
+        // 破坏存储条目的数据，校验和应当不匹配
+        let mut corrupted = bytes.clone();
+        let pos = corrupted
+            .windows(16)
+            .position(|w| w == b"image/openraster")
+            .unwrap();
+        corrupted[pos] ^= 0xff;
+        let reader = ZipReader::new(&corrupted).unwrap();
+        assert!(reader.read("mimetype").is_err());
+
+        // 中央目录声称的大小远超压缩数据所能解出的大小
+        let mut inflated = ZipWriter::new(Vec::new());
+        inflated.add_deflated("a", b"hello").unwrap();
+        let mut inflated = inflated.finish().unwrap();
+        let end = inflated.len() - 22;
+        let directory = read_u32(&inflated, end + 16).unwrap() as usize;
+        inflated[directory + 24..directory + 28].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+        let reader = ZipReader::new(&inflated).unwrap();
+        assert!(reader.read("a").is_err());
+
+        // 中央目录指向不存在的偏移
+        let mut dangling = bytes;
+        let end = dangling.len() - 22;
+        dangling[end + 16..end + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(ZipReader::new(&dangling).is_err());
+    }
+}